@@ -10,7 +10,7 @@ use clap::{value_parser, Arg, ArgAction, ArgMatches, Command, ValueEnum};
 use compute_api::spec::ComputeMode;
 use control_plane::endpoint::ComputeControlPlane;
 use control_plane::local_env::{InitForceMode, LocalEnv};
-use control_plane::pageserver::{PageServerNode, PAGESERVER_REMOTE_STORAGE_DIR};
+use control_plane::pageserver::{ImportOptions, PageServerNode, PAGESERVER_REMOTE_STORAGE_DIR};
 use control_plane::safekeeper::SafekeeperNode;
 use control_plane::storage_controller::StorageController;
 use control_plane::{broker, local_env};
@@ -121,7 +121,7 @@ fn main() -> Result<()> {
 
     // Check for 'neon init' command first.
     let subcommand_result = if sub_name == "init" {
-        handle_init(sub_args).map(Some)
+        handle_init(sub_args)
     } else {
         // all other commands need an existing config
         let mut env = LocalEnv::load_config().context("Error loading config")?;
@@ -342,7 +342,7 @@ fn parse_timeline_id(sub_match: &ArgMatches) -> anyhow::Result<Option<TimelineId
         .context("Failed to parse timeline id from the argument string")
 }
 
-fn handle_init(init_match: &ArgMatches) -> anyhow::Result<LocalEnv> {
+fn handle_init(init_match: &ArgMatches) -> anyhow::Result<Option<LocalEnv>> {
     let num_pageservers = init_match
         .get_one::<u16>("num-pageservers")
         .expect("num-pageservers arg has a default");
@@ -368,6 +368,14 @@ fn handle_init(init_match: &ArgMatches) -> anyhow::Result<LocalEnv> {
     let mut env =
         LocalEnv::parse_config(&toml_file).context("Failed to create neon configuration")?;
     let force = init_match.get_one("force").expect("we set a default value");
+    if init_match.get_flag("dry-run") {
+        env.check_init(pg_version, force)?;
+        println!(
+            "Neon repository can be initialized at {:?}",
+            env.base_data_dir
+        );
+        return Ok(None);
+    }
     env.init(pg_version, force)
         .context("Failed to initialize neon repository")?;
 
@@ -384,7 +392,7 @@ fn handle_init(init_match: &ArgMatches) -> anyhow::Result<LocalEnv> {
             });
     }
 
-    Ok(env)
+    Ok(Some(env))
 }
 
 /// The default pageserver is the one where CLI tenant/timeline operations are sent by default.
@@ -611,10 +619,24 @@ async fn handle_timeline(timeline_match: &ArgMatches, env: &mut local_env::Local
                 .copied()
                 .context("Failed to parse postgres version from the argument string")?;
 
+            let defaults = ImportOptions::default();
+            let options = ImportOptions {
+                connect_timeout: import_match
+                    .get_one::<humantime::Duration>("connect-timeout")
+                    .map_or(defaults.connect_timeout, |d| (*d).into()),
+                write_timeout: import_match
+                    .get_one::<humantime::Duration>("write-timeout")
+                    .map_or(defaults.write_timeout, |d| (*d).into()),
+                connect_retries: import_match
+                    .get_one::<u32>("connect-retries")
+                    .copied()
+                    .unwrap_or(defaults.connect_retries),
+            };
+
             let mut cplane = ComputeControlPlane::load(env.clone())?;
             println!("Importing timeline into pageserver ...");
             pageserver
-                .timeline_import(tenant_id, timeline_id, base, pg_wal, pg_version)
+                .timeline_import(tenant_id, timeline_id, base, pg_wal, pg_version, options)
                 .await?;
             env.register_branch_mapping(name.to_string(), tenant_id, timeline_id)?;
 
@@ -673,6 +695,24 @@ async fn handle_timeline(timeline_match: &ArgMatches, env: &mut local_env::Local
                 timeline_info.timeline_id
             );
         }
+        Some(("rename", rename_match)) => {
+            let tenant_id = get_tenant_id(rename_match, env)?;
+            let branch_name = rename_match
+                .get_one::<String>("branch-name")
+                .ok_or_else(|| anyhow!("No branch name provided"))?;
+            let new_branch_name = rename_match
+                .get_one::<String>("new-branch-name")
+                .ok_or_else(|| anyhow!("No new branch name provided"))?;
+            // Other commands fall back to the default branch when no branch is given.
+            if branch_name == DEFAULT_BRANCH_NAME {
+                bail!("cannot rename the default branch '{DEFAULT_BRANCH_NAME}'");
+            }
+
+            let timeline_id = env.rename_branch_mapping(tenant_id, branch_name, new_branch_name)?;
+            println!(
+                "Renamed branch '{branch_name}' of timeline {timeline_id} to '{new_branch_name}'"
+            );
+        }
         Some((sub_name, _)) => bail!("Unexpected tenant subcommand '{sub_name}'"),
         None => bail!("no tenant subcommand provided"),
     }
@@ -1430,6 +1470,12 @@ fn cli() -> Command {
                 )
                 .arg(pg_version_arg.clone())
                 .arg(force_arg)
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("Only check that the repository can be initialized, without writing anything"),
+                )
         )
         .subcommand(
             Command::new("timeline")
@@ -1445,6 +1491,12 @@ fn cli() -> Command {
                     .help("Use last Lsn of another timeline (and its data) as base when creating the new timeline. The timeline gets resolved by its branch name.").required(false))
                 .arg(Arg::new("ancestor-start-lsn").long("ancestor-start-lsn")
                     .help("When using another timeline as base, use a specific Lsn in it instead of the latest one").required(false)))
+            .subcommand(Command::new("rename")
+                .about("Rename a branch, keeping its timeline")
+                .arg(tenant_id_arg.clone())
+                .arg(branch_name_arg.clone().help("Current name of the branch").required(true))
+                .arg(Arg::new("new-branch-name").long("new-branch-name")
+                    .help("New name of the branch").required(true)))
             .subcommand(Command::new("create")
                 .about("Create a new blank timeline")
                 .arg(tenant_id_arg.clone())
@@ -1474,6 +1526,15 @@ fn cli() -> Command {
                     .help("Lsn the basebackup ends at"))
                 .arg(pg_version_arg.clone())
                 .arg(update_catalog.clone())
+                .arg(Arg::new("connect-timeout").long("connect-timeout")
+                    .value_parser(value_parser!(humantime::Duration))
+                    .help("Timeout of each attempt to connect to the pageserver, 10s by default"))
+                .arg(Arg::new("write-timeout").long("write-timeout")
+                    .value_parser(value_parser!(humantime::Duration))
+                    .help("Fail the import if the pageserver doesn't accept data for this long, 60s by default"))
+                .arg(Arg::new("connect-retries").long("connect-retries")
+                    .value_parser(value_parser!(u32))
+                    .help("How often to retry connecting to the pageserver, 3 by default"))
             )
         ).subcommand(
             Command::new("tenant")
@@ -603,7 +603,7 @@ impl Endpoint {
             .open(self.endpoint_path().join("compute.log"))?;
 
         // Launch compute_ctl
-        let conn_str = self.connstr("cloud_admin", "postgres");
+        let conn_str = self.connstr(&self.env.superuser, "postgres");
         println!("Starting postgres node at '{}'", conn_str);
         if create_test_user {
             let conn_str = self.connstr("test", "neondb");
@@ -3,10 +3,11 @@
 //! Now it also provides init method which acts like a stub for proper installation
 //! script which will use local paths.
 
-use anyhow::{bail, ensure, Context};
+use anyhow::{bail, Context};
 
 use clap::ValueEnum;
 use postgres_backend::AuthType;
+use postgres_connection::validate_role_name;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -18,14 +19,24 @@ use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use utils::{
-    auth::{encode_from_key_file, Claims},
+    auth::{encode_from_key_file, Claims, JwtAuth, Scope},
     id::{NodeId, TenantId, TenantTimelineId, TimelineId},
 };
 
+use crate::endpoint::ComputeControlPlane;
 use crate::safekeeper::SafekeeperNode;
 
 pub const DEFAULT_PG_VERSION: u32 = 15;
 
+pub const DEFAULT_SUPERUSER: &str = "cloud_admin";
+
+/// Directory with the public keys that services validate tokens with, once the keys
+/// have been rotated: the current key, and the previous one until
+/// [`LocalEnv::finish_key_rotation`].
+const AUTH_PUBLIC_KEYS_DIR: &str = "auth_public_keys";
+pub const CURRENT_PUBLIC_KEY: &str = "current.pem";
+const OLD_PUBLIC_KEY: &str = "old.pem";
+
 //
 // This data structures represents neon_local CLI config
 //
@@ -82,6 +93,11 @@ pub struct LocalEnv {
     #[serde(default)]
     pub control_plane_compute_hook_api: Option<Url>,
 
+    /// Superuser role created by initdb in new tenants, and used by neon_local to
+    /// connect to compute endpoints. Propagated into each pageserver's configuration.
+    #[serde(default = "default_superuser")]
+    pub superuser: String,
+
     /// Keep human-readable aliases in memory (and persist them to config), to hide ZId hex strings from the user.
     #[serde(default)]
     // A `HashMap<String, HashMap<TenantId, TimelineId>>` would be more appropriate here,
@@ -90,6 +106,10 @@ pub struct LocalEnv {
     branch_name_mappings: HashMap<String, Vec<(TenantId, TimelineId)>>,
 }
 
+fn default_superuser() -> String {
+    DEFAULT_SUPERUSER.to_string()
+}
+
 /// Broker config for cluster internal communication.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 #[serde(default)]
@@ -315,6 +335,70 @@ impl LocalEnv {
             .map(TimelineId::from)
     }
 
+    /// Give the branch `old_name` of the tenant the name `new_name`. Only the name changes:
+    /// the branch keeps its timeline, and the endpoints on it keep working.
+    pub fn rename_branch_mapping(
+        &mut self,
+        tenant_id: TenantId,
+        old_name: &str,
+        new_name: &str,
+    ) -> anyhow::Result<TimelineId> {
+        if self.get_branch_timeline_id(new_name, tenant_id).is_some() {
+            bail!("branch '{new_name}' already exists for tenant {tenant_id}");
+        }
+        let timeline_id = self
+            .get_branch_timeline_id(old_name, tenant_id)
+            .with_context(|| format!("no branch '{old_name}' for tenant {tenant_id}"))?;
+
+        let old_values = self
+            .branch_name_mappings
+            .get_mut(old_name)
+            .expect("branch was just found");
+        old_values.retain(|(mapped_tenant_id, _)| mapped_tenant_id != &tenant_id);
+        if old_values.is_empty() {
+            self.branch_name_mappings.remove(old_name);
+        }
+        self.register_branch_mapping(new_name.to_string(), tenant_id, timeline_id)?;
+        Ok(timeline_id)
+    }
+
+    /// libpq connection string for the compute endpoint of the given branch, as
+    /// the superuser to the `postgres` database. When the pageservers use JWT auth, a
+    /// tenant-scoped token is passed as the password.
+    pub fn compute_connstr(
+        &self,
+        tenant_id: TenantId,
+        branch_name: &str,
+    ) -> anyhow::Result<String> {
+        let timeline_id = self
+            .get_branch_timeline_id(branch_name, tenant_id)
+            .with_context(|| format!("no branch '{branch_name}' for tenant {tenant_id}"))?;
+        let cplane = ComputeControlPlane::load(self.clone())?;
+        let endpoint = cplane
+            .endpoints
+            .values()
+            .find(|ep| ep.tenant_id == tenant_id && ep.timeline_id == timeline_id)
+            .with_context(|| {
+                format!("no endpoint for branch '{branch_name}' of tenant {tenant_id}")
+            })?;
+
+        let needs_token = self
+            .pageservers
+            .iter()
+            .any(|ps| ps.pg_auth_type == AuthType::NeonJWT);
+        if !needs_token {
+            return Ok(endpoint.connstr(&self.superuser, "postgres"));
+        }
+        let token = self.generate_auth_token(&Claims::new(Some(tenant_id), Scope::Tenant))?;
+        Ok(format!(
+            "postgresql://{}:{}@{}:{}/postgres",
+            self.superuser,
+            token,
+            endpoint.pg_address.ip(),
+            endpoint.pg_address.port(),
+        ))
+    }
+
     pub fn timeline_name_mappings(&self) -> HashMap<TenantTimelineId, String> {
         self.branch_name_mappings
             .iter()
@@ -355,6 +439,8 @@ impl LocalEnv {
             anyhow::bail!("Configuration must contain at least one pageserver");
         }
 
+        validate_role_name(&env.superuser).context("invalid superuser")?;
+
         env.base_data_dir = base_path();
 
         Ok(env)
@@ -376,9 +462,15 @@ impl LocalEnv {
         // load and parse file
         let config = fs::read_to_string(repopath.join("config"))?;
         let mut env: LocalEnv = toml::from_str(config.as_str())?;
+        validate_role_name(&env.superuser).context("invalid superuser")?;
 
         env.base_data_dir = repopath;
 
+        if env.auth_keys_needed() {
+            env.check_auth_keys()
+                .context("JWT authentication is enabled, but the auth keys are not usable")?;
+        }
+
         Ok(env)
     }
 
@@ -430,65 +522,114 @@ impl LocalEnv {
         }
     }
 
-    //
-    // Initialize a new Neon repository
-    //
-    pub fn init(&mut self, pg_version: u32, force: &InitForceMode) -> anyhow::Result<()> {
-        // check if config already exists
-        let base_path = &self.base_data_dir;
-        ensure!(
-            base_path != Path::new(""),
-            "repository base path is missing"
-        );
+    /// The public key that services validate tokens with, or the directory of public
+    /// keys once the keys have been rotated.
+    pub fn get_public_key_path(&self) -> PathBuf {
+        let keys_dir = self.base_data_dir.join(AUTH_PUBLIC_KEYS_DIR);
+        if keys_dir.exists() {
+            keys_dir
+        } else {
+            self.base_data_dir.join("auth_public_key.pem")
+        }
+    }
 
-        if base_path.exists() {
+    /// Checks everything [`Self::init`] needs from the environment: the state of the
+    /// repository directory, the postgres and neon binaries, and openssl if auth keys are
+    /// to be generated. Nothing is written; all problems found are reported together.
+    pub fn check_init(&self, pg_version: u32, force: &InitForceMode) -> anyhow::Result<()> {
+        let mut problems = Vec::new();
+
+        let base_path = &self.base_data_dir;
+        if base_path == Path::new("") {
+            problems.push("repository base path is missing".to_string());
+        } else if base_path.exists() {
             match force {
-                InitForceMode::MustNotExist => {
-                    bail!(
-                        "directory '{}' already exists. Perhaps already initialized?",
-                        base_path.display()
-                    );
-                }
-                InitForceMode::EmptyDirOk => {
-                    if let Some(res) = std::fs::read_dir(base_path)?.next() {
-                        res.context("check if directory is empty")?;
-                        anyhow::bail!("directory not empty: {base_path:?}");
-                    }
-                }
-                InitForceMode::RemoveAllContents => {
-                    println!("removing all contents of '{}'", base_path.display());
-                    // instead of directly calling `remove_dir_all`, we keep the original dir but removing
-                    // all contents inside. This helps if the developer symbol links another directory (i.e.,
-                    // S3 local SSD) to the `.neon` base directory.
-                    for entry in std::fs::read_dir(base_path)? {
-                        let entry = entry?;
-                        let path = entry.path();
-                        if path.is_dir() {
-                            fs::remove_dir_all(&path)?;
-                        } else {
-                            fs::remove_file(&path)?;
+                InitForceMode::MustNotExist => problems.push(format!(
+                    "directory '{}' already exists. Perhaps already initialized?",
+                    base_path.display()
+                )),
+                InitForceMode::EmptyDirOk => match std::fs::read_dir(base_path) {
+                    Ok(mut entries) => {
+                        if entries.next().is_some() {
+                            problems.push(format!("directory not empty: {base_path:?}"));
                         }
                     }
-                }
+                    Err(e) => problems.push(format!("cannot read directory {base_path:?}: {e}")),
+                },
+                InitForceMode::RemoveAllContents => {}
             }
         }
 
-        if !self.pg_bin_dir(pg_version)?.join("postgres").exists() {
-            bail!(
-                "Can't find postgres binary at {}",
-                self.pg_bin_dir(pg_version)?.display()
-            );
+        match self.pg_bin_dir(pg_version) {
+            Ok(pg_bin_dir) => {
+                if !pg_bin_dir.join("postgres").exists() {
+                    problems.push(format!(
+                        "Can't find postgres binary at {}",
+                        pg_bin_dir.display()
+                    ));
+                }
+            }
+            Err(e) => problems.push(format!("{e:#}")),
         }
         for binary in ["pageserver", "safekeeper"] {
             if !self.neon_distrib_dir.join(binary).exists() {
-                bail!(
+                problems.push(format!(
                     "Can't find binary '{binary}' in neon distrib dir '{}'",
                     self.neon_distrib_dir.display()
+                ));
+            }
+        }
+
+        // Without auth, a failure to generate the keypair is not fatal, see init().
+        if self.private_key_path == PathBuf::new() && self.auth_keys_needed() {
+            if let Err(e) = check_openssl() {
+                problems.push(format!("{e:#}"));
+            }
+        }
+
+        if !problems.is_empty() {
+            bail!(
+                "cannot initialize neon repository:\n  {}",
+                problems.join("\n  ")
+            );
+        }
+        Ok(())
+    }
+
+    //
+    // Initialize a new Neon repository
+    //
+    pub fn init(&mut self, pg_version: u32, force: &InitForceMode) -> anyhow::Result<()> {
+        self.check_init(pg_version, force)?;
+
+        let base_path = self.base_data_dir.clone();
+        let created_base_path = !base_path.exists();
+        let res = self.init_files(&base_path, force);
+        if res.is_err() {
+            // Don't leave a half-initialized repository behind: it would only make the
+            // next attempt fail with "already exists".
+            let cleanup = if created_base_path {
+                fs::remove_dir_all(&base_path)
+            } else {
+                remove_dir_contents(&base_path)
+            };
+            if let Err(e) = cleanup {
+                eprintln!(
+                    "failed to clean up '{}' after failed init: {e}",
+                    base_path.display()
                 );
             }
         }
+        res
+    }
 
-        if !base_path.exists() {
+    fn init_files(&mut self, base_path: &Path, force: &InitForceMode) -> anyhow::Result<()> {
+        if base_path.exists() {
+            if let InitForceMode::RemoveAllContents = force {
+                println!("removing all contents of '{}'", base_path.display());
+                remove_dir_contents(base_path)?;
+            }
+        } else {
             fs::create_dir(base_path)?;
         }
 
@@ -528,6 +669,95 @@ impl LocalEnv {
         self.persist_config(base_path)
     }
 
+    /// Checks that a token signed with the private key validates against the public keys
+    /// that the services are configured with.
+    fn check_auth_keys(&self) -> anyhow::Result<()> {
+        let public_key_path = self.get_public_key_path();
+        let auth = camino::Utf8Path::from_path(&public_key_path)
+            .with_context(|| format!("{} is not UTF-8", public_key_path.display()))
+            .and_then(JwtAuth::from_key_path)
+            .with_context(|| format!("loading {}", public_key_path.display()))?;
+        let token = self
+            .generate_auth_token(&Claims::new(None, Scope::PageServerApi))
+            .with_context(|| {
+                format!(
+                    "signing a token with {}",
+                    self.get_private_key_path().display()
+                )
+            })?;
+        auth.decode(&token).map_err(|e| {
+            anyhow::anyhow!(
+                "a token signed with {} does not validate against {}: {e}",
+                self.get_private_key_path().display(),
+                public_key_path.display()
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Replaces the JWT signing keypair with a freshly generated one and saves the
+    /// config.
+    ///
+    /// Tokens are generated from the private key on demand, so new tokens are signed
+    /// with the new key right away. The public keys move to the `auth_public_keys`
+    /// directory, where the previous key stays next to the new one: services started
+    /// from then on accept tokens signed with either, until
+    /// [`Self::finish_key_rotation`]. Running services keep the keys they loaded at
+    /// startup until they are restarted.
+    pub fn rotate_keys(&mut self) -> anyhow::Result<()> {
+        let base_path = self.base_data_dir.clone();
+        let private_key_path = base_path.join("auth_private_key.pem");
+        let public_key_path = base_path.join("auth_public_key.pem");
+        let new_private_key_path = base_path.join("auth_private_key.pem.new");
+        let new_public_key_path = base_path.join("auth_public_key.pem.new");
+
+        // Generate both new keys next to the current ones first, so that a failure
+        // leaves those intact.
+        generate_auth_keys(&new_private_key_path, &new_public_key_path)
+            .context("failed to generate new auth keys")?;
+
+        // Then put the new public key next to the old one, before the new private key
+        // is put in place: whichever step fails, the private key in place has its
+        // public key in the directory.
+        let keys_dir = base_path.join(AUTH_PUBLIC_KEYS_DIR);
+        fs::create_dir_all(&keys_dir)
+            .with_context(|| format!("failed to create {}", keys_dir.display()))?;
+        let current_key_path = keys_dir.join(CURRENT_PUBLIC_KEY);
+        // Not in the directory, where services would try to load it as a key.
+        let tmp_path = base_path.join("auth_public_key.pem.tmp");
+        let old_key_path = if current_key_path.exists() {
+            &current_key_path
+        } else {
+            &public_key_path
+        };
+        if old_key_path.exists() {
+            replace_with_copy(old_key_path, &keys_dir.join(OLD_PUBLIC_KEY), &tmp_path)
+                .context("failed to keep the old public key")?;
+        }
+        replace_with_copy(&new_public_key_path, &current_key_path, &tmp_path)
+            .context("failed to install the new public key")?;
+
+        fs::rename(&new_private_key_path, &private_key_path)?;
+        fs::rename(&new_public_key_path, &public_key_path)?;
+
+        self.private_key_path = PathBuf::from("auth_private_key.pem");
+        self.persist_config(&base_path)
+    }
+
+    /// Ends the grace period that [`Self::rotate_keys`] started: services started from
+    /// now on only accept tokens signed with the current key.
+    pub fn finish_key_rotation(&self) -> anyhow::Result<()> {
+        let old_key_path = self
+            .base_data_dir
+            .join(AUTH_PUBLIC_KEYS_DIR)
+            .join(OLD_PUBLIC_KEY);
+        match fs::remove_file(&old_key_path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("removing {}", old_key_path.display())),
+        }
+    }
+
     fn auth_keys_needed(&self) -> bool {
         self.pageservers.iter().any(|ps| {
             ps.pg_auth_type == AuthType::NeonJWT || ps.http_auth_type == AuthType::NeonJWT
@@ -535,6 +765,8 @@ impl LocalEnv {
     }
 }
 
+/// The repository directory: `NEON_REPO_DIR` if set, taken as an OS string so that
+/// non-UTF-8 paths work too, or `.neon` otherwise.
 fn base_path() -> PathBuf {
     match std::env::var_os("NEON_REPO_DIR") {
         Some(val) => PathBuf::from(val),
@@ -542,6 +774,47 @@ fn base_path() -> PathBuf {
     }
 }
 
+/// Removes everything inside `path`, but not `path` itself.
+///
+/// Keeping the directory helps if the developer symlinks another directory (i.e.,
+/// S3 local SSD) to the `.neon` base directory.
+fn remove_dir_contents(path: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies `from` over `to` by way of `tmp_path`, so that `to` has either its old or
+/// the new contents at any point.
+fn replace_with_copy(from: &Path, to: &Path, tmp_path: &Path) -> anyhow::Result<()> {
+    fs::copy(from, tmp_path)
+        .with_context(|| format!("copying {} to {}", from.display(), tmp_path.display()))?;
+    fs::rename(tmp_path, to)
+        .with_context(|| format!("renaming {} to {}", tmp_path.display(), to.display()))?;
+    Ok(())
+}
+
+/// Checks that the openssl binary used by [`generate_auth_keys`] can be run.
+fn check_openssl() -> anyhow::Result<()> {
+    let output = Command::new("openssl")
+        .arg("version")
+        .output()
+        .context("failed to run openssl, needed to generate auth keys")?;
+    if !output.status.success() {
+        bail!(
+            "openssl failed: '{}'",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
 /// Generate a public/private key pair for JWT authentication
 fn generate_auth_keys(private_key_path: &Path, public_key_path: &Path) -> anyhow::Result<()> {
     // Generate the key pair
@@ -16,7 +16,7 @@ use std::time::Duration;
 
 use anyhow::{bail, Context};
 use camino::Utf8PathBuf;
-use futures::SinkExt;
+use futures::{SinkExt, StreamExt};
 use pageserver_api::models::{
     self, LocationConfig, ShardParameters, TenantHistorySize, TenantInfo, TimelineInfo,
 };
@@ -24,6 +24,7 @@ use pageserver_api::shard::TenantShardId;
 use pageserver_client::mgmt_api;
 use postgres_backend::AuthType;
 use postgres_connection::{parse_host_port, PgConnectionConfig};
+use tokio_util::sync::CancellationToken;
 use utils::auth::{Claims, Scope};
 use utils::{
     id::{TenantId, TimelineId},
@@ -36,6 +37,29 @@ use crate::{background_process, local_env::LocalEnv};
 /// Directory within .neon which will be used by default for LocalFs remote storage.
 pub const PAGESERVER_REMOTE_STORAGE_DIR: &str = "local_fs_remote_storage/pageserver";
 
+/// Timeouts and retries of the connection [`PageServerNode::timeline_import`] sends the
+/// tarballs over. Only connecting is retried: an import that failed half-way has left
+/// a timeline behind, and is not safe to repeat.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportOptions {
+    pub connect_timeout: Duration,
+    /// Limit on the pageserver accepting one chunk of a tarball, so that a stalled
+    /// pageserver fails the import instead of hanging it.
+    pub write_timeout: Duration,
+    /// Connection attempts after the first, with exponential backoff in between.
+    pub connect_retries: u32,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            connect_timeout: Duration::from_secs(10),
+            write_timeout: Duration::from_secs(60),
+            connect_retries: 3,
+        }
+    }
+}
+
 //
 // Control routines for pageserver.
 //
@@ -113,6 +137,7 @@ impl PageServerNode {
         };
 
         let broker_endpoint_param = format!("broker_endpoint='{}'", self.env.broker.client_url());
+        let superuser_param = format!("initial_superuser_name='{}'", self.env.superuser);
 
         let mut overrides = vec![
             id,
@@ -122,6 +147,7 @@ impl PageServerNode {
             listen_http_addr_param,
             listen_pg_addr_param,
             broker_endpoint_param,
+            superuser_param,
             virtual_file_io_engine,
             get_vectored_impl,
         ];
@@ -155,7 +181,14 @@ impl PageServerNode {
         if *http_auth_type != AuthType::Trust || *pg_auth_type != AuthType::Trust {
             // Keys are generated in the toplevel repo dir, pageservers' workdirs
             // are one level below that, so refer to keys with ../
-            overrides.push("auth_validation_public_key_path='../auth_public_key.pem'".to_owned());
+            let public_key_path = self.env.get_public_key_path();
+            let public_key_name = public_key_path
+                .file_name()
+                .expect("public key path has a file name");
+            overrides.push(format!(
+                "auth_validation_public_key_path='../{}'",
+                public_key_name.to_string_lossy()
+            ));
         }
 
         // Apply the user-provided overrides
@@ -617,6 +650,7 @@ impl PageServerNode {
     /// * `timeline_id` - id to assign to imported timeline
     /// * `base` - (start lsn of basebackup, path to `base.tar` file)
     /// * `pg_wal` - if there's any wal to import: (end lsn, path to `pg_wal.tar`)
+    /// * `options` - timeouts and retries of the connection to the pageserver
     pub async fn timeline_import(
         &self,
         tenant_id: TenantId,
@@ -624,8 +658,27 @@ impl PageServerNode {
         base: (Lsn, PathBuf),
         pg_wal: Option<(Lsn, PathBuf)>,
         pg_version: u32,
+        options: ImportOptions,
     ) -> anyhow::Result<()> {
-        let (client, conn) = self.page_server_psql_client().await?;
+        let (client, conn) = utils::backoff::retry(
+            || async {
+                tokio::time::timeout(options.connect_timeout, self.page_server_psql_client())
+                    .await
+                    .map_err(|_| anyhow::anyhow!("timed out after {:?}", options.connect_timeout))?
+            },
+            // An error reported by the pageserver, like failed auth, won't go away.
+            |e: &anyhow::Error| {
+                e.downcast_ref::<tokio_postgres::Error>()
+                    .is_some_and(|e| e.as_db_error().is_some())
+            },
+            1,
+            options.connect_retries,
+            "connect to pageserver",
+            &CancellationToken::new(),
+        )
+        .await
+        .expect("not cancelled")
+        .context("failed to connect to pageserver")?;
         // The connection object performs the actual communication with the database,
         // so spawn it off to run on its own.
         tokio::spawn(async move {
@@ -658,9 +711,20 @@ impl PageServerNode {
                     std::io::Error::new(std::io::ErrorKind::Other, format!("{e}"))
                 });
                 let mut reader = std::pin::pin!(reader);
-                writer.send_all(&mut reader).await?;
-                writer.into_inner().finish().await?;
-                anyhow::Ok(())
+                // Chunk by chunk rather than send_all, to notice a stalled pageserver.
+                while let Some(chunk) = reader.next().await {
+                    tokio::time::timeout(options.write_timeout, writer.send(chunk?))
+                        .await
+                        .map_err(|_| {
+                            anyhow::anyhow!(
+                                "pageserver did not accept data for {:?}",
+                                options.write_timeout
+                            )
+                        })??;
+                }
+                // The row count of the command tag.
+                let rows = writer.into_inner().finish().await?;
+                anyhow::Ok(rows)
             }
         };
 
@@ -674,11 +738,27 @@ impl PageServerNode {
         .await?;
         // Import wal if necessary
         if let Some(wal_reader) = wal_reader {
-            copy_in(
+            let result = copy_in(
                 wal_reader,
                 format!("import wal {tenant_id} {timeline_id} {start_lsn} {end_lsn}"),
             )
-            .await?;
+            .await;
+            let records = match result {
+                Ok(records) => records,
+                Err(e) => {
+                    // The WAL ingested before the failure stays on the timeline.
+                    let reached = self
+                        .http_client
+                        .timeline_info(tenant_id, timeline_id, mgmt_api::ForceAwaitLogicalSize::No)
+                        .await
+                        .map(|info| info.last_record_lsn.to_string())
+                        .unwrap_or_else(|_| "an unknown LSN".to_string());
+                    return Err(e.context(format!(
+                        "failed to import WAL from {start_lsn} to {end_lsn}, stopped at {reached}"
+                    )));
+                }
+            };
+            println!("Imported {records} WAL records up to {end_lsn}");
         }
 
         Ok(())
@@ -161,7 +161,7 @@ impl SafekeeperNode {
             args.extend(["--remote-storage".to_owned(), remote_storage.clone()]);
         }
 
-        let key_path = self.env.base_data_dir.join("auth_public_key.pem");
+        let key_path = self.env.get_public_key_path();
         if self.conf.auth_enabled {
             let key_path_string = key_path
                 .to_str()
@@ -1,4 +1,7 @@
-use crate::{background_process, local_env::LocalEnv};
+use crate::{
+    background_process,
+    local_env::{LocalEnv, CURRENT_PUBLIC_KEY},
+};
 use camino::{Utf8Path, Utf8PathBuf};
 use hyper::Method;
 use pageserver_api::{
@@ -99,25 +102,17 @@ impl StorageController {
 
                 // If pageserver auth is enabled, this implicitly enables auth for this service,
                 // using the same credentials.
-                let public_key_path =
-                    camino::Utf8PathBuf::try_from(env.base_data_dir.join("auth_public_key.pem"))
-                        .unwrap();
+                let public_key_path = env.get_public_key_path();
 
                 // This service takes keys as a string rather than as a path to a file/dir: read the key into memory.
                 let public_key = if std::fs::metadata(&public_key_path)
                     .expect("Can't stat public key")
                     .is_dir()
                 {
-                    // Our config may specify a directory: this is for the pageserver's ability to handle multiple
-                    // keys.  We only use one key at a time, so, arbitrarily load the first one in the directory.
-                    let mut dir =
-                        std::fs::read_dir(&public_key_path).expect("Can't readdir public key path");
-                    let dent = dir
-                        .next()
-                        .expect("Empty key dir")
-                        .expect("Error reading key dir");
-
-                    std::fs::read_to_string(dent.path()).expect("Can't read public key")
+                    // The keys have been rotated, see LocalEnv::rotate_keys. This service
+                    // takes only one key, so it doesn't accept tokens signed with the old one.
+                    std::fs::read_to_string(public_key_path.join(CURRENT_PUBLIC_KEY))
+                        .expect("Can't read public key")
                 } else {
                     std::fs::read_to_string(&public_key_path).expect("Can't read public key")
                 };
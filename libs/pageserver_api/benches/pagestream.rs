@@ -0,0 +1,27 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use criterion::{criterion_group, criterion_main, Criterion};
+use pageserver_api::models::{PagestreamBeMessage, PagestreamGetPageResponse};
+
+const BLCKSZ: usize = 8192;
+
+/// Serializing GetPage responses, as page_service does for every GetPage request: with
+/// the buffer sized up front by `serialize`, and grown from empty as it used to be.
+pub fn bench_serialize_get_page(c: &mut Criterion) {
+    let page = Bytes::from(vec![0xAB; BLCKSZ]);
+    let msg = PagestreamBeMessage::GetPage(PagestreamGetPageResponse { page: page.clone() });
+
+    let mut group = c.benchmark_group("serialize_get_page");
+    group.bench_function("presized", |b| b.iter(|| msg.serialize()));
+    group.bench_function("grown", |b| {
+        b.iter(|| {
+            let mut bytes = BytesMut::new();
+            bytes.put_u8(102); // PagestreamBeMessageTag::GetPage
+            bytes.put(&page[..]);
+            bytes.freeze()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_serialize_get_page);
+criterion_main!(benches);
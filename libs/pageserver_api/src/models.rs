@@ -27,7 +27,6 @@ use crate::{
     reltag::RelTag,
     shard::{ShardCount, ShardStripeSize, TenantShardId},
 };
-use anyhow::bail;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 /// The state of a tenant in this pageserver.
@@ -423,6 +422,8 @@ pub struct TenantCreateResponse(pub TenantId);
 #[derive(Serialize)]
 pub struct StatusResponse {
     pub id: NodeId,
+    /// False until the pageserver has finished loading its tenants at startup.
+    pub ready: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -744,6 +745,10 @@ pub enum DownloadRemoteLayersTaskState {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TimelineGcRequest {
     pub gc_horizon: Option<u64>,
+    /// Allow a `gc_horizon` below the tenant's configured one. Such a horizon makes GC
+    /// remove history that it would otherwise keep, so it must be asked for explicitly.
+    #[serde(default)]
+    pub allow_short_horizon: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -819,6 +824,16 @@ pub mod virtual_file {
     }
 }
 
+/// Version of the pagestream protocol, chosen by the command that starts the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PagestreamProtocolVersion {
+    /// `pagestream`: every request reads from the timeline named in the command.
+    V1,
+    /// `pagestream_v2`: every request starts with the 16 byte id of the timeline it reads
+    /// from, which can be any timeline of the tenant.
+    V2,
+}
+
 // Wrapped in libpq CopyData
 #[derive(PartialEq, Eq, Debug)]
 pub enum PagestreamFeMessage {
@@ -922,6 +937,12 @@ pub struct PagestreamGetSlruSegmentResponse {
     pub segment: Bytes,
 }
 
+/// Returned by [`PagestreamFeMessage::parse`] (inside the `anyhow::Error`) for a message
+/// with a tag this version does not know. The rest of the message is left unread.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("unknown smgr message tag: {0}")]
+pub struct UnknownPagestreamTag(pub u8);
+
 #[derive(Debug)]
 pub struct PagestreamErrorResponse {
     pub message: String,
@@ -1051,14 +1072,48 @@ impl PagestreamFeMessage {
                     segno: body.read_u32::<BigEndian>()?,
                 },
             )),
-            _ => bail!("unknown smgr message tag: {:?}", msg_tag),
+            _ => Err(UnknownPagestreamTag(msg_tag).into()),
         }
     }
+
+    /// [`Self::serialize`], prefixed with the timeline to read from as
+    /// [`PagestreamProtocolVersion::V2`] requires.
+    pub fn serialize_v2(&self, timeline_id: TimelineId) -> Bytes {
+        let mut bytes = BytesMut::new();
+        bytes.put_slice(timeline_id.as_ref());
+        bytes.put(self.serialize());
+        bytes.into()
+    }
+
+    /// Parse a [`PagestreamProtocolVersion::V2`] request, returning the timeline it reads
+    /// from along with it.
+    pub fn parse_v2<R: std::io::Read>(
+        body: &mut R,
+    ) -> anyhow::Result<(TimelineId, PagestreamFeMessage)> {
+        let mut timeline_id = [0u8; 16];
+        body.read_exact(&mut timeline_id)?;
+        Ok((TimelineId::from_array(timeline_id), Self::parse(body)?))
+    }
 }
 
 impl PagestreamBeMessage {
+    /// Exact size of the message produced by [`Self::serialize`], including the tag byte.
+    pub fn serialized_len(&self) -> usize {
+        1 + match self {
+            Self::Exists(_) => 1,
+            Self::Nblocks(_) => 4,
+            Self::GetPage(resp) => resp.page.len(),
+            Self::Error(resp) => resp.message.len() + 1,
+            Self::DbSize(_) => 8,
+            Self::GetSlruSegment(resp) => 4 + resp.segment.len(),
+        }
+    }
+
     pub fn serialize(&self) -> Bytes {
-        let mut bytes = BytesMut::new();
+        // Size the buffer up front: GetPage responses are on the hot path, and growing
+        // an empty BytesMut to fit an 8KiB page means reallocating and copying the page
+        // more than once.
+        let mut bytes = BytesMut::with_capacity(self.serialized_len());
 
         use PagestreamBeMessageTag as Tag;
         match self {
@@ -1214,6 +1269,47 @@ mod tests {
             let bytes = msg.serialize();
             let reconstructed = PagestreamFeMessage::parse(&mut bytes.reader()).unwrap();
             assert!(msg == reconstructed);
+
+            let timeline_id = TimelineId::generate();
+            let bytes = msg.serialize_v2(timeline_id);
+            let reconstructed = PagestreamFeMessage::parse_v2(&mut bytes.reader()).unwrap();
+            assert_eq!(reconstructed, (timeline_id, msg));
+        }
+    }
+
+    #[test]
+    fn test_pagestream_unknown_tag() {
+        let bytes = Bytes::from_static(&[0xfe, 1, 2, 3]);
+        let err = PagestreamFeMessage::parse(&mut bytes.reader()).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<UnknownPagestreamTag>(),
+            Some(&UnknownPagestreamTag(0xfe))
+        );
+    }
+
+    #[test]
+    fn test_pagestream_be_serialize() {
+        let page = Bytes::from(vec![0xAB; BLCKSZ as usize]);
+        let messages = vec![
+            PagestreamBeMessage::Exists(PagestreamExistsResponse { exists: true }),
+            PagestreamBeMessage::Nblocks(PagestreamNblocksResponse { n_blocks: 42 }),
+            PagestreamBeMessage::GetPage(PagestreamGetPageResponse { page: page.clone() }),
+            PagestreamBeMessage::Error(PagestreamErrorResponse {
+                message: "oops".to_string(),
+            }),
+            PagestreamBeMessage::DbSize(PagestreamDbSizeResponse { db_size: 8192 }),
+            PagestreamBeMessage::GetSlruSegment(PagestreamGetSlruSegmentResponse {
+                segment: page.clone(),
+            }),
+        ];
+        for msg in messages {
+            let bytes = msg.serialize();
+            assert_eq!(bytes.len(), msg.serialized_len(), "{}", msg.kind());
+            let reconstructed = PagestreamBeMessage::deserialize(bytes).unwrap();
+            assert_eq!(reconstructed.kind(), msg.kind());
+            if let PagestreamBeMessage::GetPage(resp) = reconstructed {
+                assert_eq!(resp.page, page);
+            }
         }
     }
 
@@ -1,9 +1,11 @@
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::fmt;
+use std::str::FromStr;
 
-use postgres_ffi::pg_constants::GLOBALTABLESPACE_OID;
-use postgres_ffi::relfile_utils::forknumber_to_name;
+use postgres_ffi::relfile_utils::{
+    forkname_to_number, forknumber_to_name, relfile_path, FilePathError,
+};
 use postgres_ffi::Oid;
 
 ///
@@ -68,27 +70,33 @@ impl fmt::Display for RelTag {
     }
 }
 
-impl RelTag {
-    pub fn to_segfile_name(&self, segno: u32) -> String {
-        let mut name = if self.spcnode == GLOBALTABLESPACE_OID {
-            "global/".to_string()
-        } else {
-            format!("base/{}/", self.dbnode)
-        };
-
-        name += &self.relnode.to_string();
+/// Parses the [`Display`](fmt::Display) format of [`RelTag`].
+impl FromStr for RelTag {
+    type Err = FilePathError;
 
-        if let Some(fork_name) = forknumber_to_name(self.forknum) {
-            name += "_";
-            name += fork_name;
-        }
-
-        if segno != 0 {
-            name += ".";
-            name += &segno.to_string();
-        }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/');
+        let (Some(spcnode), Some(dbnode), Some(rel), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(FilePathError::InvalidFileName);
+        };
+        let (relnode, forkname) = match rel.split_once('_') {
+            Some((relnode, forkname)) => (relnode, Some(forkname)),
+            None => (rel, None),
+        };
+        Ok(RelTag {
+            forknum: forkname_to_number(forkname)?,
+            spcnode: spcnode.parse()?,
+            dbnode: dbnode.parse()?,
+            relnode: relnode.parse()?,
+        })
+    }
+}
 
-        name
+impl RelTag {
+    pub fn to_segfile_name(&self, segno: u32) -> String {
+        relfile_path(self.spcnode, self.dbnode, self.relnode, self.forknum, segno)
     }
 
     pub fn with_forknum(&self, forknum: u8) -> Self {
@@ -142,3 +150,30 @@ impl SlruKind {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reltag_display_roundtrip() {
+        for forknum in [0, 1, 2, 3] {
+            let rel = RelTag {
+                forknum,
+                spcnode: 1663,
+                dbnode: 5,
+                relnode: 16384,
+            };
+            assert_eq!(rel.to_string().parse::<RelTag>().unwrap(), rel);
+        }
+
+        for bad in [
+            "1663/5",
+            "1663/5/16384/1",
+            "1663/5/16384_bogus",
+            "a/5/16384",
+        ] {
+            assert!(bad.parse::<RelTag>().is_err(), "{bad} should not parse");
+        }
+    }
+}
@@ -20,7 +20,7 @@ use tracing::{debug, error, info, trace, warn};
 
 use pq_proto::framed::{ConnectionError, Framed, FramedReader, FramedWriter};
 use pq_proto::{
-    BeMessage, FeMessage, FeStartupPacket, ProtocolError, SQLSTATE_ADMIN_SHUTDOWN,
+    BeMessage, CancelKeyData, FeMessage, FeStartupPacket, ProtocolError, SQLSTATE_ADMIN_SHUTDOWN,
     SQLSTATE_INTERNAL_ERROR, SQLSTATE_SUCCESSFUL_COMPLETION,
 };
 
@@ -45,6 +45,12 @@ pub enum QueryError {
     Unauthorized(std::borrow::Cow<'static, str>),
     #[error("Simulated Connection Error")]
     SimulatedConnectionError,
+    /// Query failed for a reason the handler reports with its own SQLSTATE code
+    #[error("{message}")]
+    Coded {
+        code: &'static [u8; 5],
+        message: std::borrow::Cow<'static, str>,
+    },
     /// Some other error
     #[error(transparent)]
     Other(#[from] anyhow::Error),
@@ -61,7 +67,9 @@ impl QueryError {
         match self {
             Self::Disconnected(_) | Self::SimulatedConnectionError | Self::Reconnect => b"08006", // connection failure
             Self::Shutdown => SQLSTATE_ADMIN_SHUTDOWN,
-            Self::Unauthorized(_) | Self::NotFound(_) => SQLSTATE_INTERNAL_ERROR,
+            Self::Unauthorized(_) => b"28000", // invalid authorization specification
+            Self::NotFound(_) => SQLSTATE_INTERNAL_ERROR,
+            Self::Coded { code, .. } => code,
             Self::Other(_) => SQLSTATE_INTERNAL_ERROR, // internal error
         }
     }
@@ -111,6 +119,12 @@ pub trait Handler<IO> {
     ) -> Result<(), QueryError> {
         Err(QueryError::Other(anyhow::anyhow!("JWT auth failed")))
     }
+
+    /// Called on a CancelRequest, with the key the client got from the connection whose
+    /// query it wants to cancel (see [`PostgresBackend::set_cancel_key`]). The cancel
+    /// request comes in on a connection of its own, which is closed afterwards without a
+    /// response, as postgres does.
+    fn cancel_request(&mut self, _key: &CancelKeyData) {}
 }
 
 /// PostgresBackend protocol state.
@@ -274,6 +288,16 @@ pub struct PostgresBackend<IO> {
 
     peer_addr: SocketAddr,
     pub tls_config: Option<Arc<rustls::ServerConfig>>,
+
+    /// Extended query protocol: a Describe of the unnamed portal is waiting for its
+    /// answer. We only learn the result columns when the query runs, so the answer
+    /// is sent by the next Execute, see [`Self::write_message_noflush`].
+    portal_describe_pending: bool,
+    /// Extended query protocol: the handler is running the query of an Execute.
+    in_execute: bool,
+
+    /// Sent to the client in BackendKeyData once the connection is established.
+    cancel_key: Option<CancelKeyData>,
 }
 
 pub type PostgresBackendTCP = PostgresBackend<tokio::net::TcpStream>;
@@ -309,6 +333,9 @@ impl PostgresBackend<tokio::net::TcpStream> {
             auth_type,
             tls_config,
             peer_addr,
+            portal_describe_pending: false,
+            in_execute: false,
+            cancel_key: None,
         })
     }
 }
@@ -328,6 +355,9 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> PostgresBackend<IO> {
             auth_type,
             tls_config,
             peer_addr,
+            portal_describe_pending: false,
+            in_execute: false,
+            cancel_key: None,
         })
     }
 
@@ -335,6 +365,36 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> PostgresBackend<IO> {
         &self.peer_addr
     }
 
+    /// Reject incoming messages longer than `len` bytes with a protocol error, which
+    /// closes the connection. Defaults to [`pq_proto::DEFAULT_MAX_MESSAGE_LEN`].
+    pub fn set_max_message_len(&mut self, len: usize) -> anyhow::Result<()> {
+        match &mut self.framed {
+            MaybeWriteOnly::Full(framed) => {
+                framed.set_max_message_len(len);
+                Ok(())
+            }
+            MaybeWriteOnly::WriteOnly(_) => {
+                anyhow::bail!("cannot change the message size limit in split state")
+            }
+            MaybeWriteOnly::Broken => panic!("set_max_message_len on framed in invalid state"),
+        }
+    }
+
+    /// Hand out `key` to the client, so that it can cancel queries on this connection
+    /// with a CancelRequest. Must be called before the connection is established, e.g.
+    /// from [`Handler::startup`].
+    pub fn set_cancel_key(&mut self, key: CancelKeyData) {
+        self.cancel_key = Some(key);
+    }
+
+    /// Buffer BackendKeyData, if there is a cancel key.
+    fn write_cancel_key_noflush(&mut self) -> Result<&mut Self, ConnectionError> {
+        match self.cancel_key {
+            Some(key) => self.write_message_noflush(&BeMessage::BackendKeyData(key)),
+            None => Ok(self),
+        }
+    }
+
     /// Read full message or return None if connection is cleanly closed with no
     /// unprocessed data.
     pub async fn read_message(&mut self) -> Result<Option<FeMessage>, ConnectionError> {
@@ -358,10 +418,24 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> PostgresBackend<IO> {
     /// Write message into internal output buffer, doesn't flush it. Technically
     /// error type can be only ProtocolError here (if, unlikely, serialization
     /// fails), but callers typically wrap it anyway.
+    ///
+    /// Handlers write the same messages for a query whether it came in a Query or
+    /// an Execute. In the extended protocol, the RowDescription instead answers the
+    /// Describe of the portal, and Execute doesn't send one; for results without
+    /// rows, the Describe is answered with NoData.
     pub fn write_message_noflush(
         &mut self,
         message: &BeMessage<'_>,
     ) -> Result<&mut Self, ConnectionError> {
+        if self.in_execute {
+            let describe_pending = std::mem::take(&mut self.portal_describe_pending);
+            match message {
+                BeMessage::RowDescription(_) if !describe_pending => return Ok(self),
+                BeMessage::RowDescription(_) | BeMessage::ErrorResponse(..) => {}
+                _ if describe_pending => self.framed.write_message_noflush(&BeMessage::NoData)?,
+                _ => {}
+            }
+        }
         self.framed.write_message_noflush(message)?;
         trace!("wrote msg {:?}", message);
         Ok(self)
@@ -468,6 +542,10 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> PostgresBackend<IO> {
                 handshake_r?;
             }
         );
+        if self.state == ProtoState::Closed {
+            // The client only came to send a CancelRequest.
+            return Ok(());
+        }
 
         // Authentication completed
         let mut query_string = Bytes::new();
@@ -633,6 +711,7 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> PostgresBackend<IO> {
 
                     self.write_message_noflush(&BeMessage::AuthenticationOk)?
                         .write_message_noflush(&BeMessage::CLIENT_ENCODING)?
+                        .write_cancel_key_noflush()?
                         .write_message(&BeMessage::ReadyForQuery)
                         .await?;
                     self.state = ProtoState::Established;
@@ -708,6 +787,7 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> PostgresBackend<IO> {
                             .write_message_noflush(&BeMessage::INTEGER_DATETIMES)?
                             // The async python driver requires a valid server_version
                             .write_message_noflush(&BeMessage::server_version("14.1"))?
+                            .write_cancel_key_noflush()?
                             .write_message(&BeMessage::ReadyForQuery)
                             .await?;
                         self.state = ProtoState::Established;
@@ -719,10 +799,9 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> PostgresBackend<IO> {
                     }
                 }
             }
-            FeStartupPacket::CancelRequest { .. } => {
-                return Err(QueryError::Other(anyhow::anyhow!(
-                    "Unexpected CancelRequest message during handshake"
-                )));
+            FeStartupPacket::CancelRequest(key) => {
+                handler.cancel_request(&key);
+                self.state = ProtoState::Closed;
             }
         }
         Ok(())
@@ -768,7 +847,13 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> PostgresBackend<IO> {
                 self.write_message_noflush(&BeMessage::ParseComplete)?;
             }
 
+            FeMessage::Describe(m) if m.kind == b'P' => {
+                self.portal_describe_pending = true;
+            }
+
             FeMessage::Describe(_) => {
+                // The result columns are only known once the query runs, so a
+                // prepared statement is described as returning no rows.
                 self.write_message_noflush(&BeMessage::ParameterDescription)?
                     .write_message_noflush(&BeMessage::NoData)?;
             }
@@ -784,12 +869,29 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> PostgresBackend<IO> {
             FeMessage::Execute(_) => {
                 let query_string = cstr_to_str(unnamed_query_string)?;
                 trace!("got execute {query_string:?}");
-                if let Err(e) = handler.process_query(self, query_string).await {
-                    log_query_error(query_string, &e);
-                    self.write_message_noflush(&BeMessage::ErrorResponse(
-                        &e.to_string(),
-                        Some(e.pg_error_code()),
-                    ))?;
+                self.in_execute = true;
+                let result = handler.process_query(self, query_string).await;
+                self.in_execute = false;
+                // A query that wrote nothing returns no rows. An error answers the
+                // Describe of the portal by itself.
+                if std::mem::take(&mut self.portal_describe_pending) && result.is_ok() {
+                    self.write_message_noflush(&BeMessage::NoData)?;
+                }
+                if let Err(e) = result {
+                    match e {
+                        QueryError::Shutdown => return Ok(ProcessMsgResult::Break),
+                        QueryError::SimulatedConnectionError => {
+                            return Err(QueryError::SimulatedConnectionError)
+                        }
+                        e => {
+                            log_query_error(query_string, &e);
+                            let short_error = short_error(&e);
+                            self.write_message_noflush(&BeMessage::ErrorResponse(
+                                &short_error,
+                                Some(e.pg_error_code()),
+                            ))?;
+                        }
+                    }
                 }
                 // NOTE there is no ReadyForQuery message. This handler is used
                 // for basebackup and it uses CopyOut which doesn't require
@@ -798,6 +900,9 @@ impl<IO: AsyncRead + AsyncWrite + Unpin> PostgresBackend<IO> {
             }
 
             FeMessage::Sync => {
+                if std::mem::take(&mut self.portal_describe_pending) {
+                    self.write_message_noflush(&BeMessage::NoData)?;
+                }
                 self.write_message_noflush(&BeMessage::ReadyForQuery)?;
             }
 
@@ -988,6 +1093,7 @@ pub fn short_error(e: &QueryError) -> String {
         QueryError::NotFound(_) => "not found".to_string(),
         QueryError::Unauthorized(_e) => "JWT authentication error".to_string(),
         QueryError::SimulatedConnectionError => "simulated connection error".to_string(),
+        QueryError::Coded { message, .. } => message.to_string(),
         QueryError::Other(e) => format!("{e:#}"),
     }
 }
@@ -1019,6 +1125,9 @@ fn log_query_error(query: &str, e: &QueryError) {
         QueryError::Unauthorized(e) => {
             warn!("query handler for '{query}' failed with authentication error: {e}");
         }
+        QueryError::Coded { message, .. } => {
+            info!("query handler for '{query}' failed: {message}")
+        }
         QueryError::Other(e) => {
             error!("query handler for '{query}' failed: {e:?}");
         }
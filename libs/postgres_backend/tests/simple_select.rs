@@ -1,10 +1,11 @@
 /// Test postgres_backend_async with tokio_postgres
 use once_cell::sync::Lazy;
 use postgres_backend::{AuthType, Handler, PostgresBackend, QueryError};
-use pq_proto::{BeMessage, RowDescriptor};
+use pq_proto::{BeMessage, CancelKeyData, FeStartupPacket, RowDescriptor};
 use std::io::Cursor;
+use std::sync::Mutex;
 use std::{future, sync::Arc};
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
 use tokio_postgres::config::SslMode;
 use tokio_postgres::tls::MakeTlsConnect;
@@ -72,6 +73,145 @@ async fn simple_select() {
     }
 }
 
+const CANCEL_KEY: CancelKeyData = CancelKeyData {
+    backend_pid: 42,
+    cancel_key: 4242,
+};
+
+/// Hands out [`CANCEL_KEY`] and records the cancel requests it gets.
+struct CancelHandler {
+    cancel_requests: Arc<Mutex<Vec<CancelKeyData>>>,
+}
+
+#[async_trait::async_trait]
+impl<IO: AsyncRead + AsyncWrite + Unpin + Send> Handler<IO> for CancelHandler {
+    async fn process_query(
+        &mut self,
+        pgb: &mut PostgresBackend<IO>,
+        query_string: &str,
+    ) -> Result<(), QueryError> {
+        TestHandler {}.process_query(pgb, query_string).await
+    }
+
+    fn startup(
+        &mut self,
+        pgb: &mut PostgresBackend<IO>,
+        _sm: &FeStartupPacket,
+    ) -> Result<(), QueryError> {
+        pgb.set_cancel_key(CANCEL_KEY);
+        Ok(())
+    }
+
+    fn cancel_request(&mut self, key: &CancelKeyData) {
+        self.cancel_requests.lock().unwrap().push(*key);
+    }
+}
+
+// test that the cancel key reaches the client, and its cancel request the handler
+#[tokio::test]
+async fn cancel_request() {
+    let cancel_requests = Arc::new(Mutex::new(Vec::new()));
+    let (client_sock, server_sock) = make_tcp_pair().await;
+    let pgbackend =
+        PostgresBackend::new(server_sock, AuthType::Trust, None).expect("pgbackend creation");
+    let mut handler = CancelHandler {
+        cancel_requests: Arc::clone(&cancel_requests),
+    };
+    tokio::spawn(async move { pgbackend.run(&mut handler, future::pending::<()>).await });
+
+    let (client, connection) = Config::new()
+        .connect_raw(client_sock, NoTls)
+        .await
+        .expect("connect");
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("connection error: {}", e);
+        }
+    });
+
+    // The cancel request comes in on a connection of its own.
+    let (cancel_client_sock, cancel_server_sock) = make_tcp_pair().await;
+    let pgbackend = PostgresBackend::new(cancel_server_sock, AuthType::Trust, None)
+        .expect("pgbackend creation");
+    let mut handler = CancelHandler {
+        cancel_requests: Arc::clone(&cancel_requests),
+    };
+    let cancel_server =
+        tokio::spawn(async move { pgbackend.run(&mut handler, future::pending::<()>).await });
+    client
+        .cancel_token()
+        .cancel_query_raw(cancel_client_sock, NoTls)
+        .await
+        .expect("cancel request");
+    cancel_server
+        .await
+        .unwrap()
+        .expect("cancel connection closed cleanly");
+
+    assert_eq!(*cancel_requests.lock().unwrap(), vec![CANCEL_KEY]);
+}
+
+/// Frontend message with the given tag and body.
+fn fe_message(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut msg = vec![tag];
+    msg.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+    msg.extend_from_slice(body);
+    msg
+}
+
+/// Read backend messages up to ReadyForQuery, returning their tags.
+async fn read_until_ready(stream: &mut TcpStream) -> Vec<u8> {
+    let mut tags = Vec::new();
+    loop {
+        let tag = stream.read_u8().await.expect("read tag");
+        let len = stream.read_i32().await.expect("read len");
+        let mut body = vec![0; len as usize - 4];
+        stream.read_exact(&mut body).await.expect("read body");
+        tags.push(tag);
+        if tag == b'Z' {
+            return tags;
+        }
+    }
+}
+
+// test that a query runs through Parse/Bind/Describe/Execute, as libpq's
+// PQexecParams sends it
+#[tokio::test]
+async fn extended_query_select() {
+    let (mut client_sock, server_sock) = make_tcp_pair().await;
+
+    let pgbackend =
+        PostgresBackend::new(server_sock, AuthType::Trust, None).expect("pgbackend creation");
+
+    tokio::spawn(async move {
+        let mut handler = TestHandler {};
+        pgbackend.run(&mut handler, future::pending::<()>).await
+    });
+
+    let mut startup = Vec::new();
+    startup.extend_from_slice(&196608i32.to_be_bytes());
+    startup.extend_from_slice(b"user\0test\0\0");
+    let mut packet = (startup.len() as i32 + 4).to_be_bytes().to_vec();
+    packet.extend_from_slice(&startup);
+    client_sock.write_all(&packet).await.expect("startup");
+    assert_eq!(read_until_ready(&mut client_sock).await.last(), Some(&b'Z'));
+
+    let mut messages = Vec::new();
+    messages.extend(fe_message(b'P', b"\0SELECT 42;\0\0\0"));
+    messages.extend(fe_message(b'B', b"\0\0\0\0\0\0\0\0"));
+    messages.extend(fe_message(b'D', b"P\0"));
+    messages.extend(fe_message(b'E', b"\0\0\0\0\0"));
+    messages.extend(fe_message(b'S', b""));
+    client_sock
+        .write_all(&messages)
+        .await
+        .expect("extended query");
+
+    // ParseComplete, BindComplete, the RowDescription answering Describe, then the
+    // rows of Execute.
+    assert_eq!(read_until_ready(&mut client_sock).await, b"12TDCZ");
+}
+
 static KEY: Lazy<rustls::pki_types::PrivateKeyDer<'static>> = Lazy::new(|| {
     let mut cursor = Cursor::new(include_bytes!("key.pem"));
     let key = rustls_pemfile::rsa_private_keys(&mut cursor)
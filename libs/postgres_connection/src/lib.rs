@@ -63,6 +63,52 @@ mod tests_parse_host_port {
     }
 }
 
+/// Checks that `name` can be used as a role name without quoting: lowercase ASCII
+/// letters, digits, `_` and `$`, not starting with a digit or `$`, and at most 63
+/// bytes long (`NAMEDATALEN - 1`).
+pub fn validate_role_name(name: &str) -> anyhow::Result<()> {
+    const MAX_IDENTIFIER_LEN: usize = 63;
+
+    let Some(first) = name.chars().next() else {
+        bail!("role name is empty");
+    };
+    if name.len() > MAX_IDENTIFIER_LEN {
+        bail!("role name '{name}' is longer than {MAX_IDENTIFIER_LEN} bytes");
+    }
+    if !(first.is_ascii_lowercase() || first == '_') {
+        bail!("role name '{name}' must start with a lowercase letter or underscore");
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|&c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '$'))
+    {
+        bail!("role name '{name}' contains invalid character {c:?}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests_validate_role_name {
+    use crate::validate_role_name;
+
+    #[test]
+    fn test_valid() {
+        validate_role_name("cloud_admin").unwrap();
+        validate_role_name("_admin$2").unwrap();
+        validate_role_name(&"a".repeat(63)).unwrap();
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(validate_role_name("").is_err());
+        assert!(validate_role_name("1admin").is_err());
+        assert!(validate_role_name("Admin").is_err());
+        assert!(validate_role_name("cloud admin").is_err());
+        assert!(validate_role_name("admin\"").is_err());
+        assert!(validate_role_name(&"a".repeat(64)).is_err());
+    }
+}
+
 #[derive(Clone)]
 pub struct PgConnectionConfig {
     host: Host,
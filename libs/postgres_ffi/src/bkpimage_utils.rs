@@ -0,0 +1,214 @@
+//!
+//! Common utilities for restoring full-page images from WAL records.
+//!
+use anyhow::{bail, ensure};
+
+use crate::{bkpimage_is_compressed, pg_constants, BLCKSZ};
+
+/// Reconstruct a page from the block image of a WAL record, like
+/// RestoreBlockImage() in xlogreader.c: decompress it if it is compressed, and
+/// put back the zeroed hole. Only pglz compression is supported.
+pub fn restore_block_image(
+    pg_version: u32,
+    bimg_info: u8,
+    hole_offset: u16,
+    hole_length: u16,
+    data: &[u8],
+) -> anyhow::Result<[u8; BLCKSZ as usize]> {
+    let has_hole = bimg_info & pg_constants::BKPIMAGE_HAS_HOLE != 0;
+    if !has_hole {
+        ensure!(
+            hole_offset == 0 && hole_length == 0,
+            "block image has no hole, but hole offset {hole_offset} length {hole_length}"
+        );
+    }
+    let hole_offset = hole_offset as usize;
+    let hole_length = hole_length as usize;
+    ensure!(
+        hole_offset + hole_length <= BLCKSZ as usize,
+        "block image hole offset {hole_offset} length {hole_length} is out of page bounds"
+    );
+    let image_len = BLCKSZ as usize - hole_length;
+
+    let decompressed;
+    let image = if bkpimage_is_compressed(bimg_info, pg_version)? {
+        if !is_pglz(bimg_info, pg_version) {
+            bail!("block image compression method is not supported, bimg_info {bimg_info:#04x}");
+        }
+        decompressed = pglz_decompress(data, image_len)?;
+        &decompressed[..]
+    } else {
+        data
+    };
+    ensure!(
+        image.len() == image_len,
+        "block image of {} bytes with a hole of {hole_length} bytes is not a full page",
+        image.len()
+    );
+
+    let mut page = [0u8; BLCKSZ as usize];
+    page[..hole_offset].copy_from_slice(&image[..hole_offset]);
+    page[hole_offset + hole_length..].copy_from_slice(&image[hole_offset..]);
+    Ok(page)
+}
+
+fn is_pglz(bimg_info: u8, pg_version: u32) -> bool {
+    match pg_version {
+        // v14 only supports pglz, BKPIMAGE_IS_COMPRESSED doesn't say which.
+        14 => true,
+        15 => bimg_info & crate::v15::bindings::BKPIMAGE_COMPRESS_PGLZ != 0,
+        16 => bimg_info & crate::v16::bindings::BKPIMAGE_COMPRESS_PGLZ != 0,
+        _ => false,
+    }
+}
+
+/// Port of pglz_decompress() from pg_lzcompress.c, with check_complete: the
+/// whole input must decompress to exactly `rawsize` bytes.
+fn pglz_decompress(src: &[u8], rawsize: usize) -> anyhow::Result<Vec<u8>> {
+    let mut dst = Vec::with_capacity(rawsize);
+    let mut sp = 0;
+    while sp < src.len() && dst.len() < rawsize {
+        // Each control byte describes the next 8 items: a set bit is a tag
+        // referring back into the output, a clear bit a literal byte.
+        let mut ctrl = src[sp];
+        sp += 1;
+        for _ in 0..8 {
+            if sp >= src.len() || dst.len() >= rawsize {
+                break;
+            }
+            if ctrl & 1 != 0 {
+                ensure!(sp + 2 <= src.len(), "pglz: truncated tag");
+                let mut len = (src[sp] & 0x0f) as usize + 3;
+                let off = ((src[sp] & 0xf0) as usize) << 4 | src[sp + 1] as usize;
+                sp += 2;
+                if len == 18 {
+                    ensure!(sp < src.len(), "pglz: truncated tag");
+                    len += src[sp] as usize;
+                    sp += 1;
+                }
+                ensure!(
+                    off != 0 && off <= dst.len(),
+                    "pglz: invalid back-reference offset {off}"
+                );
+                let len = len.min(rawsize - dst.len());
+                // The referenced bytes may overlap with the ones being written,
+                // so copy them one at a time.
+                let start = dst.len() - off;
+                for i in 0..len {
+                    dst.push(dst[start + i]);
+                }
+            } else {
+                dst.push(src[sp]);
+                sp += 1;
+            }
+            ctrl >>= 1;
+        }
+    }
+    ensure!(
+        dst.len() == rawsize && sp == src.len(),
+        "pglz: compressed data does not decompress to {rawsize} bytes"
+    );
+    Ok(dst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compress `len` bytes of `byte` as pglz: one literal, then back-references
+    /// of the maximal length to it.
+    fn pglz_repeat(byte: u8, len: usize) -> Vec<u8> {
+        let mut items: Vec<Vec<u8>> = vec![vec![byte]];
+        let mut remaining = len - 1;
+        while remaining > 0 {
+            let n = remaining.min(273);
+            items.push(if n >= 18 {
+                vec![0x0f, 0x01, (n - 18) as u8]
+            } else if n >= 3 {
+                vec![(n - 3) as u8, 0x01]
+            } else {
+                // Too short for a tag, use a literal instead.
+                vec![byte]
+            });
+            remaining -= if n >= 3 { n } else { 1 };
+        }
+        let mut out = Vec::new();
+        for chunk in items.chunks(8) {
+            let mut ctrl = 0u8;
+            for (i, item) in chunk.iter().enumerate() {
+                if item.len() > 1 {
+                    ctrl |= 1 << i;
+                }
+            }
+            out.push(ctrl);
+            chunk.iter().for_each(|item| out.extend_from_slice(item));
+        }
+        out
+    }
+
+    #[test]
+    fn test_pglz_decompress() {
+        // "abc", then a back-reference of 6 bytes at offset 3.
+        let src = [0x08, b'a', b'b', b'c', 0x03, 0x03];
+        assert_eq!(pglz_decompress(&src, 9).unwrap(), b"abcabcabc");
+        assert!(pglz_decompress(&src, 10).is_err());
+        assert!(pglz_decompress(&src, 8).is_err());
+
+        // Back-reference before the start of the output.
+        let src = [0x02, b'a', 0x03, 0x02];
+        assert!(pglz_decompress(&src, 7).is_err());
+
+        assert_eq!(
+            pglz_decompress(&pglz_repeat(7, 1000), 1000).unwrap(),
+            [7; 1000]
+        );
+    }
+
+    #[test]
+    fn test_restore_uncompressed_block_image() {
+        let mut expected = [0u8; BLCKSZ as usize];
+        expected[..100].fill(1);
+        expected[200..].fill(2);
+
+        let mut data = vec![1u8; 100];
+        data.extend_from_slice(&[2u8; BLCKSZ as usize - 200]);
+        let page =
+            restore_block_image(16, pg_constants::BKPIMAGE_HAS_HOLE, 100, 100, &data).unwrap();
+        assert_eq!(page, expected);
+
+        let page = restore_block_image(16, 0, 0, 0, &expected).unwrap();
+        assert_eq!(page, expected);
+
+        // Sizes that don't add up to a page.
+        assert!(restore_block_image(16, pg_constants::BKPIMAGE_HAS_HOLE, 100, 99, &data).is_err());
+        assert!(restore_block_image(16, 0, 0, 0, &data).is_err());
+        assert!(restore_block_image(16, 0, 100, 100, &data).is_err());
+        assert!(
+            restore_block_image(16, pg_constants::BKPIMAGE_HAS_HOLE, 8000, 300, &data).is_err()
+        );
+    }
+
+    #[test]
+    fn test_restore_compressed_block_image() {
+        let hole_length = 100;
+        let data = pglz_repeat(3, BLCKSZ as usize - hole_length);
+        let mut expected = [3u8; BLCKSZ as usize];
+        expected[24..24 + hole_length].fill(0);
+
+        let v14_info =
+            pg_constants::BKPIMAGE_HAS_HOLE | crate::v14::bindings::BKPIMAGE_IS_COMPRESSED;
+        let page = restore_block_image(14, v14_info, 24, hole_length as u16, &data).unwrap();
+        assert_eq!(page, expected);
+
+        let v16_info =
+            pg_constants::BKPIMAGE_HAS_HOLE | crate::v16::bindings::BKPIMAGE_COMPRESS_PGLZ;
+        let page = restore_block_image(16, v16_info, 24, hole_length as u16, &data).unwrap();
+        assert_eq!(page, expected);
+
+        // Decompresses to the wrong size for this hole.
+        assert!(restore_block_image(16, v16_info, 24, 50, &data).is_err());
+
+        let lz4_info = crate::v16::bindings::BKPIMAGE_COMPRESS_LZ4;
+        assert!(restore_block_image(16, lz4_info, 0, 0, &data).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//!
+//! Common utilities for interpreting PostgreSQL database (RM_DBASE_ID) WAL records.
+//!
+use crate::pg_constants;
+use crate::Oid;
+
+#[derive(Debug, Clone, thiserror::Error, PartialEq, Eq)]
+pub enum DbaseDecodeError {
+    #[error("unknown dbase record xl_info {info:#04x} for Postgres version {pg_version}")]
+    UnknownOpcode { pg_version: u32, info: u8 },
+    #[error("unsupported Postgres version {0}")]
+    UnsupportedVersion(u32),
+    #[error("dbase record is too short: {0} bytes")]
+    TooShort(usize),
+    #[error("invalid tablespace {0} in dbase record")]
+    InvalidTablespace(Oid),
+}
+
+/// Database created by copying the files of a template database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreateDatabase {
+    pub db_id: Oid,
+    pub tablespace_id: Oid,
+    pub src_db_id: Oid,
+    pub src_tablespace_id: Oid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbaseRecord {
+    /// XLOG_DBASE_CREATE in v14, XLOG_DBASE_CREATE_FILE_COPY since v15.
+    CreateFileCopy(CreateDatabase),
+    /// XLOG_DBASE_CREATE_WAL_LOG, since v15. The contents of the new database
+    /// are WAL-logged by separate records.
+    CreateWalLog { db_id: Oid, tablespace_id: Oid },
+    /// XLOG_DBASE_DROP: the database is removed from each of the tablespaces.
+    Drop {
+        db_id: Oid,
+        tablespace_ids: Vec<Oid>,
+    },
+}
+
+/// Decode the main data of an RM_DBASE_ID record. `info` is xl_info without the
+/// XLR_INFO_MASK bits, `pg_version` the major Postgres version.
+pub fn decode_dbase_record(
+    pg_version: u32,
+    info: u8,
+    body: &[u8],
+) -> Result<DbaseRecord, DbaseDecodeError> {
+    let (create_file_copy, create_wal_log, drop) = match pg_version {
+        14 => (
+            Some(crate::v14::bindings::XLOG_DBASE_CREATE),
+            None,
+            crate::v14::bindings::XLOG_DBASE_DROP,
+        ),
+        15 => (
+            Some(crate::v15::bindings::XLOG_DBASE_CREATE_FILE_COPY),
+            Some(crate::v15::bindings::XLOG_DBASE_CREATE_WAL_LOG),
+            crate::v15::bindings::XLOG_DBASE_DROP,
+        ),
+        16 => (
+            Some(crate::v16::bindings::XLOG_DBASE_CREATE_FILE_COPY),
+            Some(crate::v16::bindings::XLOG_DBASE_CREATE_WAL_LOG),
+            crate::v16::bindings::XLOG_DBASE_DROP,
+        ),
+        _ => return Err(DbaseDecodeError::UnsupportedVersion(pg_version)),
+    };
+
+    let oids = read_oids(body);
+    let record = if Some(info) == create_file_copy {
+        // xl_dbase_create_rec in v14, xl_dbase_create_file_copy_rec since v15,
+        // the layout is the same.
+        let [db_id, tablespace_id, src_db_id, src_tablespace_id, ..] = oids[..] else {
+            return Err(DbaseDecodeError::TooShort(body.len()));
+        };
+        validate_tablespace(tablespace_id)?;
+        validate_tablespace(src_tablespace_id)?;
+        DbaseRecord::CreateFileCopy(CreateDatabase {
+            db_id,
+            tablespace_id,
+            src_db_id,
+            src_tablespace_id,
+        })
+    } else if Some(info) == create_wal_log {
+        // xl_dbase_create_wal_log_rec
+        let [db_id, tablespace_id, ..] = oids[..] else {
+            return Err(DbaseDecodeError::TooShort(body.len()));
+        };
+        validate_tablespace(tablespace_id)?;
+        DbaseRecord::CreateWalLog {
+            db_id,
+            tablespace_id,
+        }
+    } else if info == drop {
+        // xl_dbase_drop_rec, followed by the tablespace ids
+        let [db_id, ntablespaces, ref tablespace_ids @ ..] = oids[..] else {
+            return Err(DbaseDecodeError::TooShort(body.len()));
+        };
+        if tablespace_ids.len() < ntablespaces as usize {
+            return Err(DbaseDecodeError::TooShort(body.len()));
+        }
+        let tablespace_ids = tablespace_ids[..ntablespaces as usize].to_vec();
+        for &tablespace_id in &tablespace_ids {
+            validate_tablespace(tablespace_id)?;
+        }
+        DbaseRecord::Drop {
+            db_id,
+            tablespace_ids,
+        }
+    } else {
+        return Err(DbaseDecodeError::UnknownOpcode { pg_version, info });
+    };
+    Ok(record)
+}
+
+fn read_oids(body: &[u8]) -> Vec<Oid> {
+    body.chunks_exact(4)
+        .map(|chunk| Oid::from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+/// Databases live in pg_default or in a user-defined tablespace, never in
+/// pg_global or another system OID.
+fn validate_tablespace(tablespace_id: Oid) -> Result<(), DbaseDecodeError> {
+    if tablespace_id == pg_constants::DEFAULTTABLESPACE_OID
+        || tablespace_id >= pg_constants::FIRST_NORMAL_OBJECT_ID
+    {
+        Ok(())
+    } else {
+        Err(DbaseDecodeError::InvalidTablespace(tablespace_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(oids: &[Oid]) -> Vec<u8> {
+        oids.iter().flat_map(|oid| oid.to_le_bytes()).collect()
+    }
+
+    const DEFAULT: Oid = pg_constants::DEFAULTTABLESPACE_OID;
+
+    #[test]
+    fn test_decode_create_database() {
+        let create = DbaseRecord::CreateFileCopy(CreateDatabase {
+            db_id: 16400,
+            tablespace_id: DEFAULT,
+            src_db_id: 1,
+            src_tablespace_id: DEFAULT,
+        });
+        let rec = body(&[16400, DEFAULT, 1, DEFAULT]);
+        assert_eq!(decode_dbase_record(14, 0x00, &rec), Ok(create.clone()));
+        assert_eq!(decode_dbase_record(15, 0x00, &rec), Ok(create.clone()));
+        assert_eq!(decode_dbase_record(16, 0x00, &rec), Ok(create));
+
+        let rec = body(&[16400, 16390]);
+        assert_eq!(
+            decode_dbase_record(16, 0x10, &rec),
+            Ok(DbaseRecord::CreateWalLog {
+                db_id: 16400,
+                tablespace_id: 16390
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_drop_database() {
+        let drop = DbaseRecord::Drop {
+            db_id: 16400,
+            tablespace_ids: vec![DEFAULT, 16390],
+        };
+        let rec = body(&[16400, 2, DEFAULT, 16390]);
+        assert_eq!(decode_dbase_record(14, 0x10, &rec), Ok(drop.clone()));
+        assert_eq!(decode_dbase_record(15, 0x20, &rec), Ok(drop));
+
+        assert_eq!(
+            decode_dbase_record(16, 0x20, &body(&[16400, 2, DEFAULT])),
+            Err(DbaseDecodeError::TooShort(12))
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid_dbase_record() {
+        let global = pg_constants::GLOBALTABLESPACE_OID;
+        assert_eq!(
+            decode_dbase_record(14, 0x00, &body(&[16400, global, 1, DEFAULT])),
+            Err(DbaseDecodeError::InvalidTablespace(global))
+        );
+        assert_eq!(
+            decode_dbase_record(16, 0x20, &body(&[16400, 1, global])),
+            Err(DbaseDecodeError::InvalidTablespace(global))
+        );
+        assert_eq!(
+            decode_dbase_record(14, 0x00, &body(&[16400, DEFAULT])),
+            Err(DbaseDecodeError::TooShort(8))
+        );
+        // There is no XLOG_DBASE_CREATE_WAL_LOG in v14.
+        assert_eq!(
+            decode_dbase_record(14, 0x20, &body(&[16400, DEFAULT])),
+            Err(DbaseDecodeError::UnknownOpcode {
+                pg_version: 14,
+                info: 0x20
+            })
+        );
+    }
+}
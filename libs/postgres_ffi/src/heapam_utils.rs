@@ -0,0 +1,107 @@
+//!
+//! Common utilities for interpreting PostgreSQL heap WAL records.
+//!
+use crate::pg_constants;
+use crate::{BlockNumber, OffsetNumber};
+
+/// Size of xl_heap_insert, from heapam_xlog.h. It is the same in all supported
+/// Postgres versions.
+pub const SIZE_OF_HEAP_INSERT: usize = 3;
+
+/// All XLH_INSERT_* flags defined by heapam_xlog.h.
+const XLH_INSERT_ALL_FLAGS: u8 = pg_constants::XLH_INSERT_ALL_VISIBLE_CLEARED
+    | pg_constants::XLH_INSERT_LAST_IN_MULTI
+    | pg_constants::XLH_INSERT_IS_SPECULATIVE
+    | pg_constants::XLH_INSERT_CONTAINS_NEW_TUPLE
+    | pg_constants::XLH_INSERT_ON_TOAST_RELATION
+    | pg_constants::XLH_INSERT_ALL_FROZEN_SET;
+
+#[derive(Debug, Clone, thiserror::Error, PartialEq, Eq)]
+pub enum HeapDecodeError {
+    #[error("xl_heap_insert is {0} bytes long, expected {SIZE_OF_HEAP_INSERT}")]
+    InvalidLength(usize),
+    #[error("unknown xl_heap_insert flags {0:#04x}")]
+    UnknownFlags(u8),
+}
+
+/// Tuple inserted by an XLOG_HEAP_INSERT record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapInsert {
+    pub blkno: BlockNumber,
+    pub offnum: OffsetNumber,
+    /// The insert cleared the all-visible bit of the page in the visibility map.
+    pub all_visible_cleared: bool,
+    /// The insert set the page all-frozen, as done by COPY FREEZE.
+    pub all_frozen_set: bool,
+}
+
+/// Decode the main data of an XLOG_HEAP_INSERT record, xl_heap_insert. The
+/// target block is not part of it but of the record's block reference 0, so
+/// the caller passes it in as `blkno`.
+pub fn decode_heap_insert(blkno: BlockNumber, body: &[u8]) -> Result<HeapInsert, HeapDecodeError> {
+    if body.len() != SIZE_OF_HEAP_INSERT {
+        return Err(HeapDecodeError::InvalidLength(body.len()));
+    }
+    let offnum = u16::from_le_bytes([body[0], body[1]]);
+    let flags = body[2];
+    if flags & !XLH_INSERT_ALL_FLAGS != 0 {
+        return Err(HeapDecodeError::UnknownFlags(flags));
+    }
+    Ok(HeapInsert {
+        blkno,
+        offnum,
+        all_visible_cleared: flags & pg_constants::XLH_INSERT_ALL_VISIBLE_CLEARED != 0,
+        all_frozen_set: flags & pg_constants::XLH_INSERT_ALL_FROZEN_SET != 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_heap_insert() {
+        let insert = decode_heap_insert(7, &[3, 0, 0]).unwrap();
+        assert_eq!(
+            insert,
+            HeapInsert {
+                blkno: 7,
+                offnum: 3,
+                all_visible_cleared: false,
+                all_frozen_set: false,
+            }
+        );
+
+        let flags =
+            pg_constants::XLH_INSERT_ALL_VISIBLE_CLEARED | pg_constants::XLH_INSERT_ALL_FROZEN_SET;
+        let insert = decode_heap_insert(7, &[0x01, 0x01, flags]).unwrap();
+        assert_eq!(insert.offnum, 0x0101);
+        assert!(insert.all_visible_cleared);
+        assert!(insert.all_frozen_set);
+
+        // Flags that don't affect the visibility map are accepted but not reported.
+        let flags = pg_constants::XLH_INSERT_IS_SPECULATIVE
+            | pg_constants::XLH_INSERT_CONTAINS_NEW_TUPLE
+            | pg_constants::XLH_INSERT_ON_TOAST_RELATION
+            | pg_constants::XLH_INSERT_LAST_IN_MULTI;
+        let insert = decode_heap_insert(7, &[1, 0, flags]).unwrap();
+        assert!(!insert.all_visible_cleared);
+        assert!(!insert.all_frozen_set);
+    }
+
+    #[test]
+    fn test_decode_invalid_heap_insert() {
+        assert_eq!(
+            decode_heap_insert(0, &[1, 0]),
+            Err(HeapDecodeError::InvalidLength(2))
+        );
+        assert_eq!(
+            decode_heap_insert(0, &[1, 0, 0, 0]),
+            Err(HeapDecodeError::InvalidLength(4))
+        );
+        assert_eq!(
+            decode_heap_insert(0, &[1, 0, 0x40]),
+            Err(HeapDecodeError::UnknownFlags(0x40))
+        );
+    }
+}
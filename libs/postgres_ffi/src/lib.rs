@@ -106,8 +106,12 @@ macro_rules! dispatch_pgversion {
     };
 }
 
+pub mod bkpimage_utils;
+pub mod dbase_utils;
+pub mod heapam_utils;
 pub mod pg_constants;
 pub mod relfile_utils;
+pub mod xact_utils;
 
 // Export some widely used datatypes that are unlikely to change across Postgres versions
 pub use v14::bindings::{uint32, uint64, Oid};
@@ -172,6 +176,40 @@ pub fn generate_pg_control(
     )
 }
 
+/// The pg_control fields that matter when debugging startup or WAL replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct PgControlSummary {
+    pub system_identifier: u64,
+    /// Location of the checkpoint record, as stored in pg_control.
+    pub checkpoint_lsn: Lsn,
+    /// Redo pointer of the latest checkpoint.
+    pub checkpoint_redo_lsn: Lsn,
+    pub wal_segment_size: u32,
+}
+
+/// Decode a stored pg_control file, together with the latest checkpoint, which
+/// is kept separately and is newer than the copy inside pg_control.
+pub fn decode_pg_control(
+    pg_control_bytes: &[u8],
+    checkpoint_bytes: &[u8],
+    pg_version: u32,
+) -> anyhow::Result<PgControlSummary> {
+    dispatch_pgversion!(
+        pg_version,
+        {
+            let pg_control = pgv::ControlFileData::decode(pg_control_bytes)?;
+            let checkpoint = pgv::CheckPoint::decode(checkpoint_bytes)?;
+            Ok(PgControlSummary {
+                system_identifier: pg_control.system_identifier,
+                checkpoint_lsn: Lsn(pg_control.checkPoint),
+                checkpoint_redo_lsn: Lsn(checkpoint.redo),
+                wal_segment_size: pg_control.xlog_seg_size,
+            })
+        },
+        anyhow::bail!("Unknown version {}", pg_version)
+    )
+}
+
 // PG timeline is always 1, changing it doesn't have any useful meaning in Neon.
 //
 // NOTE: this is not to be confused with Neon timelines; different concept!
@@ -219,6 +257,55 @@ pub fn page_set_lsn(pg: &mut [u8], lsn: Lsn) {
     pg[4..8].copy_from_slice(&(lsn.0 as u32).to_le_bytes());
 }
 
+/// The fields of PageHeaderData (see bufpage.h) that describe the page layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageHeader {
+    pub pd_lsn: Lsn,
+    /// Offset to start of free space.
+    pub pd_lower: u16,
+    /// Offset to end of free space.
+    pub pd_upper: u16,
+    /// Offset to start of special space.
+    pub pd_special: u16,
+}
+
+impl PageHeader {
+    /// Parse the header of a page. A new page, with all of the header zeroed,
+    /// is accepted; otherwise the offsets must be consistent with each other
+    /// and the page size, like in PageIsVerifiedExtended().
+    pub fn parse(page: &[u8]) -> anyhow::Result<PageHeader> {
+        let header_size = pg_constants::SIZE_OF_PAGE_HEADER as usize;
+        if page.len() < header_size {
+            anyhow::bail!(
+                "page of {} bytes is too short for a page header",
+                page.len()
+            );
+        }
+        let get_u16 = |off: usize| u16::from_le_bytes([page[off], page[off + 1]]);
+        let header = PageHeader {
+            pd_lsn: page_get_lsn(page),
+            pd_lower: get_u16(12),
+            pd_upper: get_u16(14),
+            pd_special: get_u16(16),
+        };
+        if !page_is_new(page)
+            && !(header_size <= header.pd_lower as usize
+                && header.pd_lower <= header.pd_upper
+                && header.pd_upper <= header.pd_special
+                && header.pd_special as usize <= page.len())
+        {
+            anyhow::bail!("corrupted page header: {header:?}");
+        }
+        Ok(header)
+    }
+
+    /// The unused space between the line pointers and the tuples, which can be
+    /// left out when storing or sending the page.
+    pub fn hole(&self) -> std::ops::Range<usize> {
+        self.pd_lower as usize..self.pd_upper as usize
+    }
+}
+
 // This is port of function with the same name from freespace.c.
 // The only difference is that it does not have "level" parameter because XLogRecordPageWithFreeSpace
 // always call it with level=FSM_BOTTOM_LEVEL
@@ -240,8 +327,38 @@ pub fn fsm_logical_to_physical(addr: BlockNumber) -> BlockNumber {
     pages - 1
 }
 
+// Whether the pageserver can skip a multixact or standby record without
+// applying it. Standby lock and invalidation records only matter to hot standby
+// queries; XLOG_RUNNING_XACTS and all multixact records modify the SLRUs or the
+// checkpoint, and have to be applied. Unknown opcodes, and records of other
+// resource managers, are never reported as skippable.
+pub fn is_skippable_record(rmid: u8, info: u8) -> bool {
+    let info = info & pg_constants::XLR_RMGR_INFO_MASK;
+    match rmid {
+        pg_constants::RM_STANDBY_ID => {
+            info == pg_constants::XLOG_STANDBY_LOCK || info == pg_constants::XLOG_INVALIDATIONS
+        }
+        _ => false,
+    }
+}
+
+// Read the visibility map bits of a heap block, like visibilitymap_get_status()
+// in visibilitymap.c. `vm_page` must be the VM block that covers `heapblk`, see
+// HEAPBLK_TO_MAPBLOCK. Returns (all_visible, all_frozen).
+pub fn vm_get_bits(vm_page: &[u8], heapblk: u32) -> (bool, bool) {
+    let map = &vm_page[pg_constants::SIZE_OF_PAGE_HEADER as usize..];
+    let map_byte = pg_constants::HEAPBLK_TO_MAPBYTE(heapblk) as usize;
+    let map_offset = pg_constants::HEAPBLK_TO_OFFSET(heapblk);
+    let bits = (map[map_byte] >> map_offset) & pg_constants::VISIBILITYMAP_VALID_BITS;
+    (
+        bits & pg_constants::VISIBILITYMAP_ALL_VISIBLE != 0,
+        bits & pg_constants::VISIBILITYMAP_ALL_FROZEN != 0,
+    )
+}
+
 pub mod waldecoder {
     use bytes::{Buf, Bytes, BytesMut};
+    use std::cmp::min;
     use std::num::NonZeroU32;
     use thiserror::Error;
     use utils::lsn::Lsn;
@@ -304,4 +421,281 @@ pub mod waldecoder {
             )
         }
     }
+
+    /// Iterator over the records of a WAL segment, like XLogReadRecord() in a loop.
+    ///
+    /// Yields (end LSN, rmgr id, info, record) for each record, where the LSN is the
+    /// end of the record as returned by [`WalStreamDecoder::poll_decode`], and the
+    /// record includes the XLogRecord header. Iteration stops at the end of the
+    /// valid WAL: at the zeroed tail of the segment, after an XLOG_SWITCH record or
+    /// when a record continues in the next segment. A bad record is returned as an
+    /// error with its LSN, and ends the iteration.
+    pub struct XLogRecords<'a> {
+        segment: &'a [u8],
+        /// Offset in `segment` up to which it has been fed to the decoder.
+        fed: usize,
+        decoder: WalStreamDecoder,
+        done: bool,
+    }
+
+    impl<'a> XLogRecords<'a> {
+        /// `segment` is the contents of the WAL segment containing `start_lsn`, from its
+        /// beginning. `start_lsn` must point to the start of a record or a page.
+        pub fn new(segment: &'a [u8], start_lsn: Lsn, pg_version: u32) -> XLogRecords<'a> {
+            XLogRecords {
+                segment,
+                fed: start_lsn.segment_offset(crate::WAL_SEGMENT_SIZE),
+                decoder: WalStreamDecoder::new(start_lsn, pg_version),
+                done: false,
+            }
+        }
+
+        // In a segment that hasn't been filled up, the WAL ends with a record of
+        // zero length.
+        fn at_zeroed_tail(&self) -> bool {
+            matches!(self.decoder.state, State::WaitingForRecord)
+                && self.decoder.inputbuf.get(..4) == Some(&[0u8; 4])
+        }
+    }
+
+    impl<'a> Iterator for XLogRecords<'a> {
+        type Item = Result<(Lsn, u8, u8, Bytes), WalDecodeError>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.done {
+                return None;
+            }
+            let res = loop {
+                match self.decoder.poll_decode() {
+                    Ok(Some((lsn, record))) => {
+                        let xlogrec = &record[..crate::XLOG_SIZE_OF_XLOG_RECORD];
+                        // Checked by the decoder already.
+                        let hdr = crate::XLogRecord::from_slice(xlogrec).unwrap();
+                        return Some(Ok((lsn, hdr.xl_rmid, hdr.xl_info, record)));
+                    }
+                    Ok(None) if self.fed < self.segment.len() => {
+                        // Feed the decoder a page at a time, rather than copying the
+                        // whole segment into it upfront.
+                        let end = min(self.fed + crate::XLOG_BLCKSZ, self.segment.len());
+                        self.decoder.feed_bytes(&self.segment[self.fed..end]);
+                        self.fed = end;
+                    }
+                    Ok(None) => break None,
+                    Err(_) if self.at_zeroed_tail() => break None,
+                    Err(e) => break Some(Err(e)),
+                }
+            };
+            self.done = true;
+            res
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_header() {
+        // A heap page of pg_class.
+        let page = include_bytes!("../samples/heap_v14.page");
+        let header = PageHeader::parse(page).unwrap();
+        assert_eq!(
+            header,
+            PageHeader {
+                pd_lsn: Lsn(0x016D4080),
+                pd_lower: 212,
+                pd_upper: 7584,
+                pd_special: 8192,
+            }
+        );
+        assert_eq!(header.hole(), 212..7584);
+
+        let header = PageHeader::parse(&[0u8; BLCKSZ as usize]).unwrap();
+        assert_eq!(header.hole(), 0..0);
+
+        assert!(
+            PageHeader::parse(&page[..pg_constants::SIZE_OF_PAGE_HEADER as usize - 1]).is_err()
+        );
+
+        // pd_upper below pd_lower
+        let mut corrupted = *page;
+        corrupted[14..16].copy_from_slice(&100u16.to_le_bytes());
+        assert!(PageHeader::parse(&corrupted).is_err());
+    }
+
+    #[test]
+    fn test_is_skippable_record() {
+        use pg_constants::*;
+
+        assert!(is_skippable_record(RM_STANDBY_ID, XLOG_STANDBY_LOCK));
+        assert!(is_skippable_record(RM_STANDBY_ID, XLOG_INVALIDATIONS));
+        // Bits outside of XLR_RMGR_INFO_MASK don't matter.
+        assert!(is_skippable_record(RM_STANDBY_ID, XLOG_STANDBY_LOCK | 0x01));
+        assert!(!is_skippable_record(RM_STANDBY_ID, XLOG_RUNNING_XACTS));
+        assert!(!is_skippable_record(RM_STANDBY_ID, 0x30));
+
+        for info in [
+            XLOG_MULTIXACT_ZERO_OFF_PAGE,
+            XLOG_MULTIXACT_ZERO_MEM_PAGE,
+            XLOG_MULTIXACT_CREATE_ID,
+            XLOG_MULTIXACT_TRUNCATE_ID,
+        ] {
+            assert!(!is_skippable_record(RM_MULTIXACT_ID, info));
+        }
+        assert!(!is_skippable_record(RM_HEAP_ID, XLOG_STANDBY_LOCK));
+    }
+
+    #[test]
+    fn test_vm_get_bits() {
+        let mut page = vec![0u8; BLCKSZ as usize];
+        let header = pg_constants::SIZE_OF_PAGE_HEADER as usize;
+        // First heap block of the page: all-visible.
+        page[header] = pg_constants::VISIBILITYMAP_ALL_VISIBLE;
+        // Second heap block: all-visible and all-frozen.
+        page[header] |= pg_constants::VISIBILITYMAP_VALID_BITS << 2;
+        // Last heap block of the page, in the last byte: all-frozen.
+        page[BLCKSZ as usize - 1] = pg_constants::VISIBILITYMAP_ALL_FROZEN << 6;
+
+        assert_eq!(vm_get_bits(&page, 0), (true, false));
+        assert_eq!(vm_get_bits(&page, 1), (true, true));
+        assert_eq!(vm_get_bits(&page, 2), (false, false));
+        let last = pg_constants::HEAPBLOCKS_PER_PAGE - 1;
+        assert_eq!(vm_get_bits(&page, last - 1), (false, false));
+        assert_eq!(vm_get_bits(&page, last), (false, true));
+
+        // The first heap block of the next VM page maps to the start of its page.
+        let next = pg_constants::HEAPBLOCKS_PER_PAGE;
+        assert_eq!(pg_constants::HEAPBLK_TO_MAPBLOCK(next), 1);
+        assert_eq!(vm_get_bits(&page, next), (true, false));
+        assert_eq!(vm_get_bits(&page, next + 1), (true, true));
+    }
+
+    /// Write `records` one after another into WAL segment 1, from its start,
+    /// adding page headers where they cross pages. Returns the segment and
+    /// the end LSNs of the records.
+    fn craft_segment(records: &[Vec<u8>]) -> (Vec<u8>, Vec<Lsn>) {
+        use v14::bindings::{XLogLongPageHeaderData, XLogPageHeaderData, XLOG_PAGE_MAGIC};
+
+        let seg_start = WAL_SEGMENT_SIZE;
+        let long_hdr = XLogLongPageHeaderData {
+            std: XLogPageHeaderData {
+                xlp_magic: XLOG_PAGE_MAGIC as u16,
+                xlp_info: pg_constants::XLP_LONG_HEADER,
+                xlp_tli: PG_TLI,
+                xlp_pageaddr: seg_start as u64,
+                ..Default::default()
+            },
+            xlp_sysid: 42,
+            xlp_seg_size: WAL_SEGMENT_SIZE as u32,
+            xlp_xlog_blcksz: XLOG_BLCKSZ as u32,
+        };
+        let mut seg = long_hdr.encode().unwrap().to_vec();
+        let mut end_lsns = Vec::new();
+        for rec in records {
+            let tot_len = u32::from_le_bytes(rec[0..4].try_into().unwrap()) as usize;
+            let mut written = 0;
+            while written < rec.len() {
+                if seg.len() % XLOG_BLCKSZ == 0 {
+                    let contrecord = written > 0;
+                    let hdr = XLogPageHeaderData {
+                        xlp_magic: XLOG_PAGE_MAGIC as u16,
+                        xlp_info: if contrecord {
+                            pg_constants::XLP_FIRST_IS_CONTRECORD
+                        } else {
+                            0
+                        },
+                        xlp_tli: PG_TLI,
+                        xlp_pageaddr: (seg_start + seg.len()) as u64,
+                        xlp_rem_len: if contrecord { tot_len - written } else { 0 } as u32,
+                        ..Default::default()
+                    };
+                    seg.extend_from_slice(&hdr.encode().unwrap());
+                }
+                let n = std::cmp::min(rec.len() - written, XLOG_BLCKSZ - seg.len() % XLOG_BLCKSZ);
+                seg.extend_from_slice(&rec[written..written + n]);
+                written += n;
+            }
+            end_lsns.push(Lsn((seg_start + seg.len()) as u64));
+        }
+        seg.resize(WAL_SEGMENT_SIZE, 0);
+        (seg, end_lsns)
+    }
+
+    fn xlog_switch_record() -> Vec<u8> {
+        let mut header = XLogRecord {
+            xl_tot_len: XLOG_SIZE_OF_XLOG_RECORD as u32,
+            xl_xid: 0,
+            xl_prev: 0,
+            xl_info: pg_constants::XLOG_SWITCH,
+            xl_rmid: pg_constants::RM_XLOG_ID,
+            __bindgen_padding_0: [0u8; 2usize],
+            xl_crc: 0,
+        };
+        let header_bytes = header.encode().unwrap();
+        header.xl_crc = crc32c::crc32c(&header_bytes[0..v14::xlog_utils::XLOG_RECORD_CRC_OFFS]);
+        header.encode().unwrap().to_vec()
+    }
+
+    #[test]
+    fn test_xlog_records() {
+        // Enough records to fill more than a page.
+        let records: Vec<Vec<u8>> = (0..50)
+            .map(|i| encode_logical_message("prefix", &"x".repeat(100 + i)))
+            .collect();
+        let (seg, end_lsns) = craft_segment(&records);
+        assert!(end_lsns.last().unwrap().segment_offset(WAL_SEGMENT_SIZE) > XLOG_BLCKSZ);
+
+        let seg_start = Lsn(WAL_SEGMENT_SIZE as u64);
+        let decoded: Vec<_> = waldecoder::XLogRecords::new(&seg, seg_start, 14)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(decoded.len(), records.len());
+        for ((lsn, rmid, info, record), (rec, end_lsn)) in
+            decoded.iter().zip(records.iter().zip(end_lsns.iter()))
+        {
+            assert_eq!(lsn, end_lsn);
+            assert_eq!((*rmid, *info), (pg_constants::RM_LOGICALMSG_ID, 0));
+            assert_eq!(record[..], rec[..record.len()]);
+        }
+
+        // Start in the middle of the segment.
+        let mut iter = waldecoder::XLogRecords::new(&seg, end_lsns[9], 14);
+        assert_eq!(iter.next().unwrap().unwrap().0, end_lsns[10]);
+        assert_eq!(iter.count(), records.len() - 11);
+
+        // A corrupted record ends the iteration with an error.
+        let mut corrupted = seg.clone();
+        let offset = end_lsns[20].segment_offset(WAL_SEGMENT_SIZE);
+        corrupted[offset + XLOG_SIZE_OF_XLOG_RECORD + 2] ^= 0xff;
+        let mut iter = waldecoder::XLogRecords::new(&corrupted, seg_start, 14);
+        assert_eq!(iter.by_ref().take_while(|r| r.is_ok()).count(), 21);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_xlog_records_switch() {
+        let records = vec![
+            encode_logical_message("prefix", "before switch"),
+            xlog_switch_record(),
+            encode_logical_message("prefix", "after switch"),
+        ];
+        let (seg, end_lsns) = craft_segment(&records);
+        let decoded: Vec<_> = waldecoder::XLogRecords::new(&seg, Lsn(WAL_SEGMENT_SIZE as u64), 16)
+            .map(|r| r.unwrap())
+            .map(|(lsn, rmid, info, _)| (lsn, rmid, info))
+            .collect();
+        assert_eq!(
+            decoded,
+            vec![
+                (end_lsns[0], pg_constants::RM_LOGICALMSG_ID, 0),
+                // The rest of the segment is skipped.
+                (
+                    Lsn(2 * WAL_SEGMENT_SIZE as u64),
+                    pg_constants::RM_XLOG_ID,
+                    pg_constants::XLOG_SWITCH
+                ),
+            ]
+        );
+    }
 }
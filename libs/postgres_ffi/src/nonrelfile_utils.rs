@@ -34,6 +34,40 @@ pub fn transaction_id_get_status(xid: u32, page: &[u8]) -> u8 {
     (page[byteno] >> bshift) & pg_constants::CLOG_XACT_BITMASK
 }
 
+/// Status bits of `xid` in a CLOG page: 0 for in progress, or one of the
+/// TRANSACTION_STATUS_* values. Only normal xids have a status in CLOG.
+pub fn clog_status(page: &[u8], xid: u32) -> anyhow::Result<u8> {
+    check_clog_xid(xid)?;
+    Ok(transaction_id_get_status(xid, page))
+}
+
+/// Set the status bits of `xid` in a CLOG page, as when replaying a commit or
+/// abort record.
+pub fn clog_set_status(page: &mut BytesMut, xid: u32, status: u8) -> anyhow::Result<()> {
+    check_clog_xid(xid)?;
+    if !matches!(
+        status,
+        pg_constants::TRANSACTION_STATUS_COMMITTED
+            | pg_constants::TRANSACTION_STATUS_ABORTED
+            | pg_constants::TRANSACTION_STATUS_SUB_COMMITTED
+    ) {
+        anyhow::bail!("invalid transaction status {status} for xid {xid}");
+    }
+    transaction_id_set_status(xid, status, page);
+    Ok(())
+}
+
+/// Invalid, bootstrap and frozen xids are not tracked in CLOG.
+fn check_clog_xid(xid: u32) -> anyhow::Result<()> {
+    if xid == pg_constants::INVALID_TRANSACTION_ID {
+        anyhow::bail!("invalid xid has no CLOG status");
+    }
+    if xid < pg_constants::FIRST_NORMAL_TRANSACTION_ID {
+        anyhow::bail!("special xid {xid} has no CLOG status");
+    }
+    Ok(())
+}
+
 // See CLOGPagePrecedes in clog.c
 pub const fn clogpage_precedes(page1: u32, page2: u32) -> bool {
     let mut xid1 = page1 * pg_constants::CLOG_XACTS_PER_PAGE;
@@ -86,6 +120,56 @@ pub fn mx_offset_to_member_segment(xid: u32) -> i32 {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_clog_status() {
+        let mut page = BytesMut::zeroed(crate::BLCKSZ as usize);
+        // First and last xid of a byte, and of the page.
+        let xids = [4, 7, 8, pg_constants::CLOG_XACTS_PER_PAGE - 1];
+        for xid in xids {
+            assert_eq!(clog_status(&page, xid).unwrap(), 0);
+        }
+
+        let statuses = [
+            pg_constants::TRANSACTION_STATUS_COMMITTED,
+            pg_constants::TRANSACTION_STATUS_ABORTED,
+            pg_constants::TRANSACTION_STATUS_SUB_COMMITTED,
+            pg_constants::TRANSACTION_STATUS_COMMITTED,
+        ];
+        for (xid, status) in xids.iter().zip(statuses) {
+            clog_set_status(&mut page, *xid, status).unwrap();
+        }
+        for (xid, status) in xids.iter().zip(statuses) {
+            assert_eq!(clog_status(&page, *xid).unwrap(), status);
+        }
+        // Neighbours of the xids set above are untouched.
+        assert_eq!(clog_status(&page, 5).unwrap(), 0);
+        assert_eq!(clog_status(&page, 9).unwrap(), 0);
+        // xid on the next page maps to the same position.
+        let next_page_xid = pg_constants::CLOG_XACTS_PER_PAGE + 4;
+        assert_eq!(
+            clog_status(&page, next_page_xid).unwrap(),
+            pg_constants::TRANSACTION_STATUS_COMMITTED
+        );
+
+        // Overwriting the status replaces both bits.
+        clog_set_status(&mut page, 8, pg_constants::TRANSACTION_STATUS_ABORTED).unwrap();
+        assert_eq!(
+            clog_status(&page, 8).unwrap(),
+            pg_constants::TRANSACTION_STATUS_ABORTED
+        );
+    }
+
+    #[test]
+    fn test_clog_status_invalid() {
+        let mut page = BytesMut::zeroed(crate::BLCKSZ as usize);
+        assert!(clog_status(&page, pg_constants::INVALID_TRANSACTION_ID).is_err());
+        assert!(clog_status(&page, pg_constants::FIRST_NORMAL_TRANSACTION_ID - 1).is_err());
+        assert!(clog_set_status(&mut page, 1, pg_constants::TRANSACTION_STATUS_COMMITTED).is_err());
+        assert!(clog_set_status(&mut page, 10, 0).is_err());
+        assert!(clog_set_status(&mut page, 10, 4).is_err());
+        assert_eq!(page.iter().filter(|b| **b != 0).count(), 0);
+    }
+
     #[test]
     fn test_multixid_calc() {
         // Check that the mx_offset_* functions produce the same values as the
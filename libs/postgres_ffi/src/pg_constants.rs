@@ -81,7 +81,9 @@ pub const XLOG_XACT_COMMIT_PREPARED: u8 = 0x30;
 pub const XLOG_XACT_ABORT_PREPARED: u8 = 0x40;
 
 // From standbydefs.h
+pub const XLOG_STANDBY_LOCK: u8 = 0x00;
 pub const XLOG_RUNNING_XACTS: u8 = 0x10;
+pub const XLOG_INVALIDATIONS: u8 = 0x20;
 
 // From srlu.h
 pub const SLRU_PAGES_PER_SEGMENT: u32 = 32;
@@ -148,6 +150,10 @@ pub const XLOG_HEAP2_LOCK_UPDATED: u8 = 0x60;
 pub const XLH_LOCK_ALL_FROZEN_CLEARED: u8 = 0x01;
 pub const XLH_INSERT_ALL_FROZEN_SET: u8 = (1 << 5) as u8;
 pub const XLH_INSERT_ALL_VISIBLE_CLEARED: u8 = (1 << 0) as u8;
+pub const XLH_INSERT_LAST_IN_MULTI: u8 = (1 << 1) as u8;
+pub const XLH_INSERT_IS_SPECULATIVE: u8 = (1 << 2) as u8;
+pub const XLH_INSERT_CONTAINS_NEW_TUPLE: u8 = (1 << 3) as u8;
+pub const XLH_INSERT_ON_TOAST_RELATION: u8 = (1 << 4) as u8;
 pub const XLH_UPDATE_OLD_ALL_VISIBLE_CLEARED: u8 = (1 << 0) as u8;
 pub const XLH_UPDATE_NEW_ALL_VISIBLE_CLEARED: u8 = (1 << 1) as u8;
 pub const XLH_DELETE_ALL_VISIBLE_CLEARED: u8 = (1 << 0) as u8;
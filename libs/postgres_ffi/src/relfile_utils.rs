@@ -4,6 +4,9 @@
 use once_cell::sync::OnceCell;
 use regex::Regex;
 
+use crate::pg_constants::{DEFAULTTABLESPACE_OID, GLOBALTABLESPACE_OID};
+use crate::{Oid, RELSEG_SIZE};
+
 //
 // Fork numbers, from relpath.h
 //
@@ -90,10 +93,133 @@ pub fn parse_relfilename(fname: &str) -> Result<(u32, u8, u32), FilePathError> {
     Ok((relnode, forknum, segno))
 }
 
+/// Path of a relation data file segment, relative to the data directory.
+///
+/// Relations in the global tablespace are stored in `global/`, all others in
+/// `base/<dbnode>/`. Only [`DEFAULTTABLESPACE_OID`] and [`GLOBALTABLESPACE_OID`]
+/// are supported; relations of other tablespaces are placed in the default one.
+///
+/// See functions relpath() and _mdfd_segpath() in PostgreSQL sources.
+pub fn relfile_path(spcnode: Oid, dbnode: Oid, relnode: Oid, forknum: u8, segno: u32) -> String {
+    let mut path = if spcnode == GLOBALTABLESPACE_OID {
+        "global/".to_string()
+    } else {
+        format!("base/{}/", dbnode)
+    };
+
+    path += &relnode.to_string();
+
+    if let Some(fork_name) = forknumber_to_name(forknum) {
+        path += "_";
+        path += fork_name;
+    }
+
+    if segno != 0 {
+        path += ".";
+        path += &segno.to_string();
+    }
+
+    path
+}
+
+/// Segment file number and the block offset within that segment of relation
+/// block `blkno`. See _mdfd_getseg() in PostgreSQL sources.
+pub fn rel_block_to_segment(blkno: u32) -> (u32, u32) {
+    (blkno / RELSEG_SIZE, blkno % RELSEG_SIZE)
+}
+
+/// Inverse of [`rel_block_to_segment`]. Returns None if `offset` doesn't fit in
+/// a segment, or the block number would overflow.
+pub fn segment_to_rel_block(segno: u32, offset: u32) -> Option<u32> {
+    if offset >= RELSEG_SIZE {
+        return None;
+    }
+    segno.checked_mul(RELSEG_SIZE)?.checked_add(offset)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_rel_block_to_segment() {
+        assert_eq!(rel_block_to_segment(0), (0, 0));
+        assert_eq!(rel_block_to_segment(RELSEG_SIZE - 1), (0, RELSEG_SIZE - 1));
+        // The first block of the second segment.
+        assert_eq!(rel_block_to_segment(RELSEG_SIZE), (1, 0));
+        assert_eq!(rel_block_to_segment(3 * RELSEG_SIZE + 5), (3, 5));
+        let (segno, offset) = rel_block_to_segment(u32::MAX);
+        assert_eq!(segment_to_rel_block(segno, offset), Some(u32::MAX));
+
+        for blkno in [0, 1, RELSEG_SIZE - 1, RELSEG_SIZE, RELSEG_SIZE + 1] {
+            let (segno, offset) = rel_block_to_segment(blkno);
+            assert_eq!(segment_to_rel_block(segno, offset), Some(blkno));
+        }
+
+        assert_eq!(segment_to_rel_block(0, RELSEG_SIZE), None);
+        assert_eq!(segment_to_rel_block(u32::MAX / RELSEG_SIZE + 1, 0), None);
+    }
+
+    #[test]
+    fn test_relfile_path() {
+        let path = |spcnode, forknum, segno| relfile_path(spcnode, 5, 16384, forknum, segno);
+
+        assert_eq!(path(DEFAULTTABLESPACE_OID, MAIN_FORKNUM, 0), "base/5/16384");
+        assert_eq!(
+            path(DEFAULTTABLESPACE_OID, FSM_FORKNUM, 0),
+            "base/5/16384_fsm"
+        );
+        assert_eq!(
+            path(DEFAULTTABLESPACE_OID, VISIBILITYMAP_FORKNUM, 0),
+            "base/5/16384_vm"
+        );
+        assert_eq!(
+            path(DEFAULTTABLESPACE_OID, INIT_FORKNUM, 0),
+            "base/5/16384_init"
+        );
+        assert_eq!(
+            path(DEFAULTTABLESPACE_OID, MAIN_FORKNUM, 3),
+            "base/5/16384.3"
+        );
+        assert_eq!(
+            path(DEFAULTTABLESPACE_OID, FSM_FORKNUM, 1),
+            "base/5/16384_fsm.1"
+        );
+
+        // Shared catalogs have no database.
+        assert_eq!(
+            relfile_path(GLOBALTABLESPACE_OID, 0, 1262, MAIN_FORKNUM, 0),
+            "global/1262"
+        );
+        assert_eq!(
+            path(GLOBALTABLESPACE_OID, MAIN_FORKNUM, 2),
+            "global/16384.2"
+        );
+        assert_eq!(
+            path(GLOBALTABLESPACE_OID, FSM_FORKNUM, 0),
+            "global/16384_fsm"
+        );
+        assert_eq!(
+            path(GLOBALTABLESPACE_OID, VISIBILITYMAP_FORKNUM, 0),
+            "global/16384_vm"
+        );
+        assert_eq!(
+            path(GLOBALTABLESPACE_OID, INIT_FORKNUM, 0),
+            "global/16384_init"
+        );
+
+        for forknum in [
+            MAIN_FORKNUM,
+            FSM_FORKNUM,
+            VISIBILITYMAP_FORKNUM,
+            INIT_FORKNUM,
+        ] {
+            let path = path(DEFAULTTABLESPACE_OID, forknum, 7);
+            let fname = path.rsplit('/').next().unwrap();
+            assert_eq!(parse_relfilename(fname), Ok((16384, forknum, 7)));
+        }
+    }
+
     #[test]
     fn test_parse_valid_relfilenames() {
         assert_eq!(parse_relfilename("1234"), Ok((1234, 0, 0)));
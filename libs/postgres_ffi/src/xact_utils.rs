@@ -0,0 +1,192 @@
+//!
+//! Common utilities for interpreting PostgreSQL transaction (RM_XACT_ID) WAL records.
+//!
+use crate::pg_constants;
+use crate::TimestampTz;
+
+#[derive(Debug, Clone, thiserror::Error, PartialEq, Eq)]
+pub enum XactDecodeError {
+    #[error("not a commit, abort or prepare xact record, xl_info {0:#04x}")]
+    UnknownOpcode(u8),
+    #[error("xact record is too short: {0} bytes")]
+    TooShort(usize),
+}
+
+/// What a transaction record does to its transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XactOutcome {
+    Commit,
+    Abort,
+    Prepare,
+    CommitPrepared,
+    AbortPrepared,
+}
+
+/// Optional sections following the xl_xact_xinfo field of a commit or abort
+/// record, see xact.h.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XactSections {
+    pub dbinfo: bool,
+    pub subxacts: bool,
+    pub relfilenodes: bool,
+    pub invals: bool,
+    pub twophase: bool,
+}
+
+impl XactSections {
+    pub fn from_xinfo(xinfo: u32) -> XactSections {
+        XactSections {
+            dbinfo: xinfo & pg_constants::XACT_XINFO_HAS_DBINFO != 0,
+            subxacts: xinfo & pg_constants::XACT_XINFO_HAS_SUBXACTS != 0,
+            relfilenodes: xinfo & pg_constants::XACT_XINFO_HAS_RELFILENODES != 0,
+            invals: xinfo & pg_constants::XACT_XINFO_HAS_INVALS != 0,
+            twophase: xinfo & pg_constants::XACT_XINFO_HAS_TWOPHASE != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XactRecordInfo {
+    pub outcome: XactOutcome,
+    /// Commit or abort time, None for PREPARE records.
+    pub xact_time: Option<TimestampTz>,
+    /// Always empty for PREPARE records, which don't have xinfo.
+    pub sections: XactSections,
+}
+
+/// Classify an xact record by its `xl_info` and main data. Only the fixed
+/// header of commit and abort records is read, the sections themselves are left
+/// to the caller.
+pub fn classify_xact_record(xl_info: u8, body: &[u8]) -> Result<XactRecordInfo, XactDecodeError> {
+    let outcome = match xl_info & pg_constants::XLOG_XACT_OPMASK {
+        pg_constants::XLOG_XACT_COMMIT => XactOutcome::Commit,
+        pg_constants::XLOG_XACT_ABORT => XactOutcome::Abort,
+        pg_constants::XLOG_XACT_PREPARE => XactOutcome::Prepare,
+        pg_constants::XLOG_XACT_COMMIT_PREPARED => XactOutcome::CommitPrepared,
+        pg_constants::XLOG_XACT_ABORT_PREPARED => XactOutcome::AbortPrepared,
+        _ => return Err(XactDecodeError::UnknownOpcode(xl_info)),
+    };
+    if outcome == XactOutcome::Prepare {
+        return Ok(XactRecordInfo {
+            outcome,
+            xact_time: None,
+            sections: XactSections::default(),
+        });
+    }
+
+    // xl_xact_commit and xl_xact_abort start with the timestamp, followed by
+    // xl_xact_xinfo if XLOG_XACT_HAS_INFO is set.
+    let has_xinfo = xl_info & pg_constants::XLOG_XACT_HAS_INFO != 0;
+    let header_len = if has_xinfo { 12 } else { 8 };
+    if body.len() < header_len {
+        return Err(XactDecodeError::TooShort(body.len()));
+    }
+    let xact_time = TimestampTz::from_le_bytes(body[0..8].try_into().unwrap());
+    let xinfo = if has_xinfo {
+        u32::from_le_bytes(body[8..12].try_into().unwrap())
+    } else {
+        0
+    };
+    Ok(XactRecordInfo {
+        outcome,
+        xact_time: Some(xact_time),
+        sections: XactSections::from_xinfo(xinfo),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_XINFO_FLAGS: [u32; 5] = [
+        pg_constants::XACT_XINFO_HAS_DBINFO,
+        pg_constants::XACT_XINFO_HAS_SUBXACTS,
+        pg_constants::XACT_XINFO_HAS_RELFILENODES,
+        pg_constants::XACT_XINFO_HAS_INVALS,
+        pg_constants::XACT_XINFO_HAS_TWOPHASE,
+    ];
+
+    fn xact_body(xact_time: TimestampTz, xinfo: Option<u32>) -> Vec<u8> {
+        let mut body = xact_time.to_le_bytes().to_vec();
+        if let Some(xinfo) = xinfo {
+            body.extend_from_slice(&xinfo.to_le_bytes());
+        }
+        // Some section data, which must not be interpreted.
+        body.extend_from_slice(&[0xff; 16]);
+        body
+    }
+
+    #[test]
+    fn test_classify_xact_flag_combinations() {
+        let opcodes = [
+            (pg_constants::XLOG_XACT_COMMIT, XactOutcome::Commit),
+            (pg_constants::XLOG_XACT_ABORT, XactOutcome::Abort),
+            (
+                pg_constants::XLOG_XACT_COMMIT_PREPARED,
+                XactOutcome::CommitPrepared,
+            ),
+            (
+                pg_constants::XLOG_XACT_ABORT_PREPARED,
+                XactOutcome::AbortPrepared,
+            ),
+        ];
+        for (opcode, outcome) in opcodes {
+            for mask in 0..(1u32 << ALL_XINFO_FLAGS.len()) {
+                let xinfo = ALL_XINFO_FLAGS
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| mask & (1 << i) != 0)
+                    .fold(0, |acc, (_, flag)| acc | flag);
+                let body = xact_body(12345, Some(xinfo));
+                let info =
+                    classify_xact_record(opcode | pg_constants::XLOG_XACT_HAS_INFO, &body).unwrap();
+                assert_eq!(info.outcome, outcome);
+                assert_eq!(info.xact_time, Some(12345));
+                assert_eq!(
+                    info.sections,
+                    XactSections {
+                        dbinfo: mask & 1 != 0,
+                        subxacts: mask & 2 != 0,
+                        relfilenodes: mask & 4 != 0,
+                        invals: mask & 8 != 0,
+                        twophase: mask & 16 != 0,
+                    },
+                    "xinfo {xinfo:#x}"
+                );
+            }
+
+            // Without XLOG_XACT_HAS_INFO there is no xinfo and no sections.
+            let info = classify_xact_record(opcode, &xact_body(1, None)).unwrap();
+            assert_eq!(info.outcome, outcome);
+            assert_eq!(info.sections, XactSections::default());
+        }
+    }
+
+    #[test]
+    fn test_classify_xact_prepare() {
+        let info = classify_xact_record(pg_constants::XLOG_XACT_PREPARE, &[]).unwrap();
+        assert_eq!(info.outcome, XactOutcome::Prepare);
+        assert_eq!(info.xact_time, None);
+        assert_eq!(info.sections, XactSections::default());
+    }
+
+    #[test]
+    fn test_classify_invalid_xact() {
+        // XLOG_XACT_ASSIGNMENT
+        assert_eq!(
+            classify_xact_record(0x50, &[]),
+            Err(XactDecodeError::UnknownOpcode(0x50))
+        );
+        assert_eq!(
+            classify_xact_record(
+                pg_constants::XLOG_XACT_COMMIT | pg_constants::XLOG_XACT_HAS_INFO,
+                &[0; 10]
+            ),
+            Err(XactDecodeError::TooShort(10))
+        );
+        assert_eq!(
+            classify_xact_record(pg_constants::XLOG_XACT_ABORT, &[0; 4]),
+            Err(XactDecodeError::TooShort(4))
+        );
+    }
+}
@@ -17,7 +17,7 @@ use std::{
 };
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
 
-use crate::{BeMessage, FeMessage, FeStartupPacket, ProtocolError};
+use crate::{BeMessage, FeMessage, FeStartupPacket, ProtocolError, DEFAULT_MAX_MESSAGE_LEN};
 
 const INITIAL_CAPACITY: usize = 8 * 1024;
 
@@ -47,6 +47,7 @@ pub struct Framed<S> {
     stream: S,
     read_buf: BytesMut,
     write_buf: BytesMut,
+    max_message_len: usize,
 }
 
 impl<S> Framed<S> {
@@ -55,9 +56,16 @@ impl<S> Framed<S> {
             stream,
             read_buf: BytesMut::with_capacity(INITIAL_CAPACITY),
             write_buf: BytesMut::with_capacity(INITIAL_CAPACITY),
+            max_message_len: DEFAULT_MAX_MESSAGE_LEN,
         }
     }
 
+    /// Reject incoming messages longer than `len` bytes with a protocol error,
+    /// instead of [`DEFAULT_MAX_MESSAGE_LEN`].
+    pub fn set_max_message_len(&mut self, len: usize) {
+        self.max_message_len = len;
+    }
+
     /// Get a shared reference to the underlying stream.
     pub fn get_ref(&self) -> &S {
         &self.stream
@@ -80,6 +88,7 @@ impl<S> Framed<S> {
             stream,
             read_buf: self.read_buf,
             write_buf: self.write_buf,
+            max_message_len: self.max_message_len,
         })
     }
 }
@@ -92,7 +101,11 @@ impl<S: AsyncRead + Unpin> Framed<S> {
     }
 
     pub async fn read_message(&mut self) -> Result<Option<FeMessage>, ConnectionError> {
-        read_message(&mut self.stream, &mut self.read_buf, FeMessage::parse).await
+        let max_len = self.max_message_len;
+        read_message(&mut self.stream, &mut self.read_buf, |buf| {
+            FeMessage::parse(buf, max_len)
+        })
+        .await
     }
 }
 
@@ -123,6 +136,7 @@ impl<S: AsyncRead + AsyncWrite + Unpin> Framed<S> {
         let reader = FramedReader {
             stream: read_half,
             read_buf: self.read_buf,
+            max_message_len: self.max_message_len,
         };
         let writer = FramedWriter {
             stream: write_half,
@@ -137,6 +151,7 @@ impl<S: AsyncRead + AsyncWrite + Unpin> Framed<S> {
             stream: reader.stream.unsplit(writer.stream),
             read_buf: reader.read_buf,
             write_buf: writer.write_buf,
+            max_message_len: reader.max_message_len,
         }
     }
 }
@@ -145,11 +160,16 @@ impl<S: AsyncRead + AsyncWrite + Unpin> Framed<S> {
 pub struct FramedReader<S> {
     stream: ReadHalf<S>,
     read_buf: BytesMut,
+    max_message_len: usize,
 }
 
 impl<S: AsyncRead + Unpin> FramedReader<S> {
     pub async fn read_message(&mut self) -> Result<Option<FeMessage>, ConnectionError> {
-        read_message(&mut self.stream, &mut self.read_buf, FeMessage::parse).await
+        let max_len = self.max_message_len;
+        read_message(&mut self.stream, &mut self.read_buf, |buf| {
+            FeMessage::parse(buf, max_len)
+        })
+        .await
     }
 }
 
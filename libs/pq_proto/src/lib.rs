@@ -16,6 +16,7 @@ pub use postgres_protocol::PG_EPOCH;
 pub type Oid = u32;
 pub type SystemId = u64;
 
+pub const BOOL_OID: Oid = 16;
 pub const INT8_OID: Oid = 20;
 pub const INT4_OID: Oid = 23;
 pub const TEXT_OID: Oid = 25;
@@ -213,10 +214,11 @@ impl FeMessage {
     /// next message in this case to save the repeated calls.
     ///
     /// Returns Error if message is malformed, the only possible ErrorKind is
-    /// InvalidInput.
+    /// InvalidInput. A message longer than `max_len` bytes is malformed too, so
+    /// that a bogus length can't make us buffer an arbitrary amount of data.
     //
     // Inspired by rust-postgres Message::parse.
-    pub fn parse(buf: &mut BytesMut) -> Result<Option<FeMessage>, ProtocolError> {
+    pub fn parse(buf: &mut BytesMut, max_len: usize) -> Result<Option<FeMessage>, ProtocolError> {
         // Every message contains message type byte and 4 bytes len; can't do
         // much without them.
         if buf.len() < 5 {
@@ -235,6 +237,11 @@ impl FeMessage {
                 len
             )));
         }
+        if len as usize > max_len {
+            return Err(ProtocolError::Protocol(format!(
+                "message length {len} exceeds the limit of {max_len} bytes"
+            )));
+        }
 
         // length field includes itself, but not message type.
         let total_len = len as usize + 1;
@@ -410,13 +417,13 @@ impl FeParseMessage {
 impl FeDescribeMessage {
     fn parse(mut buf: Bytes) -> Result<FeMessage, ProtocolError> {
         let kind = buf.get_u8();
-        let _pstmt_name = read_cstr(&mut buf)?;
+        let _pstmt_or_portal_name = read_cstr(&mut buf)?;
 
         // FIXME: see FeParseMessage::parse
-        if kind != b'S' {
-            return Err(ProtocolError::BadMessage(
-                "only prepared statemement Describe is implemented".to_string(),
-            ));
+        if kind != b'S' && kind != b'P' {
+            return Err(ProtocolError::BadMessage(format!(
+                "invalid Describe kind {kind}"
+            )));
         }
 
         Ok(FeMessage::Describe(FeDescribeMessage { kind }))
@@ -592,6 +599,20 @@ impl RowDescriptor<'_> {
         }
     }
 
+    /// Convenience function to create a RowDescriptor message for a bool column, whose
+    /// values are sent as `t` or `f`
+    pub const fn bool_col(name: &[u8]) -> RowDescriptor {
+        RowDescriptor {
+            name,
+            tableoid: 0,
+            attnum: 0,
+            typoid: BOOL_OID,
+            typlen: 1,
+            typmod: 0,
+            formatcode: 0,
+        }
+    }
+
     pub const fn text_col(name: &[u8]) -> RowDescriptor {
         RowDescriptor {
             name,
@@ -671,8 +692,13 @@ pub fn read_cstr(buf: &mut Bytes) -> Result<Bytes, ProtocolError> {
     Ok(result)
 }
 
+/// Default limit on the length of incoming messages: the limit postgres applies to
+/// messages that may be large, like CopyData.
+pub const DEFAULT_MAX_MESSAGE_LEN: usize = 0x3fff_fffe;
+
 pub const SQLSTATE_INTERNAL_ERROR: &[u8; 5] = b"XX000";
 pub const SQLSTATE_ADMIN_SHUTDOWN: &[u8; 5] = b"57P01";
+pub const SQLSTATE_QUERY_CANCELED: &[u8; 5] = b"57014";
 pub const SQLSTATE_SUCCESSFUL_COMPLETION: &[u8; 5] = b"00000";
 
 impl<'a> BeMessage<'a> {
@@ -950,6 +976,27 @@ fn terminate_code(code: &[u8; 5]) -> [u8; 6] {
 mod tests {
     use super::*;
 
+    #[test]
+    fn message_length_limit() {
+        let mut query = BytesMut::new();
+        query.put_u8(b'Q');
+        query.put_u32(4 + 9);
+        query.put_slice(b"SELECT 1\0");
+
+        assert!(FeMessage::parse(&mut query.clone(), 12).is_err());
+        assert!(matches!(
+            FeMessage::parse(&mut query.clone(), 13),
+            Ok(Some(FeMessage::Query(_)))
+        ));
+
+        // Rejected on the header alone, before the body is buffered.
+        let mut header = BytesMut::new();
+        header.put_u8(b'd');
+        header.put_u32(u32::MAX);
+        assert!(FeMessage::parse(&mut header, DEFAULT_MAX_MESSAGE_LEN).is_err());
+        assert!(header.capacity() < 1024);
+    }
+
     #[test]
     fn test_startup_message_params_options_escaped() {
         fn split_options(params: &StartupMessageParams) -> Vec<Cow<'_, str>> {
@@ -3,7 +3,7 @@
 use arc_swap::ArcSwap;
 use std::{borrow::Cow, fmt::Display, fs, sync::Arc};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use camino::Utf8Path;
 use jsonwebtoken::{
     decode, encode, Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
@@ -106,7 +106,9 @@ impl JwtAuth {
     }
 
     pub fn from_key_path(key_path: &Utf8Path) -> Result<Self> {
-        let metadata = key_path.metadata()?;
+        let metadata = key_path
+            .metadata()
+            .with_context(|| format!("accessing {key_path}"))?;
         let decoding_keys = if metadata.is_dir() {
             let mut keys = Vec::new();
             for entry in fs::read_dir(key_path)? {
@@ -115,13 +117,18 @@ impl JwtAuth {
                     // Ignore directories (don't recurse)
                     continue;
                 }
-                let public_key = fs::read(path)?;
-                keys.push(DecodingKey::from_ed_pem(&public_key)?);
+                let public_key =
+                    fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+                keys.push(
+                    DecodingKey::from_ed_pem(&public_key)
+                        .with_context(|| format!("parsing Ed25519 PEM key {}", path.display()))?,
+                );
             }
             keys
         } else if metadata.is_file() {
-            let public_key = fs::read(key_path)?;
-            vec![DecodingKey::from_ed_pem(&public_key)?]
+            let public_key = fs::read(key_path).with_context(|| format!("reading {key_path}"))?;
+            vec![DecodingKey::from_ed_pem(&public_key)
+                .with_context(|| format!("parsing Ed25519 PEM key {key_path}"))?]
         } else {
             anyhow::bail!("path is neither a directory or a file")
         };
@@ -232,4 +239,25 @@ MC4CAQAwBQYDK2VwBCIEID/Drmc1AA6U/znNRWpF3zEGegOATQxfkdWxitcOMsIH
 
         assert_eq!(decoded.claims, claims);
     }
+
+    #[test]
+    fn test_from_key_path() {
+        let dir = camino_tempfile::tempdir().unwrap();
+
+        let missing = dir.path().join("missing.pem");
+        let err = JwtAuth::from_key_path(&missing).unwrap_err();
+        assert!(format!("{err:#}").contains("missing.pem"), "{err:#}");
+
+        let good = dir.path().join("good.pem");
+        fs::write(&good, TEST_PUB_KEY_ED25519).unwrap();
+        JwtAuth::from_key_path(&good).unwrap();
+        JwtAuth::from_key_path(dir.path()).unwrap();
+
+        let bad = dir.path().join("bad.pem");
+        fs::write(&bad, b"not a key").unwrap();
+        let err = JwtAuth::from_key_path(&bad).unwrap_err();
+        assert!(format!("{err:#}").contains("bad.pem"), "{err:#}");
+        // One malformed key in a directory fails the whole directory.
+        JwtAuth::from_key_path(dir.path()).unwrap_err();
+    }
 }
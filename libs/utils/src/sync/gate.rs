@@ -131,6 +131,9 @@ impl Gate {
     /// This will wait for all guards to be destroyed.  For this to complete promptly, it is
     /// important that the holders of such guards are respecting a CancellationToken which has
     /// been cancelled before entering this function.
+    ///
+    /// Cancellation safe: dropping the future before it completes leaves the gate as if
+    /// close() had not been called, so `enter()` succeeds again and close() can be retried.
     pub async fn close(&self) {
         let started_at = std::time::Instant::now();
         let mut do_close = std::pin::pin!(self.do_close());
@@ -147,13 +150,15 @@ impl Gate {
             "closing is taking longer than expected"
         );
 
-        // close operation is not trying to be cancellation safe as pageserver does not need it.
-        //
         // note: "closing" is not checked in Gate::enter -- it exists just for observability,
         // dropping of GateGuard after this will log who they were.
-        self.inner.closing.store(true, Ordering::Relaxed);
+        //
+        // If we are cancelled, dropping `do_close` gives the units it acquired so far back to
+        // the semaphore, which opens the gate again: clear the flag along with it.
+        let closing = ClosingFlag::set(&self.inner.closing);
 
         do_close.await;
+        closing.completed();
 
         tracing::info!(
             gate = ?self.as_ptr(),
@@ -199,6 +204,28 @@ impl Gate {
     }
 }
 
+/// Clears [`GateInner::closing`] when dropped, unless the close completed.
+struct ClosingFlag<'a>(Option<&'a AtomicBool>);
+
+impl<'a> ClosingFlag<'a> {
+    fn set(flag: &'a AtomicBool) -> Self {
+        flag.store(true, Ordering::Relaxed);
+        ClosingFlag(Some(flag))
+    }
+
+    fn completed(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ClosingFlag<'_> {
+    fn drop(&mut self) {
+        if let Some(flag) = self.0 {
+            flag.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -250,4 +277,35 @@ mod tests {
         // Attempting to enter() is still forbidden
         gate.enter().expect_err("enter should fail finishing close");
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn close_cancelled() {
+        let gate = Gate::default();
+        let forever = Duration::from_secs(24 * 7 * 365);
+
+        let guard = gate.enter().unwrap();
+
+        // Give up on closing after close has started blaming the guards
+        tokio::time::timeout(Duration::from_secs(10), gate.close())
+            .await
+            .unwrap_err();
+        assert!(!gate.close_complete());
+        assert!(!gate.inner.closing.load(Ordering::Relaxed));
+
+        // The gate is open again, as if close had not been called
+        let other_guard = gate
+            .enter()
+            .expect("enter should succeed after a cancelled close");
+        drop(guard);
+
+        // Closing again waits for the guard taken since
+        let mut close_fut = std::pin::pin!(gate.close());
+        tokio::time::timeout(forever, &mut close_fut)
+            .await
+            .unwrap_err();
+        drop(other_guard);
+        close_fut.await;
+
+        gate.enter().expect_err("enter should fail after close");
+    }
 }
@@ -1,9 +1,19 @@
 use std::{
     io,
-    net::{TcpListener, ToSocketAddrs},
+    net::{SocketAddr, TcpListener, ToSocketAddrs},
+    os::fd::AsRawFd,
 };
 
-use nix::sys::socket::{setsockopt, sockopt::ReuseAddr};
+use nix::{
+    fcntl::{fcntl, FcntlArg, FdFlag},
+    sys::socket::{
+        bind as bind_socket, listen, setsockopt, socket, sockopt::ReuseAddr, AddressFamily,
+        SockFlag, SockType, SockaddrStorage,
+    },
+};
+
+/// Default accept backlog, same as the one the standard library uses for [`TcpListener::bind`].
+pub const DEFAULT_BACKLOG: usize = 128;
 
 /// Bind a [`TcpListener`] to addr with `SO_REUSEADDR` set to true.
 pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<TcpListener> {
@@ -13,3 +23,59 @@ pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<TcpListener> {
 
     Ok(listener)
 }
+
+/// Bind a [`TcpListener`] to addr with the given accept backlog.
+///
+/// Unlike [`bind`], `SO_REUSEADDR` is set before the socket is bound, so a quick restart
+/// does not fail with "address already in use" while old connections are in `TIME_WAIT`.
+/// Like [`TcpListener::bind`], every address that `addr` resolves to is tried in turn.
+pub fn bind_with_backlog<A: ToSocketAddrs>(addr: A, backlog: usize) -> io::Result<TcpListener> {
+    let mut last_err = None;
+    for addr in addr.to_socket_addrs()? {
+        match bind_one(addr, backlog) {
+            Ok(listener) => return Ok(listener),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "could not resolve to any addresses",
+        )
+    }))
+}
+
+fn bind_one(addr: SocketAddr, backlog: usize) -> io::Result<TcpListener> {
+    let family = match addr {
+        SocketAddr::V4(_) => AddressFamily::Inet,
+        SocketAddr::V6(_) => AddressFamily::Inet6,
+    };
+    let fd = socket(family, SockType::Stream, SockFlag::empty(), None)?;
+    // SOCK_CLOEXEC is not available everywhere, so set it separately.
+    fcntl(fd.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+    setsockopt(&fd, ReuseAddr, &true)?;
+    bind_socket(fd.as_raw_fd(), &SockaddrStorage::from(addr))?;
+    listen(&fd, backlog)?;
+    Ok(TcpListener::from(fd))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebind_after_close() {
+        let listener = bind_with_backlog("127.0.0.1:0", 16).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Leave a connection behind so that the port has sockets in TIME_WAIT.
+        let client = std::net::TcpStream::connect(addr).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        drop(server);
+        drop(client);
+        drop(listener);
+
+        let listener = bind_with_backlog(addr, DEFAULT_BACKLOG).unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+    }
+}
@@ -0,0 +1,18 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    // Embed the build time, reported by `--version` and the `build_info` page service
+    // command. Honor SOURCE_DATE_EPOCH for reproducible builds.
+    //
+    // No rerun-if-changed directives on purpose: with them, cargo would keep the
+    // timestamp of the first build until the listed files change.
+    let timestamp = match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => epoch,
+        Err(_) => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time is before the epoch")
+            .as_secs()
+            .to_string(),
+    };
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={timestamp}");
+}
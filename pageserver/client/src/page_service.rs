@@ -4,7 +4,7 @@ use futures::SinkExt;
 use pageserver_api::{
     models::{
         PagestreamBeMessage, PagestreamFeMessage, PagestreamGetPageRequest,
-        PagestreamGetPageResponse,
+        PagestreamGetPageResponse, PagestreamProtocolVersion,
     },
     reltag::RelTag,
 };
@@ -58,9 +58,35 @@ impl Client {
         tenant_id: TenantId,
         timeline_id: TimelineId,
     ) -> anyhow::Result<PagestreamClient> {
+        self.pagestream_version(tenant_id, timeline_id, PagestreamProtocolVersion::V1)
+            .await
+    }
+
+    /// Like [`Self::pagestream`], but each request names its timeline, so that one
+    /// connection can read from several timelines of the tenant. `timeline_id` is the
+    /// timeline requests go to unless [`PagestreamClient::getpage_on`] says otherwise.
+    pub async fn pagestream_v2(
+        self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+    ) -> anyhow::Result<PagestreamClient> {
+        self.pagestream_version(tenant_id, timeline_id, PagestreamProtocolVersion::V2)
+            .await
+    }
+
+    async fn pagestream_version(
+        self,
+        tenant_id: TenantId,
+        timeline_id: TimelineId,
+        protocol_version: PagestreamProtocolVersion,
+    ) -> anyhow::Result<PagestreamClient> {
+        let command = match protocol_version {
+            PagestreamProtocolVersion::V1 => "pagestream",
+            PagestreamProtocolVersion::V2 => "pagestream_v2",
+        };
         let copy_both: tokio_postgres::CopyBothDuplex<bytes::Bytes> = self
             .client
-            .copy_both_simple(&format!("pagestream {tenant_id} {timeline_id}"))
+            .copy_both_simple(&format!("{command} {tenant_id} {timeline_id}"))
             .await?;
         let Client {
             cancel_on_client_drop,
@@ -71,6 +97,8 @@ impl Client {
             copy_both: Box::pin(copy_both),
             conn_task,
             cancel_on_client_drop,
+            timeline_id,
+            protocol_version,
         })
     }
 
@@ -100,6 +128,8 @@ pub struct PagestreamClient {
     copy_both: Pin<Box<tokio_postgres::CopyBothDuplex<bytes::Bytes>>>,
     cancel_on_client_drop: Option<tokio_util::sync::DropGuard>,
     conn_task: JoinHandle<()>,
+    timeline_id: TimelineId,
+    protocol_version: PagestreamProtocolVersion,
 }
 
 pub struct RelTagBlockNo {
@@ -113,6 +143,8 @@ impl PagestreamClient {
             copy_both,
             cancel_on_client_drop: cancel_conn_task,
             conn_task,
+            timeline_id: _,
+            protocol_version: _,
         } = self;
         // The `copy_both` contains internal channel sender, the receiver of which is polled by `conn_task`.
         // When `conn_task` observes the sender has been dropped, it sends a `FeMessage::CopyFail` into the connection.
@@ -139,9 +171,29 @@ impl PagestreamClient {
     pub async fn getpage(
         &mut self,
         req: PagestreamGetPageRequest,
+    ) -> anyhow::Result<PagestreamGetPageResponse> {
+        self.getpage_on(self.timeline_id, req).await
+    }
+
+    /// Read a page of `timeline_id`, which must be the connection's timeline unless it
+    /// was opened with [`Client::pagestream_v2`].
+    pub async fn getpage_on(
+        &mut self,
+        timeline_id: TimelineId,
+        req: PagestreamGetPageRequest,
     ) -> anyhow::Result<PagestreamGetPageResponse> {
         let req = PagestreamFeMessage::GetPage(req);
-        let req: bytes::Bytes = req.serialize();
+        let req: bytes::Bytes = match self.protocol_version {
+            PagestreamProtocolVersion::V1 => {
+                anyhow::ensure!(
+                    timeline_id == self.timeline_id,
+                    "pagestream connection of timeline {} can't read from {timeline_id}",
+                    self.timeline_id
+                );
+                req.serialize()
+            }
+            PagestreamProtocolVersion::V2 => req.serialize_v2(timeline_id),
+        };
         // let mut req = tokio_util::io::ReaderStream::new(&req);
         let mut req = tokio_stream::once(Ok(req));
 
@@ -60,7 +60,7 @@ pub(crate) enum LayerCmd {
 async fn read_delta_file(path: impl AsRef<Path>, ctx: &RequestContext) -> Result<()> {
     let path = Utf8Path::from_path(path.as_ref()).expect("non-Unicode path");
     virtual_file::init(10, virtual_file::api::IoEngineKind::StdFs);
-    page_cache::init(100);
+    page_cache::init(100, None);
     let file = VirtualFile::open(path).await?;
     let file_id = page_cache::next_file_id();
     let block_reader = FileBlockReader::new(&file, file_id);
@@ -190,7 +190,7 @@ pub(crate) async fn main(cmd: &LayerCmd) -> Result<()> {
             new_timeline_id,
         } => {
             pageserver::virtual_file::init(10, virtual_file::api::IoEngineKind::StdFs);
-            pageserver::page_cache::init(100);
+            pageserver::page_cache::init(100, None);
 
             let ctx = RequestContext::new(TaskKind::DebugTool, DownloadBehavior::Error);
 
@@ -38,9 +38,33 @@ use postgres_ffi::PG_TLI;
 use postgres_ffi::{BLCKSZ, RELSEG_SIZE, WAL_SEGMENT_SIZE};
 use utils::lsn::Lsn;
 
+/// Check that `timeline` has the control data needed to build a basebackup at `lsn`.
+///
+/// A timeline that was created empty, e.g. as the target of `import basebackup`, has
+/// no control file until the import has completed. Callers should check this before
+/// they start streaming the tarball, so that the client gets a clean error instead of
+/// a truncated archive.
+pub(crate) async fn check_control_file_exists(
+    timeline: &Timeline,
+    lsn: Lsn,
+    ctx: &RequestContext,
+) -> anyhow::Result<()> {
+    timeline.get_control_file(lsn, ctx).await.with_context(|| {
+        format!(
+            "no control file found for timeline {} at {lsn}",
+            timeline.timeline_id
+        )
+    })?;
+    Ok(())
+}
+
 /// Create basebackup with non-rel data in it.
 /// Only include relational data if 'full_backup' is true.
 ///
+/// All files are read at the same LSN, `req_lsn` or the last record LSN when this is
+/// called. The caller must keep GC from removing the history at that LSN until the
+/// tarball has been written, e.g. with `Timeline::pin_lsn`.
+///
 /// Currently we use empty 'req_lsn' in two cases:
 ///  * During the basebackup right after timeline creation
 ///  * When working without safekeepers. In this situation it is important to match the lsn
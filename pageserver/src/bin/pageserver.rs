@@ -12,6 +12,7 @@ use camino::Utf8Path;
 use clap::{Arg, ArgAction, Command};
 
 use metrics::launch_timestamp::{set_launch_timestamp_metric, LaunchTimestamp};
+use pageserver::build_info::BUILD_INFO;
 use pageserver::control_plane_client::ControlPlaneClient;
 use pageserver::disk_usage_eviction_task::{self, launch_disk_usage_global_eviction_task};
 use pageserver::metrics::{STARTUP_DURATION, STARTUP_IS_LOADING};
@@ -19,14 +20,16 @@ use pageserver::task_mgr::WALRECEIVER_RUNTIME;
 use pageserver::tenant::{secondary, TenantSharedResources};
 use remote_storage::GenericRemoteStorage;
 use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
 use tracing::*;
 
 use metrics::set_build_info_metric;
 use pageserver::{
     config::{defaults::*, PageServerConf},
     context::{DownloadBehavior, RequestContext},
+    cpu_affinity,
     deletion_queue::DeletionQueue,
-    http, page_cache, page_service, task_mgr,
+    http, offline_check, page_cache, page_service, task_mgr,
     task_mgr::TaskKind,
     task_mgr::{BACKGROUND_RUNTIME, COMPUTE_REQUEST_RUNTIME, MGMT_REQUEST_RUNTIME},
     tenant::mgr,
@@ -54,7 +57,9 @@ const FEATURES: &[&str] = &[
 
 fn version() -> String {
     format!(
-        "{GIT_VERSION} failpoints: {}, features: {:?}",
+        "{GIT_VERSION} version: {}, build timestamp: {}, failpoints: {}, features: {:?}",
+        BUILD_INFO.version,
+        BUILD_INFO.build_timestamp,
         fail::has_failpoints(),
         FEATURES,
     )
@@ -79,6 +84,7 @@ fn main() -> anyhow::Result<()> {
         .with_context(|| format!("Error opening workdir '{workdir}'"))?;
 
     let cfg_file_path = workdir.join("pageserver.toml");
+    let check = arg_matches.get_flag("check");
 
     // Set CWD to workdir for non-daemon modes
     env::set_current_dir(&workdir)
@@ -120,6 +126,10 @@ fn main() -> anyhow::Result<()> {
         &[("node_id", &conf.id.to_string())],
     );
 
+    if check {
+        return check_data_dir(conf);
+    }
+
     // after setting up logging, log the effective IO engine choice
     info!(?conf.virtual_file_io_engine, "starting with virtual_file IO engine");
 
@@ -134,7 +144,14 @@ fn main() -> anyhow::Result<()> {
 
     // Basic initialization of things that don't change after startup
     virtual_file::init(conf.max_file_descriptors, conf.virtual_file_io_engine);
-    page_cache::init(conf.page_cache_size);
+    virtual_file::set_io_uring_launch_failure_stats_interval(
+        conf.io_uring_launch_failure_stats_interval,
+    );
+    if let Some(count) = conf.io_uring_shared_systems {
+        virtual_file::set_io_uring_shared_systems(count);
+    }
+    page_cache::init(conf.page_cache_size, conf.page_cache_tenant_max_pages);
+    cpu_affinity::init(conf);
 
     start_pageserver(launch_ts, conf).context("Failed to start pageserver")?;
 
@@ -142,6 +159,24 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Run the offline check of the data directory, and exit nonzero if it finds problems.
+fn check_data_dir(conf: &'static PageServerConf) -> anyhow::Result<()> {
+    let problems = BACKGROUND_RUNTIME.block_on(async {
+        let remote_storage = create_remote_storage_client(conf)?;
+        offline_check::check_data_dir(conf, remote_storage.as_ref(), &CancellationToken::new())
+            .await
+    })?;
+    if problems.is_empty() {
+        info!("data directory check found no problems");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{problem}");
+    }
+    error!("data directory check found {} problems", problems.len());
+    std::process::exit(1);
+}
+
 fn initialize_config(
     cfg_file_path: &Utf8Path,
     arg_matches: clap::ArgMatches,
@@ -198,6 +233,11 @@ fn initialize_config(
     let conf = PageServerConf::parse_and_validate(&toml, workdir)
         .context("Failed to parse pageserver configuration")?;
 
+    if arg_matches.get_flag("dump-config") {
+        print!("{}", conf.to_effective_toml(&toml)?);
+        return Ok(ControlFlow::Break(()));
+    }
+
     if update_config {
         info!("Writing pageserver config to '{cfg_file_path}'");
 
@@ -319,7 +359,26 @@ fn start_pageserver(
     let pg_addr = &conf.listen_pg_addr;
 
     info!("Starting pageserver pg protocol handler on {pg_addr}");
-    let pageserver_listener = tcp_listener::bind(pg_addr)?;
+    let pageserver_listener = tcp_listener::bind_with_backlog(pg_addr, conf.listen_pg_backlog)?;
+
+    let pageserver_unix_listener = match &conf.listen_socket {
+        Some(path) => {
+            info!("Starting pageserver pg protocol handler on unix socket {path}");
+            // A socket file left behind by a previous run would make bind() fail. We
+            // hold the pid file lock, so no other pageserver is using it.
+            match std::fs::remove_file(path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("remove stale unix socket {path}"))
+                }
+            }
+            let listener = std::os::unix::net::UnixListener::bind(path)
+                .with_context(|| format!("bind unix socket {path}"))?;
+            Some(listener)
+        }
+        None => None,
+    };
 
     // Launch broker client
     // The storage_broker::connect call needs to happen inside a tokio runtime thread.
@@ -343,7 +402,8 @@ fn start_pageserver(
         let key_path = conf.auth_validation_public_key_path.as_ref().unwrap();
         info!("Loading public key(s) for verifying JWT tokens from {key_path:?}");
 
-        let jwt_auth = JwtAuth::from_key_path(key_path)?;
+        let jwt_auth =
+            JwtAuth::from_key_path(key_path).context("Failed to load JWT auth public key(s)")?;
         let auth: Arc<SwappableJwtAuth> = Arc::new(SwappableJwtAuth::new(jwt_auth));
 
         http_auth = match &conf.http_auth_type {
@@ -467,6 +527,7 @@ fn start_pageserver(
                     "Initial load completed",
                 );
                 STARTUP_IS_LOADING.set(0);
+                pageserver::set_ready();
             });
 
             let WaitForPhaseResult {
@@ -659,6 +720,7 @@ fn start_pageserver(
                     broker_client,
                     pg_auth,
                     pageserver_listener,
+                    pageserver_unix_listener,
                     conf.pg_auth_type,
                     libpq_ctx,
                     task_mgr::shutdown_token(),
@@ -771,6 +833,20 @@ fn cli() -> Command {
                 .action(ArgAction::SetTrue)
                 .help("Update the config file when started"),
         )
+        .arg(
+            Arg::new("dump-config")
+                .long("dump-config")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["init", "update-config"])
+                .help("Print the configuration in effect, with all overrides applied and defaults filled in, after validating it, and exit"),
+        )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["init", "update-config", "dump-config"])
+                .help("Check the data directory for inconsistencies without starting the pageserver or modifying anything, and exit nonzero if any are found"),
+        )
         .arg(
             Arg::new("enabled-features")
                 .long("enabled-features")
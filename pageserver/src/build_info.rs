@@ -0,0 +1,26 @@
+//! Version and build metadata of the pageserver, for `--version` and the page
+//! service `build_info` command.
+
+use serde::Serialize;
+use utils::{project_build_tag, project_git_version};
+
+project_git_version!(GIT_VERSION);
+project_build_tag!(BUILD_TAG);
+
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    /// Version of the pageserver crate.
+    pub version: &'static str,
+    /// Git commit, as `git:<sha>` or `git-env:<sha>`, see [`utils::project_git_version`].
+    pub git_version: &'static str,
+    pub build_tag: &'static str,
+    /// Unix time of the build, in seconds.
+    pub build_timestamp: &'static str,
+}
+
+pub const BUILD_INFO: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    git_version: GIT_VERSION,
+    build_tag: BUILD_TAG,
+    build_timestamp: env!("BUILD_TIMESTAMP"),
+};
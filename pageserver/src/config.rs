@@ -25,19 +25,21 @@ use toml_edit::{Document, Item};
 
 use camino::{Utf8Path, Utf8PathBuf};
 use postgres_backend::AuthType;
+use postgres_connection::validate_role_name;
 use utils::{
     id::{NodeId, TimelineId},
     logging::LogFormat,
 };
 
 use crate::tenant::config::TenantConfOpt;
+use crate::tenant::storage_layer::LayerCompression;
 use crate::tenant::timeline::GetVectoredImpl;
 use crate::tenant::vectored_blob_io::MaxVectoredReadBytes;
 use crate::tenant::{
     TENANTS_SEGMENT_NAME, TENANT_DELETED_MARKER_FILE_NAME, TIMELINES_SEGMENT_NAME,
 };
 use crate::{disk_usage_eviction_task::DiskUsageEvictionTaskConfig, virtual_file::io_engine};
-use crate::{tenant::config::TenantConf, virtual_file};
+use crate::{page_cache, tenant::config::TenantConf, virtual_file};
 use crate::{
     IGNORED_TENANT_FILE_NAME, TENANT_CONFIG_NAME, TENANT_HEATMAP_BASENAME,
     TENANT_LOCATION_CONFIG_NAME, TIMELINE_DELETE_MARK_SUFFIX,
@@ -97,6 +99,22 @@ pub mod defaults {
 
     pub const DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB: usize = 0;
 
+    pub const DEFAULT_PG_LISTEN_BACKLOG: usize = utils::tcp_listener::DEFAULT_BACKLOG;
+
+    pub const DEFAULT_WAL_CONNECT_RETRY_MIN_BACKOFF: &str = "100 ms";
+
+    pub const DEFAULT_WAL_CONNECT_RETRY_MAX_BACKOFF: &str = "15 s";
+
+    pub const DEFAULT_IO_URING_LAUNCH_FAILURE_STATS_INTERVAL: &str = "1 min";
+
+    pub const DEFAULT_WALREDO_PROCESSES_PER_TENANT: usize = 1;
+
+    pub const DEFAULT_FLUSH_COMPRESSION: &str = "none";
+
+    pub const DEFAULT_LSN_LEASE_TTL: &str = "10 min";
+
+    pub const DEFAULT_PAGE_SERVICE_MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
     ///
     /// Default built-in configuration file.
     ///
@@ -110,6 +128,7 @@ pub mod defaults {
 #wal_redo_timeout = '{DEFAULT_WAL_REDO_TIMEOUT}'
 
 #page_cache_size = {DEFAULT_PAGE_CACHE_SIZE}
+#page_cache_size_mb = ..
 #max_file_descriptors = {DEFAULT_MAX_FILE_DESCRIPTORS}
 
 # initial superuser role name to use when creating a new tenant
@@ -132,6 +151,7 @@ pub mod defaults {
 
 #ingest_batch_size = {DEFAULT_INGEST_BATCH_SIZE}
 
+# 'std-fs' never sets up io_uring, for environments that forbid it
 #virtual_file_io_engine = '{DEFAULT_VIRTUAL_FILE_IO_ENGINE}'
 
 #get_vectored_impl = '{DEFAULT_GET_VECTORED_IMPL}'
@@ -140,6 +160,40 @@ pub mod defaults {
 
 #validate_vectored_get = '{DEFAULT_VALIDATE_VECTORED_GET}'
 
+#listen_pg_backlog = {DEFAULT_PG_LISTEN_BACKLOG}
+
+#walredo_cpu_affinity = []
+
+#worker_cpu_affinity = []
+
+#page_cache_tenant_max_pages = ..
+
+#page_service_socket_send_buffer = ..
+
+#page_service_socket_recv_buffer = ..
+
+#max_concurrent_basebackups_per_tenant = ..
+
+#wal_connect_retry_min_backoff = '{DEFAULT_WAL_CONNECT_RETRY_MIN_BACKOFF}'
+
+#wal_connect_retry_max_backoff = '{DEFAULT_WAL_CONNECT_RETRY_MAX_BACKOFF}'
+
+#io_uring_launch_failure_stats_interval = '{DEFAULT_IO_URING_LAUNCH_FAILURE_STATS_INTERVAL}'
+
+#io_uring_shared_systems = 2
+
+#listen_socket = ..
+
+#walredo_processes_per_tenant = {DEFAULT_WALREDO_PROCESSES_PER_TENANT}
+
+#flush_compression = '{DEFAULT_FLUSH_COMPRESSION}'
+
+#lsn_lease_ttl = '{DEFAULT_LSN_LEASE_TTL}'
+
+#lazy_tenant_load = false
+
+#page_service_max_message_size = {DEFAULT_PAGE_SERVICE_MAX_MESSAGE_SIZE}
+
 [tenant_config]
 #checkpoint_distance = {DEFAULT_CHECKPOINT_DISTANCE} # in bytes
 #checkpoint_timeout = {DEFAULT_CHECKPOINT_TIMEOUT}
@@ -187,6 +241,8 @@ pub struct PageServerConf {
 
     pub superuser: String,
 
+    /// Size of the page cache, in pages. Can also be given in megabytes, as
+    /// `page_cache_size_mb`.
     pub page_cache_size: usize,
     pub max_file_descriptors: usize,
 
@@ -276,6 +332,9 @@ pub struct PageServerConf {
     /// Maximum number of WAL records to be ingested and committed at the same time
     pub ingest_batch_size: u64,
 
+    /// IO engine used by [`crate::virtual_file::VirtualFile`] for the whole process.
+    /// With `std-fs`, no io_uring instance is ever launched, which makes the pageserver
+    /// usable where io_uring is forbidden, e.g. by a seccomp profile.
     pub virtual_file_io_engine: virtual_file::IoEngineKind,
 
     pub get_vectored_impl: GetVectoredImpl,
@@ -290,6 +349,79 @@ pub struct PageServerConf {
     ///
     /// Setting this to zero disables limits on total ephemeral layer size.
     pub ephemeral_bytes_per_memory_kb: usize,
+
+    /// Accept backlog of the libpq listener socket. Raise it for deployments that
+    /// see bursts of incoming page service connections.
+    pub listen_pg_backlog: usize,
+
+    /// CPU ids that walredo processes are pinned to. Empty means no pinning.
+    pub walredo_cpu_affinity: Vec<usize>,
+
+    /// CPU ids that the worker threads of the pageserver tokio runtimes are pinned to.
+    /// Empty means no pinning.
+    pub worker_cpu_affinity: Vec<usize>,
+
+    /// Maximum number of materialized pages a single tenant shard may hold in the page
+    /// cache. Once a tenant reaches it, new pages of that tenant only replace its own
+    /// older ones. Unlimited if unset.
+    pub page_cache_tenant_max_pages: Option<usize>,
+
+    /// SO_SNDBUF to request on accepted page service connections, in bytes. Raising it helps
+    /// large basebackups over links with a high bandwidth-delay product. OS default if unset.
+    pub page_service_socket_send_buffer: Option<usize>,
+
+    /// SO_RCVBUF to request on accepted page service connections, in bytes. OS default if unset.
+    pub page_service_socket_recv_buffer: Option<usize>,
+
+    /// Maximum number of basebackups of a single tenant shard that may be sent at the same
+    /// time. Further requests are rejected until one finishes. Unlimited if unset.
+    pub max_concurrent_basebackups_per_tenant: Option<usize>,
+
+    /// Initial delay before reconnecting to a safekeeper after a WAL streaming connection
+    /// attempt failed or the connection broke. Grows exponentially with every further failure.
+    pub wal_connect_retry_min_backoff: Duration,
+
+    /// Upper bound of the delay between reconnection attempts to a safekeeper. A safekeeper
+    /// that stays unreachable is retried at this interval.
+    pub wal_connect_retry_max_backoff: Duration,
+
+    /// Minimum interval between the process stats that are logged when launching an io_uring
+    /// instance fails. The first failure is always logged.
+    pub io_uring_launch_failure_stats_interval: Duration,
+
+    /// If set, all threads share this many io_uring instances instead of each executor thread
+    /// launching its own. Uses less locked memory, at the cost of contention on the shared
+    /// instances.
+    pub io_uring_shared_systems: Option<usize>,
+
+    /// Path of a Unix socket to serve the page service on, in addition to the TCP
+    /// `listen_pg_addr`. Clients connect to it through the libpq `host=<directory>`
+    /// syntax, so the file name should be `.s.PGSQL.<port>`. Disabled if unset.
+    pub listen_socket: Option<Utf8PathBuf>,
+
+    /// Number of walredo processes each tenant shard may run, to replay WAL for several
+    /// pages concurrently. Requests are spread over them round-robin.
+    pub walredo_processes_per_tenant: usize,
+
+    /// Compression of the values in the delta layers written by flushing in-memory
+    /// layers, `none` or `zstd`. Trades CPU time on flush and on reads for smaller
+    /// layer files. Layers written with compression cannot be read by pageservers
+    /// that don't know about it.
+    pub flush_compression: LayerCompression,
+
+    /// How long a lease taken with the page service `pin_lsn` command holds back GC,
+    /// unless it is released earlier with `unpin_lsn`.
+    pub lsn_lease_ttl: Duration,
+
+    /// If true, the tenants found at startup are not warmed up in the background, but
+    /// only loaded when a client first accesses them. Startup then doesn't wait for
+    /// them either.
+    pub lazy_tenant_load: bool,
+
+    /// Largest message a page service client may send, in bytes. Protects against
+    /// clients that announce a huge message, e.g. a bogus CopyData, and make us buffer
+    /// it. Large enough for any pagestream request and for the chunks of imports.
+    pub page_service_max_message_size: usize,
 }
 
 /// We do not want to store this in a PageServerConf because the latter may be logged
@@ -413,6 +545,40 @@ struct PageServerConfigBuilder {
     validate_vectored_get: BuilderValue<bool>,
 
     ephemeral_bytes_per_memory_kb: BuilderValue<usize>,
+
+    listen_pg_backlog: BuilderValue<usize>,
+
+    walredo_cpu_affinity: BuilderValue<Vec<usize>>,
+
+    worker_cpu_affinity: BuilderValue<Vec<usize>>,
+
+    page_cache_tenant_max_pages: BuilderValue<Option<usize>>,
+
+    page_service_socket_send_buffer: BuilderValue<Option<usize>>,
+
+    page_service_socket_recv_buffer: BuilderValue<Option<usize>>,
+
+    max_concurrent_basebackups_per_tenant: BuilderValue<Option<usize>>,
+
+    wal_connect_retry_min_backoff: BuilderValue<Duration>,
+
+    wal_connect_retry_max_backoff: BuilderValue<Duration>,
+
+    io_uring_launch_failure_stats_interval: BuilderValue<Duration>,
+
+    io_uring_shared_systems: BuilderValue<Option<usize>>,
+
+    listen_socket: BuilderValue<Option<Utf8PathBuf>>,
+
+    walredo_processes_per_tenant: BuilderValue<usize>,
+
+    flush_compression: BuilderValue<LayerCompression>,
+
+    lsn_lease_ttl: BuilderValue<Duration>,
+
+    lazy_tenant_load: BuilderValue<bool>,
+
+    page_service_max_message_size: BuilderValue<usize>,
 }
 
 impl PageServerConfigBuilder {
@@ -500,6 +666,33 @@ impl PageServerConfigBuilder {
             )),
             validate_vectored_get: Set(DEFAULT_VALIDATE_VECTORED_GET),
             ephemeral_bytes_per_memory_kb: Set(DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB),
+            listen_pg_backlog: Set(DEFAULT_PG_LISTEN_BACKLOG),
+            walredo_cpu_affinity: Set(Vec::new()),
+            worker_cpu_affinity: Set(Vec::new()),
+            page_cache_tenant_max_pages: Set(None),
+            page_service_socket_send_buffer: Set(None),
+            page_service_socket_recv_buffer: Set(None),
+            max_concurrent_basebackups_per_tenant: Set(None),
+            wal_connect_retry_min_backoff: Set(humantime::parse_duration(
+                DEFAULT_WAL_CONNECT_RETRY_MIN_BACKOFF,
+            )
+            .expect("cannot parse default wal connect retry min backoff")),
+            wal_connect_retry_max_backoff: Set(humantime::parse_duration(
+                DEFAULT_WAL_CONNECT_RETRY_MAX_BACKOFF,
+            )
+            .expect("cannot parse default wal connect retry max backoff")),
+            io_uring_launch_failure_stats_interval: Set(humantime::parse_duration(
+                DEFAULT_IO_URING_LAUNCH_FAILURE_STATS_INTERVAL,
+            )
+            .expect("cannot parse default io_uring launch failure stats interval")),
+            io_uring_shared_systems: Set(None),
+            listen_socket: Set(None),
+            walredo_processes_per_tenant: Set(DEFAULT_WALREDO_PROCESSES_PER_TENANT),
+            flush_compression: Set(DEFAULT_FLUSH_COMPRESSION.parse().unwrap()),
+            lsn_lease_ttl: Set(humantime::parse_duration(DEFAULT_LSN_LEASE_TTL)
+                .expect("cannot parse default lsn lease ttl")),
+            lazy_tenant_load: Set(false),
+            page_service_max_message_size: Set(DEFAULT_PAGE_SERVICE_MAX_MESSAGE_SIZE),
         }
     }
 }
@@ -683,6 +876,74 @@ impl PageServerConfigBuilder {
         self.ephemeral_bytes_per_memory_kb = BuilderValue::Set(value);
     }
 
+    pub fn listen_pg_backlog(&mut self, value: usize) {
+        self.listen_pg_backlog = BuilderValue::Set(value);
+    }
+
+    pub fn walredo_cpu_affinity(&mut self, value: Vec<usize>) {
+        self.walredo_cpu_affinity = BuilderValue::Set(value);
+    }
+
+    pub fn worker_cpu_affinity(&mut self, value: Vec<usize>) {
+        self.worker_cpu_affinity = BuilderValue::Set(value);
+    }
+
+    pub fn page_cache_tenant_max_pages(&mut self, value: Option<usize>) {
+        self.page_cache_tenant_max_pages = BuilderValue::Set(value);
+    }
+
+    pub fn page_service_socket_send_buffer(&mut self, value: Option<usize>) {
+        self.page_service_socket_send_buffer = BuilderValue::Set(value);
+    }
+
+    pub fn page_service_socket_recv_buffer(&mut self, value: Option<usize>) {
+        self.page_service_socket_recv_buffer = BuilderValue::Set(value);
+    }
+
+    pub fn max_concurrent_basebackups_per_tenant(&mut self, value: Option<usize>) {
+        self.max_concurrent_basebackups_per_tenant = BuilderValue::Set(value);
+    }
+
+    pub fn wal_connect_retry_min_backoff(&mut self, value: Duration) {
+        self.wal_connect_retry_min_backoff = BuilderValue::Set(value);
+    }
+
+    pub fn wal_connect_retry_max_backoff(&mut self, value: Duration) {
+        self.wal_connect_retry_max_backoff = BuilderValue::Set(value);
+    }
+
+    pub fn io_uring_launch_failure_stats_interval(&mut self, value: Duration) {
+        self.io_uring_launch_failure_stats_interval = BuilderValue::Set(value);
+    }
+
+    pub fn io_uring_shared_systems(&mut self, value: Option<usize>) {
+        self.io_uring_shared_systems = BuilderValue::Set(value);
+    }
+
+    pub fn listen_socket(&mut self, value: Option<Utf8PathBuf>) {
+        self.listen_socket = BuilderValue::Set(value);
+    }
+
+    pub fn walredo_processes_per_tenant(&mut self, value: usize) {
+        self.walredo_processes_per_tenant = BuilderValue::Set(value);
+    }
+
+    pub fn flush_compression(&mut self, value: LayerCompression) {
+        self.flush_compression = BuilderValue::Set(value);
+    }
+
+    pub fn lsn_lease_ttl(&mut self, value: Duration) {
+        self.lsn_lease_ttl = BuilderValue::Set(value);
+    }
+
+    pub fn lazy_tenant_load(&mut self, value: bool) {
+        self.lazy_tenant_load = BuilderValue::Set(value);
+    }
+
+    pub fn page_service_max_message_size(&mut self, value: usize) {
+        self.page_service_max_message_size = BuilderValue::Set(value);
+    }
+
     pub fn build(self) -> anyhow::Result<PageServerConf> {
         let default = Self::default_values();
 
@@ -739,6 +1000,23 @@ impl PageServerConfigBuilder {
                 max_vectored_read_bytes,
                 validate_vectored_get,
                 ephemeral_bytes_per_memory_kb,
+                listen_pg_backlog,
+                walredo_cpu_affinity,
+                worker_cpu_affinity,
+                page_cache_tenant_max_pages,
+                page_service_socket_send_buffer,
+                page_service_socket_recv_buffer,
+                max_concurrent_basebackups_per_tenant,
+                wal_connect_retry_min_backoff,
+                wal_connect_retry_max_backoff,
+                io_uring_launch_failure_stats_interval,
+                io_uring_shared_systems,
+                listen_socket,
+                walredo_processes_per_tenant,
+                flush_compression,
+                lsn_lease_ttl,
+                lazy_tenant_load,
+                page_service_max_message_size,
             }
             CUSTOM LOGIC
             {
@@ -932,8 +1210,18 @@ impl PageServerConf {
                 "availability_zone" => builder.availability_zone(Some(parse_toml_string(key, item)?)),
                 "wait_lsn_timeout" => builder.wait_lsn_timeout(parse_toml_duration(key, item)?),
                 "wal_redo_timeout" => builder.wal_redo_timeout(parse_toml_duration(key, item)?),
-                "initial_superuser_name" => builder.superuser(parse_toml_string(key, item)?),
+                "initial_superuser_name" => {
+                    let superuser = parse_toml_string(key, item)?;
+                    validate_role_name(&superuser)
+                        .with_context(|| format!("invalid value for '{key}'"))?;
+                    builder.superuser(superuser)
+                }
                 "page_cache_size" => builder.page_cache_size(parse_toml_u64(key, item)? as usize),
+                "page_cache_size_mb" => {
+                    let mb = NonZeroUsize::new(parse_toml_u64(key, item)? as usize)
+                        .context("page_cache_size_mb must be positive")?;
+                    builder.page_cache_size(mb.get() * 1024 * 1024 / page_cache::PAGE_SZ)
+                }
                 "max_file_descriptors" => {
                     builder.max_file_descriptors(parse_toml_u64(key, item)? as usize)
                 }
@@ -952,7 +1240,7 @@ impl PageServerConf {
                     t_conf = TenantConfOpt::try_from(item.to_owned()).context(format!("failed to parse: '{key}'"))?;
                 }
                 "id" => builder.id(NodeId(parse_toml_u64(key, item)?)),
-                "broker_endpoint" => builder.broker_endpoint(parse_toml_string(key, item)?.parse().context("failed to parse broker endpoint")?),
+                "broker_endpoint" => builder.broker_endpoint(storage_broker::parse_endpoint(&parse_toml_string(key, item)?)?),
                 "broker_keepalive_interval" => builder.broker_keepalive_interval(parse_toml_duration(key, item)?),
                 "log_format" => builder.log_format(
                     LogFormat::from_config(&parse_toml_string(key, item)?)?
@@ -1032,6 +1320,57 @@ impl PageServerConf {
                 "ephemeral_bytes_per_memory_kb" => {
                     builder.get_ephemeral_bytes_per_memory_kb(parse_toml_u64("ephemeral_bytes_per_memory_kb", item)? as usize)
                 }
+                "listen_pg_backlog" => {
+                    builder.listen_pg_backlog(parse_toml_u64(key, item)? as usize)
+                }
+                "walredo_cpu_affinity" => {
+                    builder.walredo_cpu_affinity(deserialize_from_item(key, item)?)
+                }
+                "worker_cpu_affinity" => {
+                    builder.worker_cpu_affinity(deserialize_from_item(key, item)?)
+                }
+                "page_cache_tenant_max_pages" => {
+                    builder.page_cache_tenant_max_pages(Some(parse_toml_u64(key, item)? as usize))
+                }
+                "page_service_socket_send_buffer" => {
+                    builder.page_service_socket_send_buffer(Some(parse_toml_u64(key, item)? as usize))
+                }
+                "page_service_socket_recv_buffer" => {
+                    builder.page_service_socket_recv_buffer(Some(parse_toml_u64(key, item)? as usize))
+                }
+                "max_concurrent_basebackups_per_tenant" => {
+                    builder.max_concurrent_basebackups_per_tenant(Some(parse_toml_u64(key, item)? as usize))
+                }
+                "wal_connect_retry_min_backoff" => {
+                    builder.wal_connect_retry_min_backoff(parse_toml_duration(key, item)?)
+                }
+                "wal_connect_retry_max_backoff" => {
+                    builder.wal_connect_retry_max_backoff(parse_toml_duration(key, item)?)
+                }
+                "io_uring_launch_failure_stats_interval" => {
+                    builder.io_uring_launch_failure_stats_interval(parse_toml_duration(key, item)?)
+                }
+                "io_uring_shared_systems" => builder.io_uring_shared_systems(Some(
+                    NonZeroUsize::new(parse_toml_u64(key, item)? as usize)
+                        .context("io_uring_shared_systems must be positive")?
+                        .get(),
+                )),
+                "listen_socket" => builder.listen_socket(Some(
+                    Utf8PathBuf::from(parse_toml_string(key, item)?),
+                )),
+                "walredo_processes_per_tenant" => builder.walredo_processes_per_tenant(
+                    NonZeroUsize::new(parse_toml_u64(key, item)? as usize)
+                        .context("walredo_processes_per_tenant must be positive")?
+                        .get(),
+                ),
+                "flush_compression" => {
+                    builder.flush_compression(parse_toml_from_str("flush_compression", item)?)
+                }
+                "lsn_lease_ttl" => builder.lsn_lease_ttl(parse_toml_duration(key, item)?),
+                "lazy_tenant_load" => builder.lazy_tenant_load(parse_toml_bool(key, item)?),
+                "page_service_max_message_size" => builder.page_service_max_message_size(
+                    parse_toml_u64(key, item)? as usize,
+                ),
                 _ => bail!("unrecognized pageserver option '{key}'"),
             }
         }
@@ -1055,6 +1394,128 @@ impl PageServerConf {
         Ok(conf)
     }
 
+    /// The configuration in effect, as a pageserver.toml document that
+    /// [`Self::parse_and_validate`] accepts: every option, with the built-in defaults
+    /// filled in. `toml` is the document the configuration was parsed from; the
+    /// `remote_storage`, `metric_collection_bucket` and `disk_usage_based_eviction`
+    /// sections are taken over from it as given. `control_plane_api_token` is left out,
+    /// so that the output can be shared.
+    pub fn to_effective_toml(&self, toml: &Document) -> anyhow::Result<Document> {
+        use toml_edit::value;
+
+        fn duration(d: Duration) -> Item {
+            value(humantime::format_duration(d).to_string())
+        }
+        fn int(n: impl TryInto<i64>) -> Item {
+            value(n.try_into().unwrap_or(i64::MAX))
+        }
+        fn array(values: &[usize]) -> Item {
+            value(
+                values
+                    .iter()
+                    .map(|v| *v as i64)
+                    .collect::<toml_edit::Array>(),
+            )
+        }
+
+        let mut doc = Document::new();
+        doc["id"] = int(self.id.0);
+        doc["listen_pg_addr"] = value(self.listen_pg_addr.as_str());
+        doc["listen_http_addr"] = value(self.listen_http_addr.as_str());
+        if let Some(listen_socket) = &self.listen_socket {
+            doc["listen_socket"] = value(listen_socket.as_str());
+        }
+        if let Some(availability_zone) = &self.availability_zone {
+            doc["availability_zone"] = value(availability_zone.as_str());
+        }
+        doc["wait_lsn_timeout"] = duration(self.wait_lsn_timeout);
+        doc["wal_redo_timeout"] = duration(self.wal_redo_timeout);
+        doc["initial_superuser_name"] = value(self.superuser.as_str());
+        doc["page_cache_size"] = int(self.page_cache_size);
+        if let Some(max_pages) = self.page_cache_tenant_max_pages {
+            doc["page_cache_tenant_max_pages"] = int(max_pages);
+        }
+        doc["max_file_descriptors"] = int(self.max_file_descriptors);
+        doc["pg_distrib_dir"] = value(self.pg_distrib_dir.as_str());
+        doc["http_auth_type"] = value(self.http_auth_type.to_string());
+        doc["pg_auth_type"] = value(self.pg_auth_type.to_string());
+        if let Some(path) = &self.auth_validation_public_key_path {
+            doc["auth_validation_public_key_path"] = value(path.as_str());
+        }
+        doc["broker_endpoint"] = value(self.broker_endpoint.to_string());
+        doc["broker_keepalive_interval"] = duration(self.broker_keepalive_interval);
+        doc["log_format"] = value(self.log_format.as_ref());
+        doc["concurrent_tenant_warmup"] =
+            value(self.concurrent_tenant_warmup.initial_permits().to_string());
+        doc["concurrent_tenant_size_logical_size_queries"] = value(
+            self.concurrent_tenant_size_logical_size_queries
+                .initial_permits()
+                .to_string(),
+        );
+        doc["metric_collection_interval"] = duration(self.metric_collection_interval);
+        doc["cached_metric_collection_interval"] = duration(self.cached_metric_collection_interval);
+        if let Some(endpoint) = &self.metric_collection_endpoint {
+            doc["metric_collection_endpoint"] = value(endpoint.as_str());
+        }
+        doc["synthetic_size_calculation_interval"] =
+            duration(self.synthetic_size_calculation_interval);
+        doc["test_remote_failures"] = int(self.test_remote_failures);
+        doc["ondemand_download_behavior_treat_error_as_warn"] =
+            value(self.ondemand_download_behavior_treat_error_as_warn);
+        doc["background_task_maximum_delay"] = duration(self.background_task_maximum_delay);
+        if let Some(api) = &self.control_plane_api {
+            doc["control_plane_api"] = value(api.as_str());
+        }
+        doc["control_plane_emergency_mode"] = value(self.control_plane_emergency_mode);
+        doc["heatmap_upload_concurrency"] = int(self.heatmap_upload_concurrency);
+        doc["secondary_download_concurrency"] = int(self.secondary_download_concurrency);
+        doc["ingest_batch_size"] = int(self.ingest_batch_size);
+        doc["virtual_file_io_engine"] = value(self.virtual_file_io_engine.to_string());
+        doc["get_vectored_impl"] = value(self.get_vectored_impl.to_string());
+        doc["max_vectored_read_bytes"] = int(self.max_vectored_read_bytes.0.get());
+        doc["validate_vectored_get"] = value(self.validate_vectored_get);
+        doc["ephemeral_bytes_per_memory_kb"] = int(self.ephemeral_bytes_per_memory_kb);
+        doc["listen_pg_backlog"] = int(self.listen_pg_backlog);
+        doc["walredo_cpu_affinity"] = array(&self.walredo_cpu_affinity);
+        doc["worker_cpu_affinity"] = array(&self.worker_cpu_affinity);
+        if let Some(size) = self.page_service_socket_send_buffer {
+            doc["page_service_socket_send_buffer"] = int(size);
+        }
+        if let Some(size) = self.page_service_socket_recv_buffer {
+            doc["page_service_socket_recv_buffer"] = int(size);
+        }
+        if let Some(max) = self.max_concurrent_basebackups_per_tenant {
+            doc["max_concurrent_basebackups_per_tenant"] = int(max);
+        }
+        doc["wal_connect_retry_min_backoff"] = duration(self.wal_connect_retry_min_backoff);
+        doc["wal_connect_retry_max_backoff"] = duration(self.wal_connect_retry_max_backoff);
+        doc["io_uring_launch_failure_stats_interval"] =
+            duration(self.io_uring_launch_failure_stats_interval);
+        if let Some(systems) = self.io_uring_shared_systems {
+            doc["io_uring_shared_systems"] = int(systems);
+        }
+        doc["walredo_processes_per_tenant"] = int(self.walredo_processes_per_tenant);
+        doc["flush_compression"] = value(self.flush_compression.to_string());
+        doc["lsn_lease_ttl"] = duration(self.lsn_lease_ttl);
+        doc["lazy_tenant_load"] = value(self.lazy_tenant_load);
+        doc["page_service_max_message_size"] = int(self.page_service_max_message_size);
+
+        for key in [
+            "remote_storage",
+            "metric_collection_bucket",
+            "disk_usage_based_eviction",
+        ] {
+            if let Some(item) = toml.get(key) {
+                doc[key] = item.clone();
+            }
+        }
+        let tenant_config = toml_edit::ser::to_document(&self.default_tenant_conf)
+            .context("serialize tenant_config")?;
+        doc["tenant_config"] = Item::Table(tenant_config.as_table().clone());
+
+        Ok(doc)
+    }
+
     #[cfg(test)]
     pub fn test_repo_dir(test_name: &str) -> Utf8PathBuf {
         let test_output_dir = std::env::var("TEST_OUTPUT").unwrap_or("../tmp_check".into());
@@ -1114,6 +1575,23 @@ impl PageServerConf {
             ),
             validate_vectored_get: defaults::DEFAULT_VALIDATE_VECTORED_GET,
             ephemeral_bytes_per_memory_kb: defaults::DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB,
+            listen_pg_backlog: defaults::DEFAULT_PG_LISTEN_BACKLOG,
+            walredo_cpu_affinity: Vec::new(),
+            worker_cpu_affinity: Vec::new(),
+            page_cache_tenant_max_pages: None,
+            page_service_socket_send_buffer: None,
+            page_service_socket_recv_buffer: None,
+            max_concurrent_basebackups_per_tenant: None,
+            wal_connect_retry_min_backoff: Duration::from_millis(100),
+            wal_connect_retry_max_backoff: Duration::from_secs(15),
+            io_uring_launch_failure_stats_interval: Duration::from_secs(60),
+            io_uring_shared_systems: None,
+            listen_socket: None,
+            walredo_processes_per_tenant: defaults::DEFAULT_WALREDO_PROCESSES_PER_TENANT,
+            flush_compression: LayerCompression::None,
+            lsn_lease_ttl: Duration::from_secs(600),
+            lazy_tenant_load: false,
+            page_service_max_message_size: defaults::DEFAULT_PAGE_SERVICE_MAX_MESSAGE_SIZE,
         }
     }
 }
@@ -1274,6 +1752,7 @@ synthetic_size_calculation_interval = '333 s'
 
 log_format = 'json'
 background_task_maximum_delay = '334 s'
+walredo_cpu_affinity = [1, 3]
 
 "#;
 
@@ -1351,7 +1830,30 @@ background_task_maximum_delay = '334 s'
                         .expect("Invalid default constant")
                 ),
                 validate_vectored_get: defaults::DEFAULT_VALIDATE_VECTORED_GET,
-                ephemeral_bytes_per_memory_kb: defaults::DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB
+                ephemeral_bytes_per_memory_kb: defaults::DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB,
+                listen_pg_backlog: defaults::DEFAULT_PG_LISTEN_BACKLOG,
+                walredo_cpu_affinity: Vec::new(),
+                worker_cpu_affinity: Vec::new(),
+                page_cache_tenant_max_pages: None,
+                page_service_socket_send_buffer: None,
+                page_service_socket_recv_buffer: None,
+                max_concurrent_basebackups_per_tenant: None,
+                wal_connect_retry_min_backoff: humantime::parse_duration(
+                    defaults::DEFAULT_WAL_CONNECT_RETRY_MIN_BACKOFF
+                )?,
+                wal_connect_retry_max_backoff: humantime::parse_duration(
+                    defaults::DEFAULT_WAL_CONNECT_RETRY_MAX_BACKOFF
+                )?,
+                io_uring_launch_failure_stats_interval: humantime::parse_duration(
+                    defaults::DEFAULT_IO_URING_LAUNCH_FAILURE_STATS_INTERVAL
+                )?,
+                io_uring_shared_systems: None,
+                listen_socket: None,
+                walredo_processes_per_tenant: defaults::DEFAULT_WALREDO_PROCESSES_PER_TENANT,
+                flush_compression: LayerCompression::None,
+                lsn_lease_ttl: humantime::parse_duration(defaults::DEFAULT_LSN_LEASE_TTL)?,
+                lazy_tenant_load: false,
+                page_service_max_message_size: defaults::DEFAULT_PAGE_SERVICE_MAX_MESSAGE_SIZE,
             },
             "Correct defaults should be used when no config values are provided"
         );
@@ -1423,7 +1925,30 @@ background_task_maximum_delay = '334 s'
                         .expect("Invalid default constant")
                 ),
                 validate_vectored_get: defaults::DEFAULT_VALIDATE_VECTORED_GET,
-                ephemeral_bytes_per_memory_kb: defaults::DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB
+                ephemeral_bytes_per_memory_kb: defaults::DEFAULT_EPHEMERAL_BYTES_PER_MEMORY_KB,
+                listen_pg_backlog: defaults::DEFAULT_PG_LISTEN_BACKLOG,
+                walredo_cpu_affinity: vec![1, 3],
+                worker_cpu_affinity: Vec::new(),
+                page_cache_tenant_max_pages: None,
+                page_service_socket_send_buffer: None,
+                page_service_socket_recv_buffer: None,
+                max_concurrent_basebackups_per_tenant: None,
+                wal_connect_retry_min_backoff: humantime::parse_duration(
+                    defaults::DEFAULT_WAL_CONNECT_RETRY_MIN_BACKOFF
+                )?,
+                wal_connect_retry_max_backoff: humantime::parse_duration(
+                    defaults::DEFAULT_WAL_CONNECT_RETRY_MAX_BACKOFF
+                )?,
+                io_uring_launch_failure_stats_interval: humantime::parse_duration(
+                    defaults::DEFAULT_IO_URING_LAUNCH_FAILURE_STATS_INTERVAL
+                )?,
+                io_uring_shared_systems: None,
+                listen_socket: None,
+                walredo_processes_per_tenant: defaults::DEFAULT_WALREDO_PROCESSES_PER_TENANT,
+                flush_compression: LayerCompression::None,
+                lsn_lease_ttl: humantime::parse_duration(defaults::DEFAULT_LSN_LEASE_TTL)?,
+                lazy_tenant_load: false,
+                page_service_max_message_size: defaults::DEFAULT_PAGE_SERVICE_MAX_MESSAGE_SIZE,
             },
             "Should be able to parse all basic config values correctly"
         );
@@ -1431,6 +1956,32 @@ background_task_maximum_delay = '334 s'
         Ok(())
     }
 
+    #[test]
+    fn effective_toml_roundtrip() -> anyhow::Result<()> {
+        let tempdir = tempdir()?;
+        let (workdir, pg_distrib_dir) = prepare_fs(&tempdir)?;
+        let broker_endpoint = storage_broker::DEFAULT_ENDPOINT;
+
+        for config_string in [
+            format!("pg_distrib_dir='{pg_distrib_dir}'\nid=10\nbroker_endpoint = '{broker_endpoint}'"),
+            format!(
+                "{ALL_BASE_VALUES_TOML}pg_distrib_dir='{pg_distrib_dir}'\nbroker_endpoint = '{broker_endpoint}'",
+            ),
+        ] {
+            let toml = config_string.parse()?;
+            let conf = PageServerConf::parse_and_validate(&toml, &workdir)?;
+
+            let effective = conf.to_effective_toml(&toml)?;
+            // Defaults are spelled out.
+            assert!(effective.contains_key("wait_lsn_timeout"), "{effective}");
+            let reparsed = PageServerConf::parse_and_validate(&effective, &workdir)
+                .unwrap_or_else(|e| panic!("Failed to parse effective config '{effective}': {e:?}"));
+            assert_eq!(reparsed, conf, "effective config:\n{effective}");
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn parse_remote_fs_storage_config() -> anyhow::Result<()> {
         let tempdir = tempdir()?;
@@ -1591,6 +2142,50 @@ trace_read_requests = {trace_read_requests}"#,
         Ok(())
     }
 
+    #[test]
+    fn parse_invalid_superuser_name() -> anyhow::Result<()> {
+        let tempdir = tempdir()?;
+        let (workdir, pg_distrib_dir) = prepare_fs(&tempdir)?;
+
+        let config_string = format!(
+            r#"pg_distrib_dir='{pg_distrib_dir}'
+initial_superuser_name = 'Cloud Admin'"#,
+        );
+        let toml = config_string.parse()?;
+
+        let error = PageServerConf::parse_and_validate(&toml, &workdir).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid value for 'initial_superuser_name'"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_page_cache_size_mb() -> anyhow::Result<()> {
+        let tempdir = tempdir()?;
+        let (workdir, pg_distrib_dir) = prepare_fs(&tempdir)?;
+
+        let config_string = format!(
+            r#"pg_distrib_dir='{pg_distrib_dir}'
+page_cache_size_mb = 16"#,
+        );
+        let toml = config_string.parse()?;
+        let conf = PageServerConf::parse_and_validate(&toml, &workdir)?;
+        assert_eq!(conf.page_cache_size, 16 * 1024 * 1024 / page_cache::PAGE_SZ);
+
+        let config_string = format!(
+            r#"pg_distrib_dir='{pg_distrib_dir}'
+page_cache_size_mb = 0"#,
+        );
+        let toml = config_string.parse()?;
+        let error = PageServerConf::parse_and_validate(&toml, &workdir).unwrap_err();
+        assert_eq!(error.to_string(), "page_cache_size_mb must be positive");
+
+        Ok(())
+    }
+
     #[test]
     fn parse_override_tenant_config() -> anyhow::Result<()> {
         let config_string = r#"tenant_config={ min_resident_size_override =  400 }"#.to_string();
@@ -0,0 +1,65 @@
+//! Optional pinning of walredo processes and tokio worker threads to a set of CPUs.
+//!
+//! On NUMA hosts, keeping the GetPage path on a fixed set of cores gives more predictable
+//! latency. Both lists are empty by default, which leaves scheduling to the OS. Pinning is
+//! only supported on Linux; elsewhere the settings are ignored with a warning at startup.
+
+use once_cell::sync::OnceCell;
+use tracing::warn;
+
+use crate::config::PageServerConf;
+
+/// CPUs for the tokio worker threads, set once by [`init`] before the runtimes start.
+static WORKER_CPUS: OnceCell<Vec<usize>> = OnceCell::new();
+
+pub fn init(conf: &PageServerConf) {
+    if !cfg!(target_os = "linux")
+        && !(conf.worker_cpu_affinity.is_empty() && conf.walredo_cpu_affinity.is_empty())
+    {
+        warn!("CPU affinity is not supported on this platform, ignoring the configured CPU lists");
+    }
+    if WORKER_CPUS.set(conf.worker_cpu_affinity.clone()).is_err() {
+        panic!("cpu_affinity::init called twice");
+    }
+}
+
+/// Pins the calling thread to the configured worker CPUs. Meant to be used as the
+/// `on_thread_start` hook of the runtimes in [`crate::task_mgr`].
+pub(crate) fn pin_worker_thread() {
+    let Some(cpus) = WORKER_CPUS.get() else {
+        return;
+    };
+    if let Err(e) = set_affinity(0, cpus) {
+        warn!("failed to pin worker thread to CPUs {cpus:?}: {e:#}");
+    }
+}
+
+/// Pins an already spawned walredo process to the configured walredo CPUs.
+pub(crate) fn pin_walredo_process(conf: &PageServerConf, pid: u32) {
+    let cpus = &conf.walredo_cpu_affinity;
+    if let Err(e) = set_affinity(pid as i32, cpus) {
+        warn!("failed to pin walredo process {pid} to CPUs {cpus:?}: {e:#}");
+    }
+}
+
+/// Sets the affinity of `pid` (0 being the calling thread). No-op for an empty list.
+#[cfg(target_os = "linux")]
+fn set_affinity(pid: i32, cpus: &[usize]) -> anyhow::Result<()> {
+    use nix::sched::{sched_setaffinity, CpuSet};
+    use nix::unistd::Pid;
+
+    if cpus.is_empty() {
+        return Ok(());
+    }
+    let mut set = CpuSet::new();
+    for &cpu in cpus {
+        set.set(cpu)?;
+    }
+    sched_setaffinity(Pid::from_raw(pid), &set)?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_pid: i32, _cpus: &[usize]) -> anyhow::Result<()> {
+    Ok(())
+}
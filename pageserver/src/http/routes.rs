@@ -8,6 +8,7 @@ use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
 use enumset::EnumSet;
+use futures::StreamExt;
 use futures::TryFutureExt;
 use humantime::format_rfc3339;
 use hyper::header;
@@ -45,6 +46,7 @@ use crate::context::{DownloadBehavior, RequestContext};
 use crate::deletion_queue::DeletionQueueClient;
 use crate::metrics::{StorageTimeOperation, STORAGE_TIME_GLOBAL};
 use crate::pgdatadir_mapping::LsnForTimestamp;
+use crate::repository::{GcProgress, GcResult};
 use crate::task_mgr::TaskKind;
 use crate::tenant::config::{LocationConf, TenantConfOpt};
 use crate::tenant::mgr::GetActiveTenantError;
@@ -287,6 +289,7 @@ impl From<crate::tenant::DeleteTimelineError> for ApiError {
                     .into_boxed_str(),
             ),
             a @ AlreadyInProgress(_) => ApiError::Conflict(a.to_string()),
+            e @ InFlightReads(_) => ApiError::ResourceUnavailable(e.to_string().into()),
             Other(e) => ApiError::InternalServerError(e),
         }
     }
@@ -434,7 +437,13 @@ async fn status_handler(
 ) -> Result<Response<Body>, ApiError> {
     check_permission(&request, None)?;
     let config = get_config(&request);
-    json_response(StatusCode::OK, StatusResponse { id: config.id })
+    json_response(
+        StatusCode::OK,
+        StatusResponse {
+            id: config.id,
+            ready: crate::is_ready(),
+        },
+    )
 }
 
 async fn reload_auth_validation_keys_handler(
@@ -552,6 +561,36 @@ async fn timeline_create_handler(
     .await
 }
 
+/// Optional `offset` and `limit` query parameters of the list endpoints. Listing
+/// everything stays the default; clients that page through a long list ask for the
+/// next page at `offset + limit` until they get fewer than `limit` entries back.
+struct Page {
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl Page {
+    fn from_request(request: &Request<Body>) -> Result<Self, ApiError> {
+        let offset: Option<usize> = parse_query_param(request, "offset")?;
+        let limit: Option<usize> = parse_query_param(request, "limit")?;
+        if limit == Some(0) {
+            return Err(ApiError::BadRequest(anyhow!("limit must be positive")));
+        }
+        Ok(Page {
+            offset: offset.unwrap_or(0),
+            limit,
+        })
+    }
+
+    fn apply<T>(&self, items: Vec<T>) -> Vec<T> {
+        items
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
 async fn timeline_list_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
@@ -561,20 +600,41 @@ async fn timeline_list_handler(
         parse_query_param(&request, "include-non-incremental-logical-size")?;
     let force_await_initial_logical_size: Option<bool> =
         parse_query_param(&request, "force-await-initial-logical-size")?;
+    let page = Page::from_request(&request)?;
+    let stream: Option<bool> = parse_query_param(&request, "stream")?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
     let state = get_state(&request);
     let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
+    let span = info_span!("timeline_list",
+                tenant_id = %tenant_shard_id.tenant_id,
+                shard_id = %tenant_shard_id.shard_slug());
 
-    let response_data = async {
+    let timelines = async {
         let tenant = state
             .tenant_manager
             .get_attached_tenant_shard(tenant_shard_id)?;
 
         tenant.wait_to_become_active(ACTIVE_TENANT_TIMEOUT).await?;
 
-        let timelines = tenant.list_timelines();
+        let mut timelines = tenant.list_timelines();
+        timelines.sort_by_key(|timeline| timeline.timeline_id);
+        Ok::<_, ApiError>(page.apply(timelines))
+    }
+    .instrument(span.clone())
+    .await?;
+
+    if stream.unwrap_or(false) {
+        return timeline_list_stream(
+            timelines,
+            include_non_incremental_logical_size.unwrap_or(false),
+            force_await_initial_logical_size.unwrap_or(false),
+            ctx,
+            span,
+        );
+    }
 
+    let response_data = async {
         let mut response_data = Vec::with_capacity(timelines.len());
         for timeline in timelines {
             let timeline_info = build_timeline_info(
@@ -592,14 +652,66 @@ async fn timeline_list_handler(
         }
         Ok::<Vec<TimelineInfo>, ApiError>(response_data)
     }
-    .instrument(info_span!("timeline_list",
-                tenant_id = %tenant_shard_id.tenant_id,
-                shard_id = %tenant_shard_id.shard_slug()))
+    .instrument(span)
     .await?;
 
     json_response(StatusCode::OK, response_data)
 }
 
+/// The `stream=true` variant of the timeline list: one JSON object per line, each sent as
+/// soon as it is built, so that neither side holds the whole list of a tenant with many
+/// timelines. Once the response has started, an error can only be reported by cutting
+/// it short, so clients must check that every line is a complete object.
+///
+/// The lines are built as hyper polls the body, and no more once the client goes away
+/// and the body is dropped.
+fn timeline_list_stream(
+    timelines: Vec<Arc<Timeline>>,
+    include_non_incremental_logical_size: bool,
+    force_await_initial_logical_size: bool,
+    ctx: RequestContext,
+    span: Span,
+) -> Result<Response<Body>, ApiError> {
+    let lines = async_stream::stream! {
+        for timeline in timelines {
+            let line = build_timeline_info(
+                &timeline,
+                include_non_incremental_logical_size,
+                force_await_initial_logical_size,
+                &ctx,
+            )
+            .instrument(info_span!(
+                parent: &span,
+                "build_timeline_info",
+                timeline_id = %timeline.timeline_id
+            ))
+            .await
+            .and_then(|timeline_info| {
+                let mut line = serde_json::to_vec(&timeline_info)?;
+                line.push(b'\n');
+                Ok(line)
+            })
+            .map_err(|e| {
+                span.in_scope(|| {
+                    warn!("failed to build timeline info, ending the list early: {e:#}")
+                });
+                std::io::Error::new(std::io::ErrorKind::Other, format!("{e:#}"))
+            });
+            let failed = line.is_err();
+            yield line;
+            if failed {
+                break;
+            }
+        }
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .body(Body::wrap_stream(lines))
+        .map_err(|e| ApiError::InternalServerError(e.into()))
+}
+
 async fn timeline_preserve_initdb_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
@@ -963,15 +1075,15 @@ async fn tenant_list_handler(
     request: Request<Body>,
     _cancel: CancellationToken,
 ) -> Result<Response<Body>, ApiError> {
+    let page = Page::from_request(&request)?;
     check_permission(&request, None)?;
     let state = get_state(&request);
 
-    let response_data = state
-        .tenant_manager
-        .list_tenants()
-        .map_err(|_| {
-            ApiError::ResourceUnavailable("Tenant map is initializing or shutting down".into())
-        })?
+    let tenants = state.tenant_manager.list_tenants().map_err(|_| {
+        ApiError::ResourceUnavailable("Tenant map is initializing or shutting down".into())
+    })?;
+    let response_data = page
+        .apply(tenants)
         .iter()
         .map(|(id, state, gen)| TenantInfo {
             id: *id,
@@ -1676,10 +1788,25 @@ async fn timeline_gc_handler(
     let timeline_id: TimelineId = parse_request_param(&request, "timeline_id")?;
     check_permission(&request, Some(tenant_shard_id.tenant_id))?;
 
+    let stream = parse_query_param::<_, bool>(&request, "stream")?.unwrap_or(false);
     let gc_req: TimelineGcRequest = json_request(&mut request).await?;
 
     let ctx = RequestContext::new(TaskKind::MgmtRequest, DownloadBehavior::Download);
-    let wait_task_done = mgr::immediate_gc(tenant_shard_id, timeline_id, gc_req, cancel, &ctx)?;
+    if stream {
+        let (progress_tx, progress_rx) = tokio::sync::watch::channel(GcProgress::default());
+        let wait_task_done = mgr::immediate_gc(
+            tenant_shard_id,
+            timeline_id,
+            gc_req,
+            Some(progress_tx),
+            cancel,
+            &ctx,
+        )?;
+        return timeline_gc_stream(progress_rx, wait_task_done);
+    }
+
+    let wait_task_done =
+        mgr::immediate_gc(tenant_shard_id, timeline_id, gc_req, None, cancel, &ctx)?;
     let gc_result = wait_task_done
         .await
         .context("wait for gc task")
@@ -1689,6 +1816,48 @@ async fn timeline_gc_handler(
     json_response(StatusCode::OK, gc_result)
 }
 
+/// A line of the `stream=true` variant of `do_gc`.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum GcStreamLine {
+    Progress(GcProgress),
+    /// The last line, if the GC succeeded.
+    Result(GcResult),
+    /// The last line, if the GC failed.
+    Error(String),
+}
+
+/// The `stream=true` variant of `do_gc`: a JSON object per line, with the progress of the
+/// GC as it goes and its result at the end, so that a client can show a long GC progress.
+/// A client that reads slower than the GC progresses gets only the latest progress.
+fn timeline_gc_stream(
+    mut progress_rx: tokio::sync::watch::Receiver<GcProgress>,
+    wait_task_done: tokio::sync::oneshot::Receiver<anyhow::Result<GcResult>>,
+) -> Result<Response<Body>, ApiError> {
+    let lines = async_stream::stream! {
+        // The GC task drops its end of the progress channel when it is done, after
+        // the last progress has been seen.
+        while progress_rx.changed().await.is_ok() {
+            let progress = progress_rx.borrow_and_update().clone();
+            yield GcStreamLine::Progress(progress);
+        }
+        yield match wait_task_done.await.context("wait for gc task") {
+            Ok(Ok(gc_result)) => GcStreamLine::Result(gc_result),
+            Ok(Err(e)) | Err(e) => GcStreamLine::Error(format!("{e:#}")),
+        };
+    }
+    .map(|line| {
+        let mut line = serde_json::to_vec(&line)?;
+        line.push(b'\n');
+        Ok::<_, serde_json::Error>(line)
+    });
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .body(Body::wrap_stream(lines))
+        .map_err(|e| ApiError::InternalServerError(e.into()))
+}
+
 // Run compaction immediately on given timeline.
 async fn timeline_compact_handler(
     request: Request<Body>,
@@ -20,6 +20,7 @@ use crate::tenant::Timeline;
 use crate::walingest::WalIngest;
 use crate::walrecord::DecodedWALRecord;
 use pageserver_api::reltag::{RelTag, SlruKind};
+use postgres_ffi::dispatch_pgversion;
 use postgres_ffi::pg_constants;
 use postgres_ffi::relfile_utils::*;
 use postgres_ffi::waldecoder::WalStreamDecoder;
@@ -146,7 +147,9 @@ async fn import_rel(
         forknum,
     };
 
-    let mut blknum: u32 = segno * (1024 * 1024 * 1024 / BLCKSZ as u32);
+    let first_blknum =
+        segment_to_rel_block(segno, 0).context("relation segment number is out of range")?;
+    let mut blknum = first_blknum;
 
     // Call put_rel_creation for every segment of the relation,
     // because there is no guarantee about the order in which we are processing segments.
@@ -177,8 +180,7 @@ async fn import_rel(
             Err(err) => match err.kind() {
                 std::io::ErrorKind::UnexpectedEof => {
                     // reached EOF. That's expected.
-                    let relative_blknum = blknum - segno * (1024 * 1024 * 1024 / BLCKSZ as u32);
-                    ensure!(relative_blknum == nblocks as u32, "unexpected EOF");
+                    ensure!(blknum - first_blknum == nblocks as u32, "unexpected EOF");
                     break;
                 }
                 _ => {
@@ -382,24 +384,40 @@ pub async fn import_basebackup_from_tar(
     }
 
     // sanity check: ensure that pg_control is loaded
-    let _pg_control = pg_control.context("pg_control file not found")?;
+    let pg_control = pg_control.context("pg_control file not found")?;
+
+    // The backup's checkpoint REDO pointer is where WAL replay would start, so the
+    // backup cannot be consistent at any earlier LSN. It may be a little past base_lsn,
+    // as basebackups generated by us round it up to the next record boundary.
+    let redo = Lsn(pg_control.checkPointCopy.redo);
+    ensure!(
+        redo >= base_lsn,
+        "base LSN {base_lsn} does not match the backup: its checkpoint REDO pointer is {redo}"
+    );
 
     modification.commit(ctx).await?;
     Ok(())
 }
 
+/// Ingest the WAL in a tar of WAL segments from `start_lsn` to `end_lsn`, returning the
+/// number of records imported.
+///
+/// The timeline's `last_record_lsn` is not advanced: the LSNs to advance it through are
+/// returned as well, for [`Timeline::flush_and_publish`].
 pub async fn import_wal_from_tar(
     tline: &Timeline,
     reader: &mut (impl AsyncRead + Send + Sync + Unpin),
     start_lsn: Lsn,
     end_lsn: Lsn,
     ctx: &RequestContext,
-) -> Result<()> {
+) -> Result<(u64, Vec<Lsn>)> {
     // Set up walingest mutable state
     let mut waldecoder = WalStreamDecoder::new(start_lsn, tline.pg_version);
     let mut segno = start_lsn.segment_number(WAL_SEGMENT_SIZE);
     let mut offset = start_lsn.segment_offset(WAL_SEGMENT_SIZE);
     let mut last_lsn = start_lsn;
+    let mut records = 0;
+    let mut record_lsns = Vec::new();
     let mut walingest = WalIngest::new(tline, start_lsn, ctx).await?;
 
     // Ingest wal until end_lsn
@@ -426,7 +444,10 @@ pub async fn import_wal_from_tar(
                     ensure!(expected_filename == file_name);
 
                     debug!("processing wal file {:?}", file_path);
-                    read_all_bytes(&mut entry).await?
+                    let bytes = read_all_bytes(&mut entry).await?;
+                    check_wal_segment(&bytes, tline.pg_version)
+                        .with_context(|| format!("invalid WAL segment {file_name}"))?;
+                    bytes
                 }
                 tokio_tar::EntryType::Directory => {
                     debug!("directory {:?}", file_path);
@@ -450,9 +471,16 @@ pub async fn import_wal_from_tar(
             if let Some((lsn, recdata)) = waldecoder.poll_decode()? {
                 walingest
                     .ingest_record(recdata, lsn, &mut modification, &mut decoded, ctx)
-                    .await?;
-                modification.commit(ctx).await?;
+                    .await
+                    .with_context(|| format!("failed to import WAL record at {lsn}"))?;
+                record_lsns.extend(
+                    modification
+                        .commit_unpublished(ctx)
+                        .await
+                        .with_context(|| format!("failed to import WAL record at {lsn}"))?,
+                );
                 last_lsn = lsn;
+                records += 1;
 
                 debug!("imported record at {} (end {})", lsn, end_lsn);
             }
@@ -477,7 +505,36 @@ pub async fn import_wal_from_tar(
         info!("skipping {:?}", file_path);
     }
 
-    Ok(())
+    Ok((records, record_lsns))
+}
+
+/// Sanity check a WAL segment before decoding it: it must be a whole segment, and
+/// start with a long page header of the timeline's PostgreSQL version.
+fn check_wal_segment(bytes: &[u8], pg_version: u32) -> Result<()> {
+    ensure!(
+        bytes.len() == WAL_SEGMENT_SIZE,
+        "segment is {} bytes, expected {WAL_SEGMENT_SIZE}",
+        bytes.len()
+    );
+    dispatch_pgversion!(
+        pg_version,
+        {
+            let hdr = pgv::bindings::XLogLongPageHeaderData::from_bytes(&mut &bytes[..])?;
+            let magic = pgv::bindings::XLOG_PAGE_MAGIC as u16;
+            ensure!(
+                hdr.std.xlp_magic == magic,
+                "bad page magic {:#06x}, expected {magic:#06x} for PostgreSQL {pg_version}",
+                hdr.std.xlp_magic
+            );
+            ensure!(
+                hdr.xlp_seg_size as usize == WAL_SEGMENT_SIZE,
+                "segment size in page header is {}, expected {WAL_SEGMENT_SIZE}",
+                hdr.xlp_seg_size
+            );
+            Ok(())
+        },
+        bail!("unknown PostgreSQL version {pg_version}")
+    )
 }
 
 async fn import_file(
@@ -1,26 +1,60 @@
 #![recursion_limit = "300"]
 #![deny(clippy::undocumented_unsafe_blocks)]
 
+/// Declare a failpoint that can use the `pause` failpoint action.
+/// We don't want to block the executor thread, hence, spawn_blocking + await.
+macro_rules! pausable_failpoint {
+    ($name:literal) => {
+        if cfg!(feature = "testing") {
+            tokio::task::spawn_blocking({
+                let current = tracing::Span::current();
+                move || {
+                    let _entered = current.entered();
+                    tracing::info!("at failpoint {}", $name);
+                    fail::fail_point!($name);
+                }
+            })
+            .await
+            .expect("spawn_blocking");
+        }
+    };
+    ($name:literal, $cond:expr) => {
+        if cfg!(feature = "testing") {
+            if $cond {
+                pausable_failpoint!($name)
+            }
+        }
+    };
+}
+
 mod auth;
 pub mod basebackup;
+pub mod build_info;
 pub mod config;
 pub mod consumption_metrics;
 pub mod context;
 pub mod control_plane_client;
+pub mod cpu_affinity;
 pub mod deletion_queue;
 pub mod disk_usage_eviction_task;
 pub mod http;
 pub mod import_datadir;
+pub mod mem_usage;
 pub use pageserver_api::keyspace;
 pub mod metrics;
+pub mod offline_check;
 pub mod page_cache;
+pub mod page_cache_warmup;
 pub mod page_service;
 pub mod pgdatadir_mapping;
+pub mod rel_history;
+pub mod replication_lag;
 pub mod repository;
 pub mod span;
 pub(crate) mod statvfs;
 pub mod task_mgr;
 pub mod tenant;
+pub mod timeline_check;
 pub mod trace;
 pub mod utilization;
 pub mod virtual_file;
@@ -43,6 +77,10 @@ use tracing::info;
 /// backwards-compatible changes to the metadata format.
 pub const STORAGE_FORMAT_VERSION: u16 = 3;
 
+/// Format version of delta layers with compressed values. Binaries that only know
+/// [`STORAGE_FORMAT_VERSION`] refuse these files instead of misreading the values.
+pub const COMPRESSED_DELTA_FORMAT_VERSION: u16 = 4;
+
 pub const DEFAULT_PG_VERSION: u32 = 15;
 
 // Magic constants used to identify different kinds of files
@@ -51,6 +89,20 @@ pub const DELTA_FILE_MAGIC: u16 = 0x5A61;
 
 static ZERO_PAGE: bytes::Bytes = bytes::Bytes::from_static(&[0u8; 8192]);
 
+/// Set once the initial load of tenants at startup has completed. Until then, the
+/// pageserver reports itself as not ready through both the `status` libpq command
+/// and the `/v1/status` HTTP endpoint, so that the control plane holds off on
+/// issuing commands.
+static STARTUP_READY: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+pub fn is_ready() -> bool {
+    STARTUP_READY.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+pub fn set_ready() {
+    STARTUP_READY.store(true, std::sync::atomic::Ordering::Relaxed);
+}
+
 pub use crate::metrics::preinitialize_metrics;
 
 #[tracing::instrument(skip_all, fields(%exit_code))]
@@ -0,0 +1,100 @@
+//! Breakdown of memory use by tenant, for the page service `mem_usage` command.
+//!
+//! On a pageserver shared by many tenants, the process RSS alone doesn't tell which
+//! tenant to look at when memory runs short. The report attributes to each tenant shard
+//! the materialized pages it holds in the page cache, the WAL buffered in the in-memory
+//! layers of its timelines, and the RSS of its walredo processes, next to the RSS of the
+//! pageserver process itself.
+//!
+//! The page cache counts of all tenants are taken at the same moment; the layers of each
+//! timeline are read under its layer map lock, one timeline after another.
+
+use pageserver_api::shard::TenantShardId;
+use serde::Serialize;
+use utils::id::TimelineId;
+
+use crate::page_cache;
+use crate::tenant::mgr;
+
+#[derive(Serialize)]
+pub struct MemUsage {
+    /// Resident set size of the pageserver process, where procfs is available.
+    pub process_rss_bytes: Option<u64>,
+    /// Size of the whole page cache, which is allocated up front.
+    pub page_cache_bytes: u64,
+    pub tenants: Vec<TenantMemUsage>,
+}
+
+#[derive(Serialize)]
+pub struct TenantMemUsage {
+    pub tenant_shard_id: TenantShardId,
+    /// Materialized pages of the tenant in the page cache. Cached pages of layer files
+    /// are not attributed to tenants.
+    pub page_cache_bytes: u64,
+    /// Resident set size of the tenant's walredo processes, where procfs is available.
+    pub walredo_rss_bytes: Option<u64>,
+    pub timelines: Vec<TimelineMemUsage>,
+}
+
+#[derive(Serialize)]
+pub struct TimelineMemUsage {
+    pub timeline_id: TimelineId,
+    /// WAL held in the timeline's open and frozen in-memory layers.
+    pub in_memory_layer_bytes: u64,
+}
+
+pub async fn collect() -> MemUsage {
+    let page_cache = page_cache::get();
+    let cached_pages = page_cache.materialized_pages_per_tenant();
+
+    let mut tenant_shards = mgr::attached_tenant_shards();
+    tenant_shards.sort_by_key(|tenant| tenant.tenant_shard_id());
+
+    let mut tenants = Vec::with_capacity(tenant_shards.len());
+    for tenant in tenant_shards {
+        let tenant_shard_id = tenant.tenant_shard_id();
+        let mut timelines = Vec::new();
+        for timeline in tenant.list_timelines() {
+            timelines.push(TimelineMemUsage {
+                timeline_id: timeline.timeline_id,
+                in_memory_layer_bytes: timeline.in_memory_layers_len().await,
+            });
+        }
+        timelines.sort_by_key(|timeline| timeline.timeline_id);
+
+        let walredo_rss_bytes = tenant
+            .wal_redo_process_ids()
+            .into_iter()
+            .map(|pid| rss_bytes(Some(pid)))
+            .sum();
+        tenants.push(TenantMemUsage {
+            tenant_shard_id,
+            page_cache_bytes: cached_pages.get(&tenant_shard_id).copied().unwrap_or(0) as u64
+                * page_cache::PAGE_SZ as u64,
+            walredo_rss_bytes,
+            timelines,
+        });
+    }
+
+    MemUsage {
+        process_rss_bytes: rss_bytes(None),
+        page_cache_bytes: page_cache.size_bytes(),
+        tenants,
+    }
+}
+
+/// RSS of the given process, or of the pageserver itself.
+#[cfg(target_os = "linux")]
+fn rss_bytes(pid: Option<u32>) -> Option<u64> {
+    let process = match pid {
+        Some(pid) => procfs::process::Process::new(pid as i32),
+        None => procfs::process::Process::myself(),
+    };
+    // VmRSS is reported in kB.
+    Some(process.ok()?.status().ok()?.vmrss? * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rss_bytes(_pid: Option<u32>) -> Option<u64> {
+    None
+}
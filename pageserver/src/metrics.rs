@@ -194,6 +194,9 @@ pub(crate) struct PageCacheMetricsForTaskKind {
     pub read_hits_immutable: IntCounter,
     pub read_hits_materialized_page_exact: IntCounter,
     pub read_hits_materialized_page_older_lsn: IntCounter,
+
+    pub read_misses_materialized_page: IntCounter,
+    pub read_misses_immutable: IntCounter,
 }
 
 pub(crate) struct PageCacheMetrics {
@@ -218,6 +221,15 @@ static PAGE_CACHE_READ_ACCESSES: Lazy<IntCounterVec> = Lazy::new(|| {
     .expect("failed to define a metric")
 });
 
+static PAGE_CACHE_READ_MISSES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_page_cache_read_misses_total",
+        "Number of read accesses to the page cache that missed",
+        &["task_kind", "key_kind", "content_kind"]
+    )
+    .expect("failed to define a metric")
+});
+
 pub(crate) static PAGE_CACHE: Lazy<PageCacheMetrics> = Lazy::new(|| PageCacheMetrics {
     map: EnumMap::from_array(std::array::from_fn(|task_kind| {
         let task_kind = <TaskKind as enum_map::Enum>::from_usize(task_kind);
@@ -269,6 +281,22 @@ pub(crate) static PAGE_CACHE: Lazy<PageCacheMetrics> = Lazy::new(|| PageCacheMet
                         ])
                         .unwrap()
                 },
+
+                read_misses_materialized_page: {
+                    PAGE_CACHE_READ_MISSES
+                        .get_metric_with_label_values(&[
+                            task_kind,
+                            "materialized_page",
+                            content_kind,
+                        ])
+                        .unwrap()
+                },
+
+                read_misses_immutable: {
+                    PAGE_CACHE_READ_MISSES
+                        .get_metric_with_label_values(&[task_kind, "immutable", content_kind])
+                        .unwrap()
+                },
             }
         }))
     })),
@@ -317,6 +345,24 @@ pub(crate) static PAGE_CACHE_SIZE: Lazy<PageCacheSizeMetrics> =
         },
     });
 
+static PAGE_CACHE_TENANT_READ_ACCESSES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_page_cache_tenant_read_accesses_total",
+        "Number of materialized page lookups in the page cache, by tenant shard",
+        &["tenant_id", "shard_id"]
+    )
+    .expect("failed to define a metric")
+});
+
+static PAGE_CACHE_TENANT_READ_HITS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "pageserver_page_cache_tenant_read_hits_total",
+        "Number of materialized page lookups in the page cache that hit, by tenant shard",
+        &["tenant_id", "shard_id"]
+    )
+    .expect("failed to define a metric")
+});
+
 pub(crate) mod page_cache_eviction_metrics {
     use std::num::NonZeroUsize;
 
@@ -707,6 +753,56 @@ pub(crate) static TIMELINE_EPHEMERAL_BYTES: Lazy<UIntGauge> = Lazy::new(|| {
     .expect("Failed to register metric")
 });
 
+/// Compression of the values of in-memory layers as they are flushed, see the
+/// `flush_compression` config option. The ratio of the byte counters tells how much
+/// IO the compression saves, the time how much CPU it costs.
+pub(crate) struct LayerFlushCompressionMetrics {
+    uncompressed_bytes: IntCounter,
+    compressed_bytes: IntCounter,
+    /// Compressed over uncompressed size, per flushed layer.
+    ratio: Histogram,
+    seconds: Histogram,
+}
+
+impl LayerFlushCompressionMetrics {
+    pub(crate) fn observe(&self, uncompressed_bytes: u64, compressed_bytes: u64, time: Duration) {
+        self.uncompressed_bytes.inc_by(uncompressed_bytes);
+        self.compressed_bytes.inc_by(compressed_bytes);
+        if uncompressed_bytes > 0 {
+            self.ratio
+                .observe(compressed_bytes as f64 / uncompressed_bytes as f64);
+        }
+        self.seconds.observe(time.as_secs_f64());
+    }
+}
+
+pub(crate) static LAYER_FLUSH_COMPRESSION: Lazy<LayerFlushCompressionMetrics> = Lazy::new(|| {
+    LayerFlushCompressionMetrics {
+        uncompressed_bytes: register_int_counter!(
+            "pageserver_layer_flush_compression_uncompressed_bytes_total",
+            "Size of the values of flushed in-memory layers before compression",
+        )
+        .expect("failed to define a metric"),
+        compressed_bytes: register_int_counter!(
+            "pageserver_layer_flush_compression_compressed_bytes_total",
+            "Size of the values of flushed in-memory layers after compression",
+        )
+        .expect("failed to define a metric"),
+        ratio: register_histogram!(
+            "pageserver_layer_flush_compression_ratio",
+            "Compressed size of the values of a flushed in-memory layer over their uncompressed size",
+            vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0],
+        )
+        .expect("failed to define a metric"),
+        seconds: register_histogram!(
+            "pageserver_layer_flush_compression_seconds",
+            "Time spent compressing the values of a flushed in-memory layer",
+            CRITICAL_OP_BUCKETS.into(),
+        )
+        .expect("failed to define a metric"),
+    }
+});
+
 /// Metrics related to the lifecycle of a [`crate::tenant::Tenant`] object: things
 /// like how long it took to load.
 ///
@@ -1290,6 +1386,15 @@ pub(crate) static BASEBACKUP_QUERY_TIME: Lazy<BasebackupQueryTime> = Lazy::new(|
     })
 });
 
+pub(crate) static BASEBACKUPS_IN_PROGRESS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "pageserver_basebackups_in_progress",
+        "Number of basebackups currently being sent, by tenant shard",
+        &["tenant_id", "shard_id"]
+    )
+    .expect("failed to define a metric")
+});
+
 pub(crate) struct BasebackupQueryTimeOngoingRecording<'a, 'c> {
     parent: &'a BasebackupQueryTime,
     ctx: &'c RequestContext,
@@ -1352,6 +1457,111 @@ impl<'a, 'c> BasebackupQueryTimeOngoingRecording<'a, 'c> {
     }
 }
 
+/// Point-in-time values of the GetPage and basebackup query counters.
+///
+/// Used by the testing-only `metrics_snapshot` page_service command, so that tests can
+/// assert on these counters without racing the asynchronous `/metrics` scrape.
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct QueryMetricsSnapshot {
+    /// Number of completed smgr queries, by query type.
+    smgr_query_count: std::collections::BTreeMap<&'static str, u64>,
+    /// Total time spent in smgr queries, by query type.
+    smgr_query_seconds_sum: std::collections::BTreeMap<&'static str, f64>,
+    /// Number of completed basebackup queries, by result.
+    basebackup_query_count: std::collections::BTreeMap<&'static str, u64>,
+}
+
+impl QueryMetricsSnapshot {
+    pub(crate) fn collect() -> Self {
+        let mut smgr_query_count = std::collections::BTreeMap::new();
+        let mut smgr_query_seconds_sum = std::collections::BTreeMap::new();
+        for op in SmgrQueryType::iter() {
+            let label: &'static str = op.into();
+            let histo = SMGR_QUERY_TIME_GLOBAL
+                .get_metric_with_label_values(&[label])
+                .unwrap();
+            smgr_query_count.insert(label, histo.get_sample_count());
+            smgr_query_seconds_sum.insert(label, histo.get_sample_sum());
+        }
+        let basebackup_query_count = ["ok", "error"]
+            .into_iter()
+            .map(|result| {
+                let histo = BASEBACKUP_QUERY_TIME
+                    .0
+                    .get_metric_with_label_values(&[result])
+                    .unwrap();
+                (result, histo.get_sample_count())
+            })
+            .collect();
+        Self {
+            smgr_query_count,
+            smgr_query_seconds_sum,
+            basebackup_query_count,
+        }
+    }
+}
+
+/// Zero the process-wide counters that tests take before/after deltas of, and return
+/// their values from before the reset, by their exported names.
+///
+/// Used by the testing-only `reset_metrics` page_service command. Gauges describe the
+/// current state, like occupancy, and are left alone, as are counters that only make
+/// sense as a pair, like the started and finished walredo stderr logger tasks.
+///
+/// Prometheus counters can't be swapped atomically, only read and then reset: an
+/// increment that lands in between is lost. Tests reset the counters of an otherwise
+/// idle pageserver, where that doesn't happen.
+pub(crate) fn reset_counters() -> std::collections::BTreeMap<String, u64> {
+    let counters: [&IntCounter; 16] = [
+        &MATERIALIZED_PAGE_CACHE_HIT_DIRECT,
+        &MATERIALIZED_PAGE_CACHE_HIT,
+        &REMOTE_ONDEMAND_DOWNLOADED_LAYERS,
+        &REMOTE_ONDEMAND_DOWNLOADED_BYTES,
+        &UNEXPECTED_ONDEMAND_DOWNLOADS,
+        &WALRECEIVER_STARTED_CONNECTIONS,
+        &WALRECEIVER_BROKER_UPDATES,
+        &WALRECEIVER_CANDIDATES_ADDED,
+        &WALRECEIVER_CANDIDATES_REMOVED,
+        &WAL_INGEST.records_received,
+        &WAL_INGEST.records_committed,
+        &WAL_INGEST.records_filtered,
+        &WAL_REDO_RECORD_COUNTER,
+        &WAL_REDO_PROCESS_COUNTERS.started,
+        &LAYER_FLUSH_COMPRESSION.uncompressed_bytes,
+        &LAYER_FLUSH_COMPRESSION.compressed_bytes,
+    ];
+    counters
+        .into_iter()
+        .map(|counter| {
+            let value = counter.get();
+            counter.reset();
+            (counter_name(counter), value)
+        })
+        .collect()
+}
+
+/// The name of `counter` as exported, followed by its label values if it belongs to a
+/// vec, e.g. `pageserver_walreceiver_candidates_events_total{event=add}`.
+fn counter_name(counter: &IntCounter) -> String {
+    use metrics::core::Collector;
+
+    let family = counter
+        .collect()
+        .pop()
+        .expect("a counter collects into one metric family");
+    let labels = family
+        .get_metric()
+        .iter()
+        .flat_map(|metric| metric.get_label())
+        .map(|label| format!("{}={}", label.get_name(), label.get_value()))
+        .collect::<Vec<_>>();
+    if labels.is_empty() {
+        family.get_name().to_owned()
+    } else {
+        format!("{}{{{}}}", family.get_name(), labels.join(","))
+    }
+}
+
 pub(crate) static LIVE_CONNECTIONS_COUNT: Lazy<IntGaugeVec> = Lazy::new(|| {
     register_int_gauge_vec!(
         "pageserver_live_connections",
@@ -1756,6 +1966,44 @@ pub(crate) static WAL_REDO_PROCESS_LAUNCH_DURATION_HISTOGRAM: Lazy<Histogram> =
     .expect("failed to define a metric")
 });
 
+static WAL_REDO_PROCESS_BUSY_SECONDS: Lazy<CounterVec> = Lazy::new(|| {
+    register_counter_vec!(
+        "pageserver_wal_redo_process_busy_seconds_total",
+        "Time spent applying WAL records, by the slot of the process in its tenant shard's pool",
+        &["tenant_id", "shard_id", "slot"]
+    )
+    .expect("failed to define a metric")
+});
+
+static WAL_REDO_PROCESS_REQUESTS_IN_FLIGHT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "pageserver_wal_redo_process_requests_in_flight",
+        "Number of WAL redo batches queued on or running in a process, by the slot of the process in its tenant shard's pool",
+        &["tenant_id", "shard_id", "slot"]
+    )
+    .expect("failed to define a metric")
+});
+
+/// Metrics of one slot of a tenant shard's WAL redo process pool, looked up once
+/// when the pool is created. Removed in [`remove_tenant_metrics`].
+pub(crate) struct WalRedoSlotMetrics {
+    pub(crate) busy_seconds: Counter,
+    pub(crate) requests_in_flight: IntGauge,
+}
+
+impl WalRedoSlotMetrics {
+    pub(crate) fn new(tenant_shard_id: &TenantShardId, slot_idx: usize) -> Self {
+        let tenant_id = tenant_shard_id.tenant_id.to_string();
+        let shard_id = tenant_shard_id.shard_slug().to_string();
+        let slot = slot_idx.to_string();
+        let labels = [tenant_id.as_str(), shard_id.as_str(), slot.as_str()];
+        WalRedoSlotMetrics {
+            busy_seconds: WAL_REDO_PROCESS_BUSY_SECONDS.with_label_values(&labels),
+            requests_in_flight: WAL_REDO_PROCESS_REQUESTS_IN_FLIGHT.with_label_values(&labels),
+        }
+    }
+}
+
 pub(crate) struct WalRedoProcessCounters {
     pub(crate) started: IntCounter,
     pub(crate) killed_by_cause: enum_map::EnumMap<WalRedoKillCause, IntCounter>,
@@ -1901,6 +2149,9 @@ pub(crate) struct TimelineMetrics {
     pub directory_entries_count_gauge: Lazy<UIntGauge, Box<dyn Send + Fn() -> UIntGauge>>,
     pub evictions: IntCounter,
     pub evictions_with_low_residence_duration: std::sync::RwLock<EvictionsWithLowResidenceDuration>,
+    /// Shared by all timelines of the tenant shard, removed in [`remove_tenant_metrics`].
+    pub page_cache_read_accesses: IntCounter,
+    pub page_cache_read_hits: IntCounter,
 }
 
 impl TimelineMetrics {
@@ -1985,6 +2236,12 @@ impl TimelineMetrics {
             .unwrap();
         let evictions_with_low_residence_duration = evictions_with_low_residence_duration_builder
             .build(&tenant_id, &shard_id, &timeline_id);
+        let page_cache_read_accesses = PAGE_CACHE_TENANT_READ_ACCESSES
+            .get_metric_with_label_values(&[&tenant_id, &shard_id])
+            .unwrap();
+        let page_cache_read_hits = PAGE_CACHE_TENANT_READ_HITS
+            .get_metric_with_label_values(&[&tenant_id, &shard_id])
+            .unwrap();
 
         TimelineMetrics {
             tenant_id,
@@ -2005,6 +2262,8 @@ impl TimelineMetrics {
             evictions_with_low_residence_duration: std::sync::RwLock::new(
                 evictions_with_low_residence_duration,
             ),
+            page_cache_read_accesses,
+            page_cache_read_hits,
         }
     }
 
@@ -2088,6 +2347,24 @@ pub(crate) fn remove_tenant_metrics(tenant_shard_id: &TenantShardId) {
         let _ = TENANT_SYNTHETIC_SIZE_METRIC.remove_label_values(&[&tid]);
     }
 
+    let tenant_id = tenant_shard_id.tenant_id.to_string();
+    let shard_id = tenant_shard_id.shard_slug().to_string();
+    let _ = PAGE_CACHE_TENANT_READ_ACCESSES.remove_label_values(&[&tenant_id, &shard_id]);
+    let _ = PAGE_CACHE_TENANT_READ_HITS.remove_label_values(&[&tenant_id, &shard_id]);
+    let _ = BASEBACKUPS_IN_PROGRESS.remove_label_values(&[&tenant_id, &shard_id]);
+    // The slots of the WAL redo pool are all registered when it is created.
+    for slot_idx in 0usize.. {
+        let slot = slot_idx.to_string();
+        let labels = [tenant_id.as_str(), shard_id.as_str(), slot.as_str()];
+        let _ = WAL_REDO_PROCESS_REQUESTS_IN_FLIGHT.remove_label_values(&labels);
+        if WAL_REDO_PROCESS_BUSY_SECONDS
+            .remove_label_values(&labels)
+            .is_err()
+        {
+            break;
+        }
+    }
+
     // we leave the BROKEN_TENANTS_SET entry if any
 }
 
@@ -2473,7 +2750,7 @@ impl<F: Future<Output = Result<O, E>>, O, E> Future for MeasuredRemoteOp<F> {
 }
 
 pub mod tokio_epoll_uring {
-    use metrics::{register_int_counter, UIntGauge};
+    use metrics::{register_histogram, register_int_counter, Histogram, UIntGauge};
     use once_cell::sync::Lazy;
 
     pub struct Collector {
@@ -2553,6 +2830,15 @@ pub mod tokio_epoll_uring {
         )
         .unwrap()
     });
+
+    pub(crate) static THREAD_LOCAL_FIRST_LAUNCH_DELAY: Lazy<Histogram> = Lazy::new(|| {
+        register_histogram!(
+            "pageserver_tokio_epoll_uring_pageserver_thread_local_first_launch_delay_seconds",
+            "Time from process start until a thread's thread_local_system was first launched successfully.",
+            vec![0.01, 0.1, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0],
+        )
+        .unwrap()
+    });
 }
 
 pub(crate) mod tenant_throttling {
@@ -2689,6 +2975,8 @@ pub fn preinitialize_metrics() {
         Lazy::force(c);
     });
 
+    Lazy::force(&tokio_epoll_uring::THREAD_LOCAL_FIRST_LAUNCH_DELAY);
+
     // Deletion queue stats
     Lazy::force(&DELETION_QUEUE);
 
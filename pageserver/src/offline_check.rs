@@ -0,0 +1,308 @@
+//! Offline consistency check of the pageserver data directory, `pageserver --check`.
+//!
+//! Walks the tenants directory and reports every problem found, instead of stopping at
+//! the first one like tenant loading would: tenant configs that don't parse, files that
+//! don't belong in a timeline directory, and layer sets whose LSN ranges don't fit
+//! together. Nothing is modified, neither locally nor in remote storage.
+//!
+//! The local directory only caches layers, and timeline metadata lives in the remote
+//! `index_part.json`. With remote storage configured, the layer ranges are therefore
+//! checked against the index, and the local files against the sizes it records;
+//! without it, only the layers present locally can be checked.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use pageserver_api::key::Key;
+use pageserver_api::shard::TenantShardId;
+use remote_storage::{DownloadError, GenericRemoteStorage};
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+use utils::generation::Generation;
+use utils::id::TimelineId;
+
+use crate::config::PageServerConf;
+use crate::tenant::config::LocationMode;
+use crate::tenant::ephemeral_file::is_ephemeral_file;
+use crate::tenant::remote_timeline_client::{self, index::IndexPart};
+use crate::tenant::storage_layer::LayerFileName;
+use crate::tenant::Tenant;
+use crate::timeline_check::find_lsn_gaps;
+use crate::{
+    is_delete_mark, is_temporary, is_uninit_mark, IGNORED_TENANT_FILE_NAME, METADATA_FILE_NAME,
+};
+
+#[derive(Debug)]
+pub struct Problem {
+    pub path: Utf8PathBuf,
+    pub description: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.description)
+    }
+}
+
+#[derive(Default)]
+struct Problems(Vec<Problem>);
+
+impl Problems {
+    fn report(&mut self, path: &Utf8Path, description: impl Into<String>) {
+        self.0.push(Problem {
+            path: path.to_owned(),
+            description: description.into(),
+        });
+    }
+}
+
+/// Check all tenants in the data directory of `conf`. Errors are returned only if the
+/// directory itself cannot be read; everything wrong with its contents is a [`Problem`].
+pub async fn check_data_dir(
+    conf: &'static PageServerConf,
+    remote_storage: Option<&GenericRemoteStorage>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<Problem>> {
+    let mut problems = Problems::default();
+
+    let tenants_path = conf.tenants_path();
+    if !tenants_path.exists() {
+        return Ok(Vec::new());
+    }
+    for tenant_path in sorted_dir_entries(&tenants_path)? {
+        // Temporary directories are left over from interrupted operations and removed
+        // at startup; ignored tenants are not loaded at all.
+        if is_temporary(&tenant_path) || tenant_path.join(IGNORED_TENANT_FILE_NAME).exists() {
+            continue;
+        }
+        let Ok(tenant_shard_id) = TenantShardId::from_str(tenant_path.file_name().unwrap_or(""))
+        else {
+            problems.report(&tenant_path, "not a tenant directory");
+            continue;
+        };
+        check_tenant(conf, tenant_shard_id, remote_storage, cancel, &mut problems).await?;
+    }
+    Ok(problems.0)
+}
+
+async fn check_tenant(
+    conf: &'static PageServerConf,
+    tenant_shard_id: TenantShardId,
+    remote_storage: Option<&GenericRemoteStorage>,
+    cancel: &CancellationToken,
+    problems: &mut Problems,
+) -> anyhow::Result<()> {
+    // Secondary locations have no generation to look up the index with.
+    let generation = match Tenant::load_tenant_config(conf, &tenant_shard_id) {
+        Ok(location_conf) => match location_conf.mode {
+            LocationMode::Attached(attached) => Some(attached.generation),
+            LocationMode::Secondary(_) => None,
+        },
+        Err(e) => {
+            problems.report(
+                &conf.tenant_path(&tenant_shard_id),
+                format!("cannot load tenant config: {e:#}"),
+            );
+            None
+        }
+    };
+
+    let timelines_path = conf.timelines_path(&tenant_shard_id);
+    let mut timeline_ids = Vec::new();
+    if timelines_path.exists() {
+        for path in sorted_dir_entries(&timelines_path)? {
+            // Marks of timelines being created or deleted, and unfinished initdb uploads
+            // are dealt with at startup.
+            if is_temporary(&path) || is_uninit_mark(&path) || is_delete_mark(&path) {
+                continue;
+            }
+            match TimelineId::from_str(path.file_name().unwrap_or("")) {
+                Ok(timeline_id) => timeline_ids.push(timeline_id),
+                Err(_) if path.is_file() => {}
+                Err(_) => problems.report(&path, "not a timeline directory"),
+            }
+        }
+    }
+
+    let all_timelines = timeline_ids.iter().copied().collect::<HashSet<_>>();
+    for timeline_id in timeline_ids {
+        let span = tracing::info_span!(
+            "check_timeline",
+            tenant_id = %tenant_shard_id.tenant_id,
+            shard_id = %tenant_shard_id.shard_slug(),
+            %timeline_id
+        );
+        let index = match (remote_storage, generation) {
+            (Some(storage), Some(generation)) => {
+                download_index(storage, tenant_shard_id, timeline_id, generation, cancel)
+                    .instrument(span)
+                    .await
+            }
+            _ => Ok(None),
+        };
+        let timeline_path = conf.timeline_path(&tenant_shard_id, &timeline_id);
+        let index = index.unwrap_or_else(|e| {
+            problems.report(&timeline_path, format!("cannot download index: {e}"));
+            None
+        });
+        check_timeline_dir(&timeline_path, index.as_ref(), &all_timelines, problems)?;
+    }
+    Ok(())
+}
+
+async fn download_index(
+    storage: &GenericRemoteStorage,
+    tenant_shard_id: TenantShardId,
+    timeline_id: TimelineId,
+    generation: Generation,
+    cancel: &CancellationToken,
+) -> Result<Option<IndexPart>, DownloadError> {
+    match remote_timeline_client::download_index_part(
+        storage,
+        &tenant_shard_id,
+        &timeline_id,
+        generation,
+        cancel,
+    )
+    .await
+    {
+        Ok(index) => Ok(Some(index)),
+        // Not uploaded yet: the timeline is still being created.
+        Err(DownloadError::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn check_timeline_dir(
+    timeline_path: &Utf8Path,
+    index: Option<&IndexPart>,
+    all_timelines: &HashSet<TimelineId>,
+    problems: &mut Problems,
+) -> anyhow::Result<()> {
+    let mut local_layers = BTreeMap::new();
+    for path in sorted_dir_entries(timeline_path)? {
+        let file_name = path.file_name().unwrap_or("");
+        if let Ok(layer) = LayerFileName::from_str(file_name) {
+            let size = path
+                .metadata()
+                .with_context(|| format!("stat {path}"))?
+                .len();
+            local_layers.insert(layer.file_name(), (layer, size));
+        } else if !(file_name == METADATA_FILE_NAME
+            || file_name.ends_with(".old")
+            || is_temporary(&path)
+            || remote_timeline_client::is_temp_download_file(&path)
+            || is_ephemeral_file(file_name))
+        {
+            problems.report(&path, "unknown file in timeline directory");
+        }
+    }
+
+    let Some(index) = index else {
+        check_layer_ranges(
+            timeline_path,
+            local_layers.values().map(|(layer, _)| layer),
+            problems,
+        );
+        return Ok(());
+    };
+
+    for (layer, metadata) in &index.layer_metadata {
+        if let Some((_, local_size)) = local_layers.get(&layer.file_name()) {
+            if *local_size != metadata.file_size {
+                problems.report(
+                    &timeline_path.join(layer.file_name()),
+                    format!(
+                        "size {local_size} differs from {} in the remote index",
+                        metadata.file_size
+                    ),
+                );
+            }
+        }
+    }
+
+    let metadata = &index.metadata;
+    if let Some(ancestor) = metadata.ancestor_timeline() {
+        if !all_timelines.contains(&ancestor) {
+            problems.report(
+                timeline_path,
+                format!("ancestor timeline {ancestor} is missing"),
+            );
+        }
+    }
+    let disk_consistent_lsn = metadata.disk_consistent_lsn();
+    for layer in index.layer_metadata.keys() {
+        if layer.is_in_future(disk_consistent_lsn) {
+            problems.report(
+                &timeline_path.join(layer.file_name()),
+                format!("layer is beyond the disk consistent LSN {disk_consistent_lsn}"),
+            );
+        }
+    }
+    let deltas = index.layer_metadata.keys().filter_map(|layer| match layer {
+        LayerFileName::Delta(delta) => Some(delta.lsn_range.clone()),
+        LayerFileName::Image(_) => None,
+    });
+    for gap in find_lsn_gaps(deltas, metadata.latest_gc_cutoff_lsn()) {
+        problems.report(
+            timeline_path,
+            format!(
+                "no delta layer covers LSNs {}..{} above the GC cutoff",
+                gap.start, gap.end
+            ),
+        );
+    }
+    check_layer_ranges(timeline_path, index.layer_metadata.keys(), problems);
+    Ok(())
+}
+
+/// Checks that hold for any subset of a timeline's layers: ranges are not empty, and
+/// L0 delta layers, which cover the whole key space, don't overlap each other.
+fn check_layer_ranges<'a>(
+    timeline_path: &Utf8Path,
+    layers: impl Iterator<Item = &'a LayerFileName>,
+    problems: &mut Problems,
+) {
+    let mut l0_deltas = Vec::new();
+    for layer in layers {
+        let (key_range, lsn_range) = match layer {
+            LayerFileName::Delta(delta) => (&delta.key_range, delta.lsn_range.clone()),
+            LayerFileName::Image(image) => (&image.key_range, image.lsn_as_range()),
+        };
+        if key_range.is_empty() || lsn_range.is_empty() {
+            problems.report(
+                &timeline_path.join(layer.file_name()),
+                "empty key or LSN range",
+            );
+        } else if *key_range == (Key::MIN..Key::MAX) && matches!(layer, LayerFileName::Delta(_)) {
+            l0_deltas.push((lsn_range, layer));
+        }
+    }
+
+    l0_deltas.sort_by_key(|(lsn_range, _)| lsn_range.start);
+    for pair in l0_deltas.windows(2) {
+        let ((prev_range, prev), (range, layer)) = (&pair[0], &pair[1]);
+        if range.start < prev_range.end {
+            problems.report(
+                &timeline_path.join(layer.file_name()),
+                format!("L0 layer overlaps {prev}"),
+            );
+        }
+    }
+}
+
+fn sorted_dir_entries(path: &Utf8Path) -> anyhow::Result<Vec<Utf8PathBuf>> {
+    let mut entries = path
+        .read_dir_utf8()
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|e| e.into_path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .with_context(|| format!("read directory {path}"))?;
+    entries.sort();
+    Ok(entries)
+}
@@ -83,7 +83,12 @@ use std::{
 use anyhow::Context;
 use once_cell::sync::OnceCell;
 use pageserver_api::shard::TenantShardId;
-use utils::{id::TimelineId, lsn::Lsn};
+use sysinfo::SystemExt;
+use tracing::{error, info, warn};
+use utils::{
+    id::{TenantId, TimelineId},
+    lsn::Lsn,
+};
 
 use crate::{
     context::RequestContext,
@@ -97,8 +102,26 @@ const TEST_PAGE_CACHE_SIZE: usize = 50;
 ///
 /// Initialize the page cache. This must be called once at page server startup.
 ///
-pub fn init(size: usize) {
-    if PAGE_CACHE.set(PageCache::new(size)).is_err() {
+pub fn init(size: usize, tenant_max_pages: Option<usize>) {
+    // The cache is allocated up front, so a size beyond what the machine has fails
+    // slowly, through swapping or the OOM killer, rather than at startup.
+    let system_memory =
+        sysinfo::System::new_with_specifics(sysinfo::RefreshKind::new().with_memory())
+            .total_memory();
+    let cache_bytes = size as u64 * PAGE_SZ as u64;
+    if system_memory > 0 && cache_bytes > system_memory / 2 {
+        warn!(
+            "page cache size of {} MiB is more than half of the system memory ({} MiB)",
+            cache_bytes / (1024 * 1024),
+            system_memory / (1024 * 1024)
+        );
+    }
+    info!("initializing page cache with {size} pages");
+
+    if PAGE_CACHE
+        .set(PageCache::new(size, tenant_max_pages))
+        .is_err()
+    {
         panic!("page cache already initialized");
     }
 }
@@ -113,7 +136,7 @@ pub fn get() -> &'static PageCache {
     // page cache is usable in unit tests.
     //
     if cfg!(test) {
-        PAGE_CACHE.get_or_init(|| PageCache::new(TEST_PAGE_CACHE_SIZE))
+        PAGE_CACHE.get_or_init(|| PageCache::new(TEST_PAGE_CACHE_SIZE, None))
     } else {
         PAGE_CACHE.get().expect("page cache not initialized")
     }
@@ -168,6 +191,49 @@ struct Version {
     slot_idx: usize,
 }
 
+/// The slots holding materialized pages of one tenant shard, so that a tenant shard at
+/// its limit can find a victim among its own pages without sweeping the whole cache.
+#[derive(Default)]
+struct TenantSlots {
+    /// In no particular order; the number of pages of the tenant shard in the cache.
+    slots: Vec<usize>,
+    /// Position of each slot in `slots`.
+    positions: HashMap<usize, usize>,
+    /// Index into `slots` of the next candidate to evict, for the Clock replacement
+    /// algorithm. This is interpreted modulo the number of slots.
+    next_evict: usize,
+}
+
+impl TenantSlots {
+    fn insert(&mut self, slot_idx: usize) {
+        if let Entry::Vacant(entry) = self.positions.entry(slot_idx) {
+            entry.insert(self.slots.len());
+            self.slots.push(slot_idx);
+        }
+    }
+
+    /// Returns false if the slot wasn't one of the tenant shard's.
+    fn remove(&mut self, slot_idx: usize) -> bool {
+        let Some(pos) = self.positions.remove(&slot_idx) else {
+            return false;
+        };
+        self.slots.swap_remove(pos);
+        if let Some(&moved) = self.slots.get(pos) {
+            self.positions.insert(moved, pos);
+        }
+        true
+    }
+
+    fn next_candidate(&mut self) -> Option<usize> {
+        if self.slots.is_empty() {
+            return None;
+        }
+        let slot_idx = self.slots[self.next_evict % self.slots.len()];
+        self.next_evict = self.next_evict.wrapping_add(1);
+        Some(slot_idx)
+    }
+}
+
 struct Slot {
     inner: tokio::sync::RwLock<SlotInner>,
     usage_count: AtomicU8,
@@ -258,6 +324,14 @@ pub struct PageCache {
     /// This is interpreted modulo the page cache size.
     next_evict_slot: AtomicUsize,
 
+    /// The slots of the materialized pages in the cache, per tenant shard. Maintained
+    /// together with `materialized_page_map`.
+    materialized_pages_per_tenant: std::sync::Mutex<HashMap<TenantShardId, TenantSlots>>,
+
+    /// If set, a tenant shard that has this many materialized pages in the cache can
+    /// only make room for a new page by evicting one of its own.
+    tenant_max_pages: Option<usize>,
+
     size_metrics: &'static PageCacheSizeMetrics,
 }
 
@@ -432,6 +506,10 @@ impl PageCache {
                 panic!("unexpected key type in slot");
             }
         } else {
+            crate::metrics::PAGE_CACHE
+                .for_ctx(ctx)
+                .read_misses_materialized_page
+                .inc();
             None
         }
     }
@@ -484,11 +562,18 @@ impl PageCache {
             }
             debug_assert!(permit.is_some());
 
-            // Not found. Find a victim buffer
-            let (slot_idx, mut inner) = self
-                .find_victim(permit.as_ref().unwrap())
-                .await
-                .context("Failed to find evict victim")?;
+            // Not found. Find a victim buffer. A tenant at its limit has to give up one
+            // of its own pages; if none of them can be evicted right now, don't cache.
+            let (slot_idx, mut inner) = if self.tenant_at_limit(&tenant_shard_id) {
+                match self.find_victim_of_tenant(&tenant_shard_id) {
+                    Some(victim) => victim,
+                    None => return Ok(()),
+                }
+            } else {
+                self.find_victim(permit.as_ref().unwrap())
+                    .await
+                    .context("Failed to find evict victim")?
+            };
 
             // Insert mapping for this. At this point, we may find that another
             // thread did the same thing concurrently. In that case, we evicted
@@ -529,6 +614,38 @@ impl PageCache {
         }
     }
 
+    /// Drop all materialized pages of all shards of `tenant_id` from the cache, returning
+    /// how many were evicted. The tenant itself stays loaded.
+    ///
+    /// Each slot is write-locked before its key is checked and cleared, so this waits for
+    /// readers that currently hold one of the tenant's pages, and readers that looked up a
+    /// slot just before it was cleared notice the changed key and treat it as a miss.
+    pub async fn evict_tenant(&self, tenant_id: TenantId) -> usize {
+        let mut evicted = 0;
+        for slot in self.slots.iter() {
+            // Cheap check first, to avoid write-locking the slots of other tenants.
+            if let Ok(inner) = slot.inner.try_read() {
+                if !matches!(&inner.key, Some(CacheKey::MaterializedPage { hash_key, .. })
+                    if hash_key.tenant_shard_id.tenant_id == tenant_id)
+                {
+                    continue;
+                }
+            }
+            let mut inner = slot.inner.write().await;
+            let Some(old_key @ CacheKey::MaterializedPage { hash_key, .. }) = &inner.key else {
+                continue;
+            };
+            if hash_key.tenant_shard_id.tenant_id != tenant_id {
+                continue;
+            }
+            self.remove_mapping(old_key);
+            inner.key = None;
+            slot.set_usage_count(0);
+            evicted += 1;
+        }
+        evicted
+    }
+
     // Section 1.2: Public interface functions for working with immutable file pages.
 
     pub async fn read_immutable_buf(
@@ -642,7 +759,7 @@ impl PageCache {
     ) -> anyhow::Result<ReadBufResult> {
         let mut permit = Some(self.try_get_pinned_slot_permit().await?);
 
-        let (read_access, hit) = match cache_key {
+        let (read_access, hit, miss) = match cache_key {
             CacheKey::MaterializedPage { .. } => {
                 unreachable!("Materialized pages use lookup_materialized_page")
             }
@@ -651,6 +768,9 @@ impl PageCache {
                     .for_ctx(ctx)
                     .read_accesses_immutable,
                 &crate::metrics::PAGE_CACHE.for_ctx(ctx).read_hits_immutable,
+                &crate::metrics::PAGE_CACHE
+                    .for_ctx(ctx)
+                    .read_misses_immutable,
             ),
         };
         read_access.inc();
@@ -666,6 +786,9 @@ impl PageCache {
                 return Ok(ReadBufResult::Found(read_guard));
             }
             debug_assert!(permit.is_some());
+            if is_first_iteration {
+                miss.inc();
+            }
             is_first_iteration = false;
 
             // Not found. Find a victim buffer
@@ -784,10 +907,14 @@ impl PageCache {
                     let versions = old_entry.get_mut();
 
                     if let Ok(version_idx) = versions.binary_search_by_key(old_lsn, |v| v.lsn) {
-                        versions.remove(version_idx);
+                        let old_version = versions.remove(version_idx);
                         self.size_metrics
                             .current_bytes_materialized_page
                             .sub_page_sz(1);
+                        self.remove_tenant_slot(
+                            &old_hash_key.tenant_shard_id,
+                            old_version.slot_idx,
+                        );
                         if versions.is_empty() {
                             old_entry.remove_entry();
                         }
@@ -831,6 +958,12 @@ impl PageCache {
                         self.size_metrics
                             .current_bytes_materialized_page
                             .add_page_sz(1);
+                        self.materialized_pages_per_tenant
+                            .lock()
+                            .unwrap()
+                            .entry(new_key.tenant_shard_id)
+                            .or_default()
+                            .insert(slot_idx);
                         None
                     }
                 }
@@ -850,6 +983,60 @@ impl PageCache {
         }
     }
 
+    fn remove_tenant_slot(&self, tenant_shard_id: &TenantShardId, slot_idx: usize) {
+        let mut tenants = self.materialized_pages_per_tenant.lock().unwrap();
+        let removed = match tenants.entry(*tenant_shard_id) {
+            Entry::Occupied(mut entry) => {
+                let removed = entry.get_mut().remove(slot_idx);
+                if entry.get().slots.is_empty() {
+                    entry.remove();
+                }
+                removed
+            }
+            Entry::Vacant(_) => false,
+        };
+        debug_assert!(
+            removed,
+            "materialized pages of {tenant_shard_id} are out of sync with the mapping"
+        );
+        if !removed {
+            // Only the per-tenant limit relies on this, so don't take the pageserver down.
+            error!("materialized pages of {tenant_shard_id} are out of sync with the mapping, slot {slot_idx} was not tracked");
+        }
+    }
+
+    /// Number of materialized pages of the given tenant shard currently in the cache.
+    pub fn materialized_pages_of_tenant(&self, tenant_shard_id: &TenantShardId) -> usize {
+        self.materialized_pages_per_tenant
+            .lock()
+            .unwrap()
+            .get(tenant_shard_id)
+            .map_or(0, |tenant| tenant.slots.len())
+    }
+
+    /// Number of materialized pages of every tenant shard in the cache, all taken at
+    /// the same moment.
+    pub fn materialized_pages_per_tenant(&self) -> HashMap<TenantShardId, usize> {
+        self.materialized_pages_per_tenant
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(tenant_shard_id, tenant)| (*tenant_shard_id, tenant.slots.len()))
+            .collect()
+    }
+
+    /// Size of the cache in bytes, which is allocated up front.
+    pub fn size_bytes(&self) -> u64 {
+        self.slots.len() as u64 * PAGE_SZ as u64
+    }
+
+    fn tenant_at_limit(&self, tenant_shard_id: &TenantShardId) -> bool {
+        match self.tenant_max_pages {
+            Some(max) => self.materialized_pages_of_tenant(tenant_shard_id) >= max,
+            None => false,
+        }
+    }
+
     //
     // Section 4: Misc internal helpers
     //
@@ -935,10 +1122,50 @@ impl PageCache {
         }
     }
 
+    /// Find a slot to evict among the materialized pages of the given tenant shard.
+    ///
+    /// Unlike [`Self::find_victim`], this does not wait for anything: slots that are
+    /// locked are skipped, and after two sweeps over the tenant shard's pages it gives
+    /// up and returns None. On success, the slot is empty and write-locked.
+    fn find_victim_of_tenant(
+        &self,
+        tenant_shard_id: &TenantShardId,
+    ) -> Option<(usize, tokio::sync::RwLockWriteGuard<SlotInner>)> {
+        let sweep_limit = self.materialized_pages_of_tenant(tenant_shard_id) * 2;
+        for i in 0..sweep_limit {
+            // Don't hold the lock over the eviction, which updates the tenant's slots.
+            let slot_idx = self
+                .materialized_pages_per_tenant
+                .lock()
+                .unwrap()
+                .get_mut(tenant_shard_id)
+                .and_then(TenantSlots::next_candidate)?;
+            let slot = &self.slots[slot_idx];
+            let Ok(mut inner) = slot.inner.try_write() else {
+                continue;
+            };
+            let Some(old_key @ CacheKey::MaterializedPage { hash_key, .. }) = &inner.key else {
+                continue;
+            };
+            if hash_key.tenant_shard_id != *tenant_shard_id || slot.dec_usage_count() != 0 {
+                continue;
+            }
+            self.remove_mapping(old_key);
+            inner.key = None;
+            page_cache_eviction_metrics::observe(
+                page_cache_eviction_metrics::Outcome::FoundSlotEvicted {
+                    iters: (i + 1).try_into().unwrap(),
+                },
+            );
+            return Some((slot_idx, inner));
+        }
+        None
+    }
+
     /// Initialize a new page cache
     ///
     /// This should be called only once at page server startup.
-    fn new(num_pages: usize) -> Self {
+    fn new(num_pages: usize, tenant_max_pages: Option<usize>) -> Self {
         assert!(num_pages > 0, "page cache size must be > 0");
 
         // We could use Vec::leak here, but that potentially also leaks
@@ -972,6 +1199,8 @@ impl PageCache {
             immutable_page_map: Default::default(),
             slots,
             next_evict_slot: AtomicUsize::new(0),
+            materialized_pages_per_tenant: Default::default(),
+            tenant_max_pages,
             size_metrics,
             pinned_slots: Arc::new(tokio::sync::Semaphore::new(num_pages)),
         }
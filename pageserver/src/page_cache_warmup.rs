@@ -0,0 +1,178 @@
+//! Pre-loading of a relation's pages into the page cache.
+//!
+//! After a restart the page cache is empty and the first GetPage requests of every
+//! compute pay for layer reads and walredo. The page service `warmup` command lets the
+//! control plane read the hottest relations before it directs compute traffic to the
+//! pageserver; it returns a warmup id right away, and `warmup_status` reports progress.
+//!
+//! Warmups run on the background runtime, one at a time, reading one page after another
+//! and yielding in between, so that they take no more than a single reader's share away
+//! from live GetPage traffic.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+use pageserver_api::key::rel_block_to_key;
+use pageserver_api::reltag::RelTag;
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+use utils::id::TenantId;
+use utils::lsn::Lsn;
+
+use crate::context::{DownloadBehavior, RequestContext};
+use crate::pgdatadir_mapping::Version;
+use crate::task_mgr::{self, TaskKind, BACKGROUND_RUNTIME};
+use crate::tenant::Timeline;
+
+/// Finished warmups are forgotten once this many newer ones have been started.
+const MAX_TRACKED_WARMUPS: u64 = 128;
+
+static NEXT_WARMUP_ID: AtomicU64 = AtomicU64::new(1);
+
+static WARMUPS: Lazy<Mutex<BTreeMap<u64, Arc<WarmupProgress>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Only one warmup reads pages at a time, the others wait in `Queued` state.
+static WARMUP_PERMITS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(1));
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WarmupState {
+    Queued,
+    Running,
+    Done,
+    Failed(String),
+}
+
+impl WarmupState {
+    pub fn as_str(&self) -> &str {
+        match self {
+            WarmupState::Queued => "queued",
+            WarmupState::Running => "running",
+            WarmupState::Done => "done",
+            WarmupState::Failed(_) => "failed",
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(self, WarmupState::Done | WarmupState::Failed(_))
+    }
+}
+
+pub struct WarmupProgress {
+    /// Tenant of the warmed up timeline, whose token may check on the progress.
+    pub tenant_id: TenantId,
+    pub rel: RelTag,
+    pub lsn: Lsn,
+    total_blocks: AtomicU32,
+    warmed_blocks: AtomicU32,
+    state: Mutex<WarmupState>,
+}
+
+impl WarmupProgress {
+    pub fn state(&self) -> WarmupState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Number of blocks in the relation, known once the warmup is running.
+    pub fn total_blocks(&self) -> u32 {
+        self.total_blocks.load(Ordering::Relaxed)
+    }
+
+    pub fn warmed_blocks(&self) -> u32 {
+        self.warmed_blocks.load(Ordering::Relaxed)
+    }
+
+    fn set_state(&self, state: WarmupState) {
+        *self.state.lock().unwrap() = state;
+    }
+}
+
+/// Start warming up the pages of `rel` as of `lsn` in the background. Returns the id
+/// to pass to [`progress`].
+pub fn start(timeline: Arc<Timeline>, rel: RelTag, lsn: Lsn) -> u64 {
+    let id = NEXT_WARMUP_ID.fetch_add(1, Ordering::Relaxed);
+    let progress = Arc::new(WarmupProgress {
+        tenant_id: timeline.tenant_shard_id.tenant_id,
+        rel,
+        lsn,
+        total_blocks: AtomicU32::new(0),
+        warmed_blocks: AtomicU32::new(0),
+        state: Mutex::new(WarmupState::Queued),
+    });
+
+    {
+        let mut warmups = WARMUPS.lock().unwrap();
+        warmups.retain(|old_id, p| id - old_id < MAX_TRACKED_WARMUPS || !p.state().is_finished());
+        warmups.insert(id, Arc::clone(&progress));
+    }
+
+    let ctx = RequestContext::new(TaskKind::PageCacheWarmup, DownloadBehavior::Download);
+    task_mgr::spawn(
+        BACKGROUND_RUNTIME.handle(),
+        TaskKind::PageCacheWarmup,
+        Some(timeline.tenant_shard_id),
+        Some(timeline.timeline_id),
+        &format!("page cache warmup {id}"),
+        false,
+        async move {
+            let state = match warmup(&timeline, &progress, &ctx).await {
+                Ok(()) => {
+                    info!(
+                        "page cache warmup {id} of {rel} at {lsn} done, {} blocks",
+                        progress.warmed_blocks()
+                    );
+                    WarmupState::Done
+                }
+                Err(e) => {
+                    warn!("page cache warmup {id} of {rel} at {lsn} failed: {e:#}");
+                    WarmupState::Failed(format!("{e:#}"))
+                }
+            };
+            progress.set_state(state);
+            Ok(())
+        },
+    );
+
+    id
+}
+
+/// Progress of a warmup started with [`start`], if it is still tracked.
+pub fn progress(id: u64) -> Option<Arc<WarmupProgress>> {
+    WARMUPS.lock().unwrap().get(&id).cloned()
+}
+
+async fn warmup(
+    timeline: &Timeline,
+    progress: &WarmupProgress,
+    ctx: &RequestContext,
+) -> anyhow::Result<()> {
+    let _permit = tokio::select! {
+        permit = WARMUP_PERMITS.acquire() => permit.expect("semaphore is never closed"),
+        _ = task_mgr::shutdown_watcher() => anyhow::bail!("cancelled"),
+    };
+    progress.set_state(WarmupState::Running);
+
+    let version = Version::Lsn(progress.lsn);
+    let nblocks = timeline
+        .get_rel_size(progress.rel, version, false, ctx)
+        .await?;
+    progress.total_blocks.store(nblocks, Ordering::Relaxed);
+
+    let shard = timeline.get_shard_identity();
+    for blknum in 0..nblocks {
+        if task_mgr::is_shutdown_requested() {
+            anyhow::bail!("cancelled");
+        }
+        // Blocks stored on other shards cannot be cached here.
+        if shard.is_key_local(&rel_block_to_key(progress.rel, blknum)) {
+            timeline
+                .get_rel_page_at_lsn(progress.rel, blknum, version, false, ctx)
+                .await?;
+        }
+        progress.warmed_blocks.fetch_add(1, Ordering::Relaxed);
+        tokio::task::yield_now().await;
+    }
+    Ok(())
+}
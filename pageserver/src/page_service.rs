@@ -4,15 +4,28 @@
 //
 //   It is possible to connect here using usual psql/pgbench/libpq. Following
 // commands are supported now:
-//     *status* -- show actual info about this pageserver,
+//     *status* -- show node id and whether the initial tenant load has completed,
+//     *build_info* -- show the version, git commit and build time of the pageserver,
 //     *pagestream* -- enter mode where smgr and pageserver talk with their
 //  custom protocol.
+//     *pagestream_v2* -- like pagestream, but each request names the timeline of
+//  the tenant that it reads from.
+//     *warmup*, *warmup_status* -- load a relation into the page cache in the
+//  background, and check on its progress.
+//     *evict_tenant* -- drop the cached pages of a tenant from the page cache.
+//     *layer_list* -- list the layer files of a timeline as JSON.
+//     *switch_walsource* -- move a timeline's WAL stream to another safekeeper.
+//     *get_controlfile* -- show the decoded pg_control of a timeline as JSON.
+//     *check_timeline* -- read a sample of a timeline's pages at its last LSN and
+//  look for holes in its layers' LSN coverage.
+//     *do_compact* -- run compaction on a timeline now (testing only).
 //
 
 use anyhow::Context;
 use async_compression::tokio::write::GzipEncoder;
 use bytes::Buf;
 use bytes::Bytes;
+use enumset::EnumSet;
 use futures::stream::FuturesUnordered;
 use futures::Stream;
 use futures::StreamExt;
@@ -23,22 +36,23 @@ use pageserver_api::models::{
     PagestreamErrorResponse, PagestreamExistsRequest, PagestreamExistsResponse,
     PagestreamFeMessage, PagestreamGetPageRequest, PagestreamGetPageResponse,
     PagestreamGetSlruSegmentRequest, PagestreamGetSlruSegmentResponse, PagestreamNblocksRequest,
-    PagestreamNblocksResponse,
+    PagestreamNblocksResponse, PagestreamProtocolVersion, UnknownPagestreamTag,
 };
 use pageserver_api::shard::ShardIndex;
 use pageserver_api::shard::ShardNumber;
 use postgres_backend::{is_expected_io_error, AuthType, PostgresBackend, QueryError};
 use pq_proto::framed::ConnectionError;
 use pq_proto::FeStartupPacket;
-use pq_proto::{BeMessage, FeMessage, RowDescriptor};
+use pq_proto::{BeMessage, CancelKeyData, FeMessage, RowDescriptor, SQLSTATE_QUERY_CANCELED};
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::io;
-use std::net::TcpListener;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::os::unix::net::UnixListener;
 use std::pin::pin;
 use std::str;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use std::time::Duration;
 use tokio::io::AsyncWriteExt;
 use tokio::io::{AsyncRead, AsyncWrite};
@@ -59,27 +73,39 @@ use crate::basebackup;
 use crate::config::PageServerConf;
 use crate::context::{DownloadBehavior, RequestContext};
 use crate::import_datadir::import_wal_from_tar;
+use crate::mem_usage;
 use crate::metrics;
 use crate::metrics::LIVE_CONNECTIONS_COUNT;
+use crate::page_cache;
+use crate::page_cache_warmup;
 use crate::pgdatadir_mapping::Version;
+use crate::rel_history;
+use crate::replication_lag;
 use crate::span::debug_assert_current_span_has_tenant_and_timeline_id;
 use crate::span::debug_assert_current_span_has_tenant_and_timeline_id_no_shard_id;
 use crate::task_mgr;
 use crate::task_mgr::TaskKind;
+use crate::tenant::config::GcSettings;
 use crate::tenant::mgr;
 use crate::tenant::mgr::get_active_tenant_with_timeout;
 use crate::tenant::mgr::GetActiveTenantError;
 use crate::tenant::mgr::ShardSelector;
-use crate::tenant::timeline::WaitLsnError;
+use crate::tenant::storage_layer::PersistentLayerDesc;
+use crate::tenant::timeline::{CompactionError, PinLsnError, WaitLsnError};
 use crate::tenant::GetTimelineError;
 use crate::tenant::PageReconstructError;
+use crate::tenant::Tenant;
 use crate::tenant::Timeline;
+use crate::timeline_check;
 use crate::trace::Tracer;
 use pageserver_api::key::rel_block_to_key;
+use pageserver_api::reltag::RelTag;
 use pageserver_api::reltag::SlruKind;
 use postgres_ffi::pg_constants::DEFAULTTABLESPACE_OID;
 use postgres_ffi::BLCKSZ;
 
+mod cancel;
+
 // How long we may wait for a [`TenantSlot::InProgress`]` and/or a [`Tenant`] which
 // is not yet in state [`TenantState::Active`].
 const ACTIVE_TENANT_TIMEOUT: Duration = Duration::from_millis(30000);
@@ -136,22 +162,38 @@ async fn read_tar_eof(mut reader: (impl AsyncRead + Unpin)) -> anyhow::Result<()
 
 ///////////////////////////////////////////////////////////////////////////////
 
+/// A page service connection, accepted on the TCP listener or on the Unix socket.
+enum PageServiceSocket {
+    Tcp(tokio::net::TcpStream),
+    Unix(tokio::net::UnixStream),
+}
+
 ///
 /// Main loop of the page service.
 ///
-/// Listens for connections, and launches a new handler task for each.
+/// Listens for connections, on the TCP listener and on the Unix socket if there is
+/// one, and launches a new handler task for each.
 ///
+#[allow(clippy::too_many_arguments)]
 pub async fn libpq_listener_main(
     conf: &'static PageServerConf,
     broker_client: storage_broker::BrokerClientChannel,
     auth: Option<Arc<SwappableJwtAuth>>,
     listener: TcpListener,
+    unix_listener: Option<UnixListener>,
     auth_type: AuthType,
     listener_ctx: RequestContext,
     cancel: CancellationToken,
 ) -> anyhow::Result<()> {
     listener.set_nonblocking(true)?;
     let tokio_listener = tokio::net::TcpListener::from_std(listener)?;
+    let unix_listener = match unix_listener {
+        Some(listener) => {
+            listener.set_nonblocking(true)?;
+            Some(tokio::net::UnixListener::from_std(listener)?)
+        }
+        None => None,
+    };
 
     // Wait for a new connection to arrive, or for server shutdown.
     while let Some(res) = tokio::select! {
@@ -163,13 +205,22 @@ pub async fn libpq_listener_main(
         }
 
         res = tokio_listener.accept() => {
-            Some(res)
+            Some(res.map(|(socket, peer_addr)| {
+                debug!("accepted connection from {}", peer_addr);
+                PageServiceSocket::Tcp(socket)
+            }))
+        }
+
+        res = accept_unix(unix_listener.as_ref()) => {
+            Some(res.map(|socket| {
+                debug!("accepted connection on the unix socket");
+                PageServiceSocket::Unix(socket)
+            }))
         }
     } {
         match res {
-            Ok((socket, peer_addr)) => {
+            Ok(socket) => {
                 // Connection established. Spawn a new task to handle it.
-                debug!("accepted connection from {}", peer_addr);
                 let local_auth = auth.clone();
 
                 let connection_ctx = listener_ctx
@@ -208,12 +259,58 @@ pub async fn libpq_listener_main(
     Ok(())
 }
 
-#[instrument(skip_all, fields(peer_addr))]
+/// Accepts a connection on the Unix socket listener, or waits forever if there is none.
+async fn accept_unix(
+    listener: Option<&tokio::net::UnixListener>,
+) -> io::Result<tokio::net::UnixStream> {
+    match listener {
+        Some(listener) => listener.accept().await.map(|(socket, _)| socket),
+        None => std::future::pending().await,
+    }
+}
+
+/// Applies the configured SO_SNDBUF/SO_RCVBUF to an accepted connection. Failures only
+/// cost throughput, so they are logged and otherwise ignored.
+fn set_socket_buffer_sizes(conf: &PageServerConf, socket: &tokio::net::TcpStream) {
+    use nix::sys::socket::sockopt;
+
+    if let Some(size) = conf.page_service_socket_send_buffer {
+        set_socket_buffer_size(socket, sockopt::SndBuf, size, "send");
+    }
+    if let Some(size) = conf.page_service_socket_recv_buffer {
+        set_socket_buffer_size(socket, sockopt::RcvBuf, size, "receive");
+    }
+}
+
+fn set_socket_buffer_size<O>(socket: &tokio::net::TcpStream, opt: O, size: usize, what: &str)
+where
+    O: nix::sys::socket::SetSockOpt<Val = usize> + nix::sys::socket::GetSockOpt<Val = usize> + Copy,
+{
+    use nix::sys::socket::{getsockopt, setsockopt};
+
+    match setsockopt(socket, opt, &size).and_then(|()| getsockopt(socket, opt)) {
+        Ok(actual) => {
+            // Linux doubles the requested size to leave room for bookkeeping, after
+            // clamping it to net.core.{w,r}mem_max.
+            let granted = if cfg!(target_os = "linux") {
+                actual / 2
+            } else {
+                actual
+            };
+            if granted < size {
+                warn!("requested a socket {what} buffer of {size} bytes, the OS granted {granted}");
+            }
+        }
+        Err(e) => warn!("failed to set socket {what} buffer size to {size}: {e}"),
+    }
+}
+
+#[instrument(skip_all, fields(peer_addr, application_name))]
 async fn page_service_conn_main(
     conf: &'static PageServerConf,
     broker_client: storage_broker::BrokerClientChannel,
     auth: Option<Arc<SwappableJwtAuth>>,
-    socket: tokio::net::TcpStream,
+    socket: PageServiceSocket,
     auth_type: AuthType,
     connection_ctx: RequestContext,
 ) -> anyhow::Result<()> {
@@ -226,13 +323,63 @@ async fn page_service_conn_main(
         gauge.dec();
     }
 
-    socket
-        .set_nodelay(true)
-        .context("could not set TCP_NODELAY")?;
-
-    let peer_addr = socket.peer_addr().context("get peer address")?;
-    tracing::Span::current().record("peer_addr", field::display(peer_addr));
+    match socket {
+        PageServiceSocket::Tcp(socket) => {
+            // The peer may reset the connection between accept() and the setup below. That
+            // is the client's business, so just drop this one connection without an error.
+            let setup = socket.set_nodelay(true).and_then(|()| socket.peer_addr());
+            let peer_addr = match setup {
+                Ok(peer_addr) => peer_addr,
+                Err(e) if is_expected_io_error(&e) || e.kind() == io::ErrorKind::NotConnected => {
+                    info!("connection closed by peer during setup: {e}");
+                    return Ok(());
+                }
+                Err(e) => return Err(e).context("could not set up connection socket"),
+            };
+            tracing::Span::current().record("peer_addr", field::display(peer_addr));
+            set_socket_buffer_sizes(conf, &socket);
+            serve_connection(
+                conf,
+                broker_client,
+                auth,
+                socket,
+                peer_addr,
+                auth_type,
+                connection_ctx,
+            )
+            .await
+        }
+        PageServiceSocket::Unix(socket) => {
+            tracing::Span::current().record("peer_addr", "unix");
+            // PostgresBackend wants an IP address, and unix socket peers have none.
+            let peer_addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0));
+            serve_connection(
+                conf,
+                broker_client,
+                auth,
+                socket,
+                peer_addr,
+                auth_type,
+                connection_ctx,
+            )
+            .await
+        }
+    }
+}
 
+/// Runs the libpq protocol on an accepted connection, whatever its transport.
+async fn serve_connection<IO>(
+    conf: &'static PageServerConf,
+    broker_client: storage_broker::BrokerClientChannel,
+    auth: Option<Arc<SwappableJwtAuth>>,
+    socket: IO,
+    peer_addr: SocketAddr,
+    auth_type: AuthType,
+    connection_ctx: RequestContext,
+) -> anyhow::Result<()>
+where
+    IO: AsyncRead + AsyncWrite + Send + Sync,
+{
     // setup read timeout of 10 minutes. the timeout is rather arbitrary for requirements:
     // - long enough for most valid compute connections
     // - less than infinite to stop us from "leaking" connections to long-gone computes
@@ -268,7 +415,8 @@ async fn page_service_conn_main(
     // But it's in a shared crate, so, we store connection_ctx inside PageServerHandler
     // and create the per-query context in process_query ourselves.
     let mut conn_handler = PageServerHandler::new(conf, broker_client, auth, connection_ctx);
-    let pgbackend = PostgresBackend::new_from_io(socket, peer_addr, auth_type, None)?;
+    let mut pgbackend = PostgresBackend::new_from_io(socket, peer_addr, auth_type, None)?;
+    pgbackend.set_max_message_len(conf.page_service_max_message_size)?;
 
     match pgbackend
         .run(&mut conn_handler, task_mgr::shutdown_watcher)
@@ -298,7 +446,7 @@ struct HandlerTimeline {
 }
 
 struct PageServerHandler {
-    _conf: &'static PageServerConf,
+    conf: &'static PageServerConf,
     broker_client: storage_broker::BrokerClientChannel,
     auth: Option<Arc<SwappableJwtAuth>>,
     claims: Option<Claims>,
@@ -316,6 +464,94 @@ struct PageServerHandler {
     /// or the ratio used when splitting shards (i.e. how many children created from one)
     /// parent shard, where a "large" number might be ~8.
     shard_timelines: HashMap<ShardIndex, HandlerTimeline>,
+
+    /// The timeline that `shard_timelines` holds the shards of. A `pagestream_v2`
+    /// connection reads from several timelines; the shards of the others are kept in
+    /// `parked_shard_timelines`, see [`Self::switch_cached_timeline`].
+    cached_timeline_id: Option<TimelineId>,
+    /// Parked shards don't hold their gate, so that a connection only ever keeps the gates
+    /// of one timeline open, however many it has read from.
+    parked_shard_timelines: HashMap<TimelineId, Vec<Weak<Timeline>>>,
+
+    /// Upper bound on the time spent serving one pagestream request, set by the client
+    /// with `SET statement_timeout`.
+    statement_timeout: Option<Duration>,
+
+    /// Log every pagestream message of this connection at debug level, turned on by the
+    /// client with `SET pagestream_trace = on`.
+    pagestream_trace: bool,
+
+    /// Lets the client cancel the running query with a CancelRequest.
+    cancel_key: Option<cancel::CancelKey>,
+}
+
+/// Failure of a page service command, reported to the client with a stable SQLSTATE
+/// code so that it can tell the different cases apart.
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum PageServiceError {
+    #[error("{0}")]
+    TenantNotFound(Cow<'static, str>),
+
+    #[error("{0}")]
+    TimelineNotFound(Cow<'static, str>),
+
+    /// The requested LSN is below the GC cutoff
+    #[error("{0}")]
+    LsnTooOld(Cow<'static, str>),
+
+    #[error("{0}")]
+    AuthFailed(Cow<'static, str>),
+
+    /// Wrong number of parameters, unparseable parameters and the like
+    #[error("{0}")]
+    BadRequest(Cow<'static, str>),
+
+    /// Turned away to shed load; the client should retry later
+    #[error("{0}")]
+    Overloaded(Cow<'static, str>),
+
+    /// The requested LSN hasn't arrived within `wait_lsn_timeout`; the client should retry
+    #[error(transparent)]
+    LsnTimeout(WaitLsnError),
+
+    /// Anything else, reported as XX000 like all errors were before the other variants
+    /// had codes of their own
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl PageServiceError {
+    pub(crate) fn pg_error_code(&self) -> &'static [u8; 5] {
+        match self {
+            // Class NP is ours: postgres leaves classes starting with other letters
+            // than A-H to implementations.
+            Self::TenantNotFound(_) => b"NP001",
+            Self::TimelineNotFound(_) => b"NP002",
+            Self::LsnTooOld(_) => b"NP003",
+            Self::AuthFailed(_) => b"28000", // invalid authorization specification
+            Self::BadRequest(_) => b"22023", // invalid parameter value
+            Self::Overloaded(_) => b"53300", // too many connections
+            Self::LsnTimeout(_) => b"NP004",
+            Self::Internal(_) => SQLSTATE_INTERNAL_ERROR,
+        }
+    }
+
+    fn bad_request(e: anyhow::Error) -> Self {
+        Self::BadRequest(format!("{e:#}").into())
+    }
+}
+
+impl From<PageServiceError> for QueryError {
+    fn from(e: PageServiceError) -> Self {
+        match e {
+            PageServiceError::AuthFailed(reason) => QueryError::Unauthorized(reason),
+            PageServiceError::Internal(e) => QueryError::Other(e),
+            e => QueryError::Coded {
+                code: e.pg_error_code(),
+                message: e.to_string().into(),
+            },
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -345,6 +581,15 @@ enum PageStreamError {
     /// Request asked for something that doesn't make sense, like an invalid LSN
     #[error("Bad request: {0}")]
     BadRequest(Cow<'static, str>),
+
+    /// The request took longer than the client's `statement_timeout`
+    #[error("canceling statement due to statement timeout of {0:?}")]
+    StatementTimeout(Duration),
+
+    /// The timeline of the request is being deleted: unlike on shutdown, the client
+    /// gets an answer, as reconnecting won't bring the timeline back.
+    #[error("timeline {0} is being deleted")]
+    TimelineDeleted(TimelineId),
 }
 
 impl From<PageReconstructError> for PageStreamError {
@@ -379,13 +624,308 @@ impl From<WaitLsnError> for PageStreamError {
 impl From<WaitLsnError> for QueryError {
     fn from(value: WaitLsnError) -> Self {
         match value {
-            e @ WaitLsnError::Timeout(_) => Self::Other(anyhow::Error::new(e)),
+            e @ WaitLsnError::Timeout(_) => PageServiceError::LsnTimeout(e).into(),
             WaitLsnError::Shutdown => Self::Shutdown,
             WaitLsnError::BadState => Self::Reconnect,
         }
     }
 }
 
+/// Run one pagestream request handler, giving up on it after `timeout`.
+///
+/// Dropping the handler future at an await point is fine: the read path holds no
+/// state across requests, and WAL redo yields between batches of records (each batch
+/// is bounded by `wal_redo_timeout` on its own), so a request stuck in a long redo is
+/// also abandoned at the next batch boundary.
+async fn with_statement_timeout<T>(
+    timeout: Option<Duration>,
+    handler: impl std::future::Future<Output = Result<T, PageStreamError>>,
+) -> Result<T, PageStreamError> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, handler)
+            .await
+            .unwrap_or(Err(PageStreamError::StatementTimeout(timeout))),
+        None => handler.await,
+    }
+}
+
+/// The name and value of a `SET [SESSION] <name> {TO | =} <value>` query.
+fn parse_set(query: &str) -> Option<(&str, &str)> {
+    fn strip_word<'a>(s: &'a str, word: &str) -> Option<&'a str> {
+        let head = s.get(..word.len())?;
+        head.eq_ignore_ascii_case(word)
+            .then(|| s[word.len()..].trim_start())
+    }
+
+    let rest = strip_word(query.trim().trim_end_matches(';'), "set ")?;
+    let rest = strip_word(rest, "session ").unwrap_or(rest);
+    let (name, rest) = rest.split_at(rest.find(|c: char| c.is_whitespace() || c == '=')?);
+    let rest = rest.trim_start();
+    let value = match rest.strip_prefix('=') {
+        Some(value) => value,
+        None => strip_word(rest, "to ")?,
+    };
+    Some((name, value.trim().trim_matches(|c| c == '\'' || c == '"')))
+}
+
+/// Parse a statement_timeout value the way postgres does: a number of milliseconds,
+/// optionally with a unit. Zero and `default` turn the timeout off.
+fn parse_statement_timeout(value: &str) -> anyhow::Result<Option<Duration>> {
+    if value.eq_ignore_ascii_case("default") {
+        return Ok(None);
+    }
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .with_context(|| format!("not a number: {value:?}"))?;
+    let timeout = match unit.trim() {
+        "" | "ms" => Duration::from_millis(amount),
+        "s" => Duration::from_secs(amount),
+        "min" => Duration::from_secs(amount * 60),
+        "h" => Duration::from_secs(amount * 60 * 60),
+        unit => anyhow::bail!("unknown unit {unit:?}, expected one of ms, s, min, h"),
+    };
+    Ok((!timeout.is_zero()).then_some(timeout))
+}
+
+/// Parse a boolean setting the way postgres does. `default` turns it off.
+fn parse_bool_setting(value: &str) -> anyhow::Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" | "default" => Ok(false),
+        _ => anyhow::bail!("not a boolean: {value:?}"),
+    }
+}
+
+/// What a client needs to be allowed to run a command, when auth is enabled.
+#[derive(Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum CommandPermission {
+    /// Anyone who connected.
+    None,
+    /// A token for the tenant named in the arguments, or an admin token.
+    Tenant,
+    /// An admin token.
+    Admin,
+}
+
+/// Entry of the `help` command's listing.
+#[derive(serde::Serialize)]
+struct CommandHelp {
+    name: &'static str,
+    args: &'static str,
+    permission: CommandPermission,
+    description: &'static str,
+    /// Only available in builds with the `testing` feature, and not listed otherwise.
+    #[serde(skip)]
+    testing: bool,
+}
+
+/// The commands of [`PageServerHandler::process_query`]. Keep in sync when adding one:
+/// clients use the listing to find out what a pageserver build supports.
+const COMMANDS: &[CommandHelp] = {
+    use CommandPermission::*;
+    const fn cmd(
+        name: &'static str,
+        args: &'static str,
+        permission: CommandPermission,
+        description: &'static str,
+    ) -> CommandHelp {
+        CommandHelp {
+            name,
+            args,
+            permission,
+            description,
+            testing: false,
+        }
+    }
+    const fn testing(command: CommandHelp) -> CommandHelp {
+        CommandHelp {
+            testing: true,
+            ..command
+        }
+    }
+    &[
+        cmd("help", "", None, "List the supported commands as JSON"),
+        cmd(
+            "pagestream",
+            "<tenant_id> <timeline_id>",
+            Tenant,
+            "Serve GetPage requests over COPY BOTH",
+        ),
+        cmd(
+            "pagestream_v2",
+            "<tenant_id> <timeline_id>",
+            Tenant,
+            "Like pagestream, but each request names the timeline of the tenant it reads from",
+        ),
+        cmd(
+            "basebackup",
+            "<tenant_id> <timeline_id> [lsn] [--gzip]",
+            Tenant,
+            "Stream a basebackup tarball; NP003 if it outlasts the LSN lease TTL",
+        ),
+        cmd(
+            "get_last_record_rlsn",
+            "<tenant_id> <timeline_id>",
+            Tenant,
+            "Previous and last record LSN of a timeline",
+        ),
+        cmd(
+            "fullbackup",
+            "<tenant_id> <timeline_id> [lsn] [prev_lsn]",
+            Tenant,
+            "Stream a basebackup tarball with relation data; NP003 if it outlasts the LSN lease TTL",
+        ),
+        cmd(
+            "import basebackup",
+            "<tenant_id> <timeline_id> <base_lsn> <end_lsn> <pg_version>",
+            Tenant,
+            "Create a timeline from a basebackup tarball sent with COPY IN",
+        ),
+        cmd(
+            "import wal",
+            "<tenant_id> <timeline_id> <start_lsn> <end_lsn>",
+            Tenant,
+            "Import a tarball of WAL segments sent with COPY IN",
+        ),
+        cmd(
+            "warmup",
+            "<tenant_id> <timeline_id> <rel> [lsn]",
+            Tenant,
+            "Start loading a relation into the page cache",
+        ),
+        cmd(
+            "warmup_status",
+            "<warmup_id>",
+            Tenant,
+            "Progress of a page cache warmup",
+        ),
+        cmd(
+            "evict_tenant",
+            "<tenant_id>",
+            Tenant,
+            "Drop the pages of a tenant from the page cache",
+        ),
+        cmd("status", "", None, "Node id and whether startup has completed"),
+        cmd("build_info", "", None, "Version of the pageserver build"),
+        testing(cmd(
+            "metrics_snapshot",
+            "",
+            Admin,
+            "GetPage and basebackup query counters as JSON",
+        )),
+        testing(cmd(
+            "reset_metrics",
+            "",
+            Admin,
+            "Zero the resettable counters, returning their previous values",
+        )),
+        cmd(
+            "mem_usage",
+            "",
+            Admin,
+            "Memory use of the process and of every tenant shard as JSON",
+        ),
+        testing(cmd(
+            "do_compact",
+            "<tenant_id> <timeline_id>",
+            Tenant,
+            "Run one compaction iteration of a timeline",
+        )),
+        cmd(
+            "layer_list",
+            "<tenant_id> <timeline_id>",
+            Tenant,
+            "Layer files of a timeline as JSON",
+        ),
+        cmd(
+            "check_timeline",
+            "<tenant_id> <timeline_id>",
+            Tenant,
+            "Check that a timeline can serve pages at its latest LSN",
+        ),
+        cmd(
+            "rel_history",
+            "<tenant_id> <timeline_id> <rel>",
+            Tenant,
+            "LSNs at which a relation was created, truncated or dropped, as JSON",
+        ),
+        cmd(
+            "switch_walsource",
+            "<tenant_id> <timeline_id> <safekeeper_connstr>",
+            Tenant,
+            "Stream WAL of a timeline from the given safekeeper",
+        ),
+        cmd(
+            "replication_lag",
+            "<tenant_id> <timeline_id>",
+            Tenant,
+            "How far a timeline is behind the WAL committed on the safekeepers, as JSON",
+        ),
+        cmd(
+            "get_controlfile",
+            "<tenant_id> <timeline_id> [lsn]",
+            Tenant,
+            "Decoded pg_control and checkpoint of a timeline as JSON",
+        ),
+        cmd(
+            "pin_lsn",
+            "<tenant_id> <timeline_id> <lsn>",
+            Tenant,
+            "Hold back GC at an LSN with an expiring lease; unsharded tenants only",
+        ),
+        cmd(
+            "unpin_lsn",
+            "<tenant_id> <timeline_id> <lease_id>",
+            Tenant,
+            "Release a lease taken with pin_lsn",
+        ),
+        cmd(
+            "set",
+            "<name> {TO | =} <value>",
+            None,
+            "Set statement_timeout or pagestream_trace for the connection; other settings are ignored",
+        ),
+        cmd(
+            "set_gc_config",
+            "<gc_horizon> <gc_period> | default",
+            Admin,
+            "Override the GC settings of tenants that don't set their own",
+        ),
+        cmd(
+            "show",
+            "<tenant_id>",
+            Tenant,
+            "Effective configuration of a tenant",
+        ),
+    ]
+};
+
+/// The decoded fields of a pagestream response, for `pagestream_trace`. Page and
+/// segment contents are only described by their length.
+fn describe_pagestream_response(msg: &PagestreamBeMessage) -> String {
+    match msg {
+        PagestreamBeMessage::Exists(resp) => format!("{resp:?}"),
+        PagestreamBeMessage::Nblocks(resp) => format!("{resp:?}"),
+        PagestreamBeMessage::GetPage(resp) => {
+            format!(
+                "PagestreamGetPageResponse {{ page: {} bytes }}",
+                resp.page.len()
+            )
+        }
+        PagestreamBeMessage::Error(resp) => format!("{resp:?}"),
+        PagestreamBeMessage::DbSize(resp) => format!("{resp:?}"),
+        PagestreamBeMessage::GetSlruSegment(resp) => format!(
+            "PagestreamGetSlruSegmentResponse {{ segment: {} bytes }}",
+            resp.segment.len()
+        ),
+    }
+}
+
 impl PageServerHandler {
     pub fn new(
         conf: &'static PageServerConf,
@@ -394,12 +934,17 @@ impl PageServerHandler {
         connection_ctx: RequestContext,
     ) -> Self {
         PageServerHandler {
-            _conf: conf,
+            conf,
             broker_client,
             auth,
             claims: None,
             connection_ctx,
             shard_timelines: HashMap::new(),
+            cached_timeline_id: None,
+            parked_shard_timelines: HashMap::new(),
+            statement_timeout: None,
+            pagestream_trace: false,
+            cancel_key: None,
         }
     }
 
@@ -545,12 +1090,13 @@ impl PageServerHandler {
         }
     }
 
-    #[instrument(skip_all)]
+    #[instrument(skip_all, fields(req_timeline_id))]
     async fn handle_pagerequests<IO>(
         &mut self,
         pgb: &mut PostgresBackend<IO>,
         tenant_id: TenantId,
         timeline_id: TimelineId,
+        protocol_version: PagestreamProtocolVersion,
         ctx: RequestContext,
     ) -> Result<(), QueryError>
     where
@@ -587,6 +1133,11 @@ impl PageServerHandler {
                 biased;
 
                 _ = self.await_connection_cancelled() => {
+                    // A timeline that is being deleted is let go of, so that the deletion
+                    // doesn't wait for this connection; its next request gets an error.
+                    if !task_mgr::is_shutdown_requested() && self.drop_deleted_timelines() {
+                        continue;
+                    }
                     // We were requested to shut down.
                     info!("shutdown request received in page handler");
                     return Err(QueryError::Shutdown)
@@ -608,32 +1159,95 @@ impl PageServerHandler {
 
             trace!("query: {copy_data_bytes:?}");
 
-            // Trace request if needed
+            // Trace request if needed. The trace tools read version 1 messages, so the
+            // timeline id of a version 2 request is left out.
             if let Some(t) = tracer.as_mut() {
-                t.trace(&copy_data_bytes)
+                match protocol_version {
+                    PagestreamProtocolVersion::V1 => t.trace(&copy_data_bytes),
+                    PagestreamProtocolVersion::V2 if copy_data_bytes.len() >= 16 => {
+                        t.trace(&copy_data_bytes.slice(16..))
+                    }
+                    PagestreamProtocolVersion::V2 => {}
+                }
             }
 
-            let neon_fe_msg = PagestreamFeMessage::parse(&mut copy_data_bytes.reader())?;
+            let parsed = match protocol_version {
+                PagestreamProtocolVersion::V1 => {
+                    PagestreamFeMessage::parse(&mut copy_data_bytes.reader())
+                        .map(|msg| (timeline_id, msg))
+                }
+                PagestreamProtocolVersion::V2 => {
+                    PagestreamFeMessage::parse_v2(&mut copy_data_bytes.reader())
+                }
+            };
+            let (req_timeline_id, neon_fe_msg) = match parsed {
+                Ok(parsed) => parsed,
+                Err(e) => match e.downcast_ref::<UnknownPagestreamTag>() {
+                    // A compute speaking a newer protocol version than us: answer this
+                    // request with an error, but keep serving the ones we understand.
+                    Some(UnknownPagestreamTag(tag)) => {
+                        warn!("unknown pagestream message tag {tag}, replying with an error");
+                        let response_msg = PagestreamBeMessage::Error(PagestreamErrorResponse {
+                            message: e.to_string(),
+                        });
+                        self.write_pagestream_response(pgb, &response_msg)?;
+                        self.flush_cancellable(pgb, &tenant.cancel).await?;
+                        continue;
+                    }
+                    None => return Err(QueryError::Other(e)),
+                },
+            };
+            if protocol_version == PagestreamProtocolVersion::V2 {
+                tracing::Span::current().record("req_timeline_id", field::display(req_timeline_id));
+            }
+            if self.pagestream_trace {
+                debug!(
+                    "pagestream request ({} bytes): {neon_fe_msg:?}",
+                    copy_data_bytes.len()
+                );
+            }
+            // Each request looks up its timeline anew, so one that doesn't exist or isn't
+            // active gets an error response like a missing page would.
+            self.switch_cached_timeline(req_timeline_id);
+            if self
+                .shard_timelines
+                .values()
+                .any(|ht| ht.timeline.cancel.is_cancelled())
+            {
+                self.drop_deleted_timelines();
+            }
 
             // TODO: We could create a new per-request context here, with unique ID.
             // Currently we use the same per-timeline context for all requests
 
+            let statement_timeout = self.statement_timeout;
             let (response, span) = match neon_fe_msg {
                 PagestreamFeMessage::Exists(req) => {
                     let span = tracing::info_span!("handle_get_rel_exists_request", rel = %req.rel, req_lsn = %req.lsn);
                     (
-                        self.handle_get_rel_exists_request(tenant_id, timeline_id, &req, &ctx)
-                            .instrument(span.clone())
-                            .await,
+                        with_statement_timeout(
+                            statement_timeout,
+                            self.handle_get_rel_exists_request(
+                                tenant_id,
+                                req_timeline_id,
+                                &req,
+                                &ctx,
+                            )
+                            .instrument(span.clone()),
+                        )
+                        .await,
                         span,
                     )
                 }
                 PagestreamFeMessage::Nblocks(req) => {
                     let span = tracing::info_span!("handle_get_nblocks_request", rel = %req.rel, req_lsn = %req.lsn);
                     (
-                        self.handle_get_nblocks_request(tenant_id, timeline_id, &req, &ctx)
-                            .instrument(span.clone())
-                            .await,
+                        with_statement_timeout(
+                            statement_timeout,
+                            self.handle_get_nblocks_request(tenant_id, req_timeline_id, &req, &ctx)
+                                .instrument(span.clone()),
+                        )
+                        .await,
                         span,
                     )
                 }
@@ -641,32 +1255,66 @@ impl PageServerHandler {
                     // shard_id is filled in by the handler
                     let span = tracing::info_span!("handle_get_page_at_lsn_request", rel = %req.rel, blkno = %req.blkno, req_lsn = %req.lsn);
                     (
-                        self.handle_get_page_at_lsn_request(tenant_id, timeline_id, &req, &ctx)
-                            .instrument(span.clone())
-                            .await,
+                        with_statement_timeout(
+                            statement_timeout,
+                            self.handle_get_page_at_lsn_request(
+                                tenant_id,
+                                req_timeline_id,
+                                &req,
+                                &ctx,
+                            )
+                            .instrument(span.clone()),
+                        )
+                        .await,
                         span,
                     )
                 }
                 PagestreamFeMessage::DbSize(req) => {
                     let span = tracing::info_span!("handle_db_size_request", dbnode = %req.dbnode, req_lsn = %req.lsn);
                     (
-                        self.handle_db_size_request(tenant_id, timeline_id, &req, &ctx)
-                            .instrument(span.clone())
-                            .await,
+                        with_statement_timeout(
+                            statement_timeout,
+                            self.handle_db_size_request(tenant_id, req_timeline_id, &req, &ctx)
+                                .instrument(span.clone()),
+                        )
+                        .await,
                         span,
                     )
                 }
                 PagestreamFeMessage::GetSlruSegment(req) => {
                     let span = tracing::info_span!("handle_get_slru_segment_request", kind = %req.kind, segno = %req.segno, req_lsn = %req.lsn);
                     (
-                        self.handle_get_slru_segment_request(tenant_id, timeline_id, &req, &ctx)
-                            .instrument(span.clone())
-                            .await,
+                        with_statement_timeout(
+                            statement_timeout,
+                            self.handle_get_slru_segment_request(
+                                tenant_id,
+                                req_timeline_id,
+                                &req,
+                                &ctx,
+                            )
+                            .instrument(span.clone()),
+                        )
+                        .await,
                         span,
                     )
                 }
             };
 
+            // A request that raced with the deletion of its timeline fails with whatever the
+            // teardown made its read fail with, typically a cancellation: tell the client
+            // what actually happened instead.
+            let response = match response {
+                Err(e)
+                    if !task_mgr::is_shutdown_requested()
+                        && is_timeline_being_deleted(&tenant, req_timeline_id) =>
+                {
+                    span.in_scope(|| info!("request failed during timeline deletion: {e:#}"));
+                    self.drop_deleted_timelines();
+                    Err(PageStreamError::TimelineDeleted(req_timeline_id))
+                }
+                r => r,
+            };
+
             match response {
                 Err(PageStreamError::Shutdown) => {
                     // If we fail to fulfil a request during shutdown, which may be _because_ of
@@ -696,15 +1344,25 @@ impl PageServerHandler {
                         // error message is enough.  Do not log if shutting down, as the anyhow::Error
                         // here includes cancellation which is not an error.
                         let full = utils::error::report_compact_sources(&e);
-                        span.in_scope(|| {
-                            error!("error reading relation or page version: {full:#}")
+                        span.in_scope(|| match &e {
+                            // The compute is ahead of the WAL we have received, e.g. a replica
+                            // reading at a recent LSN while our walreceiver lags: not a bug on
+                            // our side, and the client may simply retry.
+                            PageStreamError::LsnTimeout(_) => {
+                                warn!("timed out waiting for requested LSN: {full:#}")
+                            }
+                            PageStreamError::StatementTimeout(_) => {
+                                warn!("request exceeded the statement timeout: {full:#}")
+                            }
+                            PageStreamError::TimelineDeleted(_) => info!("{full:#}"),
+                            _ => error!("error reading relation or page version: {full:#}"),
                         });
                         PagestreamBeMessage::Error(PagestreamErrorResponse {
                             message: e.to_string(),
                         })
                     });
 
-                    pgb.write_message_noflush(&BeMessage::CopyData(&response_msg.serialize()))?;
+                    self.write_pagestream_response(pgb, &response_msg)?;
                     self.flush_cancellable(pgb, &tenant.cancel).await?;
                 }
             }
@@ -712,6 +1370,26 @@ impl PageServerHandler {
         Ok(())
     }
 
+    fn write_pagestream_response<IO>(
+        &self,
+        pgb: &mut PostgresBackend<IO>,
+        response_msg: &PagestreamBeMessage,
+    ) -> Result<(), QueryError>
+    where
+        IO: AsyncRead + AsyncWrite + Send + Sync + Unpin,
+    {
+        let response = response_msg.serialize();
+        if self.pagestream_trace {
+            debug!(
+                "pagestream response ({} bytes): {}",
+                response.len(),
+                describe_pagestream_response(response_msg)
+            );
+        }
+        pgb.write_message_noflush(&BeMessage::CopyData(&response))?;
+        Ok(())
+    }
+
     #[allow(clippy::too_many_arguments)]
     #[instrument(skip_all, fields(%base_lsn, end_lsn=%_end_lsn, %pg_version))]
     async fn handle_import_basebackup<IO>(
@@ -790,7 +1468,7 @@ impl PageServerHandler {
         start_lsn: Lsn,
         end_lsn: Lsn,
         ctx: RequestContext,
-    ) -> Result<(), QueryError>
+    ) -> Result<(Lsn, u64), QueryError>
     where
         IO: AsyncRead + AsyncWrite + Send + Sync + Unpin,
     {
@@ -799,9 +1477,7 @@ impl PageServerHandler {
             .await?;
         let last_record_lsn = timeline.get_last_record_lsn();
         if last_record_lsn != start_lsn {
-            return Err(QueryError::Other(
-                anyhow::anyhow!("Cannot import WAL from Lsn {start_lsn} because timeline does not start from the same lsn: {last_record_lsn}"))
-            );
+            return Err(PageServiceError::Internal(anyhow::anyhow!("Cannot import WAL from Lsn {start_lsn} because timeline does not start from the same lsn: {last_record_lsn}")).into());
         }
 
         // TODO leave clean state on error. For now you can use detach to clean
@@ -812,27 +1488,34 @@ impl PageServerHandler {
         pgb.write_message_noflush(&BeMessage::CopyInResponse)?;
         self.flush_cancellable(pgb, &timeline.cancel).await?;
         let mut copyin_reader = pin!(StreamReader::new(self.copyin_stream(pgb, &timeline.cancel)));
-        import_wal_from_tar(&timeline, &mut copyin_reader, start_lsn, end_lsn, &ctx).await?;
-        info!("wal import complete");
+        let (records, record_lsns) =
+            import_wal_from_tar(&timeline, &mut copyin_reader, start_lsn, end_lsn, &ctx).await?;
+        info!("wal import complete, {records} records");
 
         // Read the end of the tar archive.
         read_tar_eof(copyin_reader).await?;
 
         // TODO Does it make sense to overshoot?
-        if timeline.get_last_record_lsn() < end_lsn {
-            return Err(QueryError::Other(
-                anyhow::anyhow!("Cannot import WAL from Lsn {start_lsn} because timeline does not start from the same lsn: {last_record_lsn}"))
-            );
+        let imported_lsn = record_lsns.last().copied().unwrap_or(start_lsn);
+        if imported_lsn < end_lsn {
+            return Err(PageServiceError::Internal(anyhow::anyhow!("Cannot import WAL up to Lsn {end_lsn} because the WAL provided ends at {imported_lsn}")).into());
         }
 
         // Flush data to disk, then upload to s3. No need for a forced checkpoint.
         // We only want to persist the data, and it doesn't matter if it's in the
-        // shape of deltas or images.
+        // shape of deltas or images. The imported records only become visible once
+        // the flush is done.
         info!("flushing layers");
-        timeline.freeze_and_flush().await?;
+        timeline
+            .flush_and_publish(&record_lsns)
+            .await
+            .with_context(|| format!("failed to flush WAL imported up to {imported_lsn}"))?;
 
-        info!("done");
-        Ok(())
+        // Report what actually landed on disk, rather than what was ingested.
+        let durable_lsn = timeline.get_disk_consistent_lsn();
+
+        info!("done, durable up to {durable_lsn}");
+        Ok((durable_lsn, records))
     }
 
     /// Helper function to handle the LSN from client request.
@@ -845,8 +1528,11 @@ impl PageServerHandler {
     /// currently caught up to.
     ///
     /// In either case, if the page server hasn't received the WAL up to the
-    /// requested LSN yet, we will wait for it to arrive. The return value is
-    /// the LSN that should be used to look up the page versions.
+    /// requested LSN yet, we will wait for it to arrive, for at most
+    /// `wait_lsn_timeout`. On timeout, [`PageStreamError::LsnTimeout`] is
+    /// returned and the client gets an error response that it can retry.
+    /// The return value is the LSN that should be used to look up the page
+    /// versions.
     async fn wait_or_get_last_lsn(
         timeline: &Timeline,
         mut lsn: Lsn,
@@ -1032,13 +1718,60 @@ impl PageServerHandler {
         Err(key)
     }
 
+    /// Make `shard_timelines` hold the shards of `timeline_id`, parking those of the
+    /// timeline it held until now. Requests only ever look at `shard_timelines`, so that
+    /// the lookups stay as cheap as with a single timeline per connection.
+    ///
+    /// Parking releases the gates of the shards, and switching back enters them again.
+    /// A shard that is gone or shutting down by then is dropped, and looked up again like
+    /// any other if a request needs it.
+    fn switch_cached_timeline(&mut self, timeline_id: TimelineId) {
+        if self.cached_timeline_id == Some(timeline_id) {
+            return;
+        }
+        let previous = std::mem::take(&mut self.shard_timelines);
+        if let Some(previous_id) = self.cached_timeline_id.replace(timeline_id) {
+            let parked = previous
+                .into_values()
+                .map(|ht| Arc::downgrade(&ht.timeline))
+                .collect();
+            self.parked_shard_timelines.insert(previous_id, parked);
+        }
+        let shards = self
+            .parked_shard_timelines
+            .remove(&timeline_id)
+            .unwrap_or_default();
+        for timeline in shards.iter().filter_map(Weak::upgrade) {
+            // Fails only if the timeline is shutting down
+            let _ = self.cache_timeline(timeline);
+        }
+    }
+
+    /// Forget the cached timelines that are being deleted, releasing their gates. Returns
+    /// false if there were none.
+    ///
+    /// Called once a cached timeline has been cancelled: the deletion cancels the timeline
+    /// before it waits for the gate. Parked timelines hold no gate, so only the shards of
+    /// the current one are looked at.
+    fn drop_deleted_timelines(&mut self) -> bool {
+        let mut dropped = false;
+        let mut keep = |ht: &mut HandlerTimeline| {
+            let deleted = ht.timeline.cancel.is_cancelled() && ht.timeline.is_being_deleted();
+            dropped |= deleted;
+            !deleted
+        };
+        self.shard_timelines.retain(|_, ht| keep(ht));
+        dropped
+    }
+
     /// Having looked up the [`Timeline`] instance for a particular shard, cache it to enable
     /// use in future requests without having to traverse [`crate::tenant::mgr::TenantManager`]
     /// again.
     ///
     /// Note that all the Timelines in this cache are for the same timeline_id: they're differ
     /// in which shard they belong to.  When we serve a getpage@lsn request, we choose a shard
-    /// based on key.
+    /// based on key. Other timelines of a `pagestream_v2` connection are parked, see
+    /// [`Self::switch_cached_timeline`].
     ///
     /// The typical size of this cache is 1, as we generally create shards to distribute work
     /// across pageservers, so don't tend to have multiple shards for the same tenant on the
@@ -1208,25 +1941,103 @@ impl PageServerHandler {
         let started = std::time::Instant::now();
 
         // check that the timeline exists
-        let timeline = self
-            .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
-            .await?;
-        let latest_gc_cutoff_lsn = timeline.get_latest_gc_cutoff_lsn();
-        if let Some(lsn) = lsn {
-            // Backup was requested at a particular LSN. Wait for it to arrive.
-            info!("waiting for {}", lsn);
-            timeline.wait_lsn(lsn, ctx).await?;
-            timeline
-                .check_lsn_is_in_scope(lsn, &latest_gc_cutoff_lsn)
-                .context("invalid basebackup lsn")?;
+        let tenant = get_active_tenant_with_timeout(
+            tenant_id,
+            ShardSelector::Zero,
+            ACTIVE_TENANT_TIMEOUT,
+            &task_mgr::shutdown_token(),
+        )
+        .await?;
+        let timeline = tenant
+            .get_timeline(timeline_id, true)
+            .map_err(GetActiveTimelineError::Timeline)?;
+        set_tracing_field_shard_id(&timeline);
+
+        // Rather than queueing, turn away backups beyond the limit: the compute will retry,
+        // and meanwhile the ones in flight are not slowed down further.
+        let Ok(_permit) = tenant.basebackup_permits.try_acquire() else {
+            return Err(PageServiceError::Overloaded(
+                format!(
+                    "too many concurrent basebackups for tenant {}",
+                    tenant.tenant_shard_id
+                )
+                .into(),
+            )
+            .into());
+        };
+        let in_progress = crate::metrics::BASEBACKUPS_IN_PROGRESS.with_label_values(&[
+            &tenant.tenant_shard_id.tenant_id.to_string(),
+            &tenant.tenant_shard_id.shard_slug().to_string(),
+        ]);
+        in_progress.inc();
+        scopeguard::defer! {
+            in_progress.dec();
         }
 
+        // Every file in the tarball is read at the same LSN. A backup at the end of the
+        // timeline is taken at the last record LSN as of now, not as of when each file is
+        // read.
+        let (lsn, prev_lsn) = match lsn {
+            Some(lsn) => {
+                // Backup was requested at a particular LSN. Wait for it to arrive.
+                info!("waiting for {}", lsn);
+                timeline.wait_lsn(lsn, ctx).await?;
+                (lsn, prev_lsn)
+            }
+            None => {
+                let end_of_timeline = timeline.get_last_record_rlsn();
+                (
+                    end_of_timeline.last,
+                    prev_lsn.or(Some(end_of_timeline.prev)),
+                )
+            }
+        };
+
+        // Hold back GC at the backup LSN until the whole tarball has been sent. Taking the
+        // lease fails if GC has already moved past the LSN, before any data is sent.
+        let lease_ttl = self.conf.lsn_lease_ttl;
+        let lease_id = match timeline.pin_lsn(lsn, lease_ttl) {
+            Ok(lease_id) => lease_id,
+            Err(PinLsnError::Shutdown) => return Err(QueryError::Shutdown),
+            Err(PinLsnError::LsnTooOld(e)) => {
+                return Err(PageServiceError::LsnTooOld(
+                    format!("invalid basebackup lsn: {e:#}").into(),
+                )
+                .into())
+            }
+        };
+        let lease = scopeguard::guard(lease_id, |lease_id| {
+            timeline.unpin_lsn(lease_id);
+        });
+        // Had the lease expired, GC could have removed history that the files sent since
+        // were read from. Checked once the tarball has been written, but before the gzip
+        // footer and CopyDone, so that the client sees an error rather than a complete backup.
+        let release_lease = || {
+            if timeline.unpin_lsn(scopeguard::ScopeGuard::into_inner(lease)) {
+                Ok(())
+            } else {
+                Err(QueryError::from(PageServiceError::LsnTooOld(
+                    format!(
+                        "basebackup at {lsn} took longer than the LSN lease TTL of {}",
+                        humantime::format_duration(lease_ttl)
+                    )
+                    .into(),
+                )))
+            }
+        };
+
+        // Check before switching the client to COPYOUT, so that a timeline without
+        // control data results in an error response rather than a truncated tarball.
+        basebackup::check_control_file_exists(&timeline, lsn, ctx).await?;
+
         let lsn_awaited_after = started.elapsed();
 
         // switch client to COPYOUT
         pgb.write_message_noflush(&BeMessage::CopyOutResponse)?;
         self.flush_cancellable(pgb, &timeline.cancel).await?;
 
+        pausable_failpoint!("basebackup-before-tarball-pause");
+
         // Send a tarball of the latest layer on the timeline. Compress if not
         // fullbackup. TODO Compress in that case too (tests need to be updated)
         if full_backup {
@@ -1234,12 +2045,13 @@ impl PageServerHandler {
             basebackup::send_basebackup_tarball(
                 &mut writer,
                 &timeline,
-                lsn,
+                Some(lsn),
                 prev_lsn,
                 full_backup,
                 ctx,
             )
             .await?;
+            release_lease()?;
         } else {
             let mut writer = pgb.copyout_writer();
             if gzip {
@@ -1255,24 +2067,26 @@ impl PageServerHandler {
                 basebackup::send_basebackup_tarball(
                     &mut encoder,
                     &timeline,
-                    lsn,
+                    Some(lsn),
                     prev_lsn,
                     full_backup,
                     ctx,
                 )
                 .await?;
+                release_lease()?;
                 // shutdown the encoder to ensure the gzip footer is written
                 encoder.shutdown().await?;
             } else {
                 basebackup::send_basebackup_tarball(
                     &mut writer,
                     &timeline,
-                    lsn,
+                    Some(lsn),
                     prev_lsn,
                     full_backup,
                     ctx,
                 )
                 .await?;
+                release_lease()?;
             }
         }
 
@@ -1295,7 +2109,7 @@ impl PageServerHandler {
 
     // when accessing management api supply None as an argument
     // when using to authorize tenant pass corresponding tenant id
-    fn check_permission(&self, tenant_id: Option<TenantId>) -> Result<(), QueryError> {
+    fn check_permission(&self, tenant_id: Option<TenantId>) -> Result<(), PageServiceError> {
         if self.auth.is_none() {
             // auth is set to Trust, nothing to check so just return ok
             return Ok(());
@@ -1307,7 +2121,7 @@ impl PageServerHandler {
             .claims
             .as_ref()
             .expect("claims presence already checked");
-        check_permission(claims, tenant_id).map_err(|e| QueryError::Unauthorized(e.0))
+        check_permission(claims, tenant_id).map_err(|e| PageServiceError::AuthFailed(e.0))
     }
 
     /// Shorthand for getting a reference to a Timeline of an Active tenant.
@@ -1367,18 +2181,67 @@ where
 
     fn startup(
         &mut self,
-        _pgb: &mut PostgresBackend<IO>,
-        _sm: &FeStartupPacket,
+        pgb: &mut PostgresBackend<IO>,
+        sm: &FeStartupPacket,
     ) -> Result<(), QueryError> {
+        let cancel_key = cancel::CancelKey::register();
+        pgb.set_cancel_key(cancel_key.key());
+        self.cancel_key = Some(cancel_key);
+
+        // Computes identify themselves with application_name; put it on the connection
+        // span so that log lines can be attributed to the compute that caused them.
+        let application_name = match sm {
+            FeStartupPacket::StartupMessage { params, .. } => params.get("application_name"),
+            _ => None,
+        };
+        tracing::Span::current().record(
+            "application_name",
+            field::display(application_name.unwrap_or("unknown")),
+        );
         Ok(())
     }
 
+    fn cancel_request(&mut self, key: &CancelKeyData) {
+        if cancel::cancel_query(key) {
+            info!("cancelling query of connection {key} on client request");
+        } else {
+            debug!("ignoring cancel request for {key}, no query is running");
+        }
+    }
+
     #[instrument(skip_all, fields(tenant_id, timeline_id))]
     async fn process_query(
         &mut self,
         pgb: &mut PostgresBackend<IO>,
         query_string: &str,
     ) -> Result<(), QueryError> {
+        let Some(query) = self.cancel_key.as_ref().map(|key| key.start_query()) else {
+            return self.process_query_inner(pgb, query_string).await;
+        };
+        tokio::select! {
+            res = self.process_query_inner(pgb, query_string) => res,
+            _ = query.token.cancelled() => {
+                // Dropping the command in the middle is fine: handlers already have to
+                // cope with that on shutdown and statement timeouts.
+                info!("query {query_string:?} cancelled by the client");
+                Err(QueryError::Coded {
+                    code: SQLSTATE_QUERY_CANCELED,
+                    message: "canceling statement due to user request".into(),
+                })
+            }
+        }
+    }
+}
+
+impl PageServerHandler {
+    async fn process_query_inner<IO>(
+        &mut self,
+        pgb: &mut PostgresBackend<IO>,
+        query_string: &str,
+    ) -> Result<(), QueryError>
+    where
+        IO: AsyncRead + AsyncWrite + Send + Sync + Unpin,
+    {
         fail::fail_point!("simulated-bad-compute-connection", |_| {
             info!("Hit failpoint for bad connection");
             Err(QueryError::SimulatedConnectionError)
@@ -1386,18 +2249,26 @@ where
 
         let ctx = self.connection_ctx.attached_child();
         debug!("process query {query_string:?}");
-        if query_string.starts_with("pagestream ") {
-            let (_, params_raw) = query_string.split_at("pagestream ".len());
+        if query_string.starts_with("pagestream ") || query_string.starts_with("pagestream_v2 ") {
+            // pagestream[_v2] <tenant_id> <timeline_id>
+            let (cmd, params_raw) = query_string.split_once(' ').unwrap();
+            let protocol_version = match cmd {
+                "pagestream_v2" => PagestreamProtocolVersion::V2,
+                _ => PagestreamProtocolVersion::V1,
+            };
             let params = params_raw.split(' ').collect::<Vec<_>>();
             if params.len() != 2 {
-                return Err(QueryError::Other(anyhow::anyhow!(
-                    "invalid param number for pagestream command"
-                )));
+                return Err(PageServiceError::BadRequest(
+                    format!("invalid param number for {cmd} command").into(),
+                )
+                .into());
             }
             let tenant_id = TenantId::from_str(params[0])
-                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))?;
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
             let timeline_id = TimelineId::from_str(params[1])
-                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))?;
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
 
             tracing::Span::current()
                 .record("tenant_id", field::display(tenant_id))
@@ -1405,22 +2276,25 @@ where
 
             self.check_permission(Some(tenant_id))?;
 
-            self.handle_pagerequests(pgb, tenant_id, timeline_id, ctx)
+            self.handle_pagerequests(pgb, tenant_id, timeline_id, protocol_version, ctx)
                 .await?;
         } else if query_string.starts_with("basebackup ") {
             let (_, params_raw) = query_string.split_at("basebackup ".len());
             let params = params_raw.split_whitespace().collect::<Vec<_>>();
 
             if params.len() < 2 {
-                return Err(QueryError::Other(anyhow::anyhow!(
-                    "invalid param number for basebackup command"
-                )));
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for basebackup command".into(),
+                )
+                .into());
             }
 
             let tenant_id = TenantId::from_str(params[0])
-                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))?;
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
             let timeline_id = TimelineId::from_str(params[1])
-                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))?;
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
 
             tracing::Span::current()
                 .record("tenant_id", field::display(tenant_id))
@@ -1431,7 +2305,8 @@ where
             let lsn = if params.len() >= 3 {
                 Some(
                     Lsn::from_str(params[2])
-                        .with_context(|| format!("Failed to parse Lsn from {}", params[2]))?,
+                        .with_context(|| format!("Failed to parse Lsn from {}", params[2]))
+                        .map_err(PageServiceError::bad_request)?,
                 )
             } else {
                 None
@@ -1441,10 +2316,10 @@ where
                 if params[3] == "--gzip" {
                     true
                 } else {
-                    return Err(QueryError::Other(anyhow::anyhow!(
-                        "Parameter in position 3 unknown {}",
-                        params[3],
-                    )));
+                    return Err(PageServiceError::BadRequest(
+                        format!("Parameter in position 3 unknown {}", params[3]).into(),
+                    )
+                    .into());
                 }
             } else {
                 false
@@ -1476,15 +2351,18 @@ where
             let params = params_raw.split_whitespace().collect::<Vec<_>>();
 
             if params.len() != 2 {
-                return Err(QueryError::Other(anyhow::anyhow!(
-                    "invalid param number for get_last_record_rlsn command"
-                )));
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for get_last_record_rlsn command".into(),
+                )
+                .into());
             }
 
             let tenant_id = TenantId::from_str(params[0])
-                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))?;
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
             let timeline_id = TimelineId::from_str(params[1])
-                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))?;
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
 
             tracing::Span::current()
                 .record("tenant_id", field::display(tenant_id))
@@ -1521,15 +2399,18 @@ where
             let params = params_raw.split_whitespace().collect::<Vec<_>>();
 
             if params.len() < 2 {
-                return Err(QueryError::Other(anyhow::anyhow!(
-                    "invalid param number for fullbackup command"
-                )));
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for fullbackup command".into(),
+                )
+                .into());
             }
 
             let tenant_id = TenantId::from_str(params[0])
-                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))?;
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
             let timeline_id = TimelineId::from_str(params[1])
-                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))?;
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
 
             tracing::Span::current()
                 .record("tenant_id", field::display(tenant_id))
@@ -1539,7 +2420,8 @@ where
             let lsn = if params.len() > 2 {
                 Some(
                     Lsn::from_str(params[2])
-                        .with_context(|| format!("Failed to parse Lsn from {}", params[2]))?,
+                        .with_context(|| format!("Failed to parse Lsn from {}", params[2]))
+                        .map_err(PageServiceError::bad_request)?,
                 )
             } else {
                 None
@@ -1547,7 +2429,8 @@ where
             let prev_lsn = if params.len() > 3 {
                 Some(
                     Lsn::from_str(params[3])
-                        .with_context(|| format!("Failed to parse Lsn from {}", params[3]))?,
+                        .with_context(|| format!("Failed to parse Lsn from {}", params[3]))
+                        .map_err(PageServiceError::bad_request)?,
                 )
             } else {
                 None
@@ -1583,20 +2466,26 @@ where
             let (_, params_raw) = query_string.split_at("import basebackup ".len());
             let params = params_raw.split_whitespace().collect::<Vec<_>>();
             if params.len() != 5 {
-                return Err(QueryError::Other(anyhow::anyhow!(
-                    "invalid param number for import basebackup command"
-                )));
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for import basebackup command".into(),
+                )
+                .into());
             }
             let tenant_id = TenantId::from_str(params[0])
-                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))?;
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
             let timeline_id = TimelineId::from_str(params[1])
-                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))?;
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
             let base_lsn = Lsn::from_str(params[2])
-                .with_context(|| format!("Failed to parse Lsn from {}", params[2]))?;
+                .with_context(|| format!("Failed to parse Lsn from {}", params[2]))
+                .map_err(PageServiceError::bad_request)?;
             let end_lsn = Lsn::from_str(params[3])
-                .with_context(|| format!("Failed to parse Lsn from {}", params[3]))?;
+                .with_context(|| format!("Failed to parse Lsn from {}", params[3]))
+                .map_err(PageServiceError::bad_request)?;
             let pg_version = u32::from_str(params[4])
-                .with_context(|| format!("Failed to parse pg_version from {}", params[4]))?;
+                .with_context(|| format!("Failed to parse pg_version from {}", params[4]))
+                .map_err(PageServiceError::bad_request)?;
 
             tracing::Span::current()
                 .record("tenant_id", field::display(tenant_id))
@@ -1630,21 +2519,31 @@ where
             //
             // Files are scheduled to be persisted to remote storage, and the
             // caller should poll the http api to check when that is done.
+            //
+            // The command tag reports the LSN up to which the imported WAL is
+            // flushed to local disk, and the number of records imported:
+            // `IMPORT <durable_lsn> <records>`. Clients that only look at the
+            // row count of the tag get the number of records.
             let (_, params_raw) = query_string.split_at("import wal ".len());
             let params = params_raw.split_whitespace().collect::<Vec<_>>();
             if params.len() != 4 {
-                return Err(QueryError::Other(anyhow::anyhow!(
-                    "invalid param number for import wal command"
-                )));
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for import wal command".into(),
+                )
+                .into());
             }
             let tenant_id = TenantId::from_str(params[0])
-                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))?;
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
             let timeline_id = TimelineId::from_str(params[1])
-                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))?;
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
             let start_lsn = Lsn::from_str(params[2])
-                .with_context(|| format!("Failed to parse Lsn from {}", params[2]))?;
+                .with_context(|| format!("Failed to parse Lsn from {}", params[2]))
+                .map_err(PageServiceError::bad_request)?;
             let end_lsn = Lsn::from_str(params[3])
-                .with_context(|| format!("Failed to parse Lsn from {}", params[3]))?;
+                .with_context(|| format!("Failed to parse Lsn from {}", params[3]))
+                .map_err(PageServiceError::bad_request)?;
 
             tracing::Span::current()
                 .record("tenant_id", field::display(tenant_id))
@@ -1656,7 +2555,11 @@ where
                 .handle_import_wal(pgb, tenant_id, timeline_id, start_lsn, end_lsn, ctx)
                 .await
             {
-                Ok(()) => pgb.write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?,
+                Ok((durable_lsn, records)) => {
+                    pgb.write_message_noflush(&BeMessage::CommandComplete(
+                        format!("IMPORT {durable_lsn} {records}").as_bytes(),
+                    ))?
+                }
                 Err(e) => {
                     error!("error importing WAL between {start_lsn} and {end_lsn}: {e:?}");
                     pgb.write_message_noflush(&BeMessage::ErrorResponse(
@@ -1665,21 +2568,695 @@ where
                     ))?
                 }
             };
+        } else if query_string.starts_with("warmup ") {
+            // warmup <tenant_id> <timeline_id> <rel> [lsn]
+            let (_, params_raw) = query_string.split_at("warmup ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+            if params.len() < 3 || params.len() > 4 {
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for warmup command".into(),
+                )
+                .into());
+            }
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
+            let timeline_id = TimelineId::from_str(params[1])
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
+            let rel = RelTag::from_str(params[2])
+                .with_context(|| format!("Failed to parse relation from {}", params[2]))
+                .map_err(PageServiceError::bad_request)?;
+
+            tracing::Span::current()
+                .record("tenant_id", field::display(tenant_id))
+                .record("timeline_id", field::display(timeline_id));
+
+            self.check_permission(Some(tenant_id))?;
+
+            let timeline = self
+                .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
+                .await?;
+            let last_record_lsn = timeline.get_last_record_lsn();
+            let lsn = match params.get(3) {
+                Some(lsn) => {
+                    let lsn = Lsn::from_str(lsn)
+                        .with_context(|| format!("Failed to parse Lsn from {lsn}"))
+                        .map_err(PageServiceError::bad_request)?;
+                    if lsn > last_record_lsn {
+                        return Err(PageServiceError::BadRequest(format!(
+                            "cannot warm up at {lsn}, timeline has only ingested WAL up to {last_record_lsn}"
+                        ).into()).into());
+                    }
+                    lsn
+                }
+                None => last_record_lsn,
+            };
+
+            let warmup_id = page_cache_warmup::start(timeline, rel, lsn);
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[RowDescriptor::int8_col(
+                b"warmup_id",
+            )]))?
+            .write_message_noflush(&BeMessage::DataRow(&[Some(
+                warmup_id.to_string().as_bytes(),
+            )]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("warmup_status ") {
+            // warmup_status <warmup_id>
+            let (_, param) = query_string.split_at("warmup_status ".len());
+            let warmup_id = u64::from_str(param.trim())
+                .with_context(|| format!("Failed to parse warmup id from {param}"))
+                .map_err(PageServiceError::bad_request)?;
+
+            let progress = page_cache_warmup::progress(warmup_id).ok_or_else(|| {
+                QueryError::NotFound(format!("warmup {warmup_id} not found").into())
+            })?;
+
+            tracing::Span::current().record("tenant_id", field::display(progress.tenant_id));
+
+            self.check_permission(Some(progress.tenant_id))?;
+            let state = progress.state();
+            let error = match &state {
+                page_cache_warmup::WarmupState::Failed(e) => Some(e.as_bytes()),
+                _ => None,
+            };
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[
+                RowDescriptor::text_col(b"state"),
+                RowDescriptor::int8_col(b"warmed_blocks"),
+                RowDescriptor::int8_col(b"total_blocks"),
+                RowDescriptor::text_col(b"error"),
+            ]))?
+            .write_message_noflush(&BeMessage::DataRow(&[
+                Some(state.as_str().as_bytes()),
+                Some(progress.warmed_blocks().to_string().as_bytes()),
+                Some(progress.total_blocks().to_string().as_bytes()),
+                error,
+            ]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("evict_tenant ") {
+            // evict_tenant <tenant_id>
+            let (_, param) = query_string.split_at("evict_tenant ".len());
+            let tenant_id = TenantId::from_str(param.trim())
+                .with_context(|| format!("Failed to parse tenant id from {param}"))
+                .map_err(PageServiceError::bad_request)?;
+
+            tracing::Span::current().record("tenant_id", field::display(tenant_id));
+
+            self.check_permission(Some(tenant_id))?;
+
+            let evicted = page_cache::get().evict_tenant(tenant_id).await;
+            info!("evicted {evicted} pages of tenant {tenant_id} from the page cache");
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[RowDescriptor::int8_col(
+                b"evicted",
+            )]))?
+            .write_message_noflush(&BeMessage::DataRow(&[Some(evicted.to_string().as_bytes())]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string == "help" {
+            // Deliberately needs no permission: it only describes this build.
+            let commands = COMMANDS
+                .iter()
+                .filter(|command| !command.testing || cfg!(feature = "testing"))
+                .collect::<Vec<_>>();
+            let commands = serde_json::to_string(&commands).context("serialize command list")?;
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[RowDescriptor::text_col(
+                b"commands",
+            )]))?
+            .write_message_noflush(&BeMessage::DataRow(&[Some(commands.as_bytes())]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string == "status" {
+            // Report whether startup has completed. Kept in agreement with the
+            // `ready` field of the `/v1/status` HTTP endpoint, and like it available
+            // without a token: health checks must not need credentials, and neither the
+            // node id nor the readiness tell anything about the tenants.
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[
+                RowDescriptor::int8_col(b"id"),
+                RowDescriptor::bool_col(b"ready"),
+            ]))?
+            .write_message_noflush(&BeMessage::DataRow(&[
+                Some(self.conf.id.to_string().as_bytes()),
+                Some(if crate::is_ready() { b"t" } else { b"f" }),
+            ]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string == "build_info" {
+            // Same information as `pageserver --version`.
+            let info = &crate::build_info::BUILD_INFO;
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[
+                RowDescriptor::text_col(b"version"),
+                RowDescriptor::text_col(b"git_version"),
+                RowDescriptor::text_col(b"build_tag"),
+                RowDescriptor::text_col(b"build_timestamp"),
+            ]))?
+            .write_message_noflush(&BeMessage::DataRow(&[
+                Some(info.version.as_bytes()),
+                Some(info.git_version.as_bytes()),
+                Some(info.build_tag.as_bytes()),
+                Some(info.build_timestamp.as_bytes()),
+            ]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string == "metrics_snapshot" {
+            // Testing-only: dump the GetPage/basebackup query counters as JSON, so that
+            // tests can assert on them without scraping the http endpoint.
+            if !cfg!(feature = "testing") {
+                return Err(PageServiceError::BadRequest("Cannot take metrics snapshot because pageserver was compiled without testing APIs".into()).into());
+            }
+            self.check_permission(None)?;
+
+            let snapshot = serde_json::to_string(&metrics::QueryMetricsSnapshot::collect())
+                .context("serialize metrics snapshot")?;
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[RowDescriptor::text_col(
+                b"metrics",
+            )]))?
+            .write_message_noflush(&BeMessage::DataRow(&[Some(snapshot.as_bytes())]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string == "reset_metrics" {
+            // Testing-only: zero the resettable counters, so that tests can assert on
+            // what a single operation did. Returns the values from before the reset.
+            if !cfg!(feature = "testing") {
+                return Err(PageServiceError::BadRequest(
+                    "Cannot reset metrics because pageserver was compiled without testing APIs"
+                        .into(),
+                )
+                .into());
+            }
+            self.check_permission(None)?;
+
+            let previous =
+                serde_json::to_string(&metrics::reset_counters()).context("serialize metrics")?;
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[RowDescriptor::text_col(
+                b"metrics",
+            )]))?
+            .write_message_noflush(&BeMessage::DataRow(&[Some(previous.as_bytes())]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string == "mem_usage" {
+            // Memory use of the process and of every tenant shard, as JSON.
+            self.check_permission(None)?;
+
+            let usage = serde_json::to_string(&mem_usage::collect().await)
+                .context("serialize memory usage")?;
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[RowDescriptor::text_col(
+                b"mem_usage",
+            )]))?
+            .write_message_noflush(&BeMessage::DataRow(&[Some(usage.as_bytes())]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("do_compact ") {
+            // Testing-only: run one compaction iteration of a timeline right away.
+            // do_compact <tenant_id> <timeline_id>
+            if !cfg!(feature = "testing") {
+                return Err(PageServiceError::BadRequest(
+                    "Cannot run compaction because pageserver was compiled without testing APIs"
+                        .into(),
+                )
+                .into());
+            }
+            let (_, params_raw) = query_string.split_at("do_compact ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+            if params.len() != 2 {
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for do_compact command".into(),
+                )
+                .into());
+            }
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
+            let timeline_id = TimelineId::from_str(params[1])
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
+
+            tracing::Span::current()
+                .record("tenant_id", field::display(tenant_id))
+                .record("timeline_id", field::display(timeline_id));
+
+            self.check_permission(Some(tenant_id))?;
+
+            let timeline = self
+                .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
+                .await?;
+
+            // Compaction takes the same locks as the background loop, so running it here is
+            // no different for concurrent GetPage requests.
+            let layer_stats = |layers: Vec<Arc<PersistentLayerDesc>>| {
+                let bytes: u64 = layers.iter().map(|desc| desc.file_size).sum();
+                (layers.len(), bytes)
+            };
+            let started = std::time::Instant::now();
+            let (layers_before, bytes_before) = layer_stats(timeline.layer_file_descs().await);
+            timeline
+                .compact(&timeline.cancel, EnumSet::empty(), &ctx)
+                .await
+                .map_err(|e| match e {
+                    CompactionError::ShuttingDown => QueryError::Shutdown,
+                    CompactionError::Other(e) => QueryError::Other(e),
+                })?;
+            let (layers_after, bytes_after) = layer_stats(timeline.layer_file_descs().await);
+            let elapsed = started.elapsed();
+
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[
+                RowDescriptor::int8_col(b"layers_before"),
+                RowDescriptor::int8_col(b"layers_after"),
+                RowDescriptor::int8_col(b"bytes_before"),
+                RowDescriptor::int8_col(b"bytes_after"),
+                RowDescriptor::int8_col(b"elapsed_ms"),
+            ]))?
+            .write_message_noflush(&BeMessage::DataRow(&[
+                Some(layers_before.to_string().as_bytes()),
+                Some(layers_after.to_string().as_bytes()),
+                Some(bytes_before.to_string().as_bytes()),
+                Some(bytes_after.to_string().as_bytes()),
+                Some(elapsed.as_millis().to_string().as_bytes()),
+            ]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("layer_list ") {
+            // layer_list <tenant_id> <timeline_id>
+            let (_, params_raw) = query_string.split_at("layer_list ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+            if params.len() != 2 {
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for layer_list command".into(),
+                )
+                .into());
+            }
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
+            let timeline_id = TimelineId::from_str(params[1])
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
+
+            tracing::Span::current()
+                .record("tenant_id", field::display(tenant_id))
+                .record("timeline_id", field::display(timeline_id));
+
+            self.check_permission(Some(tenant_id))?;
+
+            let timeline = self
+                .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
+                .await?;
+            let layers = timeline
+                .layer_file_descs()
+                .await
+                .iter()
+                .map(|desc| {
+                    serde_json::json!({
+                        "name": desc.filename().file_name(),
+                        "lsn_start": desc.lsn_range.start.to_string(),
+                        "lsn_end": desc.lsn_range.end.to_string(),
+                        "key_start": desc.key_range.start.to_string(),
+                        "key_end": desc.key_range.end.to_string(),
+                        "size": desc.file_size,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let layers = serde_json::to_string(&layers).context("serialize layer list")?;
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[RowDescriptor::text_col(
+                b"layers",
+            )]))?
+            .write_message_noflush(&BeMessage::DataRow(&[Some(layers.as_bytes())]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("check_timeline ") {
+            // check_timeline <tenant_id> <timeline_id>
+            let (_, params_raw) = query_string.split_at("check_timeline ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+            if params.len() != 2 {
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for check_timeline command".into(),
+                )
+                .into());
+            }
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
+            let timeline_id = TimelineId::from_str(params[1])
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
+
+            tracing::Span::current()
+                .record("tenant_id", field::display(tenant_id))
+                .record("timeline_id", field::display(timeline_id));
+
+            self.check_permission(Some(tenant_id))?;
+
+            let timeline = self
+                .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
+                .await?;
+            let check = timeline_check::check_timeline(&timeline, &ctx).await;
+            if !check.is_ok() {
+                warn!(
+                    "timeline check at {} failed: gaps {:?}, error {:?}",
+                    check.lsn, check.gaps, check.error
+                );
+            }
+            let status = if check.is_ok() { "ok" } else { "error" };
+            let gaps = check
+                .gaps
+                .iter()
+                .map(|gap| format!("{}-{}", gap.start, gap.end))
+                .collect::<Vec<_>>()
+                .join(",");
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[
+                RowDescriptor::text_col(b"status"),
+                RowDescriptor::text_col(b"lsn"),
+                RowDescriptor::int8_col(b"sampled_pages"),
+                RowDescriptor::text_col(b"gaps"),
+                RowDescriptor::text_col(b"error"),
+            ]))?
+            .write_message_noflush(&BeMessage::DataRow(&[
+                Some(status.as_bytes()),
+                Some(check.lsn.to_string().as_bytes()),
+                Some(check.sampled_pages.to_string().as_bytes()),
+                Some(gaps.as_bytes()),
+                check.error.as_deref().map(str::as_bytes),
+            ]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("rel_history ") {
+            // rel_history <tenant_id> <timeline_id> <rel>
+            let (_, params_raw) = query_string.split_at("rel_history ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+            if params.len() != 3 {
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for rel_history command".into(),
+                )
+                .into());
+            }
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
+            let timeline_id = TimelineId::from_str(params[1])
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
+            let rel = RelTag::from_str(params[2])
+                .with_context(|| format!("Failed to parse relation from {}", params[2]))
+                .map_err(PageServiceError::bad_request)?;
+
+            tracing::Span::current()
+                .record("tenant_id", field::display(tenant_id))
+                .record("timeline_id", field::display(timeline_id));
+
+            self.check_permission(Some(tenant_id))?;
+
+            let timeline = self
+                .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
+                .await?;
+            let history = rel_history::rel_history(&timeline, rel, &ctx).await?;
+            let history = serde_json::to_string(&history).context("serialize relation history")?;
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[RowDescriptor::text_col(
+                b"history",
+            )]))?
+            .write_message_noflush(&BeMessage::DataRow(&[Some(history.as_bytes())]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("switch_walsource ") {
+            // switch_walsource <tenant_id> <timeline_id> <safekeeper_connstr>
+            let (_, params_raw) = query_string.split_at("switch_walsource ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+            if params.len() != 3 {
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for switch_walsource command".into(),
+                )
+                .into());
+            }
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
+            let timeline_id = TimelineId::from_str(params[1])
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
+
+            tracing::Span::current()
+                .record("tenant_id", field::display(tenant_id))
+                .record("timeline_id", field::display(timeline_id));
+
+            self.check_permission(Some(tenant_id))?;
+
+            let timeline = self
+                .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
+                .await?;
+            let switched = timeline.switch_wal_source(params[2].to_owned()).await?;
+            // Only report success once the new safekeeper has delivered everything it
+            // had committed when we switched.
+            timeline.wait_lsn(switched.commit_lsn, &ctx).await?;
+
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[
+                RowDescriptor::int8_col(b"safekeeper_id"),
+                RowDescriptor::text_col(b"switch_lsn"),
+                RowDescriptor::text_col(b"caught_up_lsn"),
+            ]))?
+            .write_message_noflush(&BeMessage::DataRow(&[
+                Some(switched.safekeeper_id.to_string().as_bytes()),
+                Some(switched.switch_lsn.to_string().as_bytes()),
+                Some(timeline.get_last_record_lsn().to_string().as_bytes()),
+            ]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("replication_lag ") {
+            // replication_lag <tenant_id> <timeline_id>
+            let (_, params_raw) = query_string.split_at("replication_lag ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+            if params.len() != 2 {
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for replication_lag command".into(),
+                )
+                .into());
+            }
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
+            let timeline_id = TimelineId::from_str(params[1])
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
+
+            tracing::Span::current()
+                .record("tenant_id", field::display(tenant_id))
+                .record("timeline_id", field::display(timeline_id));
+
+            self.check_permission(Some(tenant_id))?;
+
+            let timeline = self
+                .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
+                .await?;
+            let lag = serde_json::to_string(&replication_lag::replication_lag(&timeline))
+                .context("serialize replication lag")?;
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[RowDescriptor::text_col(
+                b"replication_lag",
+            )]))?
+            .write_message_noflush(&BeMessage::DataRow(&[Some(lag.as_bytes())]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("get_controlfile ") {
+            // get_controlfile <tenant_id> <timeline_id> [lsn]
+            let (_, params_raw) = query_string.split_at("get_controlfile ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+            if params.len() < 2 || params.len() > 3 {
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for get_controlfile command".into(),
+                )
+                .into());
+            }
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
+            let timeline_id = TimelineId::from_str(params[1])
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
+
+            tracing::Span::current()
+                .record("tenant_id", field::display(tenant_id))
+                .record("timeline_id", field::display(timeline_id));
+
+            self.check_permission(Some(tenant_id))?;
+
+            let timeline = self
+                .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
+                .await?;
+            let lsn = match params.get(2) {
+                Some(lsn) => Lsn::from_str(lsn)
+                    .with_context(|| format!("Failed to parse Lsn from {lsn}"))
+                    .map_err(PageServiceError::bad_request)?,
+                None => timeline.get_last_record_lsn(),
+            };
+
+            let pg_control_bytes = timeline
+                .get_control_file(lsn, &ctx)
+                .await
+                .context("failed to get control file")?;
+            let checkpoint_bytes = timeline
+                .get_checkpoint(lsn, &ctx)
+                .await
+                .context("failed to get checkpoint")?;
+            let summary = postgres_ffi::decode_pg_control(
+                &pg_control_bytes,
+                &checkpoint_bytes,
+                timeline.pg_version,
+            )?;
+            let summary = serde_json::to_string(&summary).context("serialize control file")?;
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[RowDescriptor::text_col(
+                b"controlfile",
+            )]))?
+            .write_message_noflush(&BeMessage::DataRow(&[Some(summary.as_bytes())]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("pin_lsn ") {
+            // pin_lsn <tenant_id> <timeline_id> <lsn>
+            let (_, params_raw) = query_string.split_at("pin_lsn ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+            if params.len() != 3 {
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for pin_lsn command".into(),
+                )
+                .into());
+            }
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
+            let timeline_id = TimelineId::from_str(params[1])
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
+            let lsn = Lsn::from_str(params[2])
+                .with_context(|| format!("Failed to parse Lsn from {}", params[2]))
+                .map_err(PageServiceError::bad_request)?;
+
+            tracing::Span::current()
+                .record("tenant_id", field::display(tenant_id))
+                .record("timeline_id", field::display(timeline_id));
+
+            self.check_permission(Some(tenant_id))?;
+
+            let timeline = self
+                .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
+                .await?;
+            // A lease on shard zero alone would let GC on the other shards remove the
+            // layers it is meant to keep.
+            if !timeline.tenant_shard_id.is_unsharded() {
+                return Err(PageServiceError::BadRequest(
+                    "pin_lsn is not supported for sharded tenants".into(),
+                )
+                .into());
+            }
+            let ttl = self.conf.lsn_lease_ttl;
+            let lease_id = match timeline.pin_lsn(lsn, ttl) {
+                Ok(lease_id) => lease_id,
+                Err(PinLsnError::Shutdown) => return Err(QueryError::Shutdown),
+                Err(PinLsnError::LsnTooOld(e)) => {
+                    return Err(PageServiceError::LsnTooOld(
+                        format!("cannot pin lsn: {e:#}").into(),
+                    )
+                    .into())
+                }
+            };
+
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[
+                RowDescriptor::int8_col(b"lease_id"),
+                RowDescriptor::int8_col(b"ttl_seconds"),
+            ]))?
+            .write_message_noflush(&BeMessage::DataRow(&[
+                Some(lease_id.to_string().as_bytes()),
+                Some(ttl.as_secs().to_string().as_bytes()),
+            ]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("unpin_lsn ") {
+            // unpin_lsn <tenant_id> <timeline_id> <lease_id>
+            let (_, params_raw) = query_string.split_at("unpin_lsn ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+            if params.len() != 3 {
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for unpin_lsn command".into(),
+                )
+                .into());
+            }
+            let tenant_id = TenantId::from_str(params[0])
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
+            let timeline_id = TimelineId::from_str(params[1])
+                .with_context(|| format!("Failed to parse timeline id from {}", params[1]))
+                .map_err(PageServiceError::bad_request)?;
+            let lease_id = u64::from_str(params[2])
+                .with_context(|| format!("Failed to parse lease id from {}", params[2]))
+                .map_err(PageServiceError::bad_request)?;
+
+            tracing::Span::current()
+                .record("tenant_id", field::display(tenant_id))
+                .record("timeline_id", field::display(timeline_id));
+
+            self.check_permission(Some(tenant_id))?;
+
+            let timeline = self
+                .get_active_tenant_timeline(tenant_id, timeline_id, ShardSelector::Zero)
+                .await?;
+            if !timeline.unpin_lsn(lease_id) {
+                return Err(PageServiceError::BadRequest(
+                    format!("lease {lease_id} does not exist or has expired").into(),
+                )
+                .into());
+            }
+            pgb.write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
         } else if query_string.to_ascii_lowercase().starts_with("set ") {
             // important because psycopg2 executes "SET datestyle TO 'ISO'"
-            // on connect
+            // on connect. Settings other than the ones below are ignored.
+            match parse_set(query_string) {
+                Some((name, value)) if name.eq_ignore_ascii_case("statement_timeout") => {
+                    self.statement_timeout = parse_statement_timeout(value).map_err(|e| {
+                        PageServiceError::bad_request(e.context("invalid statement_timeout"))
+                    })?;
+                }
+                Some((name, value)) if name.eq_ignore_ascii_case("pagestream_trace") => {
+                    self.pagestream_trace = parse_bool_setting(value).map_err(|e| {
+                        PageServiceError::bad_request(e.context("invalid pagestream_trace"))
+                    })?;
+                }
+                _ => {}
+            }
             pgb.write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
+        } else if query_string.starts_with("set_gc_config ") {
+            // set_gc_config <gc_horizon> <gc_period>
+            // set_gc_config default
+            let (_, params_raw) = query_string.split_at("set_gc_config ".len());
+            let params = params_raw.split_whitespace().collect::<Vec<_>>();
+            let new = match params[..] {
+                ["default"] => None,
+                [horizon, period] => Some(GcSettings {
+                    horizon: horizon
+                        .parse()
+                        .with_context(|| format!("Failed to parse gc_horizon from {horizon}"))
+                        .map_err(PageServiceError::bad_request)?,
+                    period: humantime::parse_duration(period)
+                        .with_context(|| format!("Failed to parse gc_period from {period}"))
+                        .map_err(PageServiceError::bad_request)?,
+                }),
+                _ => {
+                    return Err(PageServiceError::BadRequest(
+                        "invalid param number for set_gc_config command".into(),
+                    )
+                    .into())
+                }
+            };
+
+            self.check_permission(None)?;
+
+            let previous = GcSettings::set(new, &self.conf.default_tenant_conf);
+            match new {
+                Some(new) => info!("set global GC settings to {new:?}, previously {previous:?}"),
+                None => info!(
+                    "reset global GC settings to the configured ones, previously {previous:?}"
+                ),
+            }
+            pgb.write_message_noflush(&BeMessage::RowDescription(&[
+                RowDescriptor::int8_col(b"prev_gc_horizon"),
+                RowDescriptor::int8_col(b"prev_gc_period"),
+            ]))?
+            .write_message_noflush(&BeMessage::DataRow(&[
+                Some(previous.horizon.to_string().as_bytes()),
+                Some(previous.period.as_secs().to_string().as_bytes()),
+            ]))?
+            .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
         } else if query_string.starts_with("show ") {
             // show <tenant_id>
             let (_, params_raw) = query_string.split_at("show ".len());
             let params = params_raw.split(' ').collect::<Vec<_>>();
             if params.len() != 1 {
-                return Err(QueryError::Other(anyhow::anyhow!(
-                    "invalid param number for config command"
-                )));
+                return Err(PageServiceError::BadRequest(
+                    "invalid param number for config command".into(),
+                )
+                .into());
             }
             let tenant_id = TenantId::from_str(params[0])
-                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))?;
+                .with_context(|| format!("Failed to parse tenant id from {}", params[0]))
+                .map_err(PageServiceError::bad_request)?;
 
             tracing::Span::current().record("tenant_id", field::display(tenant_id));
 
@@ -1728,9 +3305,12 @@ where
             ]))?
             .write_message_noflush(&BeMessage::CommandComplete(b"SELECT 1"))?;
         } else {
-            return Err(QueryError::Other(anyhow::anyhow!(
+            // Not one of the failures that have their own code: keep reporting XX000, as
+            // clients may rely on that.
+            return Err(PageServiceError::Internal(anyhow::anyhow!(
                 "unknown command {query_string}"
-            )));
+            ))
+            .into());
         }
 
         Ok(())
@@ -1747,7 +3327,9 @@ impl From<GetActiveTenantError> for QueryError {
             | GetActiveTenantError::WillNotBecomeActive(TenantState::Stopping { .. }) => {
                 QueryError::Shutdown
             }
-            e @ GetActiveTenantError::NotFound(_) => QueryError::NotFound(format!("{e}").into()),
+            e @ GetActiveTenantError::NotFound(_) => {
+                PageServiceError::TenantNotFound(format!("{e}").into()).into()
+            }
             e => QueryError::Other(anyhow::anyhow!(e)),
         }
     }
@@ -1766,11 +3348,21 @@ impl From<GetActiveTimelineError> for QueryError {
         match e {
             GetActiveTimelineError::Tenant(GetActiveTenantError::Cancelled) => QueryError::Shutdown,
             GetActiveTimelineError::Tenant(e) => e.into(),
-            GetActiveTimelineError::Timeline(e) => QueryError::NotFound(format!("{e}").into()),
+            GetActiveTimelineError::Timeline(e) => {
+                PageServiceError::TimelineNotFound(format!("{e}").into()).into()
+            }
         }
     }
 }
 
+/// Whether `timeline_id` is being deleted, judged by the shard of `tenant`: the shards
+/// of a timeline are deleted together.
+fn is_timeline_being_deleted(tenant: &Tenant, timeline_id: TimelineId) -> bool {
+    tenant
+        .get_timeline(timeline_id, false)
+        .is_ok_and(|timeline| timeline.is_being_deleted())
+}
+
 fn set_tracing_field_shard_id(timeline: &Timeline) {
     debug_assert_current_span_has_tenant_and_timeline_id_no_shard_id();
     tracing::Span::current().record(
@@ -0,0 +1,113 @@
+//! Cancellation of page service queries with the postgres cancel-key protocol.
+//!
+//! Each connection gets a random [`CancelKeyData`] on startup, which the client receives
+//! in BackendKeyData. To abort a long-running query, such as a basebackup or a stuck
+//! pagestream, the client opens another connection and sends a CancelRequest with that
+//! key. The cancel token of the query running on the matching connection is then
+//! fired; a request with a key that doesn't match, or for a connection that is idle,
+//! is ignored.
+
+use std::collections::{hash_map::Entry, HashMap};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use pq_proto::CancelKeyData;
+use tokio_util::sync::CancellationToken;
+
+/// The connections that accept cancel requests, with the token of their running query.
+static CONNECTIONS: Lazy<Mutex<HashMap<CancelKeyData, Option<CancellationToken>>>> =
+    Lazy::new(Default::default);
+
+/// Registration of a connection's cancel key, removed when dropped.
+pub(super) struct CancelKey {
+    key: CancelKeyData,
+}
+
+impl CancelKey {
+    pub(super) fn register() -> Self {
+        let mut connections = CONNECTIONS.lock().unwrap();
+        loop {
+            let key = rand::random::<CancelKeyData>();
+            if let Entry::Vacant(entry) = connections.entry(key) {
+                entry.insert(None);
+                return CancelKey { key };
+            }
+        }
+    }
+
+    pub(super) fn key(&self) -> CancelKeyData {
+        self.key
+    }
+
+    /// Mark a query as running on the connection, until the returned guard is dropped.
+    pub(super) fn start_query(&self) -> RunningQuery {
+        let token = CancellationToken::new();
+        CONNECTIONS
+            .lock()
+            .unwrap()
+            .insert(self.key, Some(token.clone()));
+        RunningQuery {
+            key: self.key,
+            token,
+        }
+    }
+}
+
+impl Drop for CancelKey {
+    fn drop(&mut self) {
+        CONNECTIONS.lock().unwrap().remove(&self.key);
+    }
+}
+
+pub(super) struct RunningQuery {
+    key: CancelKeyData,
+    /// Fired by a cancel request for the query.
+    pub(super) token: CancellationToken,
+}
+
+impl Drop for RunningQuery {
+    fn drop(&mut self) {
+        if let Some(token) = CONNECTIONS.lock().unwrap().get_mut(&self.key) {
+            *token = None;
+        }
+    }
+}
+
+/// Cancel the query running on the connection with `key`. Returns false if there is
+/// no such connection, or it is not running a query.
+pub(super) fn cancel_query(key: &CancelKeyData) -> bool {
+    match CONNECTIONS.lock().unwrap().get(key) {
+        Some(Some(token)) if !token.is_cancelled() => {
+            token.cancel();
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_running_query() {
+        let conn = CancelKey::register();
+        assert!(!cancel_query(&conn.key()), "no query is running");
+
+        let query = conn.start_query();
+        assert!(cancel_query(&conn.key()));
+        assert!(query.token.is_cancelled());
+        assert!(!cancel_query(&conn.key()), "already cancelled");
+        drop(query);
+
+        // A cancel request that comes in after the query finished doesn't hit the next one.
+        assert!(!cancel_query(&conn.key()));
+        let query = conn.start_query();
+        assert!(!query.token.is_cancelled());
+        drop(query);
+
+        let key = conn.key();
+        drop(conn);
+        assert!(!CONNECTIONS.lock().unwrap().contains_key(&key));
+    }
+}
@@ -1539,6 +1539,25 @@ impl<'a> DatadirModification<'a> {
     /// All the modifications in this atomic update are stamped by the specified LSN.
     ///
     pub async fn commit(&mut self, ctx: &RequestContext) -> anyhow::Result<()> {
+        self.commit_inner(true, ctx).await?;
+        Ok(())
+    }
+
+    /// Like [`Self::commit`], but leaves `last_record_lsn` where it is. The LSNs the
+    /// commit would have advanced it through are returned instead, in order, for the
+    /// caller to pass to [`Timeline::finish_write`] once the data is durable.
+    pub(crate) async fn commit_unpublished(
+        &mut self,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Vec<Lsn>> {
+        self.commit_inner(false, ctx).await
+    }
+
+    async fn commit_inner(
+        &mut self,
+        publish: bool,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Vec<Lsn>> {
         let mut writer = self.tline.writer().await;
 
         let pending_nblocks = self.pending_nblocks;
@@ -1564,13 +1583,18 @@ impl<'a> DatadirModification<'a> {
         }
 
         self.pending_lsns.push(self.lsn);
-        for pending_lsn in self.pending_lsns.drain(..) {
-            // Ideally, we should be able to call writer.finish_write() only once
-            // with the highest LSN. However, the last_record_lsn variable in the
-            // timeline keeps track of the latest LSN and the immediate previous LSN
-            // so we need to record every LSN to not leave a gap between them.
-            writer.finish_write(pending_lsn);
-        }
+        let unpublished = if publish {
+            for pending_lsn in self.pending_lsns.drain(..) {
+                // Ideally, we should be able to call writer.finish_write() only once
+                // with the highest LSN. However, the last_record_lsn variable in the
+                // timeline keeps track of the latest LSN and the immediate previous LSN
+                // so we need to record every LSN to not leave a gap between them.
+                writer.finish_write(pending_lsn);
+            }
+            Vec::new()
+        } else {
+            std::mem::take(&mut self.pending_lsns)
+        };
 
         if pending_nblocks != 0 {
             writer.update_current_logical_size(pending_nblocks * i64::from(BLCKSZ));
@@ -1580,7 +1604,7 @@ impl<'a> DatadirModification<'a> {
             writer.update_directory_entries_count(kind, count as u64);
         }
 
-        Ok(())
+        Ok(unpublished)
     }
 
     pub(crate) fn len(&self) -> usize {
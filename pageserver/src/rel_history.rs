@@ -0,0 +1,123 @@
+//! When a relation was created, truncated or dropped, for the page service `rel_history`
+//! command.
+//!
+//! Creating, extending and truncating a relation write a new image of its size key, and
+//! creating or dropping it a new image of the relation directory of its database. The
+//! LSNs of these writes are collected from the timeline's layers, and comparing the
+//! relation's size before and after each of them tells what happened there.
+//!
+//! The history starts at the GC cutoff, or at the branch point for a timeline that
+//! branched off later: whatever happened to the relation before is not reported.
+
+use pageserver_api::key::{rel_dir_to_key, rel_size_to_key};
+use pageserver_api::reltag::RelTag;
+use postgres_ffi::BlockNumber;
+use serde::Serialize;
+use utils::lsn::Lsn;
+
+use crate::context::RequestContext;
+use crate::pgdatadir_mapping::Version;
+use crate::tenant::{PageReconstructError, Timeline};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelEventKind {
+    Created,
+    Truncated,
+    Dropped,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelEvent {
+    pub lsn: Lsn,
+    pub event: RelEventKind,
+    /// Size of the relation after the event; none once dropped.
+    pub nblocks: Option<BlockNumber>,
+}
+
+pub async fn rel_history(
+    timeline: &Timeline,
+    rel: RelTag,
+    ctx: &RequestContext,
+) -> anyhow::Result<Vec<RelEvent>> {
+    let start = std::cmp::max(
+        *timeline.get_latest_gc_cutoff_lsn(),
+        timeline.get_ancestor_lsn(),
+    );
+    let end = timeline.get_last_record_lsn();
+
+    let keys = [
+        rel_size_to_key(rel),
+        rel_dir_to_key(rel.spcnode, rel.dbnode),
+    ];
+    let write_lsns = timeline.key_write_lsns(&keys, ctx).await?;
+
+    let mut nblocks = rel_size_at(timeline, rel, start, ctx).await?;
+    let mut sizes = Vec::new();
+    for lsn in write_lsns {
+        if lsn > start && lsn <= end {
+            sizes.push((lsn, rel_size_at(timeline, rel, lsn, ctx).await?));
+        }
+    }
+
+    let mut history = Vec::new();
+    for (lsn, size) in sizes {
+        if let Some(event) = classify(nblocks, size) {
+            history.push(RelEvent {
+                lsn,
+                event,
+                nblocks: size,
+            });
+        }
+        nblocks = size;
+    }
+    Ok(history)
+}
+
+/// What changed between two successive sizes of a relation, none if it doesn't exist.
+/// Extensions and changes of other relations in the same directory are not reported.
+fn classify(before: Option<BlockNumber>, after: Option<BlockNumber>) -> Option<RelEventKind> {
+    match (before, after) {
+        (None, Some(_)) => Some(RelEventKind::Created),
+        (Some(_), None) => Some(RelEventKind::Dropped),
+        (Some(before), Some(after)) if after < before => Some(RelEventKind::Truncated),
+        _ => None,
+    }
+}
+
+/// Size of the relation at `lsn`, none if it doesn't exist then.
+async fn rel_size_at(
+    timeline: &Timeline,
+    rel: RelTag,
+    lsn: Lsn,
+    ctx: &RequestContext,
+) -> Result<Option<BlockNumber>, PageReconstructError> {
+    // The relation directory of a database that doesn't exist can't be read.
+    if !timeline
+        .list_dbdirs(lsn, ctx)
+        .await?
+        .contains_key(&(rel.spcnode, rel.dbnode))
+    {
+        return Ok(None);
+    }
+    let version = Version::Lsn(lsn);
+    if !timeline.get_rel_exists(rel, version, false, ctx).await? {
+        return Ok(None);
+    }
+    Ok(Some(timeline.get_rel_size(rel, version, false, ctx).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_size_changes() {
+        use RelEventKind::*;
+        assert_eq!(classify(None, Some(0)), Some(Created));
+        assert_eq!(classify(Some(10), Some(20)), None);
+        assert_eq!(classify(Some(20), Some(5)), Some(Truncated));
+        assert_eq!(classify(Some(5), None), Some(Dropped));
+        assert_eq!(classify(None, None), None);
+    }
+}
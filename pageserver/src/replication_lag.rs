@@ -0,0 +1,78 @@
+//! How far a timeline lags behind the WAL committed on the safekeepers, for the page
+//! service `replication_lag` command.
+//!
+//! The lag in bytes is the distance from the last record LSN of the timeline to the
+//! highest commit LSN the WAL receiver knows of, from its WAL connection or from the
+//! broker. The lag in time is estimated from when the broker updates first reported a
+//! commit LSN past the last record LSN: that WAL has been committed and not yet
+//! received for at least that long.
+
+use chrono::Utc;
+use serde::Serialize;
+use utils::id::NodeId;
+use utils::lsn::Lsn;
+
+use crate::tenant::Timeline;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WalSourceState {
+    /// Streaming WAL from a safekeeper.
+    Streaming,
+    /// The WAL receiver is running, but not connected to a safekeeper.
+    Disconnected,
+    /// No WAL receiver is active for the timeline, so nothing is known about the
+    /// safekeepers.
+    NoSourceConfigured,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplicationLag {
+    pub state: WalSourceState,
+    pub safekeeper_id: Option<NodeId>,
+    pub last_received_lsn: Lsn,
+    pub commit_lsn: Option<Lsn>,
+    pub lag_bytes: Option<u64>,
+    pub lag_seconds: Option<f64>,
+}
+
+pub fn replication_lag(timeline: &Timeline) -> ReplicationLag {
+    let last_received_lsn = timeline.get_last_record_lsn();
+    let Some(status) = timeline.walreceiver_manager_status() else {
+        return ReplicationLag {
+            state: WalSourceState::NoSourceConfigured,
+            safekeeper_id: None,
+            last_received_lsn,
+            commit_lsn: None,
+            lag_bytes: None,
+            lag_seconds: None,
+        };
+    };
+
+    let safekeeper_id = status.wal_source();
+    let commit_lsn = status.commit_lsn();
+    let lag_bytes = commit_lsn.map(|commit_lsn| commit_lsn.0.saturating_sub(last_received_lsn.0));
+    let lag_seconds = match lag_bytes {
+        Some(0) => Some(0.0),
+        Some(_) => status.committed_past_at(last_received_lsn).map(|since| {
+            (Utc::now().naive_utc() - since)
+                .to_std()
+                .unwrap_or_default()
+                .as_secs_f64()
+        }),
+        None => None,
+    };
+
+    ReplicationLag {
+        state: if safekeeper_id.is_some() {
+            WalSourceState::Streaming
+        } else {
+            WalSourceState::Disconnected
+        },
+        safekeeper_id,
+        last_received_lsn,
+        commit_lsn,
+        lag_bytes,
+        lag_seconds,
+    }
+}
@@ -107,6 +107,8 @@ pub struct GcResult {
     pub layers_needed_by_branches: u64,
     pub layers_not_updated: u64,
     pub layers_removed: u64, // # of layer files removed because they have been made obsolete by newer ondisk files.
+    /// Total size of the removed layer files.
+    pub bytes_removed: u64,
 
     #[serde(serialize_with = "serialize_duration_as_millis")]
     pub elapsed: Duration,
@@ -120,6 +122,29 @@ pub struct GcResult {
     pub(crate) doomed_layers: Vec<crate::tenant::storage_layer::Layer>,
 }
 
+/// Progress of a GC iteration, reported once the timelines to collect are known, while
+/// each of them is collected, and after each of them.
+#[derive(Default, Serialize, Debug, Clone)]
+pub struct GcProgress {
+    pub timelines_total: usize,
+    pub timelines_done: usize,
+    pub layers_examined: u64,
+    pub layers_removed: u64,
+    pub bytes_removed: u64,
+}
+
+impl GcProgress {
+    /// This progress plus the (partial) result of collecting one more timeline.
+    pub(crate) fn with_timeline(&self, timeline: &GcResult) -> Self {
+        Self {
+            layers_examined: self.layers_examined + timeline.layers_total,
+            layers_removed: self.layers_removed + timeline.layers_removed,
+            bytes_removed: self.bytes_removed + timeline.bytes_removed,
+            ..self.clone()
+        }
+    }
+}
+
 // helper function for `GcResult`, serializing a `Duration` as an integer number of milliseconds
 fn serialize_duration_as_millis<S>(d: &Duration, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -136,6 +161,7 @@ impl AddAssign for GcResult {
         self.layers_needed_by_branches += other.layers_needed_by_branches;
         self.layers_not_updated += other.layers_not_updated;
         self.layers_removed += other.layers_removed;
+        self.bytes_removed += other.bytes_removed;
 
         self.elapsed += other.elapsed;
 
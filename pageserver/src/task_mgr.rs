@@ -101,6 +101,7 @@ use utils::id::TimelineId;
 pub static COMPUTE_REQUEST_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
     tokio::runtime::Builder::new_multi_thread()
         .thread_name("compute request worker")
+        .on_thread_start(crate::cpu_affinity::pin_worker_thread)
         .enable_all()
         .build()
         .expect("Failed to create compute request runtime")
@@ -109,6 +110,7 @@ pub static COMPUTE_REQUEST_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
 pub static MGMT_REQUEST_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
     tokio::runtime::Builder::new_multi_thread()
         .thread_name("mgmt request worker")
+        .on_thread_start(crate::cpu_affinity::pin_worker_thread)
         .enable_all()
         .build()
         .expect("Failed to create mgmt request runtime")
@@ -117,6 +119,7 @@ pub static MGMT_REQUEST_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
 pub static WALRECEIVER_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
     tokio::runtime::Builder::new_multi_thread()
         .thread_name("walreceiver worker")
+        .on_thread_start(crate::cpu_affinity::pin_worker_thread)
         .enable_all()
         .build()
         .expect("Failed to create walreceiver runtime")
@@ -125,6 +128,7 @@ pub static WALRECEIVER_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
 pub static BACKGROUND_RUNTIME: Lazy<Runtime> = Lazy::new(|| {
     tokio::runtime::Builder::new_multi_thread()
         .thread_name("background op worker")
+        .on_thread_start(crate::cpu_affinity::pin_worker_thread)
         // if you change the number of worker threads please change the constant below
         .enable_all()
         .build()
@@ -284,6 +288,10 @@ pub enum TaskKind {
 
     // task that drives downloading layers
     DownloadAllRemoteLayers,
+
+    /// See [`crate::page_cache_warmup`].
+    PageCacheWarmup,
+
     // Task that calculates synthetis size for all active tenants
     CalculateSyntheticSize,
 
@@ -48,6 +48,7 @@ use utils::zstd::extract_zst_tarball;
 
 use self::config::AttachedLocationConfig;
 use self::config::AttachmentMode;
+use self::config::GcSettings;
 use self::config::LocationConf;
 use self::config::TenantConf;
 use self::delete::DeleteTenantFlow;
@@ -73,7 +74,7 @@ use crate::metrics::TENANT;
 use crate::metrics::{
     remove_tenant_metrics, BROKEN_TENANTS_SET, TENANT_STATE_METRIC, TENANT_SYNTHETIC_SIZE_METRIC,
 };
-use crate::repository::GcResult;
+use crate::repository::{GcProgress, GcResult};
 use crate::task_mgr;
 use crate::task_mgr::TaskKind;
 use crate::tenant::config::LocationMode;
@@ -119,32 +120,6 @@ use utils::{
     lsn::{Lsn, RecordLsn},
 };
 
-/// Declare a failpoint that can use the `pause` failpoint action.
-/// We don't want to block the executor thread, hence, spawn_blocking + await.
-macro_rules! pausable_failpoint {
-    ($name:literal) => {
-        if cfg!(feature = "testing") {
-            tokio::task::spawn_blocking({
-                let current = tracing::Span::current();
-                move || {
-                    let _entered = current.entered();
-                    tracing::info!("at failpoint {}", $name);
-                    fail::fail_point!($name);
-                }
-            })
-            .await
-            .expect("spawn_blocking");
-        }
-    };
-    ($name:literal, $cond:expr) => {
-        if cfg!(feature = "testing") {
-            if $cond {
-                pausable_failpoint!($name)
-            }
-        }
-    };
-}
-
 pub mod blob_io;
 pub mod block_io;
 pub mod vectored_blob_io;
@@ -288,6 +263,9 @@ pub struct Tenant {
     // with timelines, which in turn may cause dropping replication connection, expiration of wait_for_lsn
     // timeout...
     gc_cs: tokio::sync::Mutex<()>,
+    // Held by the background GC loop for the duration of one iteration, so that a graceful
+    // shutdown can let an in-flight iteration finish before it stops the timelines.
+    pub(crate) gc_loop_iteration: tokio::sync::Mutex<()>,
     walredo_mgr: Option<Arc<WalRedoManager>>,
 
     // provides access to timeline data sitting in the remote storage
@@ -321,6 +299,10 @@ pub struct Tenant {
     /// All [`Tenant::timelines`] of a given [`Tenant`] instance share the same [`throttle::Throttle`] instance.
     pub(crate) timeline_get_throttle:
         Arc<throttle::Throttle<&'static crate::metrics::tenant_throttling::TimelineGet>>,
+
+    /// Limits concurrent basebackups of this tenant, see
+    /// [`PageServerConf::max_concurrent_basebackups_per_tenant`].
+    pub(crate) basebackup_permits: tokio::sync::Semaphore,
 }
 
 impl std::fmt::Debug for Tenant {
@@ -390,6 +372,14 @@ impl WalRedoManager {
             WalRedoManager::Test(_) => None,
         }
     }
+
+    pub(crate) fn process_ids(&self) -> Vec<u32> {
+        match self {
+            WalRedoManager::Prod(m) => m.process_ids(),
+            #[cfg(test)]
+            WalRedoManager::Test(_) => Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error, PartialEq, Eq)]
@@ -426,6 +416,9 @@ pub enum DeleteTimelineError {
     #[error("Timeline deletion is already in progress")]
     AlreadyInProgress(Arc<tokio::sync::Mutex<DeleteTimelineFlow>>),
 
+    #[error("Timed out after {0:?} waiting for in-flight reads of the timeline")]
+    InFlightReads(Duration),
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -436,6 +429,7 @@ impl Debug for DeleteTimelineError {
             Self::NotFound => write!(f, "NotFound"),
             Self::HasChildren(c) => f.debug_tuple("HasChildren").field(c).finish(),
             Self::AlreadyInProgress(_) => f.debug_tuple("AlreadyInProgress").finish(),
+            Self::InFlightReads(timeout) => f.debug_tuple("InFlightReads").field(timeout).finish(),
             Self::Other(e) => f.debug_tuple("Other").field(e).finish(),
         }
     }
@@ -711,15 +705,21 @@ impl Tenant {
                         });
                     };
 
+                // With lazy_tenant_load, a tenant that no client asks for is never loaded,
+                // so it must not hold up the initial load.
+                let on_demand_only = matches!(mode, SpawnMode::Lazy) && conf.lazy_tenant_load;
+
                 let mut init_order = init_order;
                 // take the completion because initial tenant loading will complete when all of
                 // these tasks complete.
                 let _completion = init_order
                     .as_mut()
-                    .and_then(|x| x.initial_tenant_load.take());
+                    .and_then(|x| x.initial_tenant_load.take())
+                    .filter(|_| !on_demand_only);
                 let remote_load_completion = init_order
                     .as_mut()
-                    .and_then(|x| x.initial_tenant_load_remote.take());
+                    .and_then(|x| x.initial_tenant_load_remote.take())
+                    .filter(|_| !on_demand_only);
 
                 enum AttachType<'a> {
                     /// We are attaching this tenant lazily in the background.
@@ -738,7 +738,8 @@ impl Tenant {
                 let attach_type = if matches!(mode, SpawnMode::Lazy) {
                     // Before doing any I/O, wait for at least one of:
                     // - A client attempting to access to this tenant (on-demand loading)
-                    // - A permit becoming available in the warmup semaphore (background warmup)
+                    // - A permit becoming available in the warmup semaphore (background warmup),
+                    //   unless lazy_tenant_load is set
 
                     tokio::select!(
                         permit = tenant_clone.activate_now_sem.acquire() => {
@@ -746,7 +747,7 @@ impl Tenant {
                             tracing::info!("Activating tenant (on-demand)");
                             AttachType::OnDemand
                         },
-                        permit = conf.concurrent_tenant_warmup.inner().acquire() => {
+                        permit = conf.concurrent_tenant_warmup.inner().acquire(), if !on_demand_only => {
                             let _permit = permit.expect("concurrent_tenant_warmup semaphore is never closed");
                             tracing::info!("Activating tenant (warmup)");
                             AttachType::Warmup {
@@ -1593,6 +1594,21 @@ impl Tenant {
         pitr: Duration,
         cancel: &CancellationToken,
         ctx: &RequestContext,
+    ) -> anyhow::Result<GcResult> {
+        self.gc_iteration_with_progress(target_timeline_id, horizon, pitr, cancel, ctx, |_| {})
+            .await
+    }
+
+    /// [`Self::gc_iteration`], calling `progress` as it goes, for callers that want to
+    /// show the progress of a long GC.
+    pub async fn gc_iteration_with_progress(
+        &self,
+        target_timeline_id: Option<TimelineId>,
+        horizon: u64,
+        pitr: Duration,
+        cancel: &CancellationToken,
+        ctx: &RequestContext,
+        mut progress: impl FnMut(&GcProgress) + Send,
     ) -> anyhow::Result<GcResult> {
         // Don't start doing work during shutdown
         if let TenantState::Stopping { .. } = self.current_state() {
@@ -1614,8 +1630,15 @@ impl Tenant {
             }
         }
 
-        self.gc_iteration_internal(target_timeline_id, horizon, pitr, cancel, ctx)
-            .await
+        self.gc_iteration_internal(
+            target_timeline_id,
+            horizon,
+            pitr,
+            cancel,
+            ctx,
+            &mut progress,
+        )
+        .await
     }
 
     /// Perform one compaction iteration.
@@ -1686,6 +1709,13 @@ impl Tenant {
         self.walredo_mgr.as_ref().and_then(|mgr| mgr.status())
     }
 
+    pub(crate) fn wal_redo_process_ids(&self) -> Vec<u32> {
+        self.walredo_mgr
+            .as_ref()
+            .map(|mgr| mgr.process_ids())
+            .unwrap_or_default()
+    }
+
     /// Changes tenant status to active, unless shutdown was already requested.
     ///
     /// `background_jobs_can_start` is an optional barrier set to a value during pageserver startup
@@ -1823,6 +1853,14 @@ impl Tenant {
             }
         };
 
+        // On pageserver shutdown, let an in-flight background GC iteration finish before the
+        // timelines stop under it. The GC loop itself only notices shutdown between iterations,
+        // and no new iteration starts now that we are Stopping.
+        if freeze_and_flush {
+            tracing::info!("Waiting for in-flight GC iteration...");
+            drop(self.gc_loop_iteration.lock().await);
+        }
+
         let mut js = tokio::task::JoinSet::new();
         {
             let timelines = self.timelines.lock().unwrap();
@@ -2241,7 +2279,7 @@ impl Tenant {
 
     pub fn effective_config(&self) -> TenantConf {
         self.tenant_specific_overrides()
-            .merge(self.conf.default_tenant_conf.clone())
+            .merge(GcSettings::apply(self.conf.default_tenant_conf.clone()))
     }
 
     pub fn get_checkpoint_distance(&self) -> u64 {
@@ -2283,14 +2321,14 @@ impl Tenant {
         let tenant_conf = self.tenant_conf.read().unwrap().tenant_conf.clone();
         tenant_conf
             .gc_horizon
-            .unwrap_or(self.conf.default_tenant_conf.gc_horizon)
+            .unwrap_or_else(|| GcSettings::current(&self.conf.default_tenant_conf).horizon)
     }
 
     pub fn get_gc_period(&self) -> Duration {
         let tenant_conf = self.tenant_conf.read().unwrap().tenant_conf.clone();
         tenant_conf
             .gc_period
-            .unwrap_or(self.conf.default_tenant_conf.gc_period)
+            .unwrap_or_else(|| GcSettings::current(&self.conf.default_tenant_conf).period)
     }
 
     pub fn get_image_creation_threshold(&self) -> usize {
@@ -2504,6 +2542,7 @@ impl Tenant {
             timelines: Mutex::new(HashMap::new()),
             timelines_creating: Mutex::new(HashSet::new()),
             gc_cs: tokio::sync::Mutex::new(()),
+            gc_loop_iteration: tokio::sync::Mutex::new(()),
             walredo_mgr,
             remote_storage,
             deletion_queue_client,
@@ -2520,11 +2559,15 @@ impl Tenant {
                 &crate::metrics::tenant_throttling::TIMELINE_GET,
             )),
             tenant_conf: Arc::new(RwLock::new(attached_conf)),
+            basebackup_permits: tokio::sync::Semaphore::new(
+                conf.max_concurrent_basebackups_per_tenant
+                    .unwrap_or(tokio::sync::Semaphore::MAX_PERMITS),
+            ),
         }
     }
 
     /// Locate and load config
-    pub(super) fn load_tenant_config(
+    pub(crate) fn load_tenant_config(
         conf: &'static PageServerConf,
         tenant_shard_id: &TenantShardId,
     ) -> anyhow::Result<LocationConf> {
@@ -2718,6 +2761,7 @@ impl Tenant {
         pitr: Duration,
         cancel: &CancellationToken,
         ctx: &RequestContext,
+        progress: &mut (impl FnMut(&GcProgress) + Send),
     ) -> anyhow::Result<GcResult> {
         let mut totals: GcResult = Default::default();
         let now = Instant::now();
@@ -2758,13 +2802,28 @@ impl Tenant {
         //
         // See comments in [`Tenant::branch_timeline`] for more information about why branch
         // creation task can run concurrently with timeline's GC iteration.
+        let mut gc_progress = GcProgress {
+            timelines_total: gc_timelines.len(),
+            ..Default::default()
+        };
+        progress(&gc_progress);
         for timeline in gc_timelines {
-            if task_mgr::is_shutdown_requested() || cancel.is_cancelled() {
+            if task_mgr::is_shutdown_requested()
+                || cancel.is_cancelled()
+                || matches!(self.current_state(), TenantState::Stopping { .. })
+            {
                 // We were requested to shut down. Stop and return with the progress we
-                // made.
+                // made. A graceful shutdown waits for the timeline being collected, but
+                // the timelines are Stopping by then, so don't start on the next one.
                 break;
             }
-            let result = timeline.gc().await?;
+            let done = gc_progress.clone();
+            let result = timeline
+                .gc_with_progress(&mut |partial: &GcResult| progress(&done.with_timeline(partial)))
+                .await?;
+            gc_progress = gc_progress.with_timeline(&result);
+            gc_progress.timelines_done += 1;
+            progress(&gc_progress);
             totals += result;
         }
 
@@ -2965,7 +3024,11 @@ impl Tenant {
         // and then the planned GC cutoff
         {
             let gc_info = src_timeline.gc_info.read().unwrap();
-            let cutoff = min(gc_info.pitr_cutoff, gc_info.horizon_cutoff);
+            let mut cutoff = min(gc_info.pitr_cutoff, gc_info.horizon_cutoff);
+            // A lease keeps GC from reaching the planned cutoff.
+            if let Some(leased_lsn) = src_timeline.oldest_leased_lsn() {
+                cutoff = min(cutoff, leased_lsn);
+            }
             if start_lsn < cutoff {
                 return Err(CreateTimelineError::AncestorLsn(anyhow::anyhow!(
                     "invalid branch start lsn: less than planned GC cutoff {cutoff}"
@@ -5109,6 +5172,33 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_basebackup_without_control_file() -> anyhow::Result<()> {
+        let (tenant, ctx) = TenantHarness::create("test_basebackup_without_control_file")?
+            .load()
+            .await;
+
+        let initdb_lsn = Lsn(0x20);
+        let utline = tenant
+            .create_empty_timeline(TIMELINE_ID, initdb_lsn, DEFAULT_PG_VERSION, &ctx)
+            .await?;
+        let tline = utline.raw_timeline().unwrap();
+
+        // This is the state of an `import basebackup` target before pg_control was imported.
+        let mut modification = tline.begin_modification(initdb_lsn);
+        modification.init_empty()?;
+        modification.commit(&ctx).await?;
+
+        let err = crate::basebackup::check_control_file_exists(tline, initdb_lsn, &ctx)
+            .await
+            .expect_err("timeline has no control file");
+        assert!(
+            format!("{err:#}").contains("no control file found"),
+            "unexpected error: {err:#}"
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_create_guard_crash() -> anyhow::Result<()> {
         let name = "test_create_guard_crash";
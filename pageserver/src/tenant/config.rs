@@ -525,6 +525,49 @@ impl TenantConfOpt {
     }
 }
 
+/// Pageserver-wide GC settings changed at runtime with the `set_gc_config` page service
+/// command. They are kept apart from [`PageServerConf`](crate::config::PageServerConf),
+/// take the place of its `gc_horizon` and `gc_period` while set, and yield to a tenant's
+/// own settings like those do.
+static GC_SETTINGS_OVERRIDE: std::sync::RwLock<Option<GcSettings>> = std::sync::RwLock::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GcSettings {
+    pub horizon: u64,
+    pub period: Duration,
+}
+
+impl GcSettings {
+    /// The settings in effect for tenants that don't set their own: the runtime override,
+    /// if any, otherwise the ones from `defaults`.
+    pub fn current(defaults: &TenantConf) -> Self {
+        GC_SETTINGS_OVERRIDE.read().unwrap().unwrap_or(GcSettings {
+            horizon: defaults.gc_horizon,
+            period: defaults.gc_period,
+        })
+    }
+
+    /// Overrides the settings from `defaults` with `new`, or goes back to them if `None`.
+    /// Returns the settings that were in effect before.
+    pub fn set(new: Option<GcSettings>, defaults: &TenantConf) -> Self {
+        let mut settings = GC_SETTINGS_OVERRIDE.write().unwrap();
+        let previous = settings.unwrap_or(GcSettings {
+            horizon: defaults.gc_horizon,
+            period: defaults.gc_period,
+        });
+        *settings = new;
+        previous
+    }
+
+    /// `defaults` with the runtime override applied.
+    pub fn apply(mut defaults: TenantConf) -> TenantConf {
+        let current = Self::current(&defaults);
+        defaults.gc_horizon = current.horizon;
+        defaults.gc_period = current.period;
+        defaults
+    }
+}
+
 impl Default for TenantConf {
     fn default() -> Self {
         use defaults::*;
@@ -562,11 +562,18 @@ pub async fn init_tenant_mgr(
     // Determine which tenants are to be secondary or attached, and in which generation
     let tenant_modes = init_load_generations(conf, &tenant_configs, &resources, &cancel).await?;
 
-    tracing::info!(
-        "Attaching {} tenants at startup, warming up {} at a time",
-        tenant_configs.len(),
-        conf.concurrent_tenant_warmup.initial_permits()
-    );
+    if conf.lazy_tenant_load {
+        tracing::info!(
+            "Attaching {} tenants at startup, loading them on first access",
+            tenant_configs.len(),
+        );
+    } else {
+        tracing::info!(
+            "Attaching {} tenants at startup, warming up {} at a time",
+            tenant_configs.len(),
+            conf.concurrent_tenant_warmup.initial_permits()
+        );
+    }
     TENANT.startup_scheduled.inc_by(tenant_configs.len() as u64);
 
     // Construct `Tenant` objects and start them running
@@ -2710,14 +2717,31 @@ where
 }
 
 use {
-    crate::repository::GcResult, pageserver_api::models::TimelineGcRequest,
+    crate::repository::{GcProgress, GcResult},
+    pageserver_api::models::TimelineGcRequest,
     utils::http::error::ApiError,
 };
 
+/// All attached tenant shards, whatever their state.
+pub(crate) fn attached_tenant_shards() -> Vec<Arc<Tenant>> {
+    let locked = TENANTS.read().unwrap();
+    match &*locked {
+        TenantsMap::Initializing => Vec::new(),
+        TenantsMap::Open(map) | TenantsMap::ShuttingDown(map) => map
+            .values()
+            .filter_map(|slot| slot.get_attached().cloned())
+            .collect(),
+    }
+}
+
+/// Run GC on the timeline now. If `progress` is given, the progress of the GC is sent
+/// to it as the GC goes. Each progress includes the previous ones, so a receiver that
+/// falls behind only misses intermediate steps.
 pub(crate) fn immediate_gc(
     tenant_shard_id: TenantShardId,
     timeline_id: TimelineId,
     gc_req: TimelineGcRequest,
+    progress: Option<tokio::sync::watch::Sender<GcProgress>>,
     cancel: CancellationToken,
     ctx: &RequestContext,
 ) -> Result<tokio::sync::oneshot::Receiver<Result<GcResult, anyhow::Error>>, ApiError> {
@@ -2729,6 +2753,26 @@ pub(crate) fn immediate_gc(
         .with_context(|| format!("tenant {tenant_shard_id}"))
         .map_err(|e| ApiError::NotFound(e.into()))?;
 
+    // A horizon shorter than the configured one removes history that GC would otherwise
+    // keep, down to all of it for a horizon of 0: only do that when explicitly asked to.
+    // A horizon reaching back past the start of the WAL does nothing and is likely a typo.
+    // A missing timeline is reported by the GC iteration itself.
+    if let Some(gc_horizon) = gc_req.gc_horizon {
+        let configured_gc_horizon = tenant.get_gc_horizon();
+        if gc_horizon < configured_gc_horizon && !gc_req.allow_short_horizon {
+            return Err(ApiError::BadRequest(anyhow::anyhow!(
+                "gc_horizon {gc_horizon} is below the tenant's configured gc_horizon {configured_gc_horizon}, set allow_short_horizon to use it"
+            )));
+        }
+        if let Ok(timeline) = tenant.get_timeline(timeline_id, false) {
+            let last_record_lsn = timeline.get_last_record_lsn();
+            if gc_horizon > last_record_lsn.0 {
+                return Err(ApiError::BadRequest(anyhow::anyhow!(
+                    "gc_horizon {gc_horizon} exceeds the last record LSN {last_record_lsn} of timeline {timeline_id}"
+                )));
+            }
+        }
+    }
     let gc_horizon = gc_req.gc_horizon.unwrap_or_else(|| tenant.get_gc_horizon());
     // Use tenant's pitr setting
     let pitr = tenant.get_pitr_interval();
@@ -2751,7 +2795,12 @@ pub(crate) fn immediate_gc(
 
             #[allow(unused_mut)]
             let mut result = tenant
-                .gc_iteration(Some(timeline_id), gc_horizon, pitr, &cancel, &ctx)
+                .gc_iteration_with_progress(Some(timeline_id), gc_horizon, pitr, &cancel, &ctx, |p| {
+                    if let Some(progress) = &progress {
+                        // The receiver is gone if the client went away; GC goes on regardless.
+                        let _ = progress.send(p.clone());
+                    }
+                })
                 .await;
                 // FIXME: `gc_iteration` can return an error for multiple reasons; we should handle it
                 // better once the types support it.
@@ -27,8 +27,16 @@
 //! "values" part.  The actual page images and WAL records are stored in the
 //! "values" part.
 //!
+//! The values of layers flushed from an in-memory layer may be compressed, if
+//! `flush_compression` is configured. The codec is recorded in the summary, and
+//! each value is compressed on its own so that it can still be read without the
+//! others. Files written without compression have no codec in their summary,
+//! which reads as [`LayerCompression::None`]. Compressed files carry
+//! [`COMPRESSED_DELTA_FORMAT_VERSION`], so that older binaries refuse them.
+//!
 use crate::config::PageServerConf;
 use crate::context::{PageContentKind, RequestContext, RequestContextBuilder};
+use crate::metrics::LAYER_FLUSH_COMPRESSION;
 use crate::page_cache::{self, FileId, PAGE_SZ};
 use crate::repository::{Key, Value, KEY_SIZE};
 use crate::tenant::blob_io::BlobWriter;
@@ -42,8 +50,9 @@ use crate::tenant::vectored_blob_io::{
 use crate::tenant::{PageReconstructError, Timeline};
 use crate::virtual_file::{self, VirtualFile};
 use crate::{walrecord, TEMP_FILE_SUFFIX};
-use crate::{DELTA_FILE_MAGIC, STORAGE_FORMAT_VERSION};
+use crate::{COMPRESSED_DELTA_FORMAT_VERSION, DELTA_FILE_MAGIC, STORAGE_FORMAT_VERSION};
 use anyhow::{anyhow, bail, ensure, Context, Result};
+use async_compression::tokio::bufread::{ZstdDecoder, ZstdEncoder};
 use bytes::BytesMut;
 use camino::{Utf8Path, Utf8PathBuf};
 use futures::StreamExt;
@@ -53,11 +62,14 @@ use pageserver_api::models::LayerAccessKind;
 use pageserver_api::shard::TenantShardId;
 use rand::{distributions::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::fs::File;
 use std::io::SeekFrom;
 use std::ops::Range;
 use std::os::unix::fs::FileExt;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
 use tokio::sync::OnceCell;
 use tracing::*;
 
@@ -92,6 +104,61 @@ pub struct Summary {
     pub index_start_blk: u32,
     /// Block within the 'index', where the B-tree root page is stored
     pub index_root_blk: u32,
+    /// How the values are compressed. Older files end before this field, and the
+    /// zeroed rest of the block reads as `None`.
+    pub compression: LayerCompression,
+}
+
+/// Compression of the values in a delta layer file.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    strum_macros::EnumString,
+    strum_macros::Display,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum LayerCompression {
+    #[default]
+    None,
+    Zstd,
+}
+
+impl LayerCompression {
+    /// The summary `format_version` of a delta layer written with this compression.
+    fn format_version(self) -> u16 {
+        match self {
+            LayerCompression::None => STORAGE_FORMAT_VERSION,
+            _ => COMPRESSED_DELTA_FORMAT_VERSION,
+        }
+    }
+
+    async fn compress(self, buf: &[u8]) -> std::io::Result<Cow<'_, [u8]>> {
+        match self {
+            LayerCompression::None => Ok(Cow::Borrowed(buf)),
+            LayerCompression::Zstd => {
+                let mut compressed = Vec::new();
+                ZstdEncoder::new(buf).read_to_end(&mut compressed).await?;
+                Ok(Cow::Owned(compressed))
+            }
+        }
+    }
+
+    async fn decompress(self, buf: &[u8]) -> std::io::Result<Cow<'_, [u8]>> {
+        match self {
+            LayerCompression::None => Ok(Cow::Borrowed(buf)),
+            LayerCompression::Zstd => {
+                let mut decompressed = Vec::new();
+                ZstdDecoder::new(buf).read_to_end(&mut decompressed).await?;
+                Ok(Cow::Owned(decompressed))
+            }
+        }
+    }
 }
 
 impl From<&DeltaLayer> for Summary {
@@ -123,6 +190,7 @@ impl Summary {
 
             index_start_blk: 0,
             index_root_blk: 0,
+            compression: LayerCompression::None,
         }
     }
 }
@@ -217,6 +285,7 @@ pub struct DeltaLayerInner {
     // values copied from summary
     index_start_blk: u32,
     index_root_blk: u32,
+    compression: LayerCompression,
 
     file: VirtualFile,
     file_id: FileId,
@@ -229,6 +298,7 @@ impl std::fmt::Debug for DeltaLayerInner {
         f.debug_struct("DeltaLayerInner")
             .field("index_start_blk", &self.index_start_blk)
             .field("index_root_blk", &self.index_root_blk)
+            .field("compression", &self.compression)
             .finish()
     }
 }
@@ -379,6 +449,12 @@ struct DeltaLayerWriterInner {
     tree: DiskBtreeBuilder<BlockBuf, DELTA_KEY_SIZE>,
 
     blob_writer: BlobWriter<true>,
+
+    compression: LayerCompression,
+    /// Totals over the values written so far, for the compression metrics.
+    uncompressed_bytes: u64,
+    compressed_bytes: u64,
+    compression_time: Duration,
 }
 
 impl DeltaLayerWriterInner {
@@ -391,6 +467,7 @@ impl DeltaLayerWriterInner {
         tenant_shard_id: TenantShardId,
         key_start: Key,
         lsn_range: Range<Lsn>,
+        compression: LayerCompression,
     ) -> anyhow::Result<Self> {
         // Create the file initially with a temporary filename. We don't know
         // the end key yet, so we cannot form the final filename yet. We will
@@ -419,6 +496,10 @@ impl DeltaLayerWriterInner {
             lsn_range,
             tree: tree_builder,
             blob_writer,
+            compression,
+            uncompressed_bytes: 0,
+            compressed_bytes: 0,
+            compression_time: Duration::ZERO,
         })
     }
 
@@ -442,7 +523,20 @@ impl DeltaLayerWriterInner {
         will_init: bool,
     ) -> (Vec<u8>, anyhow::Result<()>) {
         assert!(self.lsn_range.start <= lsn);
-        let (val, res) = self.blob_writer.write_blob(val).await;
+        let (val, res) = if self.compression == LayerCompression::None {
+            self.blob_writer.write_blob(val).await
+        } else {
+            let started_at = Instant::now();
+            let compressed = match self.compression.compress(&val).await {
+                Ok(compressed) => compressed.into_owned(),
+                Err(e) => return (val, Err(anyhow::anyhow!(e))),
+            };
+            self.compression_time += started_at.elapsed();
+            self.uncompressed_bytes += val.len() as u64;
+            self.compressed_bytes += compressed.len() as u64;
+            let (_, res) = self.blob_writer.write_blob(compressed).await;
+            (val, res)
+        };
         let off = match res {
             Ok(off) => off,
             Err(e) => return (val, Err(anyhow::anyhow!(e))),
@@ -480,13 +574,14 @@ impl DeltaLayerWriterInner {
         // Fill in the summary on blk 0
         let summary = Summary {
             magic: DELTA_FILE_MAGIC,
-            format_version: STORAGE_FORMAT_VERSION,
+            format_version: self.compression.format_version(),
             tenant_id: self.tenant_shard_id.tenant_id,
             timeline_id: self.timeline_id,
             key_range: self.key_start..key_end,
             lsn_range: self.lsn_range.clone(),
             index_start_blk,
             index_root_blk,
+            compression: self.compression,
         };
 
         let mut buf = Vec::with_capacity(PAGE_SZ);
@@ -512,6 +607,14 @@ impl DeltaLayerWriterInner {
             metadata.len()
         );
 
+        if self.compression != LayerCompression::None {
+            LAYER_FLUSH_COMPRESSION.observe(
+                self.uncompressed_bytes,
+                self.compressed_bytes,
+                self.compression_time,
+            );
+        }
+
         // Note: Because we opened the file in write-only mode, we cannot
         // reuse the same VirtualFile for reading later. That's why we don't
         // set inner.file here. The first read will have to re-open it.
@@ -571,6 +674,7 @@ impl DeltaLayerWriter {
         tenant_shard_id: TenantShardId,
         key_start: Key,
         lsn_range: Range<Lsn>,
+        compression: LayerCompression,
     ) -> anyhow::Result<Self> {
         Ok(Self {
             inner: Some(
@@ -580,6 +684,7 @@ impl DeltaLayerWriter {
                     tenant_shard_id,
                     key_start,
                     lsn_range,
+                    compression,
                 )
                 .await?,
             ),
@@ -728,6 +833,8 @@ impl DeltaLayerInner {
             // production code path
             expected_summary.index_start_blk = actual_summary.index_start_blk;
             expected_summary.index_root_blk = actual_summary.index_root_blk;
+            expected_summary.compression = actual_summary.compression;
+            expected_summary.format_version = actual_summary.compression.format_version();
             if actual_summary != expected_summary {
                 bail!(
                     "in-file summary does not match expected summary. actual = {:?} expected = {:?}",
@@ -742,6 +849,7 @@ impl DeltaLayerInner {
             file_id,
             index_start_blk: actual_summary.index_start_blk,
             index_root_blk: actual_summary.index_root_blk,
+            compression: actual_summary.compression,
             max_vectored_read_bytes,
         }))
     }
@@ -802,7 +910,13 @@ impl DeltaLayerInner {
                 .with_context(|| {
                     format!("Failed to read blob from virtual file {}", self.file.path)
                 })?;
-            let val = Value::des(&buf).with_context(|| {
+            let decompressed = self.compression.decompress(&buf).await.with_context(|| {
+                format!(
+                    "Failed to decompress blob from virtual file {}",
+                    self.file.path
+                )
+            })?;
+            let val = Value::des(&decompressed).with_context(|| {
                 format!(
                     "Failed to deserialize file blob from virtual file {}",
                     self.file.path
@@ -1027,13 +1141,20 @@ impl DeltaLayerInner {
                     continue;
                 }
 
-                let value = Value::des(&blobs_buf.buf[meta.start..meta.end]);
+                let value = match self
+                    .compression
+                    .decompress(&blobs_buf.buf[meta.start..meta.end])
+                    .await
+                {
+                    Ok(buf) => Value::des(&buf).map_err(anyhow::Error::from),
+                    Err(e) => Err(anyhow::Error::from(e)),
+                };
                 let value = match value {
                     Ok(v) => v,
                     Err(e) => {
                         reconstruct_state.on_key_error(
                             meta.meta.key,
-                            PageReconstructError::from(anyhow!(e).context(format!(
+                            PageReconstructError::from(e.context(format!(
                                 "Failed to deserialize blob from virtual file {}",
                                 self.file.path,
                             ))),
@@ -1078,6 +1199,7 @@ impl DeltaLayerInner {
                         reader: BlockCursor::new(crate::tenant::block_io::BlockReaderRef::Adapter(
                             Adapter(self),
                         )),
+                        compression: self.compression,
                     };
                     let pos = BlobRef(value).pos();
                     if let Some(last) = all_keys.last_mut() {
@@ -1110,8 +1232,8 @@ impl DeltaLayerInner {
 
     pub(super) async fn dump(&self, ctx: &RequestContext) -> anyhow::Result<()> {
         println!(
-            "index_start_blk: {}, root {}",
-            self.index_start_blk, self.index_root_blk
+            "index_start_blk: {}, root {}, compression {}",
+            self.index_start_blk, self.index_root_blk, self.compression
         );
 
         let block_reader = FileBlockReader::new(&self.file, self.file_id);
@@ -1126,7 +1248,7 @@ impl DeltaLayerInner {
         let keys = self.load_keys(ctx).await?;
 
         async fn dump_blob(val: &ValueRef<'_>, ctx: &RequestContext) -> anyhow::Result<String> {
-            let buf = val.reader.read_blob(val.blob_ref.pos(), ctx).await?;
+            let buf = val.load_bytes(ctx).await?;
             let val = Value::des(&buf)?;
             let desc = match val {
                 Value::Image(img) => {
@@ -1161,9 +1283,7 @@ impl DeltaLayerInner {
             use pageserver_api::key::CHECKPOINT_KEY;
             use postgres_ffi::CheckPoint;
             if key == CHECKPOINT_KEY {
-                let buf = val.reader.read_blob(val.blob_ref.pos(), ctx).await?;
-                let val = Value::des(&buf)?;
-                match val {
+                match val.load(ctx).await? {
                     Value::Image(img) => {
                         let checkpoint = CheckPoint::decode(&img)?;
                         println!("   CHECKPOINT: {:?}", checkpoint);
@@ -1193,16 +1313,26 @@ pub struct DeltaEntry<'a> {
 pub struct ValueRef<'a> {
     blob_ref: BlobRef,
     reader: BlockCursor<'a>,
+    compression: LayerCompression,
 }
 
 impl<'a> ValueRef<'a> {
     /// Loads the value from disk
     pub async fn load(&self, ctx: &RequestContext) -> Result<Value> {
-        // theoretically we *could* record an access time for each, but it does not really matter
-        let buf = self.reader.read_blob(self.blob_ref.pos(), ctx).await?;
+        let buf = self.load_bytes(ctx).await?;
         let val = Value::des(&buf)?;
         Ok(val)
     }
+
+    /// Loads the serialized value from disk, decompressed
+    async fn load_bytes(&self, ctx: &RequestContext) -> Result<Vec<u8>> {
+        // theoretically we *could* record an access time for each, but it does not really matter
+        let buf = self.reader.read_blob(self.blob_ref.pos(), ctx).await?;
+        match self.compression {
+            LayerCompression::None => Ok(buf),
+            compression => Ok(compression.decompress(&buf).await?.into_owned()),
+        }
+    }
 }
 
 pub(crate) struct Adapter<T>(T);
@@ -1526,6 +1656,7 @@ mod test {
             harness.tenant_shard_id,
             entries_meta.key_range.start,
             entries_meta.lsn_range.clone(),
+            LayerCompression::None,
         )
         .await?;
 
@@ -1594,4 +1725,97 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_delta_layer_compressed_values() -> anyhow::Result<()> {
+        let harness = TenantHarness::create("test_delta_layer_compressed_values")?;
+        let (tenant, ctx) = harness.load().await;
+
+        let timeline_id = TimelineId::generate();
+        let timeline = tenant
+            .create_test_timeline(timeline_id, constants::LSN_OFFSET, DEFAULT_PG_VERSION, &ctx)
+            .await?;
+
+        let key = Key::from_i128(0x10);
+        let lsns = [Lsn(0x20), Lsn(0x28), Lsn(0x30)];
+        let values = lsns
+            .iter()
+            .map(|lsn| Value::Image(vec![lsn.0 as u8; PAGE_SZ].into()))
+            .collect::<Vec<_>>();
+
+        let mut writer = DeltaLayerWriter::new(
+            harness.conf,
+            timeline_id,
+            harness.tenant_shard_id,
+            key,
+            Lsn(0x20)..Lsn(0x38),
+            LayerCompression::Zstd,
+        )
+        .await?;
+        for (lsn, value) in lsns.iter().zip(values.iter()) {
+            writer.put_value(key, *lsn, value.clone()).await?;
+        }
+        let resident = writer.finish(key.next(), &timeline).await?;
+
+        let inner = resident.get_inner_delta(&ctx).await?;
+        assert_eq!(inner.compression, LayerCompression::Zstd);
+        // Repetitive pages shrink well below their size.
+        assert!(inner.index_start_blk <= 2);
+
+        let entries = inner.load_keys(&ctx).await?;
+        assert_eq!(entries.len(), values.len());
+        for (entry, value) in entries.iter().zip(values.iter()) {
+            assert_eq!(&entry.val.load(&ctx).await?, value);
+        }
+
+        let mut reconstruct_state = ValueReconstructState {
+            records: Vec::new(),
+            img: None,
+        };
+        inner
+            .get_value_reconstruct_data(key, Lsn(0x20)..Lsn(0x38), &mut reconstruct_state, &ctx)
+            .await?;
+        assert_eq!(
+            reconstruct_state
+                .img
+                .map(|(lsn, img)| (lsn, Value::Image(img))),
+            Some((Lsn(0x30), values[2].clone()))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary_without_compression_reads_as_none() {
+        /// The summary as written before the compression field was added.
+        #[derive(Serialize, Deserialize)]
+        struct OldSummary {
+            magic: u16,
+            format_version: u16,
+            tenant_id: TenantId,
+            timeline_id: TimelineId,
+            key_range: Range<Key>,
+            lsn_range: Range<Lsn>,
+            index_start_blk: u32,
+            index_root_blk: u32,
+        }
+
+        let old = OldSummary {
+            magic: DELTA_FILE_MAGIC,
+            format_version: STORAGE_FORMAT_VERSION,
+            tenant_id: TenantId::generate(),
+            timeline_id: TimelineId::generate(),
+            key_range: Key::from_i128(0x10)..Key::from_i128(0x20),
+            lsn_range: Lsn(0x10)..Lsn(0x20),
+            index_start_blk: 1,
+            index_root_blk: 2,
+        };
+        let mut blk = old.ser().unwrap();
+        blk.resize(PAGE_SZ, 0);
+
+        let summary = Summary::des_prefix(&blk).unwrap();
+        assert_eq!(summary.compression, LayerCompression::None);
+        assert_eq!(summary.format_version, summary.compression.format_version());
+        assert_eq!(summary.index_root_blk, 2);
+    }
 }
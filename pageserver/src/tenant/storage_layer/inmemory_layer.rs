@@ -218,6 +218,10 @@ impl InMemoryLayer {
         self.inner.try_read().map(|i| i.file.len()).ok()
     }
 
+    pub(crate) async fn len(&self) -> u64 {
+        self.inner.read().await.file.len()
+    }
+
     pub(crate) fn assert_writable(&self) {
         assert!(self.end_lsn.get().is_none());
     }
@@ -230,6 +234,16 @@ impl InMemoryLayer {
         self.start_lsn..self.end_lsn_or_max()
     }
 
+    /// LSNs at which the layer holds a value of `key`, in ascending order.
+    pub(crate) async fn key_lsns(&self, key: &Key) -> Vec<Lsn> {
+        let inner = self.inner.read().await;
+        inner
+            .index
+            .get(key)
+            .map(|versions| versions.as_slice().iter().map(|(lsn, _)| *lsn).collect())
+            .unwrap_or_default()
+    }
+
     /// debugging function to print out the contents of the layer
     ///
     /// this is likely completly unused
@@ -568,6 +582,7 @@ impl InMemoryLayer {
             self.tenant_shard_id,
             Key::MIN,
             self.start_lsn..end_lsn,
+            self.conf.flush_compression,
         )
         .await?;
 
@@ -304,6 +304,7 @@ async fn gc_loop(tenant: Arc<Tenant>, cancel: CancellationToken) {
     const MAX_BACKOFF_SECS: f64 = 300.0;
     // How many errors we have seen consequtively
     let mut error_run_count = 0;
+    let mut completed_iterations = 0;
 
     TENANT_TASK_EVENTS.with_label_values(&["start"]).inc();
     async {
@@ -341,10 +342,17 @@ async fn gc_loop(tenant: Arc<Tenant>, cancel: CancellationToken) {
                 // check again in 10 seconds, in case it's been enabled again.
                 Duration::from_secs(10)
             } else {
-                // Run gc
+                // Run gc. A graceful tenant shutdown waits on `gc_loop_iteration` before it
+                // stops the timelines, so the iteration isn't cancelled midway: `cancel` fires
+                // only after the lock has been released.
+                let iteration = tenant.gc_loop_iteration.lock().await;
+                if tenant.current_state() != TenantState::Active {
+                    break;
+                }
                 let res = tenant
                     .gc_iteration(None, gc_horizon, tenant.get_pitr_interval(), &cancel, &ctx)
                     .await;
+                drop(iteration);
                 if let Err(e) = res {
                     let wait_duration = backoff::exponential_backoff_duration_seconds(
                         error_run_count + 1,
@@ -359,6 +367,7 @@ async fn gc_loop(tenant: Arc<Tenant>, cancel: CancellationToken) {
                     wait_duration
                 } else {
                     error_run_count = 0;
+                    completed_iterations += 1;
                     period
                 }
             };
@@ -375,6 +384,25 @@ async fn gc_loop(tenant: Arc<Tenant>, cancel: CancellationToken) {
         }
     }
     .await;
+
+    // Leave a trace of how far GC got, for comparison with the state after restart.
+    if completed_iterations > 0 {
+        let cutoffs = tenant
+            .list_timelines()
+            .iter()
+            .map(|timeline| {
+                format!(
+                    "{}={}",
+                    timeline.timeline_id,
+                    *timeline.get_latest_gc_cutoff_lsn()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        info!(
+            "GC loop stopped after {completed_iterations} iterations, latest GC cutoffs: {cutoffs}"
+        );
+    }
     TENANT_TASK_EVENTS.with_label_values(&["stop"]).inc();
 }
 
@@ -4,6 +4,7 @@ mod eviction_task;
 mod init;
 pub mod layer_manager;
 pub(crate) mod logical_size;
+mod lsn_lease;
 pub mod span;
 pub mod uninit;
 mod walreceiver;
@@ -46,8 +47,8 @@ use std::sync::{Arc, Mutex, RwLock, Weak};
 use std::time::{Duration, Instant, SystemTime};
 use std::{
     array,
-    collections::{BTreeMap, HashMap, HashSet},
-    sync::atomic::AtomicU64,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::atomic::{AtomicBool, AtomicU64},
 };
 use std::{
     cmp::{max, min, Ordering},
@@ -69,8 +70,8 @@ use crate::{
     disk_usage_eviction_task::finite_f32,
     tenant::storage_layer::{
         AsLayerDesc, DeltaLayerWriter, EvictionError, ImageLayerWriter, InMemoryLayer, Layer,
-        LayerAccessStatsReset, LayerFileName, ResidentLayer, ValueReconstructResult,
-        ValueReconstructState, ValuesReconstructState,
+        LayerAccessStatsReset, LayerFileName, PersistentLayerDesc, ResidentLayer,
+        ValueReconstructResult, ValueReconstructState, ValuesReconstructState,
     },
 };
 use crate::{
@@ -116,7 +117,7 @@ pub(super) use self::eviction_task::EvictionTaskTenantState;
 use self::eviction_task::EvictionTaskTimelineState;
 use self::layer_manager::LayerManager;
 use self::logical_size::LogicalSize;
-use self::walreceiver::{WalReceiver, WalReceiverConf};
+use self::walreceiver::{SwitchedSource, WalReceiver, WalReceiverConf};
 
 use super::remote_timeline_client::RemoteTimelineClient;
 use super::secondary::heatmap::{HeatMapLayer, HeatMapTimeline};
@@ -295,6 +296,9 @@ pub struct Timeline {
     // garbage collecting data that is still needed by the child timelines.
     pub gc_info: std::sync::RwLock<GcInfo>,
 
+    /// LSNs that GC must not move the cutoff past, see [`Timeline::pin_lsn`].
+    lsn_leases: std::sync::Mutex<lsn_lease::LsnLeases>,
+
     // It may change across major versions so for simplicity
     // keep it after running initdb for a timeline.
     // It is needed in checks when we want to error on some operations
@@ -330,6 +334,9 @@ pub struct Timeline {
     /// Prevent two tasks from deleting the timeline at the same time. If held, the
     /// timeline is being deleted. If 'true', the timeline has already been deleted.
     pub delete_progress: Arc<tokio::sync::Mutex<DeleteTimelineFlow>>,
+    /// Set by [`DeleteTimelineFlow`] before it cancels the timeline, see
+    /// [`Timeline::is_being_deleted`].
+    pub(crate) deletion_started: AtomicBool,
 
     eviction_task_timeline_state: tokio::sync::Mutex<EvictionTaskTimelineState>,
 
@@ -355,8 +362,9 @@ pub struct Timeline {
 
     /// Make sure we only have one running gc at a time.
     ///
-    /// Must only be taken in two places:
+    /// Must only be taken in three places:
     /// - [`Timeline::gc`] (this file)
+    /// - [`Timeline::pin_lsn`] (this file)
     /// - [`delete::delete_local_timeline_directory`]
     ///
     /// Timeline deletion will acquire both compaction and gc locks in whatever order.
@@ -544,6 +552,16 @@ pub(crate) enum WaitLsnError {
     Timeout(String),
 }
 
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum PinLsnError {
+    #[error("Shutdown")]
+    Shutdown,
+
+    // GC has already moved the cutoff past the LSN.
+    #[error(transparent)]
+    LsnTooOld(anyhow::Error),
+}
+
 // The impls below achieve cancellation mapping for errors.
 // Perhaps there's a way of achieving this with less cruft.
 
@@ -1122,6 +1140,26 @@ impl Timeline {
         }
     }
 
+    /// Status of the WAL receiver, or `None` if it is stopped or not active.
+    pub(crate) fn walreceiver_manager_status(
+        &self,
+    ) -> Option<walreceiver::ConnectionManagerStatus> {
+        self.walreceiver.lock().unwrap().as_ref()?.status()
+    }
+
+    /// Move the WAL stream of this timeline to another safekeeper, see
+    /// [`WalReceiver::switch_source`].
+    pub(crate) async fn switch_wal_source(
+        &self,
+        safekeeper_connstr: String,
+    ) -> anyhow::Result<SwitchedSource> {
+        let switch = match &*self.walreceiver.lock().unwrap() {
+            Some(walreceiver) => walreceiver.switch_source(safekeeper_connstr),
+            None => anyhow::bail!("WAL receiver is not running"),
+        };
+        switch.await
+    }
+
     /// Check that it is valid to request operations with that lsn.
     pub(crate) fn check_lsn_is_in_scope(
         &self,
@@ -1137,6 +1175,43 @@ impl Timeline {
         Ok(())
     }
 
+    /// Keep GC from moving the cutoff past `lsn` for `ttl`, or until the returned lease
+    /// is released with [`Timeline::unpin_lsn`].
+    pub(crate) fn pin_lsn(&self, lsn: Lsn, ttl: Duration) -> Result<u64, PinLsnError> {
+        if self.cancel.is_cancelled() {
+            return Err(PinLsnError::Shutdown);
+        }
+        // GC lowers its cutoff to the oldest lease right before publishing it, under this
+        // lock. So a running GC iteration either sees the new lease, or has published the
+        // cutoff we check against here: there is no need to wait for it.
+        let mut leases = self.lsn_leases.lock().unwrap();
+        self.check_lsn_is_in_scope(lsn, &self.get_latest_gc_cutoff_lsn())
+            .map_err(PinLsnError::LsnTooOld)?;
+        let lease_id = leases.grant(lsn, ttl, Instant::now());
+        drop(leases);
+        info!(%lsn, lease_id, ttl = %humantime::format_duration(ttl), "pinned LSN against GC");
+        Ok(lease_id)
+    }
+
+    /// Release a lease taken with [`Timeline::pin_lsn`]. Returns false if there is no
+    /// such lease, or it has expired already.
+    pub(crate) fn unpin_lsn(&self, lease_id: u64) -> bool {
+        let released = self
+            .lsn_leases
+            .lock()
+            .unwrap()
+            .release(lease_id, Instant::now());
+        if released {
+            info!(lease_id, "unpinned LSN");
+        }
+        released
+    }
+
+    /// The lowest LSN that GC is held back at by a lease, if any.
+    pub(crate) fn oldest_leased_lsn(&self) -> Option<Lsn> {
+        self.lsn_leases.lock().unwrap().oldest_lsn(Instant::now())
+    }
+
     /// Flush to disk all data that was written with the put_* functions
     #[instrument(skip(self), fields(tenant_id=%self.tenant_shard_id.tenant_id, shard_id=%self.tenant_shard_id.shard_slug(), timeline_id=%self.timeline_id))]
     pub(crate) async fn freeze_and_flush(&self) -> anyhow::Result<()> {
@@ -1144,6 +1219,36 @@ impl Timeline {
         self.flush_frozen_layers_and_wait().await
     }
 
+    /// Flush data that was written without advancing `last_record_lsn`, see
+    /// [`DatadirModification::commit_unpublished`], and only then advance it through
+    /// `record_lsns`. Readers don't see those records until they are on disk.
+    ///
+    /// [`DatadirModification::commit_unpublished`]: crate::pgdatadir_mapping::DatadirModification::commit_unpublished
+    pub(crate) async fn flush_and_publish(&self, record_lsns: &[Lsn]) -> anyhow::Result<()> {
+        let Some(&end_lsn) = record_lsns.last() else {
+            return Ok(());
+        };
+        {
+            let _write_guard = self.write_lock.lock().await;
+            self.freeze_inmem_layer_at(end_lsn).await;
+        }
+        self.flush_frozen_layers_and_wait().await?;
+
+        let disk_consistent_lsn = self.get_disk_consistent_lsn();
+        ensure!(
+            disk_consistent_lsn >= end_lsn,
+            "flushed up to {disk_consistent_lsn}, expected {end_lsn}"
+        );
+        for &lsn in record_lsns {
+            self.finish_write(lsn);
+        }
+
+        // The flush stored the metadata without a prev_record_lsn, as it was not known
+        // yet. Now that last_record_lsn has caught up, store it again.
+        self.schedule_uploads(disk_consistent_lsn, std::iter::empty())?;
+        Ok(())
+    }
+
     /// If there is no writer, and conditions for rolling the latest layer are met, then freeze it.
     ///
     /// This is for use in background housekeeping, to provide guarantees of layers closing eventually
@@ -1422,6 +1527,13 @@ impl Timeline {
         self.current_state() == TimelineState::Stopping
     }
 
+    /// Whether [`DeleteTimelineFlow`] has started on this timeline. Unlike a timeline that
+    /// is stopping for a detach or a pageserver shutdown, a deleted one won't come back
+    /// elsewhere, so readers should give up on it rather than reconnect.
+    pub(crate) fn is_being_deleted(&self) -> bool {
+        self.deletion_started.load(AtomicOrdering::Acquire)
+    }
+
     pub(crate) fn subscribe_for_state_updates(&self) -> watch::Receiver<TimelineState> {
         self.state.subscribe()
     }
@@ -1451,6 +1563,79 @@ impl Timeline {
         }
     }
 
+    /// Descriptors of all layer files of this timeline, as of a single point in time:
+    /// compaction and GC cannot change the layer map while the list is taken.
+    pub(crate) async fn layer_file_descs(&self) -> Vec<Arc<PersistentLayerDesc>> {
+        let guard = self.layers.read().await;
+        guard.layer_map().iter_historic_layers().collect()
+    }
+
+    /// LSNs at which this timeline's layers hold a value of one of `keys`, in ascending
+    /// order, not including the history inherited from the ancestor. The whole index of
+    /// every delta layer covering a key is read, downloading it if need be, so this is
+    /// only meant for debugging.
+    pub(crate) async fn key_write_lsns(
+        &self,
+        keys: &[Key],
+        ctx: &RequestContext,
+    ) -> anyhow::Result<Vec<Lsn>> {
+        let (layers, in_memory_layers) = {
+            let guard = self.layers.read().await;
+            let layer_map = guard.layer_map();
+            let layers = layer_map
+                .iter_historic_layers()
+                .filter(|desc| keys.iter().any(|key| desc.key_range.contains(key)))
+                .map(|desc| (guard.get_from_desc(&desc), desc))
+                .collect::<Vec<_>>();
+            let in_memory_layers = layer_map
+                .open_layer
+                .iter()
+                .chain(layer_map.frozen_layers.iter())
+                .cloned()
+                .collect::<Vec<_>>();
+            (layers, in_memory_layers)
+        };
+
+        let mut lsns = BTreeSet::new();
+        for (layer, desc) in layers {
+            if !desc.is_delta() {
+                lsns.insert(desc.image_layer_lsn());
+                continue;
+            }
+            let resident = layer.download_and_keep_resident().await?;
+            for entry in resident.load_keys(ctx).await? {
+                if keys.contains(&entry.key) {
+                    lsns.insert(entry.lsn);
+                }
+            }
+        }
+        for layer in in_memory_layers {
+            for key in keys {
+                lsns.extend(layer.key_lsns(key).await);
+            }
+        }
+        Ok(lsns.into_iter().collect())
+    }
+
+    /// Bytes of WAL held in the open and frozen in-memory layers.
+    pub(crate) async fn in_memory_layers_len(&self) -> u64 {
+        let in_memory_layers = {
+            let guard = self.layers.read().await;
+            let layer_map = guard.layer_map();
+            layer_map
+                .open_layer
+                .iter()
+                .chain(layer_map.frozen_layers.iter())
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let mut len = 0;
+        for layer in in_memory_layers {
+            len += layer.len().await;
+        }
+        len
+    }
+
     pub(crate) async fn layer_map_info(&self, reset: LayerAccessStatsReset) -> LayerMapInfo {
         let guard = self.layers.read().await;
         let layer_map = guard.layer_map();
@@ -1765,6 +1950,7 @@ impl Timeline {
                     horizon_cutoff: Lsn(0),
                     pitr_cutoff: Lsn(0),
                 }),
+                lsn_leases: std::sync::Mutex::default(),
 
                 latest_gc_cutoff_lsn: Rcu::new(metadata.latest_gc_cutoff_lsn()),
                 initdb_lsn: metadata.initdb_lsn(),
@@ -1793,6 +1979,7 @@ impl Timeline {
                     EvictionTaskTimelineState::default(),
                 ),
                 delete_progress: Arc::new(tokio::sync::Mutex::new(DeleteTimelineFlow::default())),
+                deletion_started: AtomicBool::new(false),
 
                 cancel,
                 gate: Gate::default(),
@@ -1915,6 +2102,8 @@ impl Timeline {
                 auth_token: crate::config::SAFEKEEPER_AUTH_TOKEN.get().cloned(),
                 availability_zone: self.conf.availability_zone.clone(),
                 ingest_batch_size: self.conf.ingest_batch_size,
+                retry_min_backoff: self.conf.wal_connect_retry_min_backoff,
+                retry_max_backoff: self.conf.wal_connect_retry_max_backoff,
             },
             broker_client,
             ctx,
@@ -2991,11 +3180,13 @@ impl Timeline {
     ) -> Option<(Lsn, Bytes)> {
         let cache = page_cache::get();
 
+        self.metrics.page_cache_read_accesses.inc();
         // FIXME: It's pointless to check the cache for things that are not 8kB pages.
         // We should look at the key to determine if it's a cacheable object
         let (lsn, read_guard) = cache
             .lookup_materialized_page(self.tenant_shard_id, self.timeline_id, key, lsn, ctx)
             .await?;
+        self.metrics.page_cache_read_hits.inc();
         let img = Bytes::from(read_guard.to_vec());
         Some((lsn, img))
     }
@@ -3982,6 +4173,15 @@ impl Timeline {
     /// within a layer file. We can only remove the whole file if it's fully
     /// obsolete.
     pub(super) async fn gc(&self) -> anyhow::Result<GcResult> {
+        self.gc_with_progress(&mut |_: &GcResult| {}).await
+    }
+
+    /// [`Self::gc`], calling `progress` with the partial result as the layers are
+    /// examined and once they have been removed.
+    pub(super) async fn gc_with_progress(
+        &self,
+        progress: &mut (impl FnMut(&GcResult) + Send),
+    ) -> anyhow::Result<GcResult> {
         // this is most likely the background tasks, but it might be the spawned task from
         // immediate_gc
         let cancel = crate::task_mgr::shutdown_token();
@@ -4008,10 +4208,29 @@ impl Timeline {
             (horizon_cutoff, pitr_cutoff, retain_lsns)
         };
 
+        // Keep everything above the LSNs pinned by leases, as if they were within both
+        // the horizon and the PITR interval.
+        let (horizon_cutoff, pitr_cutoff) = match self.oldest_leased_lsn() {
+            Some(leased_lsn) => {
+                debug!("GC held back by a lease at {leased_lsn}");
+                (
+                    min(horizon_cutoff, leased_lsn),
+                    min(pitr_cutoff, leased_lsn),
+                )
+            }
+            None => (horizon_cutoff, pitr_cutoff),
+        };
+
         let new_gc_cutoff = Lsn::min(horizon_cutoff, pitr_cutoff);
 
         let res = self
-            .gc_timeline(horizon_cutoff, pitr_cutoff, retain_lsns, new_gc_cutoff)
+            .gc_timeline(
+                horizon_cutoff,
+                pitr_cutoff,
+                retain_lsns,
+                new_gc_cutoff,
+                progress,
+            )
             .instrument(
                 info_span!("gc_timeline", timeline_id = %self.timeline_id, cutoff = %new_gc_cutoff),
             )
@@ -4025,11 +4244,16 @@ impl Timeline {
 
     async fn gc_timeline(
         &self,
-        horizon_cutoff: Lsn,
-        pitr_cutoff: Lsn,
+        mut horizon_cutoff: Lsn,
+        mut pitr_cutoff: Lsn,
         retain_lsns: Vec<Lsn>,
-        new_gc_cutoff: Lsn,
+        mut new_gc_cutoff: Lsn,
+        progress: &mut (impl FnMut(&GcResult) + Send),
     ) -> anyhow::Result<GcResult> {
+        // How often to report progress while examining layers. This happens under the
+        // layer map lock, so `progress` must be cheap.
+        const PROGRESS_INTERVAL_LAYERS: u64 = 1000;
+
         let now = SystemTime::now();
         let mut result: GcResult = GcResult::default();
 
@@ -4048,6 +4272,15 @@ impl Timeline {
         //
         // The GC cutoff should only ever move forwards.
         let waitlist = {
+            // A lease may have been taken since the cutoffs were computed. Check again under
+            // the lease lock, which `pin_lsn` holds while checking against the published
+            // cutoff, so that every lease is either seen here or refused there.
+            let mut leases = self.lsn_leases.lock().unwrap();
+            if let Some(leased_lsn) = leases.oldest_lsn(Instant::now()) {
+                horizon_cutoff = min(horizon_cutoff, leased_lsn);
+                pitr_cutoff = min(pitr_cutoff, leased_lsn);
+                new_gc_cutoff = min(new_gc_cutoff, leased_lsn);
+            }
             let write_guard = self.latest_gc_cutoff_lsn.lock_for_write();
             ensure!(
                 *write_guard <= new_gc_cutoff,
@@ -4078,6 +4311,9 @@ impl Timeline {
         let layers = guard.layer_map();
         'outer: for l in layers.iter_historic_layers() {
             result.layers_total += 1;
+            if result.layers_total % PROGRESS_INTERVAL_LAYERS == 0 {
+                progress(&result);
+            }
 
             // 1. Is it newer than GC horizon cutoff point?
             if l.get_lsn_range().end > horizon_cutoff {
@@ -4171,12 +4407,14 @@ impl Timeline {
                 .collect::<Vec<Layer>>();
 
             result.layers_removed = gc_layers.len() as u64;
+            result.bytes_removed = layers_to_remove.iter().map(|l| l.file_size).sum();
 
             if let Some(remote_client) = self.remote_client.as_ref() {
                 remote_client.schedule_gc_update(&gc_layers)?;
             }
 
             guard.finish_gc_timeline(&gc_layers);
+            progress(&result);
 
             #[cfg(feature = "testing")]
             {
@@ -1,6 +1,7 @@
 use std::{
     ops::{Deref, DerefMut},
-    sync::Arc,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
 };
 
 use anyhow::Context;
@@ -23,6 +24,10 @@ use crate::{
 
 use super::{Timeline, TimelineResources};
 
+/// How long the deletion waits for in-flight reads of the timeline, such as page service
+/// requests, to drop their gate guards.
+const IN_FLIGHT_READS_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Now that the Timeline is in Stopping state, request all the related tasks to shut down.
 async fn stop_tasks(timeline: &Timeline) -> Result<(), DeleteTimelineError> {
     debug_assert_current_span_has_tenant_and_timeline_id();
@@ -68,8 +73,17 @@ async fn stop_tasks(timeline: &Timeline) -> Result<(), DeleteTimelineError> {
         ))?
     });
 
+    // Readers see the cancellation above and let go of the gate; one that is stuck
+    // must not hold up the deletion forever. Giving up on closing the gate opens it
+    // again, but the timeline stays Stopping, so no new reads start, and the deletion
+    // can be retried.
     tracing::debug!("Waiting for gate...");
-    timeline.gate.close().await;
+    if tokio::time::timeout(IN_FLIGHT_READS_TIMEOUT, timeline.gate.close())
+        .await
+        .is_err()
+    {
+        return Err(DeleteTimelineError::InFlightReads(IN_FLIGHT_READS_TIMEOUT));
+    }
     tracing::debug!("Shutdown complete");
 
     Ok(())
@@ -332,6 +346,7 @@ impl DeleteTimelineFlow {
                 .try_lock_owned()
                 .expect("cannot happen because we're the only owner"),
         );
+        timeline.deletion_started.store(true, Ordering::Release);
 
         // We meed to do this because when console retries delete request we shouldnt answer with 404
         // because 404 means successful deletion.
@@ -414,6 +429,9 @@ impl DeleteTimelineFlow {
             }
         };
 
+        // Before anything is cancelled, so that readers seeing the cancellation can tell it
+        // is a deletion. A failed deletion is retried, so the flag is never cleared.
+        timeline.deletion_started.store(true, Ordering::Release);
         timeline.set_state(TimelineState::Stopping);
 
         Ok((Arc::clone(timeline), delete_lock_guard))
@@ -0,0 +1,81 @@
+//! Leases that hold back GC on a timeline, taken with the page service `pin_lsn` command.
+//!
+//! A long-running basebackup or a branch creation needs the history at its LSN to stay
+//! around until it is done. While a lease is active, GC does not move the cutoff above
+//! the leased LSN. Leases expire after a TTL, so that a client that dies without
+//! releasing its lease doesn't block GC forever.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use utils::lsn::Lsn;
+
+/// Lease ids are unique within the process, so that a stale id can't release a lease
+/// that was granted to someone else.
+static NEXT_LEASE_ID: AtomicU64 = AtomicU64::new(1);
+
+struct Lease {
+    lsn: Lsn,
+    expires_at: Instant,
+}
+
+#[derive(Default)]
+pub(crate) struct LsnLeases {
+    leases: HashMap<u64, Lease>,
+}
+
+impl LsnLeases {
+    /// Hold back GC at `lsn` until `now + ttl`. Returns the id to release the lease with.
+    pub(crate) fn grant(&mut self, lsn: Lsn, ttl: Duration, now: Instant) -> u64 {
+        let id = NEXT_LEASE_ID.fetch_add(1, Ordering::Relaxed);
+        self.leases.insert(
+            id,
+            Lease {
+                lsn,
+                expires_at: now + ttl,
+            },
+        );
+        id
+    }
+
+    /// Returns false if there is no such lease, or it has expired already.
+    pub(crate) fn release(&mut self, id: u64, now: Instant) -> bool {
+        self.leases
+            .remove(&id)
+            .is_some_and(|lease| lease.expires_at > now)
+    }
+
+    /// The lowest LSN of the active leases, after forgetting the expired ones.
+    pub(crate) fn oldest_lsn(&mut self, now: Instant) -> Option<Lsn> {
+        self.leases.retain(|_, lease| lease.expires_at > now);
+        self.leases.values().map(|lease| lease.lsn).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leases_expire() {
+        let now = Instant::now();
+        let ttl = Duration::from_secs(60);
+        let mut leases = LsnLeases::default();
+        assert_eq!(leases.oldest_lsn(now), None);
+
+        let first = leases.grant(Lsn(0x20), ttl, now);
+        let second = leases.grant(Lsn(0x10), ttl, now + ttl / 2);
+        assert_ne!(first, second);
+        assert_eq!(leases.oldest_lsn(now), Some(Lsn(0x10)));
+
+        assert!(leases.release(second, now));
+        assert!(!leases.release(second, now));
+        assert_eq!(leases.oldest_lsn(now), Some(Lsn(0x20)));
+
+        // An expired lease no longer holds back GC, and can't be released.
+        let third = leases.grant(Lsn(0x30), ttl, now);
+        assert_eq!(leases.oldest_lsn(now + ttl), None);
+        assert!(!leases.release(third, now + ttl));
+    }
+}
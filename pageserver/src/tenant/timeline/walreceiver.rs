@@ -36,13 +36,14 @@ use std::num::NonZeroU64;
 use std::sync::Arc;
 use std::time::Duration;
 use storage_broker::BrokerClientChannel;
-use tokio::sync::watch;
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio_util::sync::CancellationToken;
 use tracing::*;
 
-use utils::id::TimelineId;
+use utils::id::{NodeId, TimelineId};
+use utils::lsn::Lsn;
 
-use self::connection_manager::ConnectionManagerStatus;
+pub(crate) use self::connection_manager::ConnectionManagerStatus;
 
 use super::Timeline;
 
@@ -57,12 +58,32 @@ pub struct WalReceiverConf {
     pub auth_token: Option<Arc<String>>,
     pub availability_zone: Option<String>,
     pub ingest_batch_size: u64,
+    /// Bounds of the exponential backoff between connection attempts to the same safekeeper.
+    pub retry_min_backoff: Duration,
+    pub retry_max_backoff: Duration,
 }
 
 pub struct WalReceiver {
     tenant_shard_id: TenantShardId,
     timeline_id: TimelineId,
     manager_status: Arc<std::sync::RwLock<Option<ConnectionManagerStatus>>>,
+    switch_requests: mpsc::Sender<SwitchSourceRequest>,
+}
+
+/// Asks the connection manager to stream WAL from the given safekeeper from now on.
+pub(crate) struct SwitchSourceRequest {
+    safekeeper_connstr: String,
+    reply: oneshot::Sender<anyhow::Result<SwitchedSource>>,
+}
+
+/// Outcome of a [`WalReceiver::switch_source`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SwitchedSource {
+    pub safekeeper_id: NodeId,
+    /// Last record LSN once the old connection was stopped; the new one resumes from here.
+    pub switch_lsn: Lsn,
+    /// Commit LSN of the new safekeeper, as last published to the broker.
+    pub commit_lsn: Lsn,
 }
 
 impl WalReceiver {
@@ -79,6 +100,7 @@ impl WalReceiver {
 
         let loop_status = Arc::new(std::sync::RwLock::new(None));
         let manager_status = Arc::clone(&loop_status);
+        let (switch_requests, mut switch_requests_rx) = mpsc::channel(1);
         task_mgr::spawn(
             WALRECEIVER_RUNTIME.handle(),
             TaskKind::WalReceiverManager,
@@ -101,6 +123,7 @@ impl WalReceiver {
                         &walreceiver_ctx,
                         &cancel,
                         &loop_status,
+                        &mut switch_requests_rx,
                     ).await;
                     match loop_step_result {
                         Ok(()) => continue,
@@ -121,6 +144,7 @@ impl WalReceiver {
             tenant_shard_id,
             timeline_id,
             manager_status,
+            switch_requests,
         }
     }
 
@@ -136,6 +160,30 @@ impl WalReceiver {
     pub(crate) fn status(&self) -> Option<ConnectionManagerStatus> {
         self.manager_status.read().unwrap().clone()
     }
+
+    /// Returns a future that stops the current WAL connection and starts streaming from
+    /// the safekeeper with the given connection string instead, which must be one of the
+    /// safekeepers that publish this timeline to the broker. It resolves once the new
+    /// connection has been started.
+    pub(crate) fn switch_source(
+        &self,
+        safekeeper_connstr: String,
+    ) -> impl Future<Output = anyhow::Result<SwitchedSource>> {
+        let switch_requests = self.switch_requests.clone();
+        async move {
+            let (reply, reply_rx) = oneshot::channel();
+            switch_requests
+                .send(SwitchSourceRequest {
+                    safekeeper_connstr,
+                    reply,
+                })
+                .await
+                .map_err(|_| anyhow::anyhow!("WAL receiver is shutting down"))?;
+            reply_rx
+                .await
+                .map_err(|_| anyhow::anyhow!("WAL receiver is shutting down"))?
+        }
+    }
 }
 
 /// A handle of an asynchronous task.
@@ -9,9 +9,15 @@
 //! then a (re)connection happens, if necessary.
 //! Only WAL streaming task expects to be finished, other loops (storage broker, connection management) never exit unless cancelled explicitly via the dedicated channel.
 
-use std::{collections::HashMap, num::NonZeroU64, ops::ControlFlow, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    num::NonZeroU64,
+    ops::ControlFlow,
+    sync::Arc,
+    time::Duration,
+};
 
-use super::{TaskStateUpdate, WalReceiverConf};
+use super::{SwitchSourceRequest, SwitchedSource, TaskStateUpdate, WalReceiverConf};
 use crate::context::{DownloadBehavior, RequestContext};
 use crate::metrics::{
     WALRECEIVER_ACTIVE_MANAGERS, WALRECEIVER_BROKER_UPDATES, WALRECEIVER_CANDIDATES_ADDED,
@@ -60,6 +66,7 @@ pub(super) async fn connection_manager_loop_step(
     ctx: &RequestContext,
     cancel: &CancellationToken,
     manager_status: &std::sync::RwLock<Option<ConnectionManagerStatus>>,
+    switch_requests: &mut tokio::sync::mpsc::Receiver<SwitchSourceRequest>,
 ) -> Result<(), Cancelled> {
     match tokio::select! {
         _ = cancel.cancelled() => { return Err(Cancelled); },
@@ -92,7 +99,13 @@ pub(super) async fn connection_manager_loop_step(
     // Subscribe to the broker updates. Stream shares underlying TCP connection
     // with other streams on this client (other connection managers). When
     // object goes out of scope, stream finishes in drop() automatically.
-    let mut broker_subscription = subscribe_for_timeline_updates(broker_client, id, cancel).await?;
+    // Updates of the safekeeper we stream WAL from, if any, matter the most.
+    let current_sk_id = connection_manager_state
+        .wal_connection
+        .as_ref()
+        .map(|connection| connection.sk_id);
+    let mut broker_subscription =
+        subscribe_for_timeline_updates(broker_client, id, current_sk_id, cancel).await?;
     debug!("Subscribed for broker timeline updates");
 
     loop {
@@ -107,6 +120,7 @@ pub(super) async fn connection_manager_loop_step(
         //  - change connection if the rules decide so, or if the current connection dies
         //  - receive updates from broker
         //      - this might change the current desired connection
+        //  - switch to the safekeeper that a switch request names
         //  - timeline state changes to something that does not allow walreceiver to run concurrently
 
         // NB: make sure each of the select expressions are cancellation-safe
@@ -167,6 +181,13 @@ pub(super) async fn connection_manager_loop_step(
                 }
             },
 
+            Some(request) = switch_requests.recv() => {
+                let result = connection_manager_state
+                    .switch_source(&request.safekeeper_connstr, ReconnectReason::SwitchRequested, ctx)
+                    .await;
+                request.reply.send(result).ok();
+            },
+
             new_event = async {
                 // Reminder: this match arm needs to be cancellation-safe.
                 loop {
@@ -216,7 +237,14 @@ pub(super) async fn connection_manager_loop_step(
             } => debug!("Waking up for the next retry after waiting for {time_until_next_retry:?}"),
         }
 
-        if let Some(new_candidate) = connection_manager_state.next_connection_candidate() {
+        if let Some((safekeeper_connstr, reason)) = connection_manager_state.leader_change() {
+            if let Err(e) = connection_manager_state
+                .switch_source(&safekeeper_connstr, reason, ctx)
+                .await
+            {
+                warn!("Failed to follow the new safekeeper leader: {e:#}");
+            }
+        } else if let Some(new_candidate) = connection_manager_state.next_connection_candidate() {
             info!("Switching to new connection candidate: {new_candidate:?}");
             connection_manager_state
                 .change_connection(new_candidate, ctx)
@@ -230,6 +258,7 @@ pub(super) async fn connection_manager_loop_step(
 async fn subscribe_for_timeline_updates(
     broker_client: &mut BrokerClientChannel,
     id: TenantTimelineId,
+    preferred_sk_id: Option<NodeId>,
     cancel: &CancellationToken,
 ) -> Result<Streaming<SafekeeperTimelineInfo>, Cancelled> {
     let mut attempt = 0;
@@ -250,6 +279,7 @@ async fn subscribe_for_timeline_updates(
         });
         let request = SubscribeSafekeeperInfoRequest {
             subscription_key: Some(key),
+            preferred_safekeeper_id: preferred_sk_id.map(|id| id.0),
         };
 
         match {
@@ -271,10 +301,15 @@ async fn subscribe_for_timeline_updates(
     }
 }
 
-const WALCONNECTION_RETRY_MIN_BACKOFF_SECONDS: f64 = 0.1;
-const WALCONNECTION_RETRY_MAX_BACKOFF_SECONDS: f64 = 15.0;
 const WALCONNECTION_RETRY_BACKOFF_MULTIPLIER: f64 = 1.5;
 
+/// How long a safekeeper has to stay the leader in broker updates before we switch to it.
+const LEADER_CHANGE_HOLD: Duration = Duration::from_secs(5);
+/// Minimum time between two switches caused by leader changes.
+const LEADER_SWITCH_COOLDOWN: Duration = Duration::from_secs(30);
+/// How many advances of the commit LSN to remember for estimating the replication lag.
+const COMMIT_LSN_HISTORY_LEN: usize = 64;
+
 /// All data that's needed to run endless broker loop and keep the WAL streaming connection alive, if possible.
 pub(super) struct ConnectionManagerState {
     id: TenantTimelineId,
@@ -287,6 +322,12 @@ pub(super) struct ConnectionManagerState {
     wal_connection_retries: HashMap<NodeId, RetryInfo>,
     /// Data about all timelines, available for connection, fetched from storage broker, grouped by their corresponding safekeeper node id.
     wal_stream_candidates: HashMap<NodeId, BrokerSkTimeline>,
+    /// Safekeeper with the highest term in the broker updates, and since when it has been one.
+    leader: Option<Leader>,
+    /// When we last switched to a new leader, to not follow a flapping leadership around.
+    last_leader_switch: Option<NaiveDateTime>,
+    /// When the highest commit LSN in the broker updates advanced, and to which LSN.
+    commit_lsn_history: VecDeque<(NaiveDateTime, Lsn)>,
 }
 
 /// An information about connection manager's current connection and connection candidates.
@@ -294,9 +335,39 @@ pub(super) struct ConnectionManagerState {
 pub struct ConnectionManagerStatus {
     existing_connection: Option<WalConnectionStatus>,
     wal_stream_candidates: HashMap<NodeId, BrokerSkTimeline>,
+    commit_lsn_history: VecDeque<(NaiveDateTime, Lsn)>,
 }
 
 impl ConnectionManagerStatus {
+    /// Safekeeper the WAL is streamed from, if there is a connection.
+    pub(crate) fn wal_source(&self) -> Option<NodeId> {
+        self.existing_connection
+            .as_ref()
+            .map(|connection| connection.node)
+    }
+
+    /// Highest commit LSN known, from the WAL connection or the broker.
+    pub(crate) fn commit_lsn(&self) -> Option<Lsn> {
+        let connection_commit_lsn = self
+            .existing_connection
+            .as_ref()
+            .and_then(|connection| connection.commit_lsn);
+        self.wal_stream_candidates
+            .values()
+            .map(|candidate| Lsn(candidate.timeline.commit_lsn))
+            .chain(connection_commit_lsn)
+            .max()
+    }
+
+    /// When the commit LSN in the broker updates first went past `lsn`, as far back as the
+    /// remembered history goes. `None` if it hasn't.
+    pub(crate) fn committed_past_at(&self, lsn: Lsn) -> Option<NaiveDateTime> {
+        self.commit_lsn_history
+            .iter()
+            .find(|(_, commit_lsn)| *commit_lsn > lsn)
+            .map(|(at, _)| *at)
+    }
+
     /// Generates a string, describing current connection status in a form, suitable for logging.
     pub fn to_human_readable_string(&self) -> String {
         let mut resulting_string = String::new();
@@ -387,10 +458,21 @@ struct NewCommittedWAL {
     discovered_at: NaiveDateTime,
 }
 
+/// The safekeeper that the broker updates point to as the timeline's leader.
+#[derive(Debug, Clone, Copy)]
+struct Leader {
+    sk_id: NodeId,
+    term: u64,
+    /// When this safekeeper became the leader, as far as broker updates tell.
+    since: NaiveDateTime,
+}
+
 #[derive(Debug, Clone, Copy)]
 struct RetryInfo {
     next_retry_at: Option<NaiveDateTime>,
     retry_duration_seconds: f64,
+    /// Number of connections to the safekeeper that failed in a row.
+    failed_attempts: u32,
 }
 
 /// Data about the timeline to connect to, received from the broker.
@@ -414,6 +496,9 @@ impl ConnectionManagerState {
             wal_connection: None,
             wal_stream_candidates: HashMap::new(),
             wal_connection_retries: HashMap::new(),
+            leader: None,
+            last_leader_switch: None,
+            commit_lsn_history: VecDeque::new(),
         }
     }
 
@@ -500,6 +585,57 @@ impl ConnectionManagerState {
         });
     }
 
+    /// Stops the current connection and connects to the safekeeper with the given connection
+    /// string instead, if it is one of the broker candidates.
+    async fn switch_source(
+        &mut self,
+        safekeeper_connstr: &str,
+        reason: ReconnectReason,
+        ctx: &RequestContext,
+    ) -> anyhow::Result<SwitchedSource> {
+        let (safekeeper_id, candidate) = self
+            .wal_stream_candidates
+            .iter()
+            .find(|(_, candidate)| candidate.timeline.safekeeper_connstr == safekeeper_connstr)
+            .map(|(sk_id, candidate)| (*sk_id, candidate.timeline.clone()))
+            .with_context(|| {
+                format!(
+                    "safekeeper {safekeeper_connstr} does not publish this timeline to the broker"
+                )
+            })?;
+        let wal_source_connconf = wal_stream_connection_config(
+            self.id,
+            safekeeper_connstr,
+            self.conf.auth_token.as_deref().map(String::as_str),
+            self.conf.availability_zone.as_deref(),
+        )?;
+
+        // Once the old connection is stopped, last_record_lsn doesn't move until the
+        // new connection starts streaming from exactly there.
+        self.drop_old_connection(true).await;
+        let switch_lsn = self.timeline.get_last_record_lsn();
+        info!("Switching WAL source to safekeeper {safekeeper_id} at {switch_lsn}: {reason:?}");
+
+        // An explicit switch overrides the backoff from earlier failed attempts.
+        self.wal_connection_retries.remove(&safekeeper_id);
+        self.change_connection(
+            NewWalConnectionCandidate {
+                safekeeper_id,
+                wal_source_connconf,
+                availability_zone: candidate.availability_zone.clone(),
+                reason,
+            },
+            ctx,
+        )
+        .await;
+
+        Ok(SwitchedSource {
+            safekeeper_id,
+            switch_lsn,
+            commit_lsn: Lsn(candidate.commit_lsn),
+        })
+    }
+
     /// Drops the current connection (if any) and updates retry timeout for the next
     /// connection attempt to the same safekeeper.
     ///
@@ -523,13 +659,17 @@ impl ConnectionManagerState {
                 .await;
         }
 
+        let min_backoff_seconds = self.conf.retry_min_backoff.as_secs_f64();
+        let max_backoff_seconds = self.conf.retry_max_backoff.as_secs_f64();
         let retry = self
             .wal_connection_retries
             .entry(wal_connection.sk_id)
             .or_insert(RetryInfo {
                 next_retry_at: None,
-                retry_duration_seconds: WALCONNECTION_RETRY_MIN_BACKOFF_SECONDS,
+                retry_duration_seconds: min_backoff_seconds,
+                failed_attempts: 0,
             });
+        retry.failed_attempts += 1;
 
         let now = Utc::now().naive_utc();
 
@@ -546,8 +686,8 @@ impl ConnectionManagerState {
         if let Some(next) = &retry.next_retry_at {
             if next > &now {
                 info!(
-                    "Next connection retry to {:?} is at {}",
-                    wal_connection.sk_id, next
+                    "Next connection retry to {:?} is at {}, after {} failed attempts",
+                    wal_connection.sk_id, next, retry.failed_attempts
                 );
             }
         }
@@ -555,9 +695,9 @@ impl ConnectionManagerState {
         let next_retry_duration =
             retry.retry_duration_seconds * WALCONNECTION_RETRY_BACKOFF_MULTIPLIER;
         // Clamp the next retry duration to the maximum allowed.
-        let next_retry_duration = next_retry_duration.min(WALCONNECTION_RETRY_MAX_BACKOFF_SECONDS);
+        let next_retry_duration = next_retry_duration.min(max_backoff_seconds);
         // Clamp the next retry duration to the minimum allowed.
-        let next_retry_duration = next_retry_duration.max(WALCONNECTION_RETRY_MIN_BACKOFF_SECONDS);
+        let next_retry_duration = next_retry_duration.max(min_backoff_seconds);
 
         retry.retry_duration_seconds = next_retry_duration;
     }
@@ -581,18 +721,110 @@ impl ConnectionManagerState {
         WALRECEIVER_BROKER_UPDATES.inc();
 
         let new_safekeeper_id = NodeId(timeline_update.safekeeper_id);
+        let commit_lsn = Lsn(timeline_update.commit_lsn);
+        let now = Utc::now().naive_utc();
         let old_entry = self.wal_stream_candidates.insert(
             new_safekeeper_id,
             BrokerSkTimeline {
                 timeline: timeline_update,
-                latest_update: Utc::now().naive_utc(),
+                latest_update: now,
             },
         );
 
+        if self
+            .commit_lsn_history
+            .back()
+            .map_or(true, |(_, highest)| commit_lsn > *highest)
+        {
+            if self.commit_lsn_history.len() == COMMIT_LSN_HISTORY_LEN {
+                self.commit_lsn_history.pop_front();
+            }
+            self.commit_lsn_history.push_back((now, commit_lsn));
+        }
+
         if old_entry.is_none() {
             info!("New SK node was added: {new_safekeeper_id}");
             WALRECEIVER_CANDIDATES_ADDED.inc();
         }
+        self.update_leader();
+    }
+
+    /// Re-evaluates which safekeeper leads the timeline: the one with the highest term,
+    /// and among those the one with the most committed WAL.
+    fn update_leader(&mut self) {
+        let Some((&sk_id, info)) = self
+            .wal_stream_candidates
+            .iter()
+            .filter(|(_, info)| !info.timeline.safekeeper_connstr.is_empty())
+            .max_by_key(|(_, info)| (info.timeline.term, info.timeline.commit_lsn))
+        else {
+            self.leader = None;
+            return;
+        };
+        let term = info.timeline.term;
+        match &mut self.leader {
+            Some(leader) if leader.sk_id == sk_id => leader.term = term,
+            _ => {
+                debug!("Safekeeper {sk_id} leads the timeline in term {term}");
+                self.leader = Some(Leader {
+                    sk_id,
+                    term,
+                    since: Utc::now().naive_utc(),
+                });
+            }
+        }
+    }
+
+    /// Returns the connection string of the safekeeper to switch to, if another safekeeper
+    /// than the connected one has taken over leadership in a newer term.
+    ///
+    /// To not churn connections while leadership flaps, the new leader must have held on
+    /// for [`LEADER_CHANGE_HOLD`], and the previous switch for a leader change must be at
+    /// least [`LEADER_SWITCH_COOLDOWN`] ago. Safekeepers push updates to the broker every
+    /// second or so, so the hold period is rechecked often enough without a separate timer.
+    fn leader_change(&mut self) -> Option<(String, ReconnectReason)> {
+        let connection = self.wal_connection.as_ref()?;
+        if !connection.status.is_connected {
+            // The regular candidate selection deals with connections that don't come up.
+            return None;
+        }
+        let leader = self.leader?;
+        if leader.sk_id == connection.sk_id {
+            return None;
+        }
+        let current_term = self
+            .wal_stream_candidates
+            .get(&connection.sk_id)?
+            .timeline
+            .term;
+        if leader.term <= current_term {
+            return None;
+        }
+
+        let now = Utc::now().naive_utc();
+        let elapsed = |since: NaiveDateTime| (now - since).to_std().unwrap_or_default();
+        if elapsed(leader.since) < LEADER_CHANGE_HOLD {
+            return None;
+        }
+        if let Some(last_switch) = self.last_leader_switch {
+            if elapsed(last_switch) < LEADER_SWITCH_COOLDOWN {
+                return None;
+            }
+        }
+        // Don't hammer a leader we are failing to connect to; regular selection retries it.
+        let (_, info, _) = self
+            .applicable_connection_candidates()
+            .find(|&(sk_id, _, _)| sk_id == leader.sk_id)?;
+        let safekeeper_connstr = info.safekeeper_connstr.clone();
+
+        self.last_leader_switch = Some(now);
+        Some((
+            safekeeper_connstr,
+            ReconnectReason::LeaderChanged {
+                current_term,
+                new_term: leader.term,
+            },
+        ))
     }
 
     /// Cleans up stale broker records and checks the rest for the new connection candidate.
@@ -862,6 +1094,7 @@ impl ConnectionManagerState {
                 self.wal_connection_retries.remove(&node_id);
                 WALRECEIVER_CANDIDATES_REMOVED.inc();
             }
+            self.update_leader();
         }
     }
 
@@ -878,6 +1111,7 @@ impl ConnectionManagerState {
         ConnectionManagerStatus {
             existing_connection: self.wal_connection.as_ref().map(|conn| conn.status),
             wal_stream_candidates: self.wal_stream_candidates.clone(),
+            commit_lsn_history: self.commit_lsn_history.clone(),
         }
     }
 }
@@ -900,6 +1134,11 @@ enum ReconnectReason {
         threshold: NonZeroU64,
     },
     SwitchAvailabilityZone,
+    SwitchRequested,
+    LeaderChanged {
+        current_term: u64,
+        new_term: u64,
+    },
     NoWalTimeout {
         current_lsn: Lsn,
         current_commit_lsn: Lsn,
@@ -921,6 +1160,8 @@ impl ReconnectReason {
             ReconnectReason::NoExistingConnection => "NoExistingConnection",
             ReconnectReason::LaggingWal { .. } => "LaggingWal",
             ReconnectReason::SwitchAvailabilityZone => "SwitchAvailabilityZone",
+            ReconnectReason::SwitchRequested => "SwitchRequested",
+            ReconnectReason::LeaderChanged { .. } => "LeaderChanged",
             ReconnectReason::NoWalTimeout { .. } => "NoWalTimeout",
             ReconnectReason::NoKeepAlives { .. } => "NoKeepAlives",
         }
@@ -1143,7 +1384,8 @@ mod tests {
             NodeId(0),
             RetryInfo {
                 next_retry_at: now.checked_add_signed(chrono::Duration::hours(1)),
-                retry_duration_seconds: WALCONNECTION_RETRY_MAX_BACKOFF_SECONDS,
+                retry_duration_seconds: state.conf.retry_max_backoff.as_secs_f64(),
+                failed_attempts: 10,
             },
         )]);
 
@@ -1378,13 +1620,135 @@ mod tests {
                 auth_token: None,
                 availability_zone: None,
                 ingest_batch_size: 1,
+                retry_min_backoff: Duration::from_millis(100),
+                retry_max_backoff: Duration::from_secs(15),
             },
             wal_connection: None,
             wal_stream_candidates: HashMap::new(),
             wal_connection_retries: HashMap::new(),
+            leader: None,
+            last_leader_switch: None,
+            commit_lsn_history: VecDeque::new(),
         }
     }
 
+    #[tokio::test]
+    async fn commit_lsn_history() -> anyhow::Result<()> {
+        let harness = TenantHarness::create("commit_lsn_history")?;
+        let mut state = dummy_state(&harness).await;
+        let now = Utc::now().naive_utc();
+
+        let update = |sk_id, commit_lsn| {
+            let mut info = dummy_broker_sk_timeline(commit_lsn, "sk", now).timeline;
+            info.safekeeper_id = sk_id;
+            info
+        };
+        state.register_timeline_update(update(1, 0x10));
+        state.register_timeline_update(update(2, 0x08));
+        state.register_timeline_update(update(2, 0x20));
+
+        let status = state.manager_status();
+        assert_eq!(status.wal_source(), None);
+        assert_eq!(status.commit_lsn(), Some(Lsn(0x20)));
+        // Only advances of the highest commit LSN are remembered.
+        assert_eq!(
+            status
+                .commit_lsn_history
+                .iter()
+                .map(|(_, lsn)| *lsn)
+                .collect::<Vec<_>>(),
+            vec![Lsn(0x10), Lsn(0x20)]
+        );
+        let first_advance = status.commit_lsn_history[0].0;
+        assert_eq!(status.committed_past_at(Lsn(0x08)), Some(first_advance));
+        assert!(status.committed_past_at(Lsn(0x10)).unwrap() >= first_advance);
+        assert_eq!(status.committed_past_at(Lsn(0x20)), None);
+
+        for commit_lsn in 0..COMMIT_LSN_HISTORY_LEN as u64 {
+            state.register_timeline_update(update(1, 0x100 + commit_lsn));
+        }
+        assert_eq!(state.commit_lsn_history.len(), COMMIT_LSN_HISTORY_LEN);
+        assert_eq!(state.commit_lsn_history[0].1, Lsn(0x100));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn follow_leader_change() -> anyhow::Result<()> {
+        let harness = TenantHarness::create("follow_leader_change")?;
+        let mut state = dummy_state(&harness).await;
+        let current_lsn = Lsn(100_000).align();
+        let now = Utc::now().naive_utc();
+
+        let connected_sk_id = NodeId(0);
+        let connection_status = WalConnectionStatus {
+            is_connected: true,
+            has_processed_wal: true,
+            latest_connection_update: now,
+            latest_wal_update: now,
+            commit_lsn: Some(current_lsn),
+            streaming_lsn: Some(current_lsn),
+            node: connected_sk_id,
+        };
+        state.wal_connection = Some(WalConnection {
+            started_at: now,
+            sk_id: connected_sk_id,
+            availability_zone: None,
+            status: connection_status,
+            connection_task: TaskHandle::spawn(move |sender, _| async move {
+                sender
+                    .send(TaskStateUpdate::Progress(connection_status))
+                    .ok();
+                Ok(())
+            }),
+            discovered_new_wal: None,
+        });
+
+        let mut connected = dummy_broker_sk_timeline(current_lsn.0, DUMMY_SAFEKEEPER_HOST, now);
+        connected.timeline.safekeeper_id = connected_sk_id.0;
+        connected.timeline.term = 1;
+        state.register_timeline_update(connected.timeline);
+        assert!(state.leader_change().is_none(), "connected to the leader");
+
+        // Another safekeeper wins an election, with the same WAL.
+        let mut new_leader = dummy_broker_sk_timeline(current_lsn.0, "new_leader", now);
+        new_leader.timeline.safekeeper_id = 1;
+        new_leader.timeline.term = 2;
+        state.register_timeline_update(new_leader.timeline.clone());
+        assert!(
+            state.leader_change().is_none(),
+            "should wait for the leadership to settle"
+        );
+
+        let hold = chrono::Duration::from_std(LEADER_CHANGE_HOLD)?;
+        state.leader.as_mut().unwrap().since = now - hold - hold;
+        let (connstr, reason) = state
+            .leader_change()
+            .expect("should switch to the new leader");
+        assert_eq!(connstr, "new_leader");
+        assert_eq!(
+            reason,
+            ReconnectReason::LeaderChanged {
+                current_term: 1,
+                new_term: 2,
+            }
+        );
+
+        // Leadership flaps back and forth: the cooldown keeps us where we are.
+        new_leader.timeline.term = 4;
+        let mut third = dummy_broker_sk_timeline(current_lsn.0, "third", now);
+        third.timeline.safekeeper_id = 2;
+        third.timeline.term = 3;
+        state.register_timeline_update(third.timeline);
+        state.register_timeline_update(new_leader.timeline);
+        state.leader.as_mut().unwrap().since = now - hold - hold;
+        assert!(
+            state.leader_change().is_none(),
+            "should not switch again within the cooldown"
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn switch_to_same_availability_zone() -> anyhow::Result<()> {
         // Pageserver and one of safekeepers will be in the same availability zone
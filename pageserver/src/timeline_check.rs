@@ -0,0 +1,162 @@
+//! Cheap probe of whether a timeline can still serve pages at its latest LSN.
+//!
+//! The page service `check_timeline` command reconstructs the metadata pages and the
+//! first and last block of a bounded number of relations at the last record LSN, and
+//! looks for holes in the LSN coverage of the delta layers above the GC cutoff. A
+//! missing or unreadable layer shows up as a reconstruction error or a gap, instead
+//! of as a failed GetPage request from compute.
+//!
+//! The reads go through the regular read path, so the check is safe to run on a live
+//! timeline; it costs at most [`MAX_SAMPLED_PAGES`] page reconstructions.
+
+use std::ops::Range;
+
+use anyhow::Context;
+use pageserver_api::key::rel_block_to_key;
+use utils::lsn::Lsn;
+
+use crate::context::RequestContext;
+use crate::pgdatadir_mapping::Version;
+use crate::tenant::Timeline;
+
+/// Upper bound on the relation pages read by one check.
+const MAX_SAMPLED_PAGES: usize = 64;
+
+pub struct TimelineCheck {
+    pub lsn: Lsn,
+    pub sampled_pages: usize,
+    /// LSN ranges above the GC cutoff that no delta layer covers.
+    pub gaps: Vec<Range<Lsn>>,
+    /// The first page that could not be reconstructed, if any.
+    pub error: Option<String>,
+}
+
+impl TimelineCheck {
+    pub fn is_ok(&self) -> bool {
+        self.gaps.is_empty() && self.error.is_none()
+    }
+}
+
+pub async fn check_timeline(timeline: &Timeline, ctx: &RequestContext) -> TimelineCheck {
+    let lsn = timeline.get_last_record_lsn();
+
+    let gc_cutoff = *timeline.get_latest_gc_cutoff_lsn();
+    let gaps = find_lsn_gaps(
+        timeline
+            .layer_file_descs()
+            .await
+            .iter()
+            .filter(|desc| desc.is_delta())
+            .map(|desc| desc.lsn_range.clone()),
+        gc_cutoff,
+    );
+
+    let mut sampled_pages = 0;
+    let error = sample_pages(timeline, lsn, &mut sampled_pages, ctx)
+        .await
+        .err()
+        .map(|e| format!("{e:#}"));
+
+    TimelineCheck {
+        lsn,
+        sampled_pages,
+        gaps,
+        error,
+    }
+}
+
+async fn sample_pages(
+    timeline: &Timeline,
+    lsn: Lsn,
+    sampled_pages: &mut usize,
+    ctx: &RequestContext,
+) -> anyhow::Result<()> {
+    timeline.get_control_file(lsn, ctx).await?;
+    timeline.get_checkpoint(lsn, ctx).await?;
+
+    let version = Version::Lsn(lsn);
+    let shard = timeline.get_shard_identity();
+    let mut dbdirs = timeline
+        .list_dbdirs(lsn, ctx)
+        .await?
+        .into_keys()
+        .collect::<Vec<_>>();
+    dbdirs.sort();
+    for (spcnode, dbnode) in dbdirs {
+        let mut rels = timeline
+            .list_rels(spcnode, dbnode, version, ctx)
+            .await?
+            .into_iter()
+            .collect::<Vec<_>>();
+        rels.sort();
+        for rel in rels {
+            let nblocks = timeline.get_rel_size(rel, version, false, ctx).await?;
+            let mut blocks = vec![0, nblocks.saturating_sub(1)];
+            blocks.dedup();
+            for blknum in blocks {
+                if blknum >= nblocks || !shard.is_key_local(&rel_block_to_key(rel, blknum)) {
+                    continue;
+                }
+                if *sampled_pages >= MAX_SAMPLED_PAGES {
+                    return Ok(());
+                }
+                timeline
+                    .get_rel_page_at_lsn(rel, blknum, version, false, ctx)
+                    .await
+                    .with_context(|| format!("block {blknum} of {rel}"))?;
+                *sampled_pages += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Holes in the union of `ranges` above `from`. Ranges entirely below `from` may
+/// have been garbage collected and are ignored.
+pub(crate) fn find_lsn_gaps(
+    ranges: impl Iterator<Item = Range<Lsn>>,
+    from: Lsn,
+) -> Vec<Range<Lsn>> {
+    let mut ranges = ranges.filter(|r| r.end > from).collect::<Vec<_>>();
+    ranges.sort_by_key(|r| r.start);
+
+    let mut gaps = Vec::new();
+    let mut covered_to: Option<Lsn> = None;
+    for range in ranges {
+        match covered_to {
+            Some(end) if range.start > end => {
+                gaps.push(end..range.start);
+                covered_to = Some(range.end);
+            }
+            Some(end) => covered_to = Some(end.max(range.end)),
+            None => covered_to = Some(range.end),
+        }
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lsn_gaps() {
+        let ranges = [
+            Lsn(0x10)..Lsn(0x20),
+            Lsn(0x30)..Lsn(0x40),
+            Lsn(0x20)..Lsn(0x28),
+            Lsn(0x38)..Lsn(0x50),
+            Lsn(0x60)..Lsn(0x70),
+        ];
+        assert_eq!(
+            find_lsn_gaps(ranges.iter().cloned(), Lsn(0)),
+            vec![Lsn(0x28)..Lsn(0x30), Lsn(0x50)..Lsn(0x60)]
+        );
+        // Ranges below the cutoff don't count, holes between them neither.
+        assert_eq!(
+            find_lsn_gaps(ranges.iter().cloned(), Lsn(0x48)),
+            vec![Lsn(0x50)..Lsn(0x60)]
+        );
+        assert!(find_lsn_gaps(std::iter::empty(), Lsn(0)).is_empty());
+    }
+}
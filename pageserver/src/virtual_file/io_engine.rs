@@ -66,9 +66,29 @@ pub(crate) fn set(engine_kind: IoEngineKind) {
 
 #[cfg(not(test))]
 pub(super) fn init(engine_kind: IoEngineKind) {
+    #[cfg(target_os = "linux")]
+    once_cell::sync::Lazy::force(&tokio_epoll_uring_ext::PROCESS_START);
     set(engine_kind);
 }
 
+/// Sets how often process stats are logged when launching an io_uring instance keeps
+/// failing. No-op on platforms without io_uring.
+pub fn set_io_uring_launch_failure_stats_interval(interval: std::time::Duration) {
+    #[cfg(target_os = "linux")]
+    tokio_epoll_uring_ext::set_launch_failure_stats_interval(interval);
+    #[cfg(not(target_os = "linux"))]
+    let _ = interval;
+}
+
+/// Makes all threads share a pool of `count` io_uring instances instead of launching one
+/// per thread. Must be called before the first IO. No-op on platforms without io_uring.
+pub fn set_io_uring_shared_systems(count: usize) {
+    #[cfg(target_os = "linux")]
+    tokio_epoll_uring_ext::set_shared_systems(count);
+    #[cfg(not(target_os = "linux"))]
+    let _ = count;
+}
+
 /// Longer-term, this API should only be used by [`super::VirtualFile`].
 pub(crate) fn get() -> IoEngine {
     let cur = IoEngine::try_from(IO_ENGINE.load(Ordering::Relaxed)).unwrap();
@@ -286,7 +306,9 @@ impl From<FeatureTestResult> for IoEngineKind {
 /// Somewhat costly under the hood, do only once.
 /// Panics if we can't set up the feature test.
 pub fn feature_test() -> anyhow::Result<FeatureTestResult> {
-    std::thread::spawn(|| {
+    std::thread::Builder::new()
+        .name("io engine feature test".to_string())
+        .spawn(|| {
 
         #[cfg(not(target_os = "linux"))]
         {
@@ -334,6 +356,7 @@ pub fn feature_test() -> anyhow::Result<FeatureTestResult> {
             })
         }
     })
+    .expect("spawn io engine feature test thread")
     .join()
     .unwrap()
 }
@@ -4,14 +4,26 @@
 //! This is primarily necessary due to ENOMEM aka OutOfMemory errors during io_uring creation
 //! on older kernels, such as some (but not all) older kernels in the Linux 5.10 series.
 //! See <https://github.com/neondatabase/neon/issues/6373#issuecomment-1905814391> for more details.
+//!
+//! By default, every executor thread gets its own [`System`]. Each system pins memory for
+//! its rings, so with many threads that rarely do IO, the locked memory limit can be the
+//! reason for those ENOMEM errors. With [`set_shared_systems`], all threads share a small
+//! pool of systems instead, and a semaphore bounds the number of operations in flight on
+//! them. This needs less locked memory, at the cost of contention on the shared rings and
+//! of waiting for a permit under load.
 
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::{Lazy, OnceCell};
 
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info, info_span, warn, Instrument};
 use utils::backoff::{DEFAULT_BASE_BACKOFF_SECONDS, DEFAULT_MAX_BACKOFF_SECONDS};
+use utils::rate_limit::RateLimit;
 
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio_epoll_uring::{System, SystemHandle};
 
 use crate::virtual_file::on_fatal_io_error;
@@ -44,69 +56,144 @@ impl ThreadLocalState {
 
 static THREAD_LOCAL_STATE_ID: AtomicU64 = AtomicU64::new(0);
 
+/// Reference point for [`metrics::THREAD_LOCAL_FIRST_LAUNCH_DELAY`], forced when the IO
+/// engine is initialized at startup.
+pub(super) static PROCESS_START: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// Limits how often [`emit_launch_failure_process_stats`] runs for retried launch failures.
+static LAUNCH_FAILURE_STATS_RATE_LIMIT: Lazy<Mutex<RateLimit>> = Lazy::new(|| {
+    let interval = humantime::parse_duration(
+        crate::config::defaults::DEFAULT_IO_URING_LAUNCH_FAILURE_STATS_INTERVAL,
+    )
+    .expect("cannot parse default io_uring launch failure stats interval");
+    Mutex::new(RateLimit::new(interval))
+});
+
+pub(super) fn set_launch_failure_stats_interval(interval: Duration) {
+    *LAUNCH_FAILURE_STATS_RATE_LIMIT.lock().unwrap() = RateLimit::new(interval);
+}
+
 thread_local! {
     static THREAD_LOCAL: ThreadLocalState = ThreadLocalState::new();
 }
 
+/// Operations in flight per system of the shared pool, see [`set_shared_systems`].
+const MAX_OPS_IN_FLIGHT_PER_SHARED_SYSTEM: usize = 128;
+
+struct SharedSystems {
+    systems: Vec<ThreadLocalState>,
+    next: AtomicU64,
+    permits: Arc<Semaphore>,
+}
+
+static SHARED_SYSTEMS: OnceCell<SharedSystems> = OnceCell::new();
+
+/// Use a pool of `count` systems shared by all threads instead of one system per thread.
+/// Must be called before the first IO, and at most once.
+pub(super) fn set_shared_systems(count: usize) {
+    assert!(
+        count > 0,
+        "the shared io_uring system pool must not be empty"
+    );
+    let shared = SharedSystems {
+        systems: (0..count).map(|_| ThreadLocalState::new()).collect(),
+        next: AtomicU64::new(0),
+        permits: Arc::new(Semaphore::new(count * MAX_OPS_IN_FLIGHT_PER_SHARED_SYSTEM)),
+    };
+    if SHARED_SYSTEMS.set(shared).is_err() {
+        panic!("shared io_uring systems set twice");
+    }
+}
+
 /// Panics if we cannot [`System::launch`].
 pub async fn thread_local_system() -> Handle {
-    let fake_cancel = CancellationToken::new();
+    if let Some(shared) = SHARED_SYSTEMS.get() {
+        let permit = Arc::clone(&shared.permits)
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed");
+        let idx = shared.next.fetch_add(1, Ordering::Relaxed) as usize % shared.systems.len();
+        let state = &shared.systems[idx];
+        // Unlike thread-local ones, shared systems may be used from any thread, so it
+        // doesn't matter where the launch leaves us.
+        while !get_or_launch(state).await {}
+        return Handle {
+            state: state.clone(),
+            _permit: Some(Arc::new(permit)),
+        };
+    }
+
     loop {
         let thread_local_state = THREAD_LOCAL.with(|arc| arc.clone());
-        let inner = &thread_local_state.0;
-        let get_or_init_res = inner
-            .cell
-            .get_or_try_init(|| async {
-                let attempt_no = inner
-                    .launch_attempts
-                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                let span = info_span!("tokio_epoll_uring_ext::thread_local_system", thread_local=%thread_local_state.make_id_string(), %attempt_no);
-                async {
-                    // Rate-limit retries per thread-local.
-                    // NB: doesn't yield to executor at attempt_no=0.
-                    utils::backoff::exponential_backoff(
-                        attempt_no,
-                        DEFAULT_BASE_BACKOFF_SECONDS,
-                        DEFAULT_MAX_BACKOFF_SECONDS,
-                        &fake_cancel,
-                    )
-                    .await;
-                    let res = System::launch()
-                    // this might move us to another executor thread => loop outside the get_or_try_init, not inside it
-                    .await;
-                    match res {
-                        Ok(system) => {
-                            info!("successfully launched system");
-                            metrics::THREAD_LOCAL_LAUNCH_SUCCESSES.inc();
-                            Ok(system)
-                        }
-                        Err(tokio_epoll_uring::LaunchResult::IoUringBuild(e)) if e.kind() == std::io::ErrorKind::OutOfMemory => {
-                            warn!("not enough locked memory to tokio-epoll-uring, will retry");
-                            info_span!("stats").in_scope(|| {
-                                emit_launch_failure_process_stats();
-                            });
-                            metrics::THREAD_LOCAL_LAUNCH_FAILURES.inc();
-                            Err(())
-                        }
-                        // abort the process instead of panicking because pageserver usually becomes half-broken if we panic somewhere.
-                        // This is equivalent to a fatal IO error.
-                        Err(ref e @ tokio_epoll_uring::LaunchResult::IoUringBuild(ref inner)) => {
-                            error!(error=%e, "failed to launch thread-local tokio-epoll-uring, this should not happen, aborting process");
+        if get_or_launch(&thread_local_state).await {
+            return Handle {
+                state: thread_local_state,
+                _permit: None,
+            };
+        }
+    }
+}
+
+/// Returns whether `thread_local_state` holds a launched system, launching it if needed. Launch failures
+/// due to ENOMEM are retried by the caller, with a back-off.
+async fn get_or_launch(thread_local_state: &ThreadLocalState) -> bool {
+    let fake_cancel = CancellationToken::new();
+    let inner = &thread_local_state.0;
+    let get_or_init_res = inner
+        .cell
+        .get_or_try_init(|| async {
+            let attempt_no = inner
+                .launch_attempts
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let span = info_span!("tokio_epoll_uring_ext::thread_local_system", thread_local=%thread_local_state.make_id_string(), %attempt_no);
+            async {
+                // Rate-limit retries per thread-local.
+                // NB: doesn't yield to executor at attempt_no=0.
+                utils::backoff::exponential_backoff(
+                    attempt_no,
+                    DEFAULT_BASE_BACKOFF_SECONDS,
+                    DEFAULT_MAX_BACKOFF_SECONDS,
+                    &fake_cancel,
+                )
+                .await;
+                let res = System::launch()
+                // this might move us to another executor thread => loop outside the get_or_try_init, not inside it
+                .await;
+                match res {
+                    Ok(system) => {
+                        info!("successfully launched system");
+                        metrics::THREAD_LOCAL_LAUNCH_SUCCESSES.inc();
+                        // The cell is only ever initialized once per thread-local.
+                        metrics::THREAD_LOCAL_FIRST_LAUNCH_DELAY
+                            .observe(PROCESS_START.elapsed().as_secs_f64());
+                        Ok(system)
+                    }
+                    Err(tokio_epoll_uring::LaunchResult::IoUringBuild(e)) if e.kind() == std::io::ErrorKind::OutOfMemory => {
+                        warn!("not enough locked memory to tokio-epoll-uring, will retry");
+                        LAUNCH_FAILURE_STATS_RATE_LIMIT.lock().unwrap().call(|| {
                             info_span!("stats").in_scope(|| {
                                 emit_launch_failure_process_stats();
                             });
-                            on_fatal_io_error(inner, "launch thread-local tokio-epoll-uring");
-                        },
+                        });
+                        metrics::THREAD_LOCAL_LAUNCH_FAILURES.inc();
+                        Err(())
                     }
+                    // abort the process instead of panicking because pageserver usually becomes half-broken if we panic somewhere.
+                    // This is equivalent to a fatal IO error.
+                    Err(ref e @ tokio_epoll_uring::LaunchResult::IoUringBuild(ref inner)) => {
+                        error!(error=%e, "failed to launch thread-local tokio-epoll-uring, this should not happen, aborting process");
+                        info_span!("stats").in_scope(|| {
+                            emit_launch_failure_process_stats();
+                        });
+                        on_fatal_io_error(inner, "launch thread-local tokio-epoll-uring");
+                    },
                 }
-                .instrument(span)
-                .await
-            })
-            .await;
-        if get_or_init_res.is_ok() {
-            return Handle(thread_local_state);
-        }
-    }
+            }
+            .instrument(span)
+            .await
+        })
+        .await;
+    get_or_init_res.is_ok()
 }
 
 fn emit_launch_failure_process_stats() {
@@ -179,14 +266,18 @@ fn emit_launch_failure_process_stats() {
 }
 
 #[derive(Clone)]
-pub struct Handle(ThreadLocalState);
+pub struct Handle {
+    state: ThreadLocalState,
+    /// Held while a system of the shared pool is in use.
+    _permit: Option<Arc<OwnedSemaphorePermit>>,
+}
 
 impl std::ops::Deref for Handle {
     type Target = SystemHandle;
 
     fn deref(&self) -> &Self::Target {
-        self.0
-             .0
+        self.state
+            .0
             .cell
             .get()
             .expect("must be already initialized when using this")
@@ -506,51 +506,6 @@ impl XlSmgrTruncate {
     }
 }
 
-#[repr(C)]
-#[derive(Debug)]
-pub struct XlCreateDatabase {
-    pub db_id: Oid,
-    pub tablespace_id: Oid,
-    pub src_db_id: Oid,
-    pub src_tablespace_id: Oid,
-}
-
-impl XlCreateDatabase {
-    pub fn decode(buf: &mut Bytes) -> XlCreateDatabase {
-        XlCreateDatabase {
-            db_id: buf.get_u32_le(),
-            tablespace_id: buf.get_u32_le(),
-            src_db_id: buf.get_u32_le(),
-            src_tablespace_id: buf.get_u32_le(),
-        }
-    }
-}
-
-#[repr(C)]
-#[derive(Debug)]
-pub struct XlDropDatabase {
-    pub db_id: Oid,
-    pub n_tablespaces: Oid, /* number of tablespace IDs */
-    pub tablespace_ids: Vec<Oid>,
-}
-
-impl XlDropDatabase {
-    pub fn decode(buf: &mut Bytes) -> XlDropDatabase {
-        let mut rec = XlDropDatabase {
-            db_id: buf.get_u32_le(),
-            n_tablespaces: buf.get_u32_le(),
-            tablespace_ids: Vec::<Oid>::new(),
-        };
-
-        for _i in 0..rec.n_tablespaces {
-            let id = buf.get_u32_le();
-            rec.tablespace_ids.push(id);
-        }
-
-        rec
-    }
-}
-
 ///
 /// Note: Parsing some fields is missing, because they're not needed.
 ///
@@ -26,7 +26,7 @@ pub(crate) mod apply_neon;
 
 use crate::config::PageServerConf;
 use crate::metrics::{
-    WAL_REDO_BYTES_HISTOGRAM, WAL_REDO_PROCESS_LAUNCH_DURATION_HISTOGRAM,
+    WalRedoSlotMetrics, WAL_REDO_BYTES_HISTOGRAM, WAL_REDO_PROCESS_LAUNCH_DURATION_HISTOGRAM,
     WAL_REDO_RECORDS_HISTOGRAM, WAL_REDO_TIME,
 };
 use crate::repository::Key;
@@ -36,6 +36,7 @@ use bytes::{Bytes, BytesMut};
 use pageserver_api::key::key_to_rel_block;
 use pageserver_api::models::WalRedoManagerStatus;
 use pageserver_api::shard::TenantShardId;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
 use std::time::Instant;
@@ -43,17 +44,23 @@ use tracing::*;
 use utils::lsn::Lsn;
 
 ///
-/// This is the real implementation that uses a Postgres process to
-/// perform WAL replay. Only one thread can use the process at a time,
-/// that is controlled by the Mutex. In the future, we might want to
-/// launch a pool of processes to allow concurrent replay of multiple
-/// records.
+/// This is the real implementation that uses Postgres processes to
+/// perform WAL replay. There is a pool of `walredo_processes_per_tenant`
+/// processes, each launched on first use. Each batch of records goes to the
+/// next idle process in round-robin order, or queues on the least busy one
+/// if none is idle. A process that fails is taken out of its slot and a new
+/// one is launched there on the next request.
 ///
 pub struct PostgresRedoManager {
     tenant_shard_id: TenantShardId,
     conf: &'static PageServerConf,
     last_redo_at: std::sync::Mutex<Option<Instant>>,
-    redo_process: RwLock<Option<Arc<process::WalRedoProcess>>>,
+    redo_processes: Vec<RwLock<Option<Arc<process::WalRedoProcess>>>>,
+    /// Number of batches queued on or running in each process, indexed like `redo_processes`.
+    redo_in_flight: Vec<AtomicUsize>,
+    /// Metrics of each process, indexed like `redo_processes`.
+    slot_metrics: Vec<WalRedoSlotMetrics>,
+    next_process: AtomicUsize,
 }
 
 ///
@@ -106,6 +113,11 @@ impl PostgresRedoManager {
 
                 batch_neon = rec_neon;
                 batch_start = i;
+
+                // A batch blocks this task until the redo process answers. Let callers
+                // that gave up on the request, e.g. on a statement timeout, drop us
+                // before starting on the next one.
+                tokio::task::yield_now().await;
             }
         }
         // last batch
@@ -134,9 +146,20 @@ impl PostgresRedoManager {
                     chrono::Utc::now().checked_sub_signed(chrono::Duration::from_std(age).ok()?)
                 })
             },
-            pid: self.redo_process.read().unwrap().as_ref().map(|p| p.id()),
+            pid: self
+                .redo_processes
+                .iter()
+                .find_map(|slot| slot.read().unwrap().as_ref().map(|p| p.id())),
         })
     }
+
+    /// Process ids of the currently running processes of the pool.
+    pub(crate) fn process_ids(&self) -> Vec<u32> {
+        self.redo_processes
+            .iter()
+            .filter_map(|slot| slot.read().unwrap().as_ref().map(|p| p.id()))
+            .collect()
+    }
 }
 
 impl PostgresRedoManager {
@@ -148,14 +171,31 @@ impl PostgresRedoManager {
         tenant_shard_id: TenantShardId,
     ) -> PostgresRedoManager {
         // The actual process is launched lazily, on first request.
+        let pool_size = conf.walredo_processes_per_tenant.max(1);
         PostgresRedoManager {
             tenant_shard_id,
             conf,
             last_redo_at: std::sync::Mutex::default(),
-            redo_process: RwLock::new(None),
+            redo_processes: (0..pool_size).map(|_| RwLock::new(None)).collect(),
+            redo_in_flight: (0..pool_size).map(|_| AtomicUsize::new(0)).collect(),
+            slot_metrics: (0..pool_size)
+                .map(|slot_idx| WalRedoSlotMetrics::new(&tenant_shard_id, slot_idx))
+                .collect(),
+            next_process: AtomicUsize::new(0),
         }
     }
 
+    /// Picks the process slot for the next batch: the first idle one in round-robin
+    /// order, or the one with the fewest batches in flight if all are busy.
+    fn pick_slot(&self) -> usize {
+        let pool_size = self.redo_processes.len();
+        let start = self.next_process.fetch_add(1, Ordering::Relaxed);
+        (0..pool_size)
+            .map(|i| (start + i) % pool_size)
+            .min_by_key(|&slot_idx| self.redo_in_flight[slot_idx].load(Ordering::Relaxed))
+            .expect("the pool has at least one process")
+    }
+
     /// This type doesn't have its own background task to check for idleness: we
     /// rely on our owner calling this function periodically in its own housekeeping
     /// loops.
@@ -164,8 +204,9 @@ impl PostgresRedoManager {
             if let Some(last_redo_at) = *g {
                 if last_redo_at.elapsed() >= idle_timeout {
                     drop(g);
-                    let mut guard = self.redo_process.write().unwrap();
-                    *guard = None;
+                    for slot in &self.redo_processes {
+                        *slot.write().unwrap() = None;
+                    }
                 }
             }
         }
@@ -191,14 +232,24 @@ impl PostgresRedoManager {
         const MAX_RETRY_ATTEMPTS: u32 = 1;
         let mut n_attempts = 0u32;
         loop {
+            let slot_idx = self.pick_slot();
+            let slot = &self.redo_processes[slot_idx];
+            let slot_metrics = &self.slot_metrics[slot_idx];
+            let in_flight = &slot_metrics.requests_in_flight;
+            self.redo_in_flight[slot_idx].fetch_add(1, Ordering::Relaxed);
+            in_flight.inc();
+            scopeguard::defer! {
+                self.redo_in_flight[slot_idx].fetch_sub(1, Ordering::Relaxed);
+                in_flight.dec();
+            }
             // launch the WAL redo process on first use
             let proc: Arc<process::WalRedoProcess> = {
-                let proc_guard = self.redo_process.read().unwrap();
+                let proc_guard = slot.read().unwrap();
                 match &*proc_guard {
                     None => {
                         // "upgrade" to write lock to launch the process
                         drop(proc_guard);
-                        let mut proc_guard = self.redo_process.write().unwrap();
+                        let mut proc_guard = slot.write().unwrap();
                         match &*proc_guard {
                             None => {
                                 let start = Instant::now();
@@ -206,6 +257,7 @@ impl PostgresRedoManager {
                                     process::WalRedoProcess::launch(
                                         self.conf,
                                         self.tenant_shard_id,
+                                        slot_idx,
                                         pg_version,
                                     )
                                     .context("launch walredo process")?,
@@ -216,6 +268,7 @@ impl PostgresRedoManager {
                                 info!(
                                     duration_ms = duration.as_millis(),
                                     pid = proc.id(),
+                                    slot = slot_idx,
                                     "launched walredo process"
                                 );
                                 *proc_guard = Some(Arc::clone(&proc));
@@ -236,6 +289,7 @@ impl PostgresRedoManager {
                 .context("apply_wal_records");
 
             let duration = started_at.elapsed();
+            slot_metrics.busy_seconds.inc_by(duration.as_secs_f64());
 
             let len = records.len();
             let nbytes = records.iter().fold(0, |acumulator, record| {
@@ -275,7 +329,7 @@ impl PostgresRedoManager {
                 // Avoid concurrent callers hitting the same issue.
                 // We can't prevent it from happening because we want to enable parallelism.
                 {
-                    let mut guard = self.redo_process.write().unwrap();
+                    let mut guard = slot.write().unwrap();
                     match &*guard {
                         Some(current_field_value) => {
                             if Arc::ptr_eq(current_field_value, &proc) {
@@ -405,6 +459,61 @@ mod tests {
         assert_eq!(&expected, &*page);
     }
 
+    #[tokio::test]
+    async fn short_v14_redo_with_process_pool() {
+        let expected = std::fs::read("test_data/short_v14_redo.page").unwrap();
+
+        let h = RedoHarness::with_processes(3).unwrap();
+
+        for _ in 0..4 {
+            let page = h
+                .manager
+                .request_redo(
+                    Key {
+                        field1: 0,
+                        field2: 1663,
+                        field3: 13010,
+                        field4: 1259,
+                        field5: 0,
+                        field6: 0,
+                    },
+                    Lsn::from_str("0/16E2408").unwrap(),
+                    None,
+                    short_records(),
+                    14,
+                )
+                .instrument(h.span())
+                .await
+                .unwrap();
+            assert_eq!(&expected, &*page);
+        }
+
+        // Requests went round-robin, launching every process of the pool.
+        let pids = h
+            .manager
+            .redo_processes
+            .iter()
+            .map(|slot| slot.read().unwrap().as_ref().map(|p| p.id()))
+            .collect::<Option<std::collections::HashSet<_>>>()
+            .expect("all processes launched");
+        assert_eq!(pids.len(), 3);
+    }
+
+    #[test]
+    fn pick_slot_prefers_idle_processes() {
+        let h = RedoHarness::with_processes(3).unwrap();
+        let manager = &h.manager;
+
+        assert_eq!(manager.pick_slot(), 0);
+        manager.redo_in_flight[1].store(2, Ordering::Relaxed);
+        assert_eq!(manager.pick_slot(), 2, "slot 1 is busy");
+
+        // With all processes busy, queue on the least busy one.
+        manager.redo_in_flight[0].store(1, Ordering::Relaxed);
+        manager.redo_in_flight[2].store(3, Ordering::Relaxed);
+        assert_eq!(manager.pick_slot(), 0);
+    }
+
     #[tokio::test]
     async fn short_v14_fails_for_wrong_key_but_returns_zero_page() {
         let h = RedoHarness::new().unwrap();
@@ -481,10 +590,15 @@ mod tests {
 
     impl RedoHarness {
         fn new() -> anyhow::Result<Self> {
+            Self::with_processes(1)
+        }
+
+        fn with_processes(walredo_processes_per_tenant: usize) -> anyhow::Result<Self> {
             crate::tenant::harness::setup_logging();
 
             let repo_dir = camino_tempfile::tempdir()?;
-            let conf = PageServerConf::dummy_conf(repo_dir.path().to_path_buf());
+            let mut conf = PageServerConf::dummy_conf(repo_dir.path().to_path_buf());
+            conf.walredo_processes_per_tenant = walredo_processes_per_tenant;
             let conf = Box::leak(Box::new(conf));
             let tenant_shard_id = TenantShardId::unsharded(TenantId::generate());
 
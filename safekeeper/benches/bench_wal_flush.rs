@@ -0,0 +1,171 @@
+//! Quantify what group commit (`--wal-flush-batch-bytes`, `--wal-flush-batch-timeout`)
+//! does to the WAL ingest throughput of a safekeeper.
+//!
+//! Each iteration writes [`NRECORDS`] WAL records to a fresh [`PhysicalStorage`], each
+//! record as if it arrived in its own AppendRequest, and flushes after every `batch`
+//! of them, the way the WalAcceptor does. `batch = 1` is the behavior without group
+//! commit: one fsync per AppendRequest.
+//!
+//! Criterion reports the throughput in bytes of WAL written. The gain depends almost
+//! entirely on the fsync latency of the disk the benchmark runs on, so compare numbers
+//! from the same machine only.
+
+use camino_tempfile::Utf8TempDir;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use postgres_ffi::v16::bindings::{XLogLongPageHeaderData, XLogPageHeaderData, XLOG_PAGE_MAGIC};
+use postgres_ffi::{encode_logical_message, pg_constants, PG_TLI, WAL_SEGMENT_SIZE, XLOG_BLCKSZ};
+use safekeeper::safekeeper::ServerInfo;
+use safekeeper::state::TimelinePersistentState;
+use safekeeper::wal_storage::{PhysicalStorage, Storage};
+use safekeeper::SafeKeeperConf;
+use std::time::{Duration, Instant};
+use utils::id::TenantTimelineId;
+use utils::lsn::Lsn;
+
+/// Records written per iteration. They all fit into the first WAL segment.
+const NRECORDS: usize = 1024;
+
+fn bench(c: &mut Criterion) {
+    let wal = Wal::new(NRECORDS);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("flush_every");
+    group.throughput(Throughput::Bytes(wal.measured_bytes()));
+    for batch in [1, 4, 16, 64, NRECORDS] {
+        group.bench_with_input(BenchmarkId::from_parameter(batch), &batch, |b, batch| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    elapsed += runtime.block_on(write_wal(&wal, *batch));
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+/// WAL of logical message records laid out from the start of segment 1, split into
+/// the pieces that the records were sent in.
+struct Wal {
+    /// Start LSN and bytes of each record, including the page headers before it.
+    pieces: Vec<(Lsn, Vec<u8>)>,
+    end_lsn: Lsn,
+}
+
+impl Wal {
+    fn new(nrecords: usize) -> Self {
+        let seg_start = WAL_SEGMENT_SIZE as u64;
+        let mut lsn = Lsn(seg_start);
+        let mut pieces = Vec::with_capacity(nrecords);
+        let record = encode_logical_message("bench", &"x".repeat(200));
+        // Without the padding to the next record.
+        let tot_len = u32::from_le_bytes(record[0..4].try_into().unwrap()) as usize;
+        for _ in 0..nrecords {
+            let start_lsn = lsn;
+            let mut piece = Vec::new();
+            let mut written = 0;
+            while written < record.len() {
+                if lsn.0 == seg_start {
+                    let hdr = XLogLongPageHeaderData {
+                        std: XLogPageHeaderData {
+                            xlp_magic: XLOG_PAGE_MAGIC as u16,
+                            xlp_info: pg_constants::XLP_LONG_HEADER,
+                            xlp_tli: PG_TLI,
+                            xlp_pageaddr: lsn.0,
+                            ..Default::default()
+                        },
+                        xlp_sysid: 0,
+                        xlp_seg_size: WAL_SEGMENT_SIZE as u32,
+                        xlp_xlog_blcksz: XLOG_BLCKSZ as u32,
+                    };
+                    let hdr = hdr.encode().unwrap();
+                    piece.extend_from_slice(&hdr);
+                    lsn += hdr.len() as u64;
+                } else if lsn.block_offset() == 0 {
+                    let contrecord = written > 0;
+                    let hdr = XLogPageHeaderData {
+                        xlp_magic: XLOG_PAGE_MAGIC as u16,
+                        xlp_info: if contrecord {
+                            pg_constants::XLP_FIRST_IS_CONTRECORD
+                        } else {
+                            0
+                        },
+                        xlp_tli: PG_TLI,
+                        xlp_pageaddr: lsn.0,
+                        xlp_rem_len: if contrecord { tot_len - written } else { 0 } as u32,
+                        ..Default::default()
+                    };
+                    let hdr = hdr.encode().unwrap();
+                    piece.extend_from_slice(&hdr);
+                    lsn += hdr.len() as u64;
+                }
+                let n = std::cmp::min(record.len() - written, lsn.remaining_in_block() as usize);
+                piece.extend_from_slice(&record[written..written + n]);
+                written += n;
+                lsn += n as u64;
+            }
+            pieces.push((start_lsn, piece));
+        }
+        assert!(
+            lsn.segment_number(WAL_SEGMENT_SIZE) == 1,
+            "WAL must fit into one segment"
+        );
+        Wal {
+            pieces,
+            end_lsn: lsn,
+        }
+    }
+
+    /// Bytes written in the measured part of an iteration, see [`write_wal`].
+    fn measured_bytes(&self) -> u64 {
+        self.pieces[1..].iter().map(|(_, p)| p.len() as u64).sum()
+    }
+}
+
+/// Write `wal` to a fresh timeline directory, flushing after every `batch` records,
+/// and return how long that took.
+async fn write_wal(wal: &Wal, batch: usize) -> Duration {
+    let timeline_dir = camino_tempfile::tempdir().unwrap();
+    let mut storage = new_storage(&timeline_dir);
+
+    // The first write creates and zero-fills the segment file, leave it out.
+    let ((first_lsn, first), rest) = wal.pieces.split_first().unwrap();
+    storage.write_wal(*first_lsn, first).await.unwrap();
+    storage.flush_wal().await.unwrap();
+
+    let started_at = Instant::now();
+    for pieces in rest.chunks(batch) {
+        for (lsn, piece) in pieces {
+            storage.write_wal(*lsn, piece).await.unwrap();
+        }
+        storage.flush_wal().await.unwrap();
+    }
+    let elapsed = started_at.elapsed();
+
+    assert_eq!(storage.flush_lsn(), wal.end_lsn);
+    elapsed
+}
+
+fn new_storage(timeline_dir: &Utf8TempDir) -> PhysicalStorage {
+    let ttid = TenantTimelineId::empty();
+    let server_info = ServerInfo {
+        pg_version: 160000,
+        system_id: 0,
+        wal_seg_size: WAL_SEGMENT_SIZE as u32,
+    };
+    let state = TimelinePersistentState::new(&ttid, server_info, vec![], Lsn(0), Lsn(0));
+    PhysicalStorage::new(
+        &ttid,
+        timeline_dir.path().to_owned(),
+        &SafeKeeperConf::dummy(),
+        &state,
+    )
+    .unwrap()
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
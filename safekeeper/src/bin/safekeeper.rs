@@ -28,7 +28,8 @@ use utils::pid_file;
 use metrics::set_build_info_metric;
 use safekeeper::defaults::{
     DEFAULT_HEARTBEAT_TIMEOUT, DEFAULT_HTTP_LISTEN_ADDR, DEFAULT_MAX_OFFLOADER_LAG_BYTES,
-    DEFAULT_PG_LISTEN_ADDR,
+    DEFAULT_MAX_UNFLUSHED_WAL_BYTES, DEFAULT_PG_LISTEN_ADDR, DEFAULT_WAL_FLUSH_BATCH_BYTES,
+    DEFAULT_WAL_FLUSH_BATCH_TIMEOUT,
 };
 use safekeeper::wal_service;
 use safekeeper::GlobalTimelines;
@@ -170,6 +171,18 @@ struct Args {
     /// still needed for existing replication connection.
     #[arg(long)]
     walsenders_keep_horizon: bool,
+    /// Maximum amount of WAL received from a walproposer connection that is not
+    /// flushed to disk yet. Reading from the connection pauses when it is reached.
+    #[arg(long, default_value_t = DEFAULT_MAX_UNFLUSHED_WAL_BYTES)]
+    max_unflushed_wal_bytes: u64,
+    /// Group commit: when no more WAL is queued, keep waiting for more before
+    /// flushing until this much WAL is written without flush, or until
+    /// --wal-flush-batch-timeout passes. 0 flushes as soon as the queue is empty.
+    #[arg(long, default_value_t = DEFAULT_WAL_FLUSH_BATCH_BYTES, verbatim_doc_comment)]
+    wal_flush_batch_bytes: u64,
+    /// Maximum time to wait for more WAL before flushing, see --wal-flush-batch-bytes.
+    #[arg(long, value_parser= humantime::parse_duration, default_value = DEFAULT_WAL_FLUSH_BATCH_TIMEOUT)]
+    wal_flush_batch_timeout: Duration,
 }
 
 // Like PathBufValueParser, but allows empty string.
@@ -300,6 +313,9 @@ async fn main() -> anyhow::Result<()> {
         http_auth,
         current_thread_runtime: args.current_thread_runtime,
         walsenders_keep_horizon: args.walsenders_keep_horizon,
+        max_unflushed_wal_bytes: args.max_unflushed_wal_bytes,
+        wal_flush_batch_bytes: args.wal_flush_batch_bytes,
+        wal_flush_batch_timeout: args.wal_flush_batch_timeout,
     };
 
     // initialize sentry if SENTRY_DSN is provided
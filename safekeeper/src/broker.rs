@@ -1,12 +1,12 @@
 //! Communication with the broker, providing safekeeper peers and pageserver coordination.
 
-use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 
 use anyhow::Error;
 use anyhow::Result;
 
+use storage_broker::dead_letters;
 use storage_broker::parse_proto_ttid;
 
 use storage_broker::proto::subscribe_safekeeper_info_request::SubscriptionKey as ProtoSubscriptionKey;
@@ -79,8 +79,11 @@ async fn pull_loop(conf: SafeKeeperConf) -> Result<()> {
     let mut client = storage_broker::connect(conf.broker_endpoint, conf.broker_keepalive_interval)?;
 
     // TODO: subscribe only to local timelines instead of all
+    // Prefer our own updates: receiving them shows that the connection to the broker
+    // is alive, see below.
     let request = SubscribeSafekeeperInfoRequest {
         subscription_key: Some(ProtoSubscriptionKey::All(())),
+        preferred_safekeeper_id: Some(conf.my_id.0),
     };
 
     let mut stream = client
@@ -94,11 +97,21 @@ async fn pull_loop(conf: SafeKeeperConf) -> Result<()> {
     let err_counter = BROKER_PULLED_UPDATES.with_label_values(&["error"]);
 
     while let Some(msg) = stream.message().await? {
-        let proto_ttid = msg
-            .tenant_timeline_id
-            .as_ref()
-            .ok_or_else(|| anyhow!("missing tenant_timeline_id"))?;
-        let ttid = parse_proto_ttid(proto_ttid)?;
+        // A malformed message says nothing about the other ones, skip it instead of
+        // resubscribing.
+        let Some(proto_ttid) = msg.tenant_timeline_id.as_ref() else {
+            warn!("skipping broker message without tenant_timeline_id");
+            dead_letters::record(&msg, "missing tenant_timeline_id");
+            continue;
+        };
+        let ttid = match parse_proto_ttid(proto_ttid) {
+            Ok(ttid) => ttid,
+            Err(status) => {
+                warn!("skipping broker message: {}", status.message());
+                dead_letters::record(&msg, status.message());
+                continue;
+            }
+        };
         if let Ok(tli) = GlobalTimelines::get(ttid) {
             // Note that we also receive *our own* info. That's
             // important, as it is used as an indication of live
@@ -5,7 +5,7 @@ use anyhow::Context;
 use std::str::{self, FromStr};
 use std::sync::Arc;
 use tokio::io::{AsyncRead, AsyncWrite};
-use tracing::{debug, info, info_span, Instrument};
+use tracing::{debug, field, info, info_span, Instrument};
 
 use crate::auth::check_permission;
 use crate::json_ctrl::{handle_json_ctrl, AppendLogicalMessage};
@@ -48,6 +48,7 @@ enum SafekeeperPostgresCommand {
     StartReplication { start_lsn: Lsn, term: Option<Term> },
     IdentifySystem,
     TimelineStatus,
+    WalRetention,
     JSONCtrl { cmd: AppendLogicalMessage },
 }
 
@@ -75,6 +76,8 @@ fn parse_cmd(cmd: &str) -> anyhow::Result<SafekeeperPostgresCommand> {
         Ok(SafekeeperPostgresCommand::IdentifySystem)
     } else if cmd.starts_with("TIMELINE_STATUS") {
         Ok(SafekeeperPostgresCommand::TimelineStatus)
+    } else if cmd.starts_with("WAL_RETENTION") {
+        Ok(SafekeeperPostgresCommand::WalRetention)
     } else if cmd.starts_with("JSON_CTRL") {
         let cmd = cmd.strip_prefix("JSON_CTRL").context("invalid prefix")?;
         Ok(SafekeeperPostgresCommand::JSONCtrl {
@@ -90,6 +93,7 @@ fn cmd_to_string(cmd: &SafekeeperPostgresCommand) -> &str {
         SafekeeperPostgresCommand::StartWalPush => "START_WAL_PUSH",
         SafekeeperPostgresCommand::StartReplication { .. } => "START_REPLICATION",
         SafekeeperPostgresCommand::TimelineStatus => "TIMELINE_STATUS",
+        SafekeeperPostgresCommand::WalRetention => "WAL_RETENTION",
         SafekeeperPostgresCommand::IdentifySystem => "IDENTIFY_SYSTEM",
         SafekeeperPostgresCommand::JSONCtrl { .. } => "JSON_CTRL",
     }
@@ -218,10 +222,15 @@ impl<IO: AsyncRead + AsyncWrite + Unpin + Send> postgres_backend::Handler<IO>
         self.check_permission(Some(tenant_id))?;
         self.ttid = TenantTimelineId::new(tenant_id, timeline_id);
 
+        let peer_addr = *pgb.get_peer_addr();
         match cmd {
             SafekeeperPostgresCommand::StartWalPush => {
                 self.handle_start_wal_push(pgb)
-                    .instrument(info_span!("WAL receiver"))
+                    .instrument(info_span!(
+                        "WAL receiver",
+                        %peer_addr,
+                        protocol_version = field::Empty
+                    ))
                     .await
             }
             SafekeeperPostgresCommand::StartReplication { start_lsn, term } => {
@@ -231,6 +240,7 @@ impl<IO: AsyncRead + AsyncWrite + Unpin + Send> postgres_backend::Handler<IO>
             }
             SafekeeperPostgresCommand::IdentifySystem => self.handle_identify_system(pgb).await,
             SafekeeperPostgresCommand::TimelineStatus => self.handle_timeline_status(pgb).await,
+            SafekeeperPostgresCommand::WalRetention => self.handle_wal_retention(pgb).await,
             SafekeeperPostgresCommand::JSONCtrl { ref cmd } => {
                 handle_json_ctrl(self, pgb, cmd).await
             }
@@ -307,6 +317,36 @@ impl SafekeeperPostgresHandler {
         Ok(())
     }
 
+    /// Report WAL of the timeline kept on disk as a single json row, or no rows
+    /// if the timeline doesn't exist.
+    async fn handle_wal_retention<IO: AsyncRead + AsyncWrite + Unpin>(
+        &mut self,
+        pgb: &mut PostgresBackend<IO>,
+    ) -> Result<(), QueryError> {
+        let tli = match GlobalTimelines::get(self.ttid) {
+            Ok(tli) => Some(tli),
+            Err(TimelineError::NotFound(_)) => None,
+            Err(e) => return Err(QueryError::Other(e.into())),
+        };
+
+        pgb.write_message_noflush(&BeMessage::RowDescription(&[RowDescriptor {
+            name: b"json",
+            typoid: TEXT_OID,
+            typlen: -1,
+            ..Default::default()
+        }]))?;
+
+        if let Some(tli) = tli {
+            let retention = tli.get_wal_retention().await;
+            let data = serde_json::to_vec(&retention)
+                .with_context(|| format!("failed to serialize {retention:?}"))?;
+            pgb.write_message_noflush(&BeMessage::DataRow(&[Some(&data)]))?;
+        }
+
+        pgb.write_message_noflush(&BeMessage::CommandComplete(b"WAL_RETENTION"))?;
+        Ok(())
+    }
+
     ///
     /// Handle IDENTIFY_SYSTEM replication command
     ///
@@ -48,6 +48,9 @@ pub mod defaults {
 
     pub const DEFAULT_HEARTBEAT_TIMEOUT: &str = "5000ms";
     pub const DEFAULT_MAX_OFFLOADER_LAG_BYTES: u64 = 128 * (1 << 20);
+    pub const DEFAULT_MAX_UNFLUSHED_WAL_BYTES: u64 = 32 * (1 << 20);
+    pub const DEFAULT_WAL_FLUSH_BATCH_BYTES: u64 = 0;
+    pub const DEFAULT_WAL_FLUSH_BATCH_TIMEOUT: &str = "0ms";
 }
 
 #[derive(Debug, Clone)]
@@ -79,6 +82,9 @@ pub struct SafeKeeperConf {
     pub http_auth: Option<Arc<SwappableJwtAuth>>,
     pub current_thread_runtime: bool,
     pub walsenders_keep_horizon: bool,
+    pub max_unflushed_wal_bytes: u64,
+    pub wal_flush_batch_bytes: u64,
+    pub wal_flush_batch_timeout: Duration,
 }
 
 impl SafeKeeperConf {
@@ -97,8 +103,8 @@ impl SafeKeeperConf {
 }
 
 impl SafeKeeperConf {
-    #[cfg(test)]
-    fn dummy() -> Self {
+    /// Configuration with defaults, for tests and benchmarks.
+    pub fn dummy() -> Self {
         SafeKeeperConf {
             workdir: Utf8PathBuf::from("./"),
             no_sync: false,
@@ -123,6 +129,9 @@ impl SafeKeeperConf {
             max_offloader_lag_bytes: defaults::DEFAULT_MAX_OFFLOADER_LAG_BYTES,
             current_thread_runtime: false,
             walsenders_keep_horizon: false,
+            max_unflushed_wal_bytes: defaults::DEFAULT_MAX_UNFLUSHED_WAL_BYTES,
+            wal_flush_batch_bytes: defaults::DEFAULT_WAL_FLUSH_BATCH_BYTES,
+            wal_flush_batch_timeout: Duration::ZERO,
         }
     }
 }
@@ -11,8 +11,8 @@ use futures::Future;
 use metrics::{
     core::{AtomicU64, Collector, Desc, GenericCounter, GenericGaugeVec, Opts},
     proto::MetricFamily,
-    register_int_counter, register_int_counter_pair_vec, register_int_counter_vec, Gauge,
-    IntCounter, IntCounterPairVec, IntCounterVec, IntGaugeVec,
+    register_int_counter, register_int_counter_pair_vec, register_int_counter_vec,
+    register_int_gauge, Gauge, IntCounter, IntCounterPairVec, IntCounterVec, IntGaugeVec,
 };
 use once_cell::sync::Lazy;
 
@@ -148,6 +148,14 @@ pub static RECEIVED_PS_FEEDBACKS: Lazy<IntCounter> = Lazy::new(|| {
     .expect("Failed to register safekeeper_received_ps_feedbacks_total counter")
 });
 
+pub static UNFLUSHED_WAL_BYTES: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "safekeeper_unflushed_wal_bytes",
+        "WAL received from walproposers and not flushed to disk yet"
+    )
+    .expect("Failed to register safekeeper_unflushed_wal_bytes gauge")
+});
+
 pub const LABEL_UNKNOWN: &str = "unknown";
 
 /// Labels for traffic metrics.
@@ -313,6 +321,10 @@ pub struct FullTimelineInfo {
     pub wal_backup_active: bool,
     pub timeline_is_active: bool,
     pub num_computes: u32,
+    /// Connected walproposers, i.e. compute connections not counting recovery.
+    pub num_proposers: u32,
+    /// Highest (flush_lsn, commit_lsn) reported to connected walproposers.
+    pub reported_lsns: (Lsn, Lsn),
     pub last_removed_segno: XLogSegNo,
 
     pub epoch_start_lsn: Lsn,
@@ -339,6 +351,9 @@ pub struct TimelineCollector {
     timeline_active: GenericGaugeVec<AtomicU64>,
     wal_backup_active: GenericGaugeVec<AtomicU64>,
     connected_computes: IntGaugeVec,
+    connected_proposers: IntGaugeVec,
+    reported_flush_lsn: GenericGaugeVec<AtomicU64>,
+    reported_commit_lsn: GenericGaugeVec<AtomicU64>,
     disk_usage: GenericGaugeVec<AtomicU64>,
     acceptor_term: GenericGaugeVec<AtomicU64>,
     written_wal_bytes: GenericGaugeVec<AtomicU64>,
@@ -478,6 +493,36 @@ impl TimelineCollector {
         .unwrap();
         descs.extend(connected_computes.desc().into_iter().cloned());
 
+        let connected_proposers = IntGaugeVec::new(
+            Opts::new(
+                "safekeeper_connected_proposers",
+                "Number of connected walproposers, more than one means duplicate proposers",
+            ),
+            &["tenant_id", "timeline_id"],
+        )
+        .unwrap();
+        descs.extend(connected_proposers.desc().into_iter().cloned());
+
+        let reported_flush_lsn = GenericGaugeVec::new(
+            Opts::new(
+                "safekeeper_reported_flush_lsn",
+                "flush_lsn last reported to a connected walproposer, grouped by timeline",
+            ),
+            &["tenant_id", "timeline_id"],
+        )
+        .unwrap();
+        descs.extend(reported_flush_lsn.desc().into_iter().cloned());
+
+        let reported_commit_lsn = GenericGaugeVec::new(
+            Opts::new(
+                "safekeeper_reported_commit_lsn",
+                "commit_lsn last reported to a connected walproposer, grouped by timeline",
+            ),
+            &["tenant_id", "timeline_id"],
+        )
+        .unwrap();
+        descs.extend(reported_commit_lsn.desc().into_iter().cloned());
+
         let disk_usage = GenericGaugeVec::new(
             Opts::new(
                 "safekeeper_disk_usage_bytes",
@@ -560,6 +605,9 @@ impl TimelineCollector {
             timeline_active,
             wal_backup_active,
             connected_computes,
+            connected_proposers,
+            reported_flush_lsn,
+            reported_commit_lsn,
             disk_usage,
             acceptor_term,
             written_wal_bytes,
@@ -593,6 +641,9 @@ impl Collector for TimelineCollector {
         self.timeline_active.reset();
         self.wal_backup_active.reset();
         self.connected_computes.reset();
+        self.connected_proposers.reset();
+        self.reported_flush_lsn.reset();
+        self.reported_commit_lsn.reset();
         self.disk_usage.reset();
         self.acceptor_term.reset();
         self.written_wal_bytes.reset();
@@ -651,6 +702,16 @@ impl Collector for TimelineCollector {
             self.connected_computes
                 .with_label_values(labels)
                 .set(tli.num_computes as i64);
+            self.connected_proposers
+                .with_label_values(labels)
+                .set(tli.num_proposers as i64);
+            let (reported_flush_lsn, reported_commit_lsn) = tli.reported_lsns;
+            self.reported_flush_lsn
+                .with_label_values(labels)
+                .set(reported_flush_lsn.into());
+            self.reported_commit_lsn
+                .with_label_values(labels)
+                .set(reported_commit_lsn.into());
             self.acceptor_term
                 .with_label_values(labels)
                 .set(tli.persisted_state.acceptor_state.term);
@@ -706,6 +767,9 @@ impl Collector for TimelineCollector {
         mfs.extend(self.timeline_active.collect());
         mfs.extend(self.wal_backup_active.collect());
         mfs.extend(self.connected_computes.collect());
+        mfs.extend(self.connected_proposers.collect());
+        mfs.extend(self.reported_flush_lsn.collect());
+        mfs.extend(self.reported_commit_lsn.collect());
         mfs.extend(self.disk_usage.collect());
         mfs.extend(self.acceptor_term.collect());
         mfs.extend(self.written_wal_bytes.collect());
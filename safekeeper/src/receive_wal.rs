@@ -3,14 +3,18 @@
 //! sends replies back.
 
 use crate::handler::SafekeeperPostgresHandler;
+use crate::metrics::UNFLUSHED_WAL_BYTES;
 use crate::safekeeper::AcceptorProposerMessage;
 use crate::safekeeper::ProposerAcceptorMessage;
 use crate::safekeeper::ServerInfo;
+use crate::safekeeper::Term;
+use crate::safekeeper::SK_PROTOCOL_VERSION;
 use crate::timeline::Timeline;
 use crate::wal_service::ConnectionId;
 use crate::GlobalTimelines;
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
 use bytes::BytesMut;
+use metrics::IntGauge;
 use parking_lot::MappedMutexGuard;
 use parking_lot::Mutex;
 use parking_lot::MutexGuard;
@@ -21,6 +25,7 @@ use postgres_backend::QueryError;
 use pq_proto::BeMessage;
 use serde::Deserialize;
 use serde::Serialize;
+use std::cmp::max;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::io::AsyncRead;
@@ -29,10 +34,12 @@ use tokio::sync::mpsc::channel;
 use tokio::sync::mpsc::error::TryRecvError;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::Semaphore;
 use tokio::task;
 use tokio::task::JoinHandle;
 use tokio::time::Duration;
 use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
 use tracing::*;
 use utils::id::TenantTimelineId;
 use utils::lsn::Lsn;
@@ -68,6 +75,10 @@ impl WalReceivers {
         let walreceiver = WalReceiverState {
             conn_id,
             status: WalReceiverStatus::Voting,
+            term: None,
+            reported_flush_lsn: Lsn::INVALID,
+            reported_commit_lsn: Lsn::INVALID,
+            fenced: CancellationToken::new(),
         };
         // find empty slot or create new one
         let pos = if let Some(pos) = slots.iter().position(|s| s.is_none()) {
@@ -102,6 +113,30 @@ impl WalReceivers {
         self.mutex.lock().slots.iter().flatten().count()
     }
 
+    /// Get number of walreceivers connected by walproposers, i.e. excluding
+    /// recovery. Normally 0 or 1.
+    pub fn get_num_proposers(self: &Arc<WalReceivers>) -> usize {
+        self.mutex
+            .lock()
+            .slots
+            .iter()
+            .flatten()
+            .filter(|s| s.conn_id.is_some())
+            .count()
+    }
+
+    /// Get the highest flush_lsn and commit_lsn reported to walproposers by
+    /// the current walreceivers.
+    pub fn get_reported_lsns(self: &Arc<WalReceivers>) -> (Lsn, Lsn) {
+        let mut flush_lsn = Lsn::INVALID;
+        let mut commit_lsn = Lsn::INVALID;
+        for s in self.mutex.lock().slots.iter().flatten() {
+            flush_lsn = max(flush_lsn, s.reported_flush_lsn);
+            commit_lsn = max(commit_lsn, s.reported_commit_lsn);
+        }
+        (flush_lsn, commit_lsn)
+    }
+
     /// Get state of all walreceivers.
     pub fn get_all(self: &Arc<WalReceivers>) -> Vec<WalReceiverState> {
         self.mutex.lock().slots.iter().flatten().cloned().collect()
@@ -119,6 +154,29 @@ impl WalReceivers {
             .count()
     }
 
+    /// Record that the walproposer of walreceiver `id` got elected in `term`, and
+    /// fence out walproposers elected in lower terms: there must be only one
+    /// writer, and an older one can only be stale.
+    fn elected(self: &Arc<WalReceivers>, id: WalReceiverId, term: Term) {
+        let mut shared = self.mutex.lock();
+        for (slot_id, slot) in shared.slots.iter_mut().enumerate() {
+            let Some(slot) = slot else {
+                continue;
+            };
+            if slot_id == id {
+                slot.term = Some(term);
+            } else if slot.conn_id.is_some() && slot.term.is_some_and(|t| t < term) {
+                info!(
+                    "fencing walproposer connection {} elected in term {}, new term is {}",
+                    slot.conn_id.unwrap(),
+                    slot.term.unwrap(),
+                    term
+                );
+                slot.fenced.cancel();
+            }
+        }
+    }
+
     /// Unregister walreceiver.
     fn unregister(self: &Arc<WalReceivers>, id: WalReceiverId) {
         let mut shared = self.mutex.lock();
@@ -142,6 +200,14 @@ pub struct WalReceiverState {
     /// None means it is recovery initiated by us (this safekeeper).
     pub conn_id: Option<ConnectionId>,
     pub status: WalReceiverStatus,
+    /// Term in which the walproposer got elected, once it did.
+    pub term: Option<Term>,
+    /// flush_lsn and commit_lsn of the last AppendResponse sent to the walproposer.
+    pub reported_flush_lsn: Lsn,
+    pub reported_commit_lsn: Lsn,
+    /// Cancelled when a walproposer gets elected in a higher term.
+    #[serde(skip)]
+    fenced: CancellationToken,
 }
 
 /// Walreceiver status. Currently only whether it passed voting stage and
@@ -164,6 +230,10 @@ impl WalReceiverGuard {
     fn get(&self) -> MappedMutexGuard<WalReceiverState> {
         self.walreceivers.get_slot(self.id)
     }
+
+    fn elected(&self, term: Term) {
+        self.walreceivers.elected(self.id, term)
+    }
 }
 
 impl Drop for WalReceiverGuard {
@@ -175,6 +245,77 @@ impl Drop for WalReceiverGuard {
 pub const MSG_QUEUE_SIZE: usize = 256;
 pub const REPLY_QUEUE_SIZE: usize = 16;
 
+/// Bounds the WAL that was read from a walproposer connection but not flushed by
+/// its WalAcceptor yet: the network reader takes permits for the WAL of each
+/// AppendRequest before passing it on, and the WalAcceptor returns them once it
+/// flushed.
+#[derive(Clone)]
+pub struct UnflushedWalLimit(Arc<UnflushedWalLimitInner>);
+
+struct UnflushedWalLimitInner {
+    permits: Semaphore,
+    max_permits: u32,
+    /// Unflushed WAL of all connections, [`UNFLUSHED_WAL_BYTES`] outside of tests.
+    gauge: IntGauge,
+}
+
+impl UnflushedWalLimit {
+    pub fn new(max_bytes: u64) -> Self {
+        Self::with_gauge(max_bytes, UNFLUSHED_WAL_BYTES.clone())
+    }
+
+    fn with_gauge(max_bytes: u64, gauge: IntGauge) -> Self {
+        let max_permits = max_bytes.clamp(1, Semaphore::MAX_PERMITS as u64) as u32;
+        UnflushedWalLimit(Arc::new(UnflushedWalLimitInner {
+            permits: Semaphore::new(max_permits as usize),
+            max_permits,
+            gauge,
+        }))
+    }
+
+    /// Permits that `wal_bytes` of WAL take. WAL bigger than the limit takes all of
+    /// them, so that it can still pass once everything else is flushed.
+    fn permits_for(&self, wal_bytes: usize) -> u32 {
+        wal_bytes.min(self.0.max_permits as usize) as u32
+    }
+
+    /// Wait until the WAL in `msg`, if any, fits into the limit.
+    async fn acquire(&self, msg: &ProposerAcceptorMessage) {
+        let n = match msg {
+            ProposerAcceptorMessage::AppendRequest(append_request) => {
+                self.permits_for(append_request.wal_data.len())
+            }
+            _ => 0,
+        };
+        if n == 0 {
+            return;
+        }
+        self.0
+            .permits
+            .acquire_many(n)
+            .await
+            .expect("semaphore is never closed")
+            .forget();
+        self.0.gauge.add(n as i64);
+    }
+
+    fn release(&self, n: u32) {
+        if n == 0 {
+            return;
+        }
+        self.0.permits.add_permits(n as usize);
+        self.0.gauge.sub(n as i64);
+    }
+}
+
+impl Drop for UnflushedWalLimitInner {
+    fn drop(&mut self) {
+        // WAL of messages that were still queued when the connection went away.
+        let unflushed = self.max_permits as usize - self.permits.available_permits();
+        self.gauge.sub(unflushed as i64);
+    }
+}
+
 impl SafekeeperPostgresHandler {
     /// Wrapper around handle_start_wal_push_guts handling result. Error is
     /// handled here while we're still in walreceiver ttid span; with API
@@ -217,6 +358,7 @@ impl SafekeeperPostgresHandler {
             pgb_reader: &mut pgb_reader,
             peer_addr,
             acceptor_handle: &mut acceptor_handle,
+            unflushed_wal_limit: UnflushedWalLimit::new(self.conf.max_unflushed_wal_bytes),
         };
 
         // Read first message and create timeline if needed.
@@ -273,6 +415,7 @@ struct NetworkReader<'a, IO> {
     // WalAcceptor is spawned when we learn server info from walproposer and
     // create timeline; handle is put here.
     acceptor_handle: &'a mut Option<JoinHandle<anyhow::Result<()>>>,
+    unflushed_wal_limit: UnflushedWalLimit,
 }
 
 impl<'a, IO: AsyncRead + AsyncWrite + Unpin> NetworkReader<'a, IO> {
@@ -284,9 +427,19 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> NetworkReader<'a, IO> {
         let tli = match next_msg {
             ProposerAcceptorMessage::Greeting(ref greeting) => {
                 info!(
-                    "start handshake with walproposer {} sysid {} timeline {}",
-                    self.peer_addr, greeting.system_id, greeting.tli,
+                    "start handshake with walproposer {} sysid {} timeline {} protocol version {}",
+                    self.peer_addr, greeting.system_id, greeting.tli, greeting.protocol_version,
                 );
+                // Check before creating the timeline: nothing the proposer sends later
+                // can be trusted to be parsed correctly.
+                if greeting.protocol_version != SK_PROTOCOL_VERSION {
+                    return Err(CopyStreamHandlerEnd::Other(anyhow!(
+                        "unsupported proposer protocol version {}, this safekeeper supports {}",
+                        greeting.protocol_version,
+                        SK_PROTOCOL_VERSION
+                    )));
+                }
+                Span::current().record("protocol_version", greeting.protocol_version);
                 let server_info = ServerInfo {
                     pg_version: greeting.pg_version,
                     system_id: greeting.system_id,
@@ -316,15 +469,21 @@ impl<'a, IO: AsyncRead + AsyncWrite + Unpin> NetworkReader<'a, IO> {
             msg_rx,
             reply_tx,
             Some(self.conn_id),
+            Some(self.unflushed_wal_limit.clone()),
         ));
 
         // Forward all messages to WalAcceptor
-        read_network_loop(self.pgb_reader, msg_tx, next_msg).await
+        read_network_loop(self.pgb_reader, msg_tx, next_msg, &self.unflushed_wal_limit).await
     }
 }
 
 /// Read next message from walproposer.
-/// TODO: Return Ok(None) on graceful termination.
+///
+/// A walproposer ending the stream with CopyDone or Terminate (or just closing the
+/// connection) shows up as the corresponding [`CopyStreamHandlerEnd`]. Returning it
+/// as an error ends the network loop, the WalAcceptor still processes the messages
+/// queued so far, and `handle_copy_stream_end` logs these as expected ends at info
+/// level.
 async fn read_message<IO: AsyncRead + AsyncWrite + Unpin>(
     pgb_reader: &mut PostgresBackendReader<IO>,
 ) -> Result<ProposerAcceptorMessage, CopyStreamHandlerEnd> {
@@ -337,8 +496,11 @@ async fn read_network_loop<IO: AsyncRead + AsyncWrite + Unpin>(
     pgb_reader: &mut PostgresBackendReader<IO>,
     msg_tx: Sender<ProposerAcceptorMessage>,
     mut next_msg: ProposerAcceptorMessage,
+    unflushed_wal_limit: &UnflushedWalLimit,
 ) -> Result<(), CopyStreamHandlerEnd> {
     loop {
+        // Don't read more while too much WAL is waiting to be flushed.
+        unflushed_wal_limit.acquire(&next_msg).await;
         if msg_tx.send(next_msg).await.is_err() {
             return Ok(()); // chan closed, WalAcceptor terminated
         }
@@ -407,6 +569,10 @@ pub struct WalAcceptor {
     msg_rx: Receiver<ProposerAcceptorMessage>,
     reply_tx: Sender<AcceptorProposerMessage>,
     conn_id: Option<ConnectionId>,
+    unflushed_wal_limit: Option<UnflushedWalLimit>,
+    /// Group commit settings, see `SafeKeeperConf::wal_flush_batch_bytes`.
+    flush_batch_bytes: u64,
+    flush_batch_timeout: Duration,
 }
 
 impl WalAcceptor {
@@ -415,27 +581,34 @@ impl WalAcceptor {
     /// message processing is encountered.
     ///
     /// conn_id None means WalAcceptor is used by recovery initiated at this safekeeper.
+    /// If unflushed_wal_limit is given, its permits for the WAL of processed
+    /// AppendRequests are returned once that WAL is flushed.
     pub fn spawn(
         tli: Arc<Timeline>,
         msg_rx: Receiver<ProposerAcceptorMessage>,
         reply_tx: Sender<AcceptorProposerMessage>,
         conn_id: Option<ConnectionId>,
+        unflushed_wal_limit: Option<UnflushedWalLimit>,
     ) -> JoinHandle<anyhow::Result<()>> {
-        task::spawn(async move {
-            let mut wa = WalAcceptor {
-                tli,
-                msg_rx,
-                reply_tx,
-                conn_id,
-            };
-
-            let span_ttid = wa.tli.ttid; // satisfy borrow checker
-            wa.run()
-                .instrument(
-                    info_span!("WAL acceptor", cid = %conn_id.unwrap_or(0), ttid = %span_ttid),
-                )
-                .await
-        })
+        // Created here rather than in the task, to be a child of the caller's span
+        // and inherit its fields, e.g. the walproposer's address.
+        let span = info_span!("WAL acceptor", cid = %conn_id.unwrap_or(0), ttid = %tli.ttid);
+        let conf = GlobalTimelines::get_global_config();
+        task::spawn(
+            async move {
+                let mut wa = WalAcceptor {
+                    tli,
+                    msg_rx,
+                    reply_tx,
+                    conn_id,
+                    unflushed_wal_limit,
+                    flush_batch_bytes: conf.wal_flush_batch_bytes,
+                    flush_batch_timeout: conf.wal_flush_batch_timeout,
+                };
+                wa.run().await
+            }
+            .instrument(span),
+        )
     }
 
     /// The main loop. Returns Ok(()) if either msg_rx or reply_tx got closed;
@@ -455,17 +628,26 @@ impl WalAcceptor {
         // we will send keepalives by replying to these requests once per second.
         let mut next_keepalive = Instant::now();
 
+        let fenced = walreceiver_guard.get().fenced.clone();
         loop {
-            let opt_msg = self.msg_rx.recv().await;
+            let opt_msg = tokio::select! {
+                msg = self.msg_rx.recv() => msg,
+                _ = fenced.cancelled() => {
+                    bail!("fenced out by a walproposer elected in a higher term");
+                }
+            };
             if opt_msg.is_none() {
                 return Ok(()); // chan closed, streaming terminated
             }
             let mut next_msg = opt_msg.unwrap();
 
             // Update walreceiver state in shmem for reporting.
-            if let ProposerAcceptorMessage::Elected(_) = &next_msg {
+            let elected_term = if let ProposerAcceptorMessage::Elected(elected) = &next_msg {
                 walreceiver_guard.get().status = WalReceiverStatus::Streaming;
-            }
+                Some(elected.term)
+            } else {
+                None
+            };
 
             let reply_msg = if matches!(next_msg, ProposerAcceptorMessage::AppendRequest(_)) {
                 // loop through AppendRequest's while it's readily available to
@@ -474,7 +656,16 @@ impl WalAcceptor {
                 // Note: this will need to be rewritten if we want to read non-AppendRequest messages here.
                 // Otherwise, we might end up in a situation where we read a message, but don't
                 // process it.
+                let mut unflushed_permits = 0;
+                let mut batch = FlushBatch::new(
+                    self.flush_batch_bytes,
+                    std::cmp::min(Instant::now() + self.flush_batch_timeout, next_keepalive),
+                );
                 while let ProposerAcceptorMessage::AppendRequest(append_request) = next_msg {
+                    if let Some(limit) = &self.unflushed_wal_limit {
+                        unflushed_permits += limit.permits_for(append_request.wal_data.len());
+                    }
+                    batch.add(append_request.wal_data.len());
                     let noflush_msg = ProposerAcceptorMessage::NoFlushAppendRequest(append_request);
 
                     if let Some(reply) = self.tli.process_msg(&noflush_msg).await? {
@@ -488,23 +679,41 @@ impl WalAcceptor {
                         break;
                     }
 
-                    match self.msg_rx.try_recv() {
-                        Ok(msg) => next_msg = msg,
-                        Err(TryRecvError::Empty) => break,
-                        Err(TryRecvError::Disconnected) => return Ok(()), // chan closed, streaming terminated
+                    match batch.next_msg(&mut self.msg_rx).await {
+                        BatchNext::Msg(msg) => next_msg = msg,
+                        BatchNext::Flush => break,
+                        BatchNext::Closed => return Ok(()), // chan closed, streaming terminated
                     }
                 }
 
                 // flush all written WAL to the disk
-                self.tli
+                let reply = self
+                    .tli
                     .process_msg(&ProposerAcceptorMessage::FlushWAL)
-                    .await?
+                    .await?;
+                if let Some(limit) = &self.unflushed_wal_limit {
+                    limit.release(unflushed_permits);
+                }
+                reply
             } else {
                 // process message other than AppendRequest
                 self.tli.process_msg(&next_msg).await?
             };
 
+            // Only record the election if the message was processed successfully.
+            if let Some(term) = elected_term {
+                walreceiver_guard.elected(term);
+            }
+
             if let Some(reply) = reply_msg {
+                if let Some(resp) = reply.append_response() {
+                    // Term-only responses reject the request and carry no LSNs.
+                    if resp.flush_lsn != Lsn::INVALID {
+                        let mut slot = walreceiver_guard.get();
+                        slot.reported_flush_lsn = resp.flush_lsn;
+                        slot.reported_commit_lsn = resp.commit_lsn;
+                    }
+                }
                 if self.reply_tx.send(reply).await.is_err() {
                     return Ok(()); // chan closed, streaming terminated
                 }
@@ -515,6 +724,57 @@ impl WalAcceptor {
     }
 }
 
+/// AppendRequests written since the last flush. For group commit, more of them are
+/// added while they arrive, until the batch is `max_bytes` big or `deadline` passes.
+struct FlushBatch {
+    bytes: u64,
+    max_bytes: u64,
+    deadline: Instant,
+}
+
+/// What to do after adding an AppendRequest to a [`FlushBatch`].
+enum BatchNext {
+    /// Process this message as part of the batch.
+    Msg(ProposerAcceptorMessage),
+    /// Flush the batch now.
+    Flush,
+    /// The channel got closed.
+    Closed,
+}
+
+impl FlushBatch {
+    fn new(max_bytes: u64, deadline: Instant) -> Self {
+        FlushBatch {
+            bytes: 0,
+            max_bytes,
+            deadline,
+        }
+    }
+
+    fn add(&mut self, wal_bytes: usize) {
+        self.bytes += wal_bytes as u64;
+    }
+
+    /// Messages that are already queued are always taken into the batch, so that as
+    /// much WAL as possible is written without fsyncing. Otherwise wait for the next
+    /// one, unless the batch is big or old enough already.
+    async fn next_msg(&self, msg_rx: &mut Receiver<ProposerAcceptorMessage>) -> BatchNext {
+        match msg_rx.try_recv() {
+            Ok(msg) => return BatchNext::Msg(msg),
+            Err(TryRecvError::Disconnected) => return BatchNext::Closed,
+            Err(TryRecvError::Empty) => {}
+        }
+        if self.bytes >= self.max_bytes || Instant::now() >= self.deadline {
+            return BatchNext::Flush;
+        }
+        match tokio::time::timeout_at(self.deadline, msg_rx.recv()).await {
+            Ok(Some(msg)) => BatchNext::Msg(msg),
+            Ok(None) => BatchNext::Closed,
+            Err(_) => BatchNext::Flush,
+        }
+    }
+}
+
 /// Calls update_status_notify in drop to update timeline status.
 struct ComputeConnectionGuard {
     timeline: Arc<Timeline>,
@@ -530,3 +790,205 @@ impl Drop for ComputeConnectionGuard {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safekeeper::{AppendRequest, AppendRequestHeader};
+    use bytes::Bytes;
+
+    fn append_request(wal_bytes: usize) -> ProposerAcceptorMessage {
+        ProposerAcceptorMessage::AppendRequest(AppendRequest {
+            h: AppendRequestHeader {
+                term: 1,
+                epoch_start_lsn: Lsn(0),
+                begin_lsn: Lsn(0),
+                end_lsn: Lsn(wal_bytes as u64),
+                commit_lsn: Lsn(0),
+                truncate_lsn: Lsn(0),
+                proposer_uuid: [0; 16],
+            },
+            wal_data: Bytes::from(vec![0; wal_bytes]),
+        })
+    }
+
+    /// Send `n` AppendRequests of `wal_bytes` each, `interval` apart.
+    fn send_later(
+        msg_tx: &Sender<ProposerAcceptorMessage>,
+        n: usize,
+        wal_bytes: usize,
+        interval: Duration,
+    ) {
+        let msg_tx = msg_tx.clone();
+        tokio::spawn(async move {
+            for _ in 0..n {
+                tokio::time::sleep(interval).await;
+                msg_tx.send(append_request(wal_bytes)).await.unwrap();
+            }
+        });
+    }
+
+    async fn next(
+        batch: &FlushBatch,
+        msg_rx: &mut Receiver<ProposerAcceptorMessage>,
+    ) -> &'static str {
+        match batch.next_msg(msg_rx).await {
+            BatchNext::Msg(_) => "msg",
+            BatchNext::Flush => "flush",
+            BatchNext::Closed => "closed",
+        }
+    }
+
+    #[test]
+    fn test_elected_fences_lower_terms() {
+        let walreceivers = WalReceivers::new();
+        let old = walreceivers.register(Some(1));
+        let new = walreceivers.register(Some(2));
+        let recovery = walreceivers.register(None);
+        old.elected(1);
+        new.elected(2);
+
+        // The WalAcceptor of the old walproposer waits on this token and exits.
+        assert!(old.get().fenced.is_cancelled());
+        assert!(!new.get().fenced.is_cancelled());
+        // Recovery is not a walproposer, nothing to fence.
+        assert!(!recovery.get().fenced.is_cancelled());
+
+        // A walproposer that reconnects and gets elected again in a higher term
+        // fences the previous connection.
+        let reconnected = walreceivers.register(Some(3));
+        reconnected.elected(3);
+        assert!(new.get().fenced.is_cancelled());
+        assert!(!reconnected.get().fenced.is_cancelled());
+    }
+
+    #[test]
+    fn test_elected_same_term_not_fenced() {
+        let walreceivers = WalReceivers::new();
+        let first = walreceivers.register(Some(1));
+        let voting = walreceivers.register(Some(2));
+        let second = walreceivers.register(Some(3));
+        first.elected(5);
+        second.elected(5);
+
+        // Connections of the same term, or not elected yet, are left alone: the
+        // consensus decides which of them may write.
+        assert!(!first.get().fenced.is_cancelled());
+        assert!(!voting.get().fenced.is_cancelled());
+        assert!(!second.get().fenced.is_cancelled());
+    }
+
+    fn test_gauge() -> IntGauge {
+        IntGauge::new("test_unflushed_wal_bytes", "test").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unflushed_wal_limit_blocks() {
+        let gauge = test_gauge();
+        let limit = UnflushedWalLimit::with_gauge(1000, gauge.clone());
+
+        limit.acquire(&append_request(600)).await;
+        assert_eq!(gauge.get(), 600);
+
+        // The reader has to wait until the first request is flushed.
+        let msg = append_request(600);
+        let mut acquire = std::pin::pin!(limit.acquire(&msg));
+        assert!(futures::poll!(&mut acquire).is_pending());
+        assert_eq!(gauge.get(), 600);
+
+        // Which is when the WalAcceptor releases its permits, after FlushWAL.
+        limit.release(limit.permits_for(600));
+        assert!(futures::poll!(&mut acquire).is_ready());
+        assert_eq!(gauge.get(), 600);
+
+        // Messages without WAL pass, and WAL bigger than the limit takes all of it.
+        limit.acquire(&ProposerAcceptorMessage::FlushWAL).await;
+        limit.release(limit.permits_for(600));
+        limit.acquire(&append_request(5000)).await;
+        assert_eq!(gauge.get(), 1000);
+        limit.release(limit.permits_for(5000));
+        assert_eq!(gauge.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_unflushed_wal_limit_drop() {
+        let gauge = test_gauge();
+        let limit = UnflushedWalLimit::with_gauge(1000, gauge.clone());
+        let other = UnflushedWalLimit::with_gauge(1000, gauge.clone());
+        other.acquire(&append_request(100)).await;
+
+        // The connection goes away with WAL queued that never gets flushed: only its
+        // own share is taken off the gauge, once the last clone is gone.
+        limit.acquire(&append_request(300)).await;
+        limit.acquire(&append_request(200)).await;
+        limit.release(limit.permits_for(300));
+        let acceptor_clone = limit.clone();
+        drop(limit);
+        assert_eq!(gauge.get(), 300);
+        drop(acceptor_clone);
+        assert_eq!(gauge.get(), 100);
+
+        other.release(other.permits_for(100));
+        drop(other);
+        assert_eq!(gauge.get(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flush_batch_bytes() {
+        let (msg_tx, mut msg_rx) = channel(MSG_QUEUE_SIZE);
+        let start = Instant::now();
+        let mut batch = FlushBatch::new(300, start + Duration::from_secs(1));
+        batch.add(100);
+
+        // AppendRequests arriving while the batch is small join it.
+        send_later(&msg_tx, 2, 100, Duration::from_millis(10));
+        for _ in 0..2 {
+            assert_eq!(next(&batch, &mut msg_rx).await, "msg");
+            batch.add(100);
+        }
+
+        // Queued ones still do once it is big enough, but then it is flushed without
+        // waiting for more.
+        msg_tx.send(append_request(100)).await.unwrap();
+        assert_eq!(next(&batch, &mut msg_rx).await, "msg");
+        batch.add(100);
+        assert_eq!(next(&batch, &mut msg_rx).await, "flush");
+        assert_eq!(Instant::now(), start + Duration::from_millis(20));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flush_batch_timeout() {
+        let (msg_tx, mut msg_rx) = channel(MSG_QUEUE_SIZE);
+        let start = Instant::now();
+        let mut batch = FlushBatch::new(u64::MAX, start + Duration::from_millis(100));
+        batch.add(100);
+
+        send_later(&msg_tx, 3, 100, Duration::from_millis(40));
+        for _ in 0..2 {
+            assert_eq!(next(&batch, &mut msg_rx).await, "msg");
+            batch.add(100);
+        }
+        // The third one arrives after the deadline and goes into the next batch.
+        assert_eq!(next(&batch, &mut msg_rx).await, "flush");
+        assert_eq!(Instant::now(), start + Duration::from_millis(100));
+
+        drop(msg_tx);
+        let batch = FlushBatch::new(u64::MAX, Instant::now() + Duration::from_millis(100));
+        assert_eq!(next(&batch, &mut msg_rx).await, "msg");
+        assert_eq!(next(&batch, &mut msg_rx).await, "closed");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flush_batch_disabled() {
+        // With the default zero timeout every AppendRequest is flushed on its own,
+        // unless more are queued already.
+        let (msg_tx, mut msg_rx) = channel(MSG_QUEUE_SIZE);
+        let mut batch = FlushBatch::new(
+            crate::defaults::DEFAULT_WAL_FLUSH_BATCH_BYTES,
+            Instant::now(),
+        );
+        batch.add(100);
+        send_later(&msg_tx, 1, 100, Duration::from_millis(10));
+        assert_eq!(next(&batch, &mut msg_rx).await, "flush");
+    }
+}
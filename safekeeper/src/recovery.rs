@@ -288,7 +288,7 @@ async fn recovery_stream(
     // As in normal walreceiver, do networking and writing to disk in parallel.
     let (msg_tx, msg_rx) = channel(MSG_QUEUE_SIZE);
     let (reply_tx, reply_rx) = channel(REPLY_QUEUE_SIZE);
-    let wa = WalAcceptor::spawn(tli.clone(), msg_rx, reply_tx, None);
+    let wa = WalAcceptor::spawn(tli.clone(), msg_rx, reply_tx, None, None);
 
     let res = tokio::select! {
         r = network_io(physical_stream, msg_tx, donor.clone(), tli.clone(), conf.clone()) => r,
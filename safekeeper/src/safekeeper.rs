@@ -28,7 +28,7 @@ use utils::{
     lsn::Lsn,
 };
 
-const SK_PROTOCOL_VERSION: u32 = 2;
+pub const SK_PROTOCOL_VERSION: u32 = 2;
 pub const UNKNOWN_SERVER_VERSION: u32 = 0;
 
 /// Consensus logical timestamp.
@@ -432,6 +432,14 @@ pub enum AcceptorProposerMessage {
 }
 
 impl AcceptorProposerMessage {
+    /// The AppendResponse carried by this message, if it is one.
+    pub fn append_response(&self) -> Option<&AppendResponse> {
+        match self {
+            AcceptorProposerMessage::AppendResponse(resp) => Some(resp),
+            _ => None,
+        }
+    }
+
     /// Serialize acceptor -> proposer message.
     pub fn serialize(&self, buf: &mut BytesMut) -> Result<()> {
         match self {
@@ -311,6 +311,17 @@ impl SharedState {
     }
 }
 
+/// WAL of a timeline kept on this safekeeper's disk.
+#[derive(Debug, Clone, Serialize)]
+pub struct WalRetention {
+    /// LSN since which WAL is on disk.
+    pub start_lsn: Lsn,
+    pub commit_lsn: Lsn,
+    pub flush_lsn: Lsn,
+    /// Total size of the WAL segment files between start_lsn and flush_lsn.
+    pub retained_bytes: u64,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum TimelineError {
     #[error("Timeline {0} was cancelled and cannot be used anymore")]
@@ -692,6 +703,37 @@ impl Timeline {
         (state.sk.state.inmem.clone(), state.sk.state.clone())
     }
 
+    /// Returns WAL kept on disk. Everything is read under a single lock, so the
+    /// values are consistent with each other even if WAL is being appended.
+    pub async fn get_wal_retention(&self) -> WalRetention {
+        let state = self.write_shared_state().await;
+        let wal_seg_size = state.sk.state.server.wal_seg_size as usize;
+        let flush_lsn = state.sk.wal_store.flush_lsn();
+        let commit_lsn = state.sk.state.inmem.commit_lsn;
+
+        // Segments before last_removed_segno are gone, and there never was WAL
+        // before local_start_lsn.
+        let removed_up_to = Lsn(state.last_removed_segno * wal_seg_size as u64);
+        let start_lsn = max(state.sk.state.local_start_lsn, removed_up_to);
+
+        // Segment files are preallocated, so each of them takes a full segment
+        // on disk.
+        let retained_bytes = if flush_lsn > start_lsn {
+            let first_segno = start_lsn.segment_number(wal_seg_size);
+            let last_segno = (flush_lsn.0 - 1) / wal_seg_size as u64;
+            (last_segno - first_segno + 1) * wal_seg_size as u64
+        } else {
+            0
+        };
+
+        WalRetention {
+            start_lsn,
+            commit_lsn,
+            flush_lsn,
+            retained_bytes,
+        }
+    }
+
     /// Returns latest backup_lsn.
     pub async fn get_wal_backup_lsn(&self) -> Lsn {
         self.write_shared_state().await.sk.state.inmem.backup_lsn
@@ -907,6 +949,8 @@ impl Timeline {
                 wal_backup_active: state.wal_backup_active,
                 timeline_is_active: state.active,
                 num_computes: self.walreceivers.get_num() as u32,
+                num_proposers: self.walreceivers.get_num_proposers() as u32,
+                reported_lsns: self.walreceivers.get_reported_lsns(),
                 last_removed_segno: state.last_removed_segno,
                 epoch_start_lsn: state.sk.epoch_start_lsn,
                 mem_state: state.sk.state.inmem.clone(),
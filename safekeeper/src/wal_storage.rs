@@ -343,13 +343,10 @@ impl Storage for PhysicalStorage {
             );
         }
 
-        let write_seconds = time_io_closure(self.write_exact(startpos, buf)).await?;
-        // WAL is written, updating write metrics
-        self.metrics.observe_write_seconds(write_seconds);
-        self.metrics.observe_write_bytes(buf.len());
-
-        // figure out last record's end lsn for reporting (if we got the
-        // whole record)
+        // Decode records before writing them: the decoder verifies page headers
+        // and record CRCs, so corrupted WAL is rejected before it gets to the
+        // disk. It also figures out the last record's end lsn for reporting (if
+        // we got the whole record).
         if self.decoder.available() != startpos {
             info!(
                 "restart decoder from {} to {}",
@@ -360,15 +357,28 @@ impl Storage for PhysicalStorage {
             self.decoder = WalStreamDecoder::new(startpos, pg_version);
         }
         self.decoder.feed_bytes(buf);
+        let mut last_record_lsn = None;
         loop {
-            match self.decoder.poll_decode()? {
-                None => break, // no full record yet
-                Some((lsn, _rec)) => {
-                    self.write_record_lsn = lsn;
+            match self.decoder.poll_decode() {
+                Ok(None) => break, // no full record yet
+                Ok(Some((lsn, _rec))) => last_record_lsn = Some(lsn),
+                Err(e) => {
+                    // The decoder is restarted at startpos on the next write.
+                    warn!("rejecting invalid WAL at {}: {}", e.lsn, e.msg);
+                    return Err(e).context("invalid WAL received");
                 }
             }
         }
 
+        let write_seconds = time_io_closure(self.write_exact(startpos, buf)).await?;
+        // WAL is written, updating write metrics
+        self.metrics.observe_write_seconds(write_seconds);
+        self.metrics.observe_write_bytes(buf.len());
+
+        if let Some(lsn) = last_record_lsn {
+            self.write_record_lsn = lsn;
+        }
+
         Ok(())
     }
 
@@ -754,3 +764,45 @@ pub fn wal_file_paths(
     let wal_file_partial_path = timeline_dir.join(wal_file_name + ".partial");
     Ok((wal_file_path, wal_file_partial_path))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use postgres_ffi::{encode_logical_message, WAL_SEGMENT_SIZE, XLOG_SIZE_OF_XLOG_RECORD};
+
+    fn stub_storage(timeline_dir: &Utf8Path) -> PhysicalStorage {
+        let mut state = TimelinePersistentState::empty();
+        state.server.wal_seg_size = WAL_SEGMENT_SIZE as u32;
+        state.server.pg_version = 160000;
+        PhysicalStorage::new(
+            &TenantTimelineId::empty(),
+            timeline_dir.to_owned(),
+            &SafeKeeperConf::dummy(),
+            &state,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reject_corrupted_record() {
+        let timeline_dir = camino_tempfile::tempdir().unwrap();
+        let mut storage = stub_storage(timeline_dir.path());
+
+        // Somewhere in the middle of the first page of segment 1.
+        let startpos = Lsn(WAL_SEGMENT_SIZE as u64 + 0x100);
+        let record = encode_logical_message("prefix", "message");
+        storage.write_wal(startpos, &record).await.unwrap();
+        let record_end = startpos + record.len() as u64;
+        assert_eq!(storage.internal_state().0, record_end);
+        assert_eq!(storage.internal_state().1, record_end);
+
+        let mut corrupted = encode_logical_message("prefix", "corrupted");
+        corrupted[XLOG_SIZE_OF_XLOG_RECORD + 2] ^= 0xff;
+        let err = storage.write_wal(record_end, &corrupted).await.unwrap_err();
+        assert!(format!("{err:#}").contains("crc mismatch"), "{err:#}");
+
+        // Nothing of the corrupted record was written.
+        assert_eq!(storage.internal_state().0, record_end);
+        assert_eq!(storage.internal_state().1, record_end);
+    }
+}
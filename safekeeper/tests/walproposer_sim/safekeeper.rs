@@ -176,6 +176,9 @@ pub fn run_server(os: NodeOs, disk: Arc<SafekeeperDisk>) -> Result<()> {
         http_auth: None,
         current_thread_runtime: false,
         walsenders_keep_horizon: false,
+        max_unflushed_wal_bytes: u64::MAX,
+        wal_flush_batch_bytes: 0,
+        wal_flush_batch_timeout: Duration::ZERO,
     };
 
     let mut global = GlobalMap::new(disk, conf.clone())?;
@@ -25,7 +25,7 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::broadcast;
-use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
 use tokio::time;
 use tonic::codegen::Service;
 use tonic::transport::server::Connected;
@@ -446,6 +446,14 @@ struct Broker {
     registry: Registry,
 }
 
+/// Move the messages of the `preferred` safekeeper to the front of `batch`. The messages
+/// of each safekeeper stay in the order they were published in.
+fn preferred_safekeeper_first(batch: &mut [Message], preferred: u64) {
+    batch.sort_by_key(|msg| {
+        !matches!(msg, Message::SafekeeperTimelineInfo(info) if info.safekeeper_id == preferred)
+    });
+}
+
 #[tonic::async_trait]
 impl BrokerService for Broker {
     async fn publish_safekeeper_info(
@@ -480,11 +488,12 @@ impl BrokerService for Broker {
         let remote_addr = request
             .remote_addr()
             .expect("TCPConnectInfo inserted by handler");
+        let request = request.into_inner();
         let proto_key = request
-            .into_inner()
             .subscription_key
             .ok_or_else(|| Status::new(Code::InvalidArgument, "missing subscription key"))?;
         let sub_key = SubscriptionKey::from_proto_subscription_key(proto_key)?;
+        let preferred_safekeeper_id = request.preferred_safekeeper_id;
         let mut subscriber = self.registry.register_subscriber(sub_key, remote_addr);
 
         // transform rx into stream with item = Result, as method result demands
@@ -494,11 +503,31 @@ impl BrokerService for Broker {
             loop {
                 match subscriber.sub_rx.recv().await {
                     Ok(info) => {
-                        match info {
-                            Message::SafekeeperTimelineInfo(info) => yield info,
-                            _ => {},
+                        let mut batch = vec![info];
+                        if let Some(preferred) = preferred_safekeeper_id {
+                            // Of the messages already queued, send the preferred safekeeper's first.
+                            // Publishers may keep refilling the channel, so take no more than
+                            // were queued now: at most the channel capacity.
+                            let queued = subscriber.sub_rx.len();
+                            for _ in 0..queued {
+                                match subscriber.sub_rx.try_recv() {
+                                    Ok(info) => batch.push(info),
+                                    Err(TryRecvError::Lagged(skipped_msg)) => {
+                                        BROADCAST_DROPPED_MESSAGES_TOTAL.inc_by(skipped_msg);
+                                        missed_msgs += skipped_msg;
+                                    }
+                                    Err(_) => break,
+                                }
+                            }
+                            preferred_safekeeper_first(&mut batch, preferred);
+                        }
+                        for info in batch {
+                            match info {
+                                Message::SafekeeperTimelineInfo(info) => yield info,
+                                _ => {},
+                            }
+                            BROADCASTED_MESSAGES_TOTAL.inc();
                         }
-                        BROADCASTED_MESSAGES_TOTAL.inc();
                     },
                     Err(RecvError::Lagged(skipped_msg)) => {
                         BROADCAST_DROPPED_MESSAGES_TOTAL.inc_by(skipped_msg);
@@ -713,7 +742,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 mod tests {
     use super::*;
     use storage_broker::proto::TenantTimelineId as ProtoTenantTimelineId;
-    use tokio::sync::broadcast::error::TryRecvError;
     use utils::id::{TenantId, TimelineId};
 
     fn msg(timeline_id: Vec<u8>) -> Message {
@@ -787,4 +815,27 @@ mod tests {
             TryRecvError::Empty
         );
     }
+
+    #[test]
+    fn test_preferred_safekeeper_first() {
+        let from_sk = |sk_id, commit_lsn| {
+            let Message::SafekeeperTimelineInfo(mut info) = msg(tli_from_u64(1)) else {
+                unreachable!()
+            };
+            info.safekeeper_id = sk_id;
+            info.commit_lsn = commit_lsn;
+            Message::SafekeeperTimelineInfo(info)
+        };
+        let mut batch = vec![from_sk(1, 1), from_sk(2, 2), from_sk(1, 3), from_sk(2, 4)];
+        preferred_safekeeper_first(&mut batch, 2);
+        assert_eq!(
+            batch,
+            vec![from_sk(2, 2), from_sk(2, 4), from_sk(1, 1), from_sk(1, 3)]
+        );
+
+        // A hint naming no safekeeper in the batch changes nothing.
+        let unchanged = batch.clone();
+        preferred_safekeeper_first(&mut batch, 3);
+        assert_eq!(batch, unchanged);
+    }
 }
@@ -0,0 +1,97 @@
+//! Bounded log of broker messages that a consumer could not parse.
+//!
+//! Consumers call [`record`] for every message they skip because it is malformed, which
+//! bumps [`UNPARSEABLE_MESSAGES_TOTAL`]. The message itself is only kept once capturing
+//! has been turned on with [`enable`]: then the most recent ones, up to the configured
+//! capacity, can be looked at with [`recent`] to find out what a broker is sending.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::metrics::UNPARSEABLE_MESSAGES_TOTAL;
+
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub received_at: SystemTime,
+    /// Size of the message in its protobuf encoding.
+    pub encoded_len: usize,
+    pub error: String,
+}
+
+struct Ring {
+    capacity: usize,
+    letters: VecDeque<DeadLetter>,
+}
+
+static DEAD_LETTERS: Mutex<Option<Ring>> = Mutex::new(None);
+
+/// Start keeping the last `capacity` unparseable messages. A capacity of zero turns
+/// capturing off again and forgets what was kept.
+pub fn enable(capacity: usize) {
+    let mut dead_letters = DEAD_LETTERS.lock().unwrap();
+    *dead_letters = (capacity > 0).then(|| Ring {
+        capacity,
+        letters: VecDeque::with_capacity(capacity),
+    });
+}
+
+/// Note that `msg` was skipped because it failed to parse with `error`.
+pub fn record<M: prost::Message>(msg: &M, error: &str) {
+    UNPARSEABLE_MESSAGES_TOTAL.inc();
+
+    let mut dead_letters = DEAD_LETTERS.lock().unwrap();
+    let Some(ring) = dead_letters.as_mut() else {
+        return;
+    };
+    if ring.letters.len() == ring.capacity {
+        ring.letters.pop_front();
+    }
+    ring.letters.push_back(DeadLetter {
+        received_at: SystemTime::now(),
+        encoded_len: msg.encoded_len(),
+        error: error.to_owned(),
+    });
+}
+
+/// The captured messages, oldest first.
+pub fn recent() -> Vec<DeadLetter> {
+    match &*DEAD_LETTERS.lock().unwrap() {
+        Some(ring) => ring.letters.iter().cloned().collect(),
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::SafekeeperTimelineInfo;
+
+    #[test]
+    fn ring_buffer() {
+        let msg = SafekeeperTimelineInfo {
+            commit_lsn: 1,
+            ..Default::default()
+        };
+
+        // Not captured until enabled, but still counted.
+        let counted = UNPARSEABLE_MESSAGES_TOTAL.get();
+        record(&msg, "before");
+        assert!(recent().is_empty());
+        assert_eq!(UNPARSEABLE_MESSAGES_TOTAL.get(), counted + 1);
+
+        enable(2);
+        for error in ["first", "second", "third"] {
+            record(&msg, error);
+        }
+        let letters = recent();
+        assert_eq!(
+            letters.iter().map(|l| l.error.as_str()).collect::<Vec<_>>(),
+            ["second", "third"]
+        );
+        assert_eq!(letters[0].encoded_len, prost::Message::encoded_len(&msg));
+
+        enable(0);
+        assert!(recent().is_empty());
+    }
+}
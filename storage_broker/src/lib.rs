@@ -2,13 +2,18 @@ use hyper::body::HttpBody;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
+use tokio_stream::{Stream, StreamExt};
 use tonic::codegen::StdError;
-use tonic::transport::{ClientTlsConfig, Endpoint};
+use tonic::transport::Endpoint;
 use tonic::{transport::Channel, Status};
-use utils::id::{TenantId, TenantTimelineId, TimelineId};
+use tracing::warn;
+use utils::id::{NodeId, TenantId, TenantTimelineId, TimelineId};
 
 use proto::{
-    broker_service_client::BrokerServiceClient, TenantTimelineId as ProtoTenantTimelineId,
+    broker_service_client::BrokerServiceClient,
+    subscribe_safekeeper_info_request::SubscriptionKey as ProtoSubscriptionKey,
+    SafekeeperTimelineInfo, SubscribeSafekeeperInfoRequest,
+    TenantTimelineId as ProtoTenantTimelineId,
 };
 
 // Code generated by protobuf.
@@ -20,7 +25,13 @@ pub mod proto {
     tonic::include_proto!("storage_broker");
 }
 
+pub mod dead_letters;
+pub mod messages;
 pub mod metrics;
+#[cfg(any(test, feature = "testing"))]
+pub mod mock;
+pub mod proxy;
+pub mod tls;
 
 // Re-exports to avoid direct tonic dependency in user crates.
 pub use tonic::Code;
@@ -28,6 +39,8 @@ pub use tonic::Request;
 pub use tonic::Streaming;
 
 pub use hyper::Uri;
+pub use proxy::Proxy;
+pub use tls::TlsVersion;
 
 pub const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:50051";
 pub const DEFAULT_ENDPOINT: &str = const_format::formatcp!("http://{DEFAULT_LISTEN_ADDR}");
@@ -39,32 +52,115 @@ pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_millis(5000);
 // avoid depending on tonic directly in user crates.
 pub type BrokerClientChannel = BrokerServiceClient<Channel>;
 
+/// Default limit on the size of a single message in either direction. Broker messages
+/// are a few hundred bytes, so this only guards against a misbehaving peer.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Client settings for [`connect_with_options`].
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    pub keepalive_interval: Duration,
+    /// Messages from the broker larger than this fail the request, or end a
+    /// subscription stream, with a [`Status`] of code [`Code::OutOfRange`].
+    pub max_decoding_message_size: usize,
+    /// Messages to the broker larger than this fail with [`Code::OutOfRange`].
+    pub max_encoding_message_size: usize,
+    pub proxy: Proxy,
+    /// Lowest TLS version accepted from https endpoints.
+    pub tls_min_version: TlsVersion,
+    /// Cipher suites offered to https endpoints, by their rustls names such as
+    /// `TLS13_AES_256_GCM_SHA384`. Empty means the rustls defaults.
+    pub tls_cipher_suites: Vec<String>,
+}
+
+impl ConnectOptions {
+    pub fn new(keepalive_interval: Duration) -> Self {
+        ConnectOptions {
+            keepalive_interval,
+            max_decoding_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_encoding_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            proxy: Proxy::default(),
+            tls_min_version: TlsVersion::default(),
+            tls_cipher_suites: Vec::new(),
+        }
+    }
+}
+
+// Create connection object with default message size limits, see
+// connect_with_options.
+pub fn connect<U>(endpoint: U, keepalive_interval: Duration) -> anyhow::Result<BrokerClientChannel>
+where
+    U: std::convert::TryInto<Uri>,
+    U::Error: std::error::Error + Send + Sync + 'static,
+{
+    connect_with_options(endpoint, ConnectOptions::new(keepalive_interval))
+}
+
 // Create connection object configured to run TLS if schema starts with https://
-// and plain text otherwise. Connection is lazy, only endpoint sanity is
-// validated here.
+// and plain text otherwise, tunneled through a proxy if options.proxy asks for one.
+// Connection is lazy, only endpoint, proxy and TLS settings sanity is validated here.
 //
 // NB: this function is not async, but still must be run on a tokio runtime thread
 // because that's a requirement of tonic_endpoint.connect_lazy()'s Channel::new call.
-pub fn connect<U>(endpoint: U, keepalive_interval: Duration) -> anyhow::Result<BrokerClientChannel>
+pub fn connect_with_options<U>(
+    endpoint: U,
+    options: ConnectOptions,
+) -> anyhow::Result<BrokerClientChannel>
 where
     U: std::convert::TryInto<Uri>,
     U::Error: std::error::Error + Send + Sync + 'static,
 {
     let uri: Uri = endpoint.try_into()?;
+    let proxy_connector = options.proxy.resolve(&uri)?;
     let mut tonic_endpoint: Endpoint = uri.into();
     // If schema starts with https, start encrypted connection; do plain text
     // otherwise.
     if let Some("https") = tonic_endpoint.uri().scheme_str() {
-        let tls = ClientTlsConfig::new();
+        let tls = tls::client_tls_config(options.tls_min_version, &options.tls_cipher_suites)?;
         tonic_endpoint = tonic_endpoint.tls_config(tls)?;
     }
     tonic_endpoint = tonic_endpoint
-        .http2_keep_alive_interval(keepalive_interval)
+        .http2_keep_alive_interval(options.keepalive_interval)
         .keep_alive_while_idle(true)
         .connect_timeout(DEFAULT_CONNECT_TIMEOUT);
     //  keep_alive_timeout is 20s by default on both client and server side
-    let channel = tonic_endpoint.connect_lazy();
-    Ok(BrokerClientChannel::new(channel))
+    let channel = match proxy_connector {
+        Some(connector) => tonic_endpoint.connect_with_connector_lazy(connector),
+        None => tonic_endpoint.connect_lazy(),
+    };
+    Ok(BrokerClientChannel::new(channel)
+        .max_decoding_message_size(options.max_decoding_message_size)
+        .max_encoding_message_size(options.max_encoding_message_size))
+}
+
+/// Parse a broker endpoint, checking that it has an http or https scheme and an
+/// authority. [`connect`] only validates the endpoint lazily, so use this to catch a
+/// misconfigured endpoint at startup rather than on the first request.
+pub fn parse_endpoint(endpoint: &str) -> anyhow::Result<Uri> {
+    let uri: Uri = endpoint
+        .parse()
+        .map_err(|e| anyhow::anyhow!("invalid broker endpoint '{endpoint}': {e}"))?;
+    match uri.scheme_str() {
+        Some("http") | Some("https") => {}
+        Some(scheme) => anyhow::bail!(
+            "invalid broker endpoint '{endpoint}': unsupported scheme '{scheme}', expected http or https"
+        ),
+        None => anyhow::bail!(
+            "invalid broker endpoint '{endpoint}': missing scheme, expected http:// or https://"
+        ),
+    }
+    if uri.authority().is_none() {
+        anyhow::bail!("invalid broker endpoint '{endpoint}': missing host");
+    }
+    Ok(uri)
+}
+
+/// Create a client for [`DEFAULT_ENDPOINT`] with the default keepalive interval.
+///
+/// Like [`connect`], this must be called on a tokio runtime thread.
+pub fn default_client() -> anyhow::Result<BrokerClientChannel> {
+    let keepalive_interval = humantime::parse_duration(DEFAULT_KEEPALIVE_INTERVAL)?;
+    connect(parse_endpoint(DEFAULT_ENDPOINT)?, keepalive_interval)
 }
 
 impl BrokerClientChannel {
@@ -77,6 +173,49 @@ impl BrokerClientChannel {
         let conn = tonic::transport::Endpoint::new(dst)?.connect_lazy();
         Ok(Self::new(conn))
     }
+
+    /// Subscribe to safekeeper updates of all timelines, yielding only those for which
+    /// `filter` returns true. Messages with a missing or malformed tenant/timeline id are
+    /// logged, recorded in [`dead_letters`] and skipped instead of ending the stream;
+    /// errors of the stream itself are passed through.
+    ///
+    /// If `preferred_safekeeper_id` is given, the broker sends that safekeeper's updates
+    /// ahead of the others queued for the subscription. It is only a hint: brokers that
+    /// don't know about it send the updates in the order they arrive.
+    pub async fn subscribe_safekeeper_info_filtered<F>(
+        &mut self,
+        filter: F,
+        preferred_safekeeper_id: Option<NodeId>,
+    ) -> Result<impl Stream<Item = Result<SafekeeperTimelineInfo, Status>>, Status>
+    where
+        F: Fn(&TenantTimelineId) -> bool + Send + 'static,
+    {
+        let request = SubscribeSafekeeperInfoRequest {
+            subscription_key: Some(ProtoSubscriptionKey::All(())),
+            preferred_safekeeper_id: preferred_safekeeper_id.map(|id| id.0),
+        };
+        let stream = self.subscribe_safekeeper_info(request).await?.into_inner();
+        Ok(stream.filter_map(move |msg| {
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(status) => return Some(Err(status)),
+            };
+            let Some(proto_ttid) = msg.tenant_timeline_id.as_ref() else {
+                warn!("skipping broker message without tenant_timeline_id");
+                dead_letters::record(&msg, "missing tenant_timeline_id");
+                return None;
+            };
+            match parse_proto_ttid(proto_ttid) {
+                Ok(ttid) if filter(&ttid) => Some(Ok(msg)),
+                Ok(_) => None,
+                Err(status) => {
+                    warn!("skipping broker message: {}", status.message());
+                    dead_letters::record(&msg, status.message());
+                    None
+                }
+            }
+        }))
+    }
 }
 
 // parse variable length bytes from protobuf
@@ -95,6 +234,14 @@ pub fn parse_proto_ttid(proto_ttid: &ProtoTenantTimelineId) -> Result<TenantTime
     })
 }
 
+// convert TenantTimelineId into its protobuf representation
+pub fn to_proto_ttid(ttid: &TenantTimelineId) -> ProtoTenantTimelineId {
+    ProtoTenantTimelineId {
+        tenant_id: ttid.tenant_id.as_ref().to_owned(),
+        timeline_id: ttid.timeline_id.as_ref().to_owned(),
+    }
+}
+
 // These several usages don't justify anyhow dependency, though it would work as
 // well.
 type AnyError = Box<dyn std::error::Error + Send + Sync + 'static>;
@@ -147,3 +294,23 @@ where
 fn map_option_err<T, U: Into<AnyError>>(err: Option<Result<T, U>>) -> Option<Result<T, AnyError>> {
     err.map(|e| e.map_err(Into::into))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert!(parse_endpoint(DEFAULT_ENDPOINT).is_ok());
+        assert!(parse_endpoint("https://broker.example.com").is_ok());
+
+        for bad in [
+            "127.0.0.1:50051",
+            "grpc://127.0.0.1:50051",
+            "http://",
+            "/just/a/path",
+        ] {
+            assert!(parse_endpoint(bad).is_err(), "{bad} should not parse");
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Typed counterparts of the broker's protobuf messages.
+//!
+//! The generated [`crate::proto`] structs carry ids as raw byte vectors and LSNs as
+//! plain integers. The types here use [`TenantTimelineId`], [`NodeId`] and [`Lsn`]
+//! instead, and convert to the proto messages with `From` and back with `TryFrom`,
+//! which fails with [`Status`] `InvalidArgument` on a missing or malformed id.
+
+use tonic::{Code, Status};
+use utils::id::{NodeId, TenantTimelineId};
+use utils::lsn::Lsn;
+
+use crate::proto;
+use crate::proto::subscribe_safekeeper_info_request::SubscriptionKey;
+use crate::{parse_proto_ttid, to_proto_ttid};
+
+fn required_ttid(proto_ttid: Option<&proto::TenantTimelineId>) -> Result<TenantTimelineId, Status> {
+    let proto_ttid = proto_ttid
+        .ok_or_else(|| Status::new(Code::InvalidArgument, "missing tenant_timeline_id"))?;
+    parse_proto_ttid(proto_ttid)
+}
+
+/// See [`proto::SafekeeperTimelineInfo`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafekeeperTimelineInfo {
+    pub safekeeper_id: NodeId,
+    pub ttid: TenantTimelineId,
+    pub term: u64,
+    pub last_log_term: u64,
+    pub flush_lsn: Lsn,
+    pub commit_lsn: Lsn,
+    pub backup_lsn: Lsn,
+    pub remote_consistent_lsn: Lsn,
+    pub peer_horizon_lsn: Lsn,
+    pub local_start_lsn: Lsn,
+    pub safekeeper_connstr: String,
+    pub http_connstr: String,
+    pub availability_zone: Option<String>,
+}
+
+impl TryFrom<proto::SafekeeperTimelineInfo> for SafekeeperTimelineInfo {
+    type Error = Status;
+
+    fn try_from(msg: proto::SafekeeperTimelineInfo) -> Result<Self, Self::Error> {
+        Ok(SafekeeperTimelineInfo {
+            safekeeper_id: NodeId(msg.safekeeper_id),
+            ttid: required_ttid(msg.tenant_timeline_id.as_ref())?,
+            term: msg.term,
+            last_log_term: msg.last_log_term,
+            flush_lsn: Lsn(msg.flush_lsn),
+            commit_lsn: Lsn(msg.commit_lsn),
+            backup_lsn: Lsn(msg.backup_lsn),
+            remote_consistent_lsn: Lsn(msg.remote_consistent_lsn),
+            peer_horizon_lsn: Lsn(msg.peer_horizon_lsn),
+            local_start_lsn: Lsn(msg.local_start_lsn),
+            safekeeper_connstr: msg.safekeeper_connstr,
+            http_connstr: msg.http_connstr,
+            availability_zone: msg.availability_zone,
+        })
+    }
+}
+
+impl From<SafekeeperTimelineInfo> for proto::SafekeeperTimelineInfo {
+    fn from(info: SafekeeperTimelineInfo) -> Self {
+        proto::SafekeeperTimelineInfo {
+            safekeeper_id: info.safekeeper_id.0,
+            tenant_timeline_id: Some(to_proto_ttid(&info.ttid)),
+            term: info.term,
+            last_log_term: info.last_log_term,
+            flush_lsn: info.flush_lsn.0,
+            commit_lsn: info.commit_lsn.0,
+            backup_lsn: info.backup_lsn.0,
+            remote_consistent_lsn: info.remote_consistent_lsn.0,
+            peer_horizon_lsn: info.peer_horizon_lsn.0,
+            local_start_lsn: info.local_start_lsn.0,
+            safekeeper_connstr: info.safekeeper_connstr,
+            http_connstr: info.http_connstr,
+            availability_zone: info.availability_zone,
+        }
+    }
+}
+
+/// See [`proto::SubscribeSafekeeperInfoRequest`]. A `ttid` of `None` subscribes to all
+/// timelines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscribeSafekeeperInfoRequest {
+    pub ttid: Option<TenantTimelineId>,
+    pub preferred_safekeeper_id: Option<NodeId>,
+}
+
+impl TryFrom<proto::SubscribeSafekeeperInfoRequest> for SubscribeSafekeeperInfoRequest {
+    type Error = Status;
+
+    fn try_from(msg: proto::SubscribeSafekeeperInfoRequest) -> Result<Self, Self::Error> {
+        let ttid = match msg.subscription_key {
+            Some(SubscriptionKey::All(())) => None,
+            Some(SubscriptionKey::TenantTimelineId(proto_ttid)) => {
+                Some(parse_proto_ttid(&proto_ttid)?)
+            }
+            None => {
+                return Err(Status::new(
+                    Code::InvalidArgument,
+                    "missing subscription key",
+                ))
+            }
+        };
+        Ok(SubscribeSafekeeperInfoRequest {
+            ttid,
+            preferred_safekeeper_id: msg.preferred_safekeeper_id.map(NodeId),
+        })
+    }
+}
+
+impl From<SubscribeSafekeeperInfoRequest> for proto::SubscribeSafekeeperInfoRequest {
+    fn from(req: SubscribeSafekeeperInfoRequest) -> Self {
+        let key = match req.ttid {
+            Some(ttid) => SubscriptionKey::TenantTimelineId(to_proto_ttid(&ttid)),
+            None => SubscriptionKey::All(()),
+        };
+        proto::SubscribeSafekeeperInfoRequest {
+            subscription_key: Some(key),
+            preferred_safekeeper_id: req.preferred_safekeeper_id.map(|id| id.0),
+        }
+    }
+}
+
+/// See [`proto::SafekeeperDiscoveryRequest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SafekeeperDiscoveryRequest {
+    pub ttid: TenantTimelineId,
+}
+
+impl TryFrom<proto::SafekeeperDiscoveryRequest> for SafekeeperDiscoveryRequest {
+    type Error = Status;
+
+    fn try_from(msg: proto::SafekeeperDiscoveryRequest) -> Result<Self, Self::Error> {
+        Ok(SafekeeperDiscoveryRequest {
+            ttid: required_ttid(msg.tenant_timeline_id.as_ref())?,
+        })
+    }
+}
+
+impl From<SafekeeperDiscoveryRequest> for proto::SafekeeperDiscoveryRequest {
+    fn from(req: SafekeeperDiscoveryRequest) -> Self {
+        proto::SafekeeperDiscoveryRequest {
+            tenant_timeline_id: Some(to_proto_ttid(&req.ttid)),
+        }
+    }
+}
+
+/// See [`proto::SafekeeperDiscoveryResponse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafekeeperDiscoveryResponse {
+    pub safekeeper_id: NodeId,
+    pub ttid: TenantTimelineId,
+    pub commit_lsn: Lsn,
+    pub safekeeper_connstr: String,
+    pub availability_zone: Option<String>,
+}
+
+impl TryFrom<proto::SafekeeperDiscoveryResponse> for SafekeeperDiscoveryResponse {
+    type Error = Status;
+
+    fn try_from(msg: proto::SafekeeperDiscoveryResponse) -> Result<Self, Self::Error> {
+        Ok(SafekeeperDiscoveryResponse {
+            safekeeper_id: NodeId(msg.safekeeper_id),
+            ttid: required_ttid(msg.tenant_timeline_id.as_ref())?,
+            commit_lsn: Lsn(msg.commit_lsn),
+            safekeeper_connstr: msg.safekeeper_connstr,
+            availability_zone: msg.availability_zone,
+        })
+    }
+}
+
+impl From<SafekeeperDiscoveryResponse> for proto::SafekeeperDiscoveryResponse {
+    fn from(resp: SafekeeperDiscoveryResponse) -> Self {
+        proto::SafekeeperDiscoveryResponse {
+            safekeeper_id: resp.safekeeper_id.0,
+            tenant_timeline_id: Some(to_proto_ttid(&resp.ttid)),
+            commit_lsn: resp.commit_lsn.0,
+            safekeeper_connstr: resp.safekeeper_connstr,
+            availability_zone: resp.availability_zone,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safekeeper_timeline_info_roundtrip() {
+        let info = SafekeeperTimelineInfo {
+            safekeeper_id: NodeId(1),
+            ttid: TenantTimelineId::generate(),
+            term: 2,
+            last_log_term: 2,
+            flush_lsn: Lsn(0x30),
+            commit_lsn: Lsn(0x20),
+            backup_lsn: Lsn(0x10),
+            remote_consistent_lsn: Lsn(0x10),
+            peer_horizon_lsn: Lsn(0x10),
+            local_start_lsn: Lsn(0x8),
+            safekeeper_connstr: "sk-1:5454".to_owned(),
+            http_connstr: "sk-1:7676".to_owned(),
+            availability_zone: Some("az-1".to_owned()),
+        };
+        let msg = proto::SafekeeperTimelineInfo::from(info.clone());
+        assert_eq!(SafekeeperTimelineInfo::try_from(msg).unwrap(), info);
+    }
+
+    #[test]
+    fn subscribe_request_roundtrip() {
+        let req = SubscribeSafekeeperInfoRequest {
+            ttid: Some(TenantTimelineId::generate()),
+            preferred_safekeeper_id: Some(NodeId(3)),
+        };
+        let msg = proto::SubscribeSafekeeperInfoRequest::from(req);
+        assert_eq!(msg.preferred_safekeeper_id, Some(3));
+        assert_eq!(SubscribeSafekeeperInfoRequest::try_from(msg).unwrap(), req);
+
+        // A request without the hint, as sent by older clients.
+        let msg = proto::SubscribeSafekeeperInfoRequest {
+            subscription_key: Some(SubscriptionKey::All(())),
+            preferred_safekeeper_id: None,
+        };
+        let req = SubscribeSafekeeperInfoRequest::try_from(msg).unwrap();
+        assert_eq!(req.ttid, None);
+        assert_eq!(req.preferred_safekeeper_id, None);
+    }
+
+    #[test]
+    fn missing_or_malformed_ttid_is_rejected() {
+        let msg = proto::SafekeeperDiscoveryRequest {
+            tenant_timeline_id: None,
+        };
+        let err = SafekeeperDiscoveryRequest::try_from(msg).unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+
+        let msg = proto::SafekeeperDiscoveryRequest {
+            tenant_timeline_id: Some(proto::TenantTimelineId {
+                tenant_id: vec![1, 2, 3],
+                timeline_id: vec![],
+            }),
+        };
+        let err = SafekeeperDiscoveryRequest::try_from(msg).unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+}
@@ -55,3 +55,11 @@ pub static PUBLISHED_ONEOFF_MESSAGES_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     )
     .expect("Failed to register metric")
 });
+
+pub static UNPARSEABLE_MESSAGES_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "storage_broker_unparseable_messages_total",
+        "Number of received broker messages that consumers skipped because they failed to parse"
+    )
+    .expect("Failed to register metric")
+});
@@ -0,0 +1,245 @@
+//! In-process broker for tests of crates that talk to the storage broker.
+//!
+//! [`MockBrokerServer`] serves [`BrokerService`] on a localhost port. Instead of relaying
+//! between publishers and subscribers only, it lets the test push crafted
+//! `SafekeeperTimelineInfo` messages and cut off all current subscriptions, which is what
+//! subscribe/reconnect logic needs to be tested deterministically. Only the
+//! `SafekeeperTimelineInfo` RPCs are implemented.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures_core::Stream;
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+use tonic::{Code, Request, Response, Status};
+use utils::id::TenantTimelineId;
+
+use crate::proto::broker_service_server::{BrokerService, BrokerServiceServer};
+use crate::proto::subscribe_safekeeper_info_request::SubscriptionKey as ProtoSubscriptionKey;
+use crate::proto::{
+    SafekeeperTimelineInfo, SubscribeByFilterRequest, SubscribeSafekeeperInfoRequest, TypedMessage,
+};
+use crate::{
+    connect_with_options, parse_proto_ttid, BrokerClientChannel, ConnectOptions, Proxy, Uri,
+};
+
+#[derive(Clone, Debug)]
+enum Event {
+    Message(SafekeeperTimelineInfo),
+    Disconnect,
+}
+
+pub struct MockBrokerServer {
+    addr: SocketAddr,
+    events: broadcast::Sender<Event>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+}
+
+impl MockBrokerServer {
+    /// Start serving on a free localhost port.
+    pub async fn start() -> anyhow::Result<Self> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let incoming = async_stream::stream! {
+            loop {
+                yield listener.accept().await.map(|(stream, _)| stream);
+            }
+        };
+
+        let (events, _) = broadcast::channel(1024);
+        let service = BrokerServiceServer::new(MockBroker {
+            events: events.clone(),
+        });
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let task = tokio::spawn(async move {
+            let res = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(incoming, async {
+                    shutdown_rx.await.ok();
+                })
+                .await;
+            if let Err(e) = res {
+                tracing::error!("mock broker server failed: {e}");
+            }
+        });
+
+        Ok(MockBrokerServer {
+            addr,
+            events,
+            shutdown_tx: Some(shutdown_tx),
+            task,
+        })
+    }
+
+    pub fn endpoint(&self) -> Uri {
+        format!("http://{}", self.addr)
+            .parse()
+            .expect("socket address is a valid authority")
+    }
+
+    /// A client connected to this server, bypassing any proxy configured in the
+    /// environment. Must be called on a tokio runtime thread.
+    pub fn client(&self) -> anyhow::Result<BrokerClientChannel> {
+        let options = ConnectOptions {
+            proxy: Proxy::Direct,
+            ..ConnectOptions::new(Duration::from_secs(1))
+        };
+        connect_with_options(self.endpoint(), options)
+    }
+
+    /// Send `info` to all matching subscribers, returning how many subscriptions
+    /// were open at that moment.
+    pub fn push(&self, info: SafekeeperTimelineInfo) -> usize {
+        self.events.send(Event::Message(info)).unwrap_or(0)
+    }
+
+    /// End all current subscriptions with an `Unavailable` status, as if the broker
+    /// went away. Later subscriptions are not affected.
+    pub fn disconnect_subscribers(&self) {
+        self.events.send(Event::Disconnect).ok();
+    }
+
+    /// Stop accepting connections and wait for the server task to exit.
+    pub async fn shutdown(mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            tx.send(()).ok();
+        }
+        // Open subscription streams would keep a graceful shutdown waiting.
+        self.disconnect_subscribers();
+        (&mut self.task).await.ok();
+    }
+}
+
+impl Drop for MockBrokerServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct MockBroker {
+    events: broadcast::Sender<Event>,
+}
+
+#[tonic::async_trait]
+impl BrokerService for MockBroker {
+    async fn publish_safekeeper_info(
+        &self,
+        request: Request<tonic::Streaming<SafekeeperTimelineInfo>>,
+    ) -> Result<Response<()>, Status> {
+        let mut stream = request.into_inner();
+        while let Some(msg) = stream.next().await {
+            self.events.send(Event::Message(msg?)).ok();
+        }
+        Ok(Response::new(()))
+    }
+
+    type SubscribeSafekeeperInfoStream =
+        Pin<Box<dyn Stream<Item = Result<SafekeeperTimelineInfo, Status>> + Send + 'static>>;
+
+    async fn subscribe_safekeeper_info(
+        &self,
+        request: Request<SubscribeSafekeeperInfoRequest>,
+    ) -> Result<Response<Self::SubscribeSafekeeperInfoStream>, Status> {
+        let filter: Option<TenantTimelineId> = match request.into_inner().subscription_key {
+            Some(ProtoSubscriptionKey::All(())) => None,
+            Some(ProtoSubscriptionKey::TenantTimelineId(proto_ttid)) => {
+                Some(parse_proto_ttid(&proto_ttid)?)
+            }
+            None => {
+                return Err(Status::new(
+                    Code::InvalidArgument,
+                    "missing subscription key",
+                ))
+            }
+        };
+        let mut rx = self.events.subscribe();
+
+        let output = async_stream::try_stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(Event::Message(info)) => {
+                        let matches = match (&filter, &info.tenant_timeline_id) {
+                            (None, _) => true,
+                            (Some(ttid), Some(proto_ttid)) => {
+                                parse_proto_ttid(proto_ttid).ok().as_ref() == Some(ttid)
+                            }
+                            (Some(_), None) => false,
+                        };
+                        if matches {
+                            yield info;
+                        }
+                    }
+                    Ok(Event::Disconnect) => {
+                        Err(Status::new(Code::Unavailable, "mock broker disconnected subscribers"))?;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        };
+        Ok(Response::new(
+            Box::pin(output) as Self::SubscribeSafekeeperInfoStream
+        ))
+    }
+
+    type SubscribeByFilterStream =
+        Pin<Box<dyn Stream<Item = Result<TypedMessage, Status>> + Send + 'static>>;
+
+    async fn subscribe_by_filter(
+        &self,
+        _request: Request<SubscribeByFilterRequest>,
+    ) -> Result<Response<Self::SubscribeByFilterStream>, Status> {
+        Err(Status::unimplemented("not supported by the mock broker"))
+    }
+
+    async fn publish_one(&self, _request: Request<TypedMessage>) -> Result<Response<()>, Status> {
+        Err(Status::unimplemented("not supported by the mock broker"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::to_proto_ttid;
+
+    #[tokio::test]
+    async fn push_and_disconnect() {
+        let server = MockBrokerServer::start().await.unwrap();
+        let mut client = server.client().unwrap();
+        let ttid = TenantTimelineId::generate();
+
+        let request = SubscribeSafekeeperInfoRequest {
+            subscription_key: Some(ProtoSubscriptionKey::TenantTimelineId(to_proto_ttid(&ttid))),
+            preferred_safekeeper_id: None,
+        };
+        let mut stream = client
+            .subscribe_safekeeper_info(request)
+            .await
+            .unwrap()
+            .into_inner();
+
+        // Subscription is registered on the server by the time response headers arrive.
+        server.push(SafekeeperTimelineInfo {
+            tenant_timeline_id: Some(to_proto_ttid(&TenantTimelineId::generate())),
+            commit_lsn: 1,
+            ..Default::default()
+        });
+        server.push(SafekeeperTimelineInfo {
+            tenant_timeline_id: Some(to_proto_ttid(&ttid)),
+            commit_lsn: 2,
+            ..Default::default()
+        });
+        let msg = stream.message().await.unwrap().unwrap();
+        assert_eq!(msg.commit_lsn, 2);
+
+        server.disconnect_subscribers();
+        let err = stream.message().await.unwrap_err();
+        assert_eq!(err.code(), Code::Unavailable);
+
+        server.shutdown().await;
+    }
+}
@@ -0,0 +1,170 @@
+//! TLS settings of the broker client for https endpoints.
+//!
+//! By default tonic's own rustls configuration is used, which accepts TLS 1.2 and 1.3
+//! with the rustls default cipher suites. Deployments with compliance requirements can
+//! raise the minimum version or restrict the cipher suites; the client then gets a
+//! rustls config of its own, with the same native root certificates tonic would load.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Context;
+// The versions tonic is built against, see the workspace Cargo.toml.
+use rustls_0_21 as rustls;
+use rustls_native_certs_0_6 as rustls_native_certs;
+
+use rustls::{ProtocolVersion, SupportedCipherSuite, SupportedProtocolVersion};
+use tonic::transport::ClientTlsConfig;
+
+/// Lowest TLS version the client negotiates with the broker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TlsVersion {
+    #[default]
+    Tls12,
+    Tls13,
+}
+
+impl TlsVersion {
+    fn protocol_versions(self) -> &'static [&'static SupportedProtocolVersion] {
+        match self {
+            TlsVersion::Tls12 => &[&rustls::version::TLS13, &rustls::version::TLS12],
+            TlsVersion::Tls13 => &[&rustls::version::TLS13],
+        }
+    }
+}
+
+impl FromStr for TlsVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "1.2" => Ok(TlsVersion::Tls12),
+            "1.3" => Ok(TlsVersion::Tls13),
+            _ => anyhow::bail!("invalid TLS version '{s}', expected 1.2 or 1.3"),
+        }
+    }
+}
+
+impl fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsVersion::Tls12 => write!(f, "1.2"),
+            TlsVersion::Tls13 => write!(f, "1.3"),
+        }
+    }
+}
+
+/// TLS config for an https endpoint. `cipher_suites` are names as rustls spells them,
+/// e.g. `TLS13_AES_256_GCM_SHA384`; empty means the rustls defaults.
+pub(crate) fn client_tls_config(
+    min_version: TlsVersion,
+    cipher_suites: &[String],
+) -> anyhow::Result<ClientTlsConfig> {
+    if min_version == TlsVersion::default() && cipher_suites.is_empty() {
+        return Ok(ClientTlsConfig::new());
+    }
+    let suites = select_cipher_suites(min_version, cipher_suites)?;
+
+    let mut roots = rustls::RootCertStore::empty();
+    let native_certs =
+        rustls_native_certs::load_native_certs().context("load native root certificates")?;
+    roots.add_parsable_certificates(
+        &native_certs
+            .into_iter()
+            .map(|cert| cert.0)
+            .collect::<Vec<_>>(),
+    );
+
+    let mut config = rustls::ClientConfig::builder()
+        .with_cipher_suites(&suites)
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(min_version.protocol_versions())
+        .context("build broker TLS config")?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    // The broker protocol runs over HTTP/2 only.
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    Ok(ClientTlsConfig::new().rustls_client_config(config))
+}
+
+/// The cipher suites named in `names`, or the default ones, that can be negotiated at
+/// `min_version` or above. Errors if a name is unknown or no suite is left.
+fn select_cipher_suites(
+    min_version: TlsVersion,
+    names: &[String],
+) -> anyhow::Result<Vec<SupportedCipherSuite>> {
+    let candidates = if names.is_empty() {
+        rustls::DEFAULT_CIPHER_SUITES.to_vec()
+    } else {
+        names
+            .iter()
+            .map(|name| {
+                rustls::ALL_CIPHER_SUITES
+                    .iter()
+                    .find(|suite| format!("{:?}", suite.suite()) == *name)
+                    .copied()
+                    .ok_or_else(|| anyhow::anyhow!("unknown TLS cipher suite '{name}'"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+    };
+
+    let suites = candidates
+        .into_iter()
+        .filter(|suite| {
+            min_version == TlsVersion::Tls12 || suite.version().version == ProtocolVersion::TLSv1_3
+        })
+        .collect::<Vec<_>>();
+    if suites.is_empty() {
+        anyhow::bail!(
+            "none of the TLS cipher suites {names:?} can be used with minimum TLS version {min_version}"
+        );
+    }
+    Ok(suites)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn cipher_suite_selection() {
+        let all_defaults = select_cipher_suites(TlsVersion::Tls12, &[]).unwrap();
+        assert_eq!(all_defaults.len(), rustls::DEFAULT_CIPHER_SUITES.len());
+        let tls13_defaults = select_cipher_suites(TlsVersion::Tls13, &[]).unwrap();
+        assert!(!tls13_defaults.is_empty());
+        assert!(tls13_defaults.len() < all_defaults.len());
+
+        let suites = names(&[
+            "TLS13_AES_256_GCM_SHA384",
+            "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+        ]);
+        assert_eq!(
+            select_cipher_suites(TlsVersion::Tls12, &suites)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            select_cipher_suites(TlsVersion::Tls13, &suites)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Only TLS 1.2 suites with TLS 1.3 required leaves nothing to negotiate.
+        let tls12_only = names(&["TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384"]);
+        assert!(select_cipher_suites(TlsVersion::Tls13, &tls12_only).is_err());
+        assert!(select_cipher_suites(TlsVersion::Tls12, &names(&["NO_SUCH_SUITE"])).is_err());
+    }
+
+    #[test]
+    fn tls_version_parsing() {
+        assert_eq!("1.3".parse::<TlsVersion>().unwrap(), TlsVersion::Tls13);
+        assert_eq!(TlsVersion::Tls12.to_string(), "1.2");
+        assert!("1.1".parse::<TlsVersion>().is_err());
+    }
+}
@@ -1666,6 +1666,27 @@ class NeonCli(AbstractNeonCli):
         else:
             return TimelineId(str(created_timeline_id))
 
+    def rename_branch(
+        self,
+        branch_name: str,
+        new_branch_name: str,
+        tenant_id: Optional[TenantId] = None,
+        check_return_code=True,
+    ) -> "subprocess.CompletedProcess[str]":
+        return self.raw_cli(
+            [
+                "timeline",
+                "rename",
+                "--branch-name",
+                branch_name,
+                "--new-branch-name",
+                new_branch_name,
+                "--tenant-id",
+                str(tenant_id or self.env.initial_tenant),
+            ],
+            check_return_code=check_return_code,
+        )
+
     def list_timelines(self, tenant_id: Optional[TenantId] = None) -> List[Tuple[str, TimelineId]]:
         """
         Returns a list of (branch_name, timeline_id) tuples out of parsed `neon timeline list` CLI output.
@@ -3637,6 +3658,24 @@ class Safekeeper:
                 assert isinstance(res, dict)
                 return res
 
+    def wal_retention(self, tenant_id: TenantId, timeline_id: TimelineId) -> Dict[str, Any]:
+        """
+        Send WAL_RETENTION query to get start_lsn, commit_lsn, flush_lsn and
+        retained_bytes of the WAL kept on disk for the timeline.
+        """
+        token = self.env.auth_keys.generate_tenant_token(tenant_id)
+        connstr = f"host=localhost port={self.port.pg} password={token} replication=0 options='-c timeline_id={timeline_id} tenant_id={tenant_id}'"
+
+        with closing(psycopg2.connect(connstr)) as conn:
+            conn.autocommit = True
+            with conn.cursor() as cur:
+                cur.execute("WAL_RETENTION")
+                all = cur.fetchall()
+                log.info(f"WAL_RETENTION response: {all}")
+                res = json.loads(all[0][0])
+                assert isinstance(res, dict)
+                return res
+
     def http_client(self, auth_token: Optional[str] = None) -> SafekeeperHttpClient:
         is_testing_enabled = '"testing"' in self.env.get_binary_version("safekeeper")
         return SafekeeperHttpClient(
@@ -204,8 +204,15 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         res = self.post(f"http://localhost:{self.port}/v1/reload_auth_validation_keys")
         self.verbose_error(res)
 
-    def tenant_list(self) -> List[Dict[Any, Any]]:
-        res = self.get(f"http://localhost:{self.port}/v1/tenant")
+    def tenant_list(
+        self, offset: Optional[int] = None, limit: Optional[int] = None
+    ) -> List[Dict[Any, Any]]:
+        params = {}
+        if offset is not None:
+            params["offset"] = offset
+        if limit is not None:
+            params["limit"] = limit
+        res = self.get(f"http://localhost:{self.port}/v1/tenant", params=params)
         self.verbose_error(res)
         res_json = res.json()
         assert isinstance(res_json, list)
@@ -446,17 +453,29 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         tenant_id: Union[TenantId, TenantShardId],
         include_non_incremental_logical_size: bool = False,
         include_timeline_dir_layer_file_size_sum: bool = False,
+        offset: Optional[int] = None,
+        limit: Optional[int] = None,
+        stream: bool = False,
     ) -> List[Dict[str, Any]]:
-        params = {}
+        params: Dict[str, Any] = {}
         if include_non_incremental_logical_size:
             params["include-non-incremental-logical-size"] = "true"
         if include_timeline_dir_layer_file_size_sum:
             params["include-timeline-dir-layer-file-size-sum"] = "true"
+        if offset is not None:
+            params["offset"] = offset
+        if limit is not None:
+            params["limit"] = limit
+        if stream:
+            params["stream"] = "true"
 
         res = self.get(
             f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline", params=params
         )
         self.verbose_error(res)
+        if stream:
+            assert res.headers["Content-Type"] == "application/x-ndjson"
+            return [json.loads(line) for line in res.text.splitlines()]
         res_json = res.json()
         assert isinstance(res_json, list)
         return res_json
@@ -543,10 +562,14 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         tenant_id: Union[TenantId, TenantShardId],
         timeline_id: TimelineId,
         gc_horizon: Optional[int],
+        allow_short_horizon: bool = True,
     ) -> dict[str, Any]:
         """
         Unlike most handlers, this will wait for the layers to be actually
         complete registering themselves to the deletion queue.
+
+        Tests commonly GC with horizons shorter than the configured one, so they
+        are allowed by default.
         """
         self.is_testing_enabled_or_skip()
 
@@ -555,7 +578,7 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         )
         res = self.put(
             f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/do_gc",
-            json={"gc_horizon": gc_horizon},
+            json={"gc_horizon": gc_horizon, "allow_short_horizon": allow_short_horizon},
         )
         log.info(f"Got GC request response code: {res.status_code}")
         self.verbose_error(res)
@@ -564,6 +587,27 @@ class PageserverHttpClient(requests.Session, MetricsGetter):
         assert isinstance(res_json, dict)
         return res_json
 
+    def timeline_gc_stream(
+        self,
+        tenant_id: Union[TenantId, TenantShardId],
+        timeline_id: TimelineId,
+        gc_horizon: Optional[int],
+        allow_short_horizon: bool = True,
+    ) -> List[Dict[str, Any]]:
+        """
+        `timeline_gc` with progress: the progress lines, followed by the result line.
+        """
+        self.is_testing_enabled_or_skip()
+
+        res = self.put(
+            f"http://localhost:{self.port}/v1/tenant/{tenant_id}/timeline/{timeline_id}/do_gc",
+            params={"stream": "true"},
+            json={"gc_horizon": gc_horizon, "allow_short_horizon": allow_short_horizon},
+        )
+        self.verbose_error(res)
+        assert res.headers["Content-Type"] == "application/x-ndjson"
+        return [json.loads(line) for line in res.text.splitlines()]
+
     def timeline_compact(
         self,
         tenant_id: Union[TenantId, TenantShardId],
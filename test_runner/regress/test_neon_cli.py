@@ -62,6 +62,27 @@ def test_cli_timeline_list(neon_simple_env: NeonEnv):
     assert nested_timeline_id in timelines_cli
 
 
+def test_cli_timeline_rename(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    timeline_id = env.neon_cli.create_branch("test_cli_rename_old")
+    env.neon_cli.create_branch("test_cli_rename_taken")
+
+    env.neon_cli.rename_branch("test_cli_rename_old", "test_cli_rename_new")
+    branches = dict(env.neon_cli.list_timelines())
+    assert branches["test_cli_rename_new"] == timeline_id
+    assert "test_cli_rename_old" not in branches
+
+    # The new name must be free, the old one must exist, and main stays main.
+    for old, new in [
+        ("test_cli_rename_new", "test_cli_rename_taken"),
+        ("test_cli_rename_old", "test_cli_rename_other"),
+        (DEFAULT_BRANCH_NAME, "test_cli_rename_other"),
+    ]:
+        res = env.neon_cli.rename_branch(old, new, check_return_code=False)
+        assert res.returncode != 0
+    assert dict(env.neon_cli.list_timelines())["test_cli_rename_new"] == timeline_id
+
+
 def helper_compare_tenant_list(pageserver_http_client: PageserverHttpClient, env: NeonEnv):
     tenants = pageserver_http_client.tenant_list()
     tenants_api = sorted(map(lambda t: cast(str, t["id"]), tenants))
@@ -1,13 +1,23 @@
+import io
+import json
 import subprocess
+import tarfile
+import tempfile
+from concurrent.futures import ThreadPoolExecutor
+from contextlib import closing
 from pathlib import Path
-from typing import Optional
+from typing import Dict, Optional
 
+import psycopg2.extras
+import pytest
 from fixtures.neon_fixtures import (
     DEFAULT_BRANCH_NAME,
     NeonEnv,
     NeonEnvBuilder,
+    parse_project_git_version_output,
+    wait_for_last_flush_lsn,
 )
-from fixtures.pageserver.http import PageserverHttpClient
+from fixtures.pageserver.http import PageserverApiException, PageserverHttpClient
 from fixtures.types import Lsn, TenantId, TimelineId
 from fixtures.utils import wait_until
 
@@ -190,3 +200,487 @@ def test_pageserver_http_api_client_auth_enabled(neon_env_builder: NeonEnvBuilde
 
     with env.pageserver.http_client(auth_token=pageserver_token) as client:
         check_client(env, client)
+
+
+def test_pageserver_build_info(neon_simple_env: NeonEnv, neon_binpath: Path):
+    env = neon_simple_env
+
+    out = subprocess.check_output([neon_binpath / "pageserver", "--version"]).decode("utf-8")
+    commit = parse_project_git_version_output(out)
+
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor(cursor_factory=psycopg2.extras.RealDictCursor) as pscur:
+            pscur.execute("build_info")
+            res = pscur.fetchone()
+    assert res is not None
+    assert parse_project_git_version_output(res["git_version"]) == commit
+    assert f"version: {res['version']}," in out
+    assert f"build timestamp: {res['build_timestamp']}," in out
+    assert int(res["build_timestamp"]) > 0
+
+
+def test_pageserver_unix_socket(neon_env_builder: NeonEnvBuilder):
+    # Unix socket paths are limited to about 100 bytes, too short for the test output
+    # directory, so put the socket in a temporary directory.
+    socket_dir = tempfile.mkdtemp(prefix="pageserver-")
+    port = 6400
+    neon_env_builder.pageserver_config_override = f"listen_socket='{socket_dir}/.s.PGSQL.{port}'"
+    env = neon_env_builder.init_start()
+
+    with closing(env.pageserver.connect(host=socket_dir, port=port)) as psconn:
+        with psconn.cursor() as pscur:
+            pscur.execute("status")
+            res = pscur.fetchone()
+    assert res is not None
+    assert res[0] == env.pageserver.id
+    assert res[1] is True
+
+    # TCP connections keep working.
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor() as pscur:
+            pscur.execute("status")
+            assert pscur.fetchone() is not None
+
+
+def test_pageserver_evict_tenant(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    tenant_id = env.initial_tenant
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+    # Reading the table back from a fresh compute reconstructs its pages in the
+    # pageserver, which puts them in the page cache.
+    endpoint.stop()
+    endpoint.start()
+    assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 10000
+
+    def evict_tenant() -> int:
+        with closing(env.pageserver.connect()) as psconn:
+            with psconn.cursor() as pscur:
+                pscur.execute(f"evict_tenant {tenant_id}")
+                res = pscur.fetchone()
+        assert res is not None
+        return int(res[0])
+
+    assert evict_tenant() > 0
+    assert evict_tenant() == 0
+
+    # The tenant stays loaded and serves reads as before.
+    endpoint.stop()
+    endpoint.start()
+    assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 10000
+
+
+def test_pageserver_gc_horizon_validation(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    client = env.pageserver.http_client()
+
+    last_record_lsn = client.timeline_detail(env.initial_tenant, env.initial_timeline)[
+        "last_record_lsn"
+    ]
+    with pytest.raises(PageserverApiException, match="exceeds the last record LSN"):
+        client.timeline_gc(
+            env.initial_tenant, env.initial_timeline, Lsn(last_record_lsn).lsn_int + 1
+        )
+
+    # A horizon shorter than the configured one needs to be asked for explicitly.
+    with pytest.raises(PageserverApiException, match="set allow_short_horizon"):
+        client.timeline_gc(env.initial_tenant, env.initial_timeline, 0, allow_short_horizon=False)
+    client.timeline_gc(env.initial_tenant, env.initial_timeline, 0)
+
+
+def test_pageserver_get_controlfile(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CHECKPOINT")
+    system_identifier = int(
+        endpoint.safe_psql("SELECT system_identifier FROM pg_control_system()")[0][0]
+    )
+
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor() as pscur:
+            pscur.execute(f"get_controlfile {env.initial_tenant} {env.initial_timeline}")
+            res = pscur.fetchone()
+    assert res is not None
+    controlfile = json.loads(res[0])
+    assert controlfile["system_identifier"] == system_identifier
+    assert controlfile["wal_segment_size"] == 16 * 1024 * 1024
+    assert Lsn(controlfile["checkpoint_redo_lsn"]) > Lsn(0)
+
+
+def test_pageserver_list_pagination(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    client = env.pageserver.http_client()
+    tenant_id = env.initial_tenant
+
+    for i in range(4):
+        env.neon_cli.create_branch(f"branch_{i}", tenant_id=tenant_id)
+
+    all_timelines = client.timeline_list(tenant_id)
+    assert len(all_timelines) == 5
+    paged = []
+    offset = 0
+    while True:
+        page = client.timeline_list(tenant_id, offset=offset, limit=2)
+        paged.extend(page)
+        if len(page) < 2:
+            break
+        offset += 2
+    assert [t["timeline_id"] for t in paged] == sorted(t["timeline_id"] for t in all_timelines)
+
+    streamed = client.timeline_list(tenant_id, stream=True)
+    assert [t["timeline_id"] for t in streamed] == [t["timeline_id"] for t in paged]
+    streamed_page = client.timeline_list(tenant_id, offset=1, limit=2, stream=True)
+    assert [t["timeline_id"] for t in streamed_page] == [t["timeline_id"] for t in paged[1:3]]
+
+    assert client.tenant_list(offset=0, limit=1) == client.tenant_list()[:1]
+    assert client.tenant_list(offset=100) == []
+    with pytest.raises(PageserverApiException, match="limit must be positive"):
+        client.tenant_list(limit=0)
+
+
+def test_pageserver_check_timeline(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+    endpoint.safe_psql("CHECKPOINT")
+
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor(cursor_factory=psycopg2.extras.RealDictCursor) as pscur:
+            pscur.execute(f"check_timeline {env.initial_tenant} {env.initial_timeline}")
+            res = pscur.fetchone()
+    assert res is not None
+    assert res["status"] == "ok", res
+    assert res["gaps"] == ""
+    assert res["error"] is None
+    assert res["sampled_pages"] > 0
+
+
+def test_pageserver_statement_timeout(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    env.pageserver.allowed_errors.append(".*invalid statement_timeout.*")
+
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor() as pscur:
+            pscur.execute("SET statement_timeout = '5s'")
+            pscur.execute("SET statement_timeout TO 0")
+            with pytest.raises(psycopg2.Error, match="invalid statement_timeout") as exc:
+                pscur.execute("SET statement_timeout = '5 fortnights'")
+            assert exc.value.pgcode == "22023"
+
+
+def test_pageserver_pagestream_trace(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    env.pageserver.allowed_errors.append(".*invalid pagestream_trace.*")
+
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor() as pscur:
+            pscur.execute("SET pagestream_trace = on")
+            pscur.execute("SET SESSION pagestream_trace TO off")
+            with pytest.raises(psycopg2.Error, match="invalid pagestream_trace") as exc:
+                pscur.execute("SET pagestream_trace = 'sometimes'")
+            assert exc.value.pgcode == "22023"
+
+
+def test_pageserver_error_codes(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+
+    def pgcode(query: str) -> str:
+        with closing(env.pageserver.connect()) as psconn:
+            with psconn.cursor() as pscur:
+                with pytest.raises(psycopg2.Error) as exc:
+                    pscur.execute(query)
+        return exc.value.pgcode
+
+    assert pgcode("layer_list") == "22023"
+    assert pgcode(f"layer_list not-a-tenant {env.initial_timeline}") == "22023"
+    assert pgcode(f"layer_list {TenantId.generate()} {env.initial_timeline}") == "NP001"
+    assert pgcode(f"layer_list {env.initial_tenant} {TimelineId.generate()}") == "NP002"
+
+
+def test_pageserver_check(neon_simple_env: NeonEnv, neon_binpath: Path):
+    env = neon_simple_env
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+    endpoint.stop()
+    env.pageserver.stop()
+
+    def run_check():
+        return subprocess.run(
+            [str(neon_binpath / "pageserver"), "-D", str(env.pageserver.workdir), "--check"],
+            check=False,
+            universal_newlines=True,
+            stdout=subprocess.PIPE,
+            stderr=subprocess.PIPE,
+        )
+
+    res = run_check()
+    assert res.returncode == 0, res.stdout + res.stderr
+
+    stray = env.pageserver.timeline_dir(env.initial_tenant, env.initial_timeline) / "stray"
+    stray.write_text("not a layer")
+    res = run_check()
+    assert res.returncode == 1
+    assert "/stray: unknown file in timeline directory" in res.stdout
+
+    # The check must not have touched anything.
+    assert stray.exists()
+    stray.unlink()
+    env.pageserver.start()
+
+
+def test_pageserver_set_gc_config(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor() as pscur:
+            pscur.execute("set_gc_config 1000 10s")
+            configured = pscur.fetchone()
+
+            # The previous settings are returned, so they can be restored later.
+            pscur.execute("set_gc_config 2000 1m")
+            assert pscur.fetchone() == (1000, 10)
+
+            with pytest.raises(psycopg2.Error) as exc:
+                pscur.execute("set_gc_config 2000 5 fortnights")
+            assert exc.value.pgcode == "22023"
+            with pytest.raises(psycopg2.Error, match="Failed to parse gc_period"):
+                pscur.execute("set_gc_config 2000 fortnight")
+
+            pscur.execute("set_gc_config default")
+            assert pscur.fetchone() == (2000, 60)
+            pscur.execute("set_gc_config default")
+            assert pscur.fetchone() == configured
+
+
+def test_pageserver_pin_lsn(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    pageserver_http = env.pageserver.http_client()
+
+    # Disable background GC, so that only the GC calls below move the cutoff
+    tenant_id, timeline_id = env.neon_cli.create_tenant(
+        conf={"gc_period": "0s", "pitr_interval": "0s"}
+    )
+    endpoint = env.endpoints.create_start("main", tenant_id=tenant_id)
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+    pinned_lsn = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    def gc_cutoff() -> Lsn:
+        endpoint.safe_psql("UPDATE t SET g = g + 1")
+        wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+        pageserver_http.timeline_checkpoint(tenant_id, timeline_id)
+        pageserver_http.timeline_gc(tenant_id, timeline_id, 0)
+        detail = pageserver_http.timeline_detail(tenant_id, timeline_id)
+        return Lsn(detail["latest_gc_cutoff_lsn"])
+
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor() as pscur:
+            pscur.execute(f"pin_lsn {tenant_id} {timeline_id} {pinned_lsn}")
+            lease_id, ttl_seconds = pscur.fetchone()
+            assert ttl_seconds > 0
+
+            # GC stops at the lease, so a branch can still be created at the pinned LSN
+            assert gc_cutoff() <= pinned_lsn
+            env.neon_cli.create_branch("pinned", tenant_id=tenant_id, ancestor_start_lsn=pinned_lsn)
+
+            pscur.execute(f"unpin_lsn {tenant_id} {timeline_id} {lease_id}")
+            assert gc_cutoff() > pinned_lsn
+
+            with pytest.raises(psycopg2.Error) as exc:
+                pscur.execute(f"unpin_lsn {tenant_id} {timeline_id} {lease_id}")
+            assert exc.value.pgcode == "22023"
+            with pytest.raises(psycopg2.Error) as exc:
+                pscur.execute(f"pin_lsn {tenant_id} {timeline_id} {pinned_lsn}")
+            assert exc.value.pgcode == "NP003"
+
+
+def test_basebackup_consistent_lsn(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    pageserver_http = env.pageserver.http_client()
+    failpoint = "basebackup-before-tarball-pause"
+
+    # Disable background GC, so that only the GC calls below move the cutoff
+    tenant_id, timeline_id = env.neon_cli.create_tenant(
+        conf={"gc_period": "0s", "pitr_interval": "0s"}
+    )
+    endpoint = env.endpoints.create_start("main", tenant_id=tenant_id)
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+    backup_lsn = wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+
+    def fullbackup() -> Dict[str, bytes]:
+        buf = io.BytesIO()
+        with closing(env.pageserver.connect()) as psconn:
+            with psconn.cursor() as pscur:
+                pscur.copy_expert(f"fullbackup {tenant_id} {timeline_id} {backup_lsn}", buf)
+        buf.seek(0)
+        with tarfile.open(fileobj=buf) as tar:
+            files = {
+                member.name: tar.extractfile(member).read()  # type: ignore
+                for member in tar.getmembers()
+                if member.isfile()
+            }
+        # Has the previous record LSN only while the backup LSN is the end of the timeline.
+        files.pop("zenith.signal")
+        return files
+
+    def gc_cutoff() -> Lsn:
+        endpoint.safe_psql("UPDATE t SET g = g + 1")
+        wait_for_last_flush_lsn(env, endpoint, tenant_id, timeline_id)
+        pageserver_http.timeline_checkpoint(tenant_id, timeline_id)
+        pageserver_http.timeline_gc(tenant_id, timeline_id, 0)
+        detail = pageserver_http.timeline_detail(tenant_id, timeline_id)
+        return Lsn(detail["latest_gc_cutoff_lsn"])
+
+    expected = fullbackup()
+
+    pageserver_http.configure_failpoints((failpoint, "pause"))
+    with ThreadPoolExecutor(max_workers=1) as executor:
+        paused = executor.submit(fullbackup)
+        wait_until(20, 0.5, lambda: env.pageserver.assert_log_contains(f"at failpoint {failpoint}"))
+
+        # Rewrite every row and GC while no file of the backup has been read yet. The
+        # backup holds back GC at its LSN, and reads every file at that LSN.
+        assert gc_cutoff() <= backup_lsn
+        pageserver_http.configure_failpoints((failpoint, "off"))
+        assert paused.result() == expected
+
+    # Once the backup has completed, GC moves past its LSN, and another backup at the
+    # LSN fails before sending any data.
+    assert gc_cutoff() > backup_lsn
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor() as pscur:
+            with pytest.raises(psycopg2.Error) as exc:
+                pscur.execute(f"basebackup {tenant_id} {timeline_id} {backup_lsn}")
+            assert exc.value.pgcode == "NP003"
+
+
+def test_pageserver_reset_metrics(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 1000) g")
+    wait_for_last_flush_lsn(env, endpoint, env.initial_tenant, env.initial_timeline)
+
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor() as pscur:
+            pscur.execute("reset_metrics")
+            before = json.loads(pscur.fetchone()[0])
+            assert before["pageserver_wal_ingest_records_received"] > 0
+
+            # Only what the endpoint wrote in the background since the reset is counted
+            pscur.execute("reset_metrics")
+            after = json.loads(pscur.fetchone()[0])
+            assert after.keys() == before.keys()
+            assert (
+                after["pageserver_wal_ingest_records_received"]
+                < before["pageserver_wal_ingest_records_received"]
+            )
+
+
+def test_pageserver_mem_usage(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor() as pscur:
+            pscur.execute("mem_usage")
+            usage = json.loads(pscur.fetchone()[0])
+
+    assert usage["page_cache_bytes"] > 0
+    tenant = next(t for t in usage["tenants"] if t["tenant_shard_id"] == str(env.initial_tenant))
+    timeline = next(
+        t for t in tenant["timelines"] if t["timeline_id"] == str(env.initial_timeline)
+    )
+    assert timeline["in_memory_layer_bytes"] > 0
+
+
+def test_pageserver_help(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor() as pscur:
+            pscur.execute("help")
+            commands = {c["name"]: c for c in json.loads(pscur.fetchone()[0])}
+
+            # Every listed command that takes no arguments is actually supported.
+            for name, command in commands.items():
+                if command["args"] == "" and command["permission"] != "admin":
+                    pscur.execute(name)
+
+    assert commands["pagestream"]["permission"] == "tenant"
+    assert commands["pagestream_v2"]["permission"] == "tenant"
+    assert commands["set_gc_config"]["permission"] == "admin"
+    assert commands["status"]["permission"] == "none"
+    # neon_simple_env runs a pageserver built with testing APIs.
+    assert "reset_metrics" in commands
+
+
+def test_pageserver_rel_history(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+    ((spcnode, dbnode, relnode),) = endpoint.safe_psql(
+        """
+        SELECT (SELECT oid FROM pg_tablespace WHERE spcname = 'pg_default'),
+               (SELECT oid FROM pg_database WHERE datname = current_database()),
+               pg_relation_filenode('t')
+        """
+    )
+    # TRUNCATE gives the table a new relfilenode, and drops the old one.
+    endpoint.safe_psql("TRUNCATE t")
+    wait_for_last_flush_lsn(env, endpoint, env.initial_tenant, env.initial_timeline)
+
+    def rel_history(rel: str):
+        with closing(env.pageserver.connect()) as psconn:
+            with psconn.cursor() as pscur:
+                pscur.execute(f"rel_history {env.initial_tenant} {env.initial_timeline} {rel}")
+                return json.loads(pscur.fetchone()[0])
+
+    history = rel_history(f"{spcnode}/{dbnode}/{relnode}")
+    assert [event["event"] for event in history] == ["created", "dropped"]
+    assert history[1]["nblocks"] is None
+
+    # A relation that never existed has no history.
+    assert rel_history(f"{spcnode}/{dbnode}/4000000000") == []
+
+
+def test_pageserver_max_message_size(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.pageserver_config_override = "page_service_max_message_size = 1024"
+    env = neon_env_builder.init_start()
+    env.pageserver.allowed_errors.append(".*exceeds the limit of 1024 bytes.*")
+
+    # An oversized message closes the connection.
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor() as pscur:
+            with pytest.raises(psycopg2.Error):
+                pscur.execute("status " + "x" * 2048)
+
+    with closing(env.pageserver.connect()) as psconn:
+        with psconn.cursor() as pscur:
+            pscur.execute("status")
+            assert pscur.fetchone() is not None
+
+
+def test_pageserver_gc_progress(neon_simple_env: NeonEnv):
+    env = neon_simple_env
+    endpoint = env.endpoints.create_start("main")
+    endpoint.safe_psql("CREATE TABLE t AS SELECT g FROM generate_series(1, 10000) g")
+    wait_for_last_flush_lsn(env, endpoint, env.initial_tenant, env.initial_timeline)
+
+    client = env.pageserver.http_client()
+    client.timeline_checkpoint(env.initial_tenant, env.initial_timeline)
+    lines = client.timeline_gc_stream(env.initial_tenant, env.initial_timeline, 0)
+
+    # Intermediate progress may be skipped if the GC outpaces the client, the last one is not.
+    progress = [line["progress"] for line in lines[:-1]]
+    assert progress[-1]["timelines_done"] == 1
+    assert all(p["timelines_total"] == 1 for p in progress)
+    for earlier, later in zip(progress, progress[1:]):
+        assert earlier["timelines_done"] <= later["timelines_done"]
+        assert earlier["layers_removed"] <= later["layers_removed"]
+    result = lines[-1]["result"]
+    assert progress[-1]["layers_examined"] == result["layers_total"]
+    assert progress[-1]["layers_removed"] == result["layers_removed"]
+    assert progress[-1]["bytes_removed"] == result["bytes_removed"]
@@ -878,3 +878,47 @@ def test_timeline_delete_resumed_on_attach(
             )
         ),
     )
+
+
+def test_timeline_delete_with_page_requests_in_flight(neon_env_builder: NeonEnvBuilder):
+    """
+    A compute that reads from a timeline while it is being deleted must neither hold up
+    the deletion nor get its connection dropped: the page requests get an error saying
+    the timeline is being deleted.
+    """
+    neon_env_builder.enable_pageserver_remote_storage(RemoteStorageKind.MOCK_S3)
+
+    env = neon_env_builder.init_start()
+
+    child_timeline_id = env.neon_cli.create_branch("child", "main")
+    ps_http = env.pageserver.http_client()
+
+    endpoint = env.endpoints.create_start("child")
+    pg_conn = endpoint.connect()
+    cur = pg_conn.cursor()
+    cur.execute("CREATE EXTENSION neon_test_utils")
+    cur.execute("CREATE TABLE t (id int, filler text)")
+    cur.execute("INSERT INTO t SELECT g, repeat('x', 100) FROM generate_series(1, 10000) g")
+    assert query_scalar(cur, "SELECT count(*) FROM t") == 10000
+
+    # Keep the timeline around in Stopping state after the deletion let go of its gate.
+    failpoint = "in_progress_delete"
+    ps_http.configure_failpoints((failpoint, "pause"))
+
+    # The compute's page service connection holds the timeline's gate: the deletion
+    # must get past it without the compute disconnecting.
+    ps_http.timeline_delete(env.initial_tenant, child_timeline_id, timeout=20)
+
+    def hit_failpoint():
+        env.pageserver.assert_log_contains(f".*{child_timeline_id}.*at failpoint {failpoint}")
+
+    wait_until(50, 0.1, hit_failpoint)
+
+    # Make the next scan read its pages from the pageserver.
+    cur.execute("SELECT clear_buffer_cache()")
+    with pytest.raises(Exception, match=f"timeline {child_timeline_id} is being deleted"):
+        cur.execute("SELECT count(*) FROM t")
+
+    endpoint.stop()
+    ps_http.configure_failpoints((failpoint, "off"))
+    wait_timeline_detail_404(ps_http, env.initial_tenant, child_timeline_id, iterations=10)
@@ -892,6 +892,39 @@ def delete_lazy_activating(
         wait_tenant_status_404(pageserver_http, tenant_id=delete_tenant_id, iterations=40)
 
 
+def test_lazy_tenant_load(neon_env_builder: NeonEnvBuilder):
+    """
+    With lazy_tenant_load, tenants are not warmed up in the background after a restart: startup
+    completes without them, and each one is only loaded when a client accesses it.
+    """
+    neon_env_builder.pageserver_config_override = "lazy_tenant_load = true"
+
+    env = neon_env_builder.init_start()
+    pageserver_http = env.pageserver.http_client()
+    other_tenant_id, _ = env.neon_cli.create_tenant()
+
+    env.pageserver.stop()
+    env.pageserver.start()
+
+    def startup_done():
+        assert pageserver_http.get_metric_value("pageserver_startup_is_loading") == 0
+
+    wait_until(10, 1, startup_done)
+
+    def tenant_state(tenant_id: TenantId) -> str:
+        return pageserver_http.tenant_status(tenant_id=tenant_id)["state"]["slug"]
+
+    # Give a background warmup, if there were one, the time to load the tenants
+    time.sleep(2)
+    assert tenant_state(env.initial_tenant) == "Attaching"
+    assert tenant_state(other_tenant_id) == "Attaching"
+
+    endpoint = env.endpoints.create_start(branch_name="main", tenant_id=env.initial_tenant)
+    endpoint.safe_psql("SELECT 1")
+    assert tenant_state(env.initial_tenant) == "Active"
+    assert tenant_state(other_tenant_id) == "Attaching"
+
+
 def test_timeline_logical_size_task_priority(neon_env_builder: NeonEnvBuilder):
     """
     /v1/tenant/:tenant_shard_id/timeline and /v1/tenant/:tenant_shard_id
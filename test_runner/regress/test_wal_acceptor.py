@@ -806,6 +806,27 @@ def test_sync_safekeepers(
     assert all(lsn_after_sync == lsn for lsn in lsn_after_append)
 
 
+def test_wal_retention(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+
+    tenant_id = env.initial_tenant
+    timeline_id = env.neon_cli.create_branch("test_wal_retention")
+    endpoint = env.endpoints.create_start("test_wal_retention")
+    endpoint.safe_psql("create table t(key int, value text)")
+    endpoint.safe_psql("insert into t select generate_series(1,100000), 'payload'")
+
+    sk = env.safekeepers[0]
+    retention = sk.wal_retention(tenant_id, timeline_id)
+    log.info(f"WAL retention: {retention}")
+
+    start_lsn = Lsn(retention["start_lsn"])
+    commit_lsn = Lsn(retention["commit_lsn"])
+    flush_lsn = Lsn(retention["flush_lsn"])
+    assert start_lsn <= commit_lsn <= flush_lsn
+    assert retention["retained_bytes"] >= flush_lsn - start_lsn
+    assert retention["retained_bytes"] % (16 * 1024 * 1024) == 0
+
+
 @pytest.mark.parametrize("auth_enabled", [False, True])
 def test_timeline_status(neon_env_builder: NeonEnvBuilder, auth_enabled: bool):
     neon_env_builder.auth_enabled = auth_enabled
@@ -1,8 +1,11 @@
+import json
 import time
+from contextlib import closing
 
 from fixtures.log_helper import log
 from fixtures.neon_fixtures import NeonEnv, NeonEnvBuilder
 from fixtures.types import Lsn, TenantId
+from fixtures.utils import wait_until
 
 
 # Checks that pageserver's walreceiver state is printed in the logs during WAL wait timeout.
@@ -99,6 +102,70 @@ def test_pageserver_lsn_wait_error_safekeeper_stop(neon_env_builder: NeonEnvBuil
                 ), f"Should have safekeeper {safekeeper.id} printed in walreceiver state after 2nd WAL wait timeout"
 
 
+def test_pageserver_switch_walsource(neon_env_builder: NeonEnvBuilder):
+    neon_env_builder.num_safekeepers = 3
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    insert_test_elements(env, tenant_id, start=0, count=1_000)
+    target_sk = env.safekeepers[2]
+    env.pageserver.allowed_errors.append(".*does not publish this timeline to the broker.*")
+
+    def switch_walsource():
+        with closing(env.pageserver.connect()) as psconn:
+            with psconn.cursor() as pscur:
+                pscur.execute(
+                    f"switch_walsource {tenant_id} {timeline_id} 127.0.0.1:{target_sk.port.pg}"
+                )
+                res = pscur.fetchone()
+        assert res is not None
+        return res
+
+    # The safekeeper needs to have published the timeline to the broker first.
+    safekeeper_id, switch_lsn, caught_up_lsn = wait_until(20, 0.5, switch_walsource)
+    log.info(f"switched to safekeeper {safekeeper_id} at {switch_lsn}, caught up to {caught_up_lsn}")
+    assert safekeeper_id == target_sk.id
+    assert Lsn(caught_up_lsn) >= Lsn(switch_lsn)
+
+    # WAL keeps arriving over the new connection.
+    insert_test_elements(env, tenant_id, start=1_000, count=1_000)
+    endpoint = env.endpoints.create_start("main", tenant_id=tenant_id)
+    assert endpoint.safe_psql("SELECT count(*) FROM t")[0][0] == 2_000
+
+
+def test_pageserver_replication_lag(neon_env_builder: NeonEnvBuilder):
+    env = neon_env_builder.init_start()
+    tenant_id = env.initial_tenant
+    timeline_id = env.initial_timeline
+
+    def replication_lag():
+        with closing(env.pageserver.connect()) as psconn:
+            with psconn.cursor() as pscur:
+                pscur.execute(f"replication_lag {tenant_id} {timeline_id}")
+                return json.loads(pscur.fetchone()[0])
+
+    # Nothing has been written, so the WAL receiver has nothing to connect to yet.
+    lag = replication_lag()
+    assert lag["state"] in ("disconnected", "no_source_configured"), lag
+    assert lag["safekeeper_id"] is None
+    assert lag["lag_bytes"] is None
+
+    insert_test_elements(env, tenant_id, start=0, count=1_000)
+
+    def caught_up():
+        lag = replication_lag()
+        assert lag["state"] == "streaming", lag
+        assert lag["lag_bytes"] == 0, lag
+        return lag
+
+    lag = wait_until(20, 0.5, caught_up)
+    log.info(f"replication lag: {lag}")
+    assert lag["safekeeper_id"] == env.safekeepers[0].id
+    assert Lsn(lag["last_received_lsn"]) >= Lsn(lag["commit_lsn"])
+    assert lag["lag_seconds"] == 0
+
+
 def insert_test_elements(env: NeonEnv, tenant_id: TenantId, start: int, count: int):
     first_element_id = start
     last_element_id = first_element_id + count